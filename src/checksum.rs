@@ -0,0 +1,219 @@
+use ip::Protocol;
+use std::fmt;
+use std::fmt::Formatter;
+use std::net::IpAddr;
+
+/// The fields of the IP header which TCP and UDP include in their checksum,
+/// from RFC 793 section 3.1 and RFC 8200 section 8.1.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PseudoHeader {
+    source: IpAddr,
+    destination: IpAddr,
+
+    /// Length of the upper layer packet according to the IP header.
+    length: usize,
+
+    /// Whether the rest of the upper layer packet is in other fragments.
+    fragment: bool,
+}
+
+impl PseudoHeader {
+    pub fn new(source: IpAddr, destination: IpAddr, length: usize) -> PseudoHeader {
+        PseudoHeader {
+            source,
+            destination,
+            length,
+            fragment: false,
+        }
+    }
+
+    /// The pseudo-header for the first fragment of a datagram, whose checksum
+    /// covers bytes sent in later fragments.
+    pub fn first_fragment(&self) -> PseudoHeader {
+        PseudoHeader {
+            fragment: true,
+            ..*self
+        }
+    }
+
+    pub fn is_ipv4(&self) -> bool {
+        self.source.is_ipv4()
+    }
+
+    /// The pseudo-header for a packet following an extension header of
+    /// `header_length` bytes, such as an authentication header.
+    pub fn after_header(&self, header_length: usize) -> PseudoHeader {
+        PseudoHeader {
+            length: self.length.saturating_sub(header_length),
+            ..*self
+        }
+    }
+
+    /// Checks `checksum`, the value found in the `segment` of the given
+    /// protocol.
+    pub fn verify(&self, protocol: Protocol, segment: &[u8], checksum: u16) -> ChecksumStatus {
+        if self.fragment || segment.len() < self.length {
+            return ChecksumStatus::Unverified;
+        }
+
+        let pseudo_header_sum = self.sum(protocol);
+        let sum = pseudo_header_sum + sum(&segment[..self.length]);
+
+        if fold(sum) == 0xffff {
+            return ChecksumStatus::Valid;
+        }
+
+        // Senders which leave checksumming to the network card fill the field
+        // with the pseudo-header sum, or leave it zero, before the packet is
+        // captured.
+        if checksum == fold(pseudo_header_sum) || checksum == 0 {
+            return ChecksumStatus::Offloaded;
+        }
+
+        ChecksumStatus::Invalid {
+            expected: !fold(sum + u32::from(!checksum)),
+        }
+    }
+
+    fn sum(&self, protocol: Protocol) -> u32 {
+        let length = self.length as u32;
+        let mut total = u32::from(protocol.number()) + (length >> 16) + (length & 0xffff);
+
+        for address in &[self.source, self.destination] {
+            total += match address {
+                IpAddr::V4(address) => sum(&address.octets()),
+                IpAddr::V6(address) => sum(&address.octets()),
+            };
+        }
+
+        total
+    }
+}
+
+/// Adds up `data` as big endian 16 bit words, padding odd lengths with a
+/// zero byte.
+fn sum(data: &[u8]) -> u32 {
+    data.chunks(2)
+        .map(|word| u32::from(word[0]) << 8 | u32::from(*word.get(1).unwrap_or(&0)))
+        .fold(0, |total, word| fold(total + word).into())
+}
+
+/// Folds the carries of a ones' complement sum back into 16 bits.
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    sum as u16
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChecksumStatus {
+    Valid,
+    Invalid {
+        expected: u16,
+    },
+
+    /// The checksum was left for the network card to fill in after the
+    /// packet was captured.
+    Offloaded,
+
+    /// UDP over IPv4 may leave the checksum out by setting it to zero.
+    NotPresent,
+
+    /// Part of the packet wasn't captured, or was sent in another fragment,
+    /// so the checksum can't be checked.
+    Unverified,
+}
+
+impl ChecksumStatus {
+    /// Whether the status is worth pointing out in a summary.
+    pub fn is_notable(&self) -> bool {
+        matches!(
+            self,
+            ChecksumStatus::Invalid { .. } | ChecksumStatus::Offloaded
+        )
+    }
+}
+
+impl fmt::Display for ChecksumStatus {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ChecksumStatus::Valid => write!(f, "valid"),
+            ChecksumStatus::Invalid { expected } => {
+                write!(f, "incorrect, should be {:#06x}", expected)
+            }
+            ChecksumStatus::Offloaded => write!(f, "offloaded"),
+            ChecksumStatus::NotPresent => write!(f, "not present"),
+            ChecksumStatus::Unverified => write!(f, "unverified"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use checksum::{ChecksumStatus, PseudoHeader};
+    use ip::Protocol;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    fn pseudo_header(length: usize) -> PseudoHeader {
+        PseudoHeader::new(
+            IpAddr::V4(Ipv4Addr::new(139, 133, 217, 110)),
+            IpAddr::V4(Ipv4Addr::new(139, 133, 233, 2)),
+            length,
+        )
+    }
+
+    #[test]
+    fn verify_valid() {
+        // From: https://erg.abdn.ac.uk/users/gorry/eg3561/inet-pages/packet-decode3.html
+        let segment = hex::decode("900500177214f1140000000060022238a92c0000020405b4").unwrap();
+        assert_eq!(
+            pseudo_header(24).verify(Protocol::TCP, &segment, 0xa92c),
+            ChecksumStatus::Valid
+        );
+    }
+
+    #[test]
+    fn verify_invalid() {
+        let segment = hex::decode("900500177214f1140000000060022238a92d0000020405b4").unwrap();
+        assert_eq!(
+            pseudo_header(24).verify(Protocol::TCP, &segment, 0xa92d),
+            ChecksumStatus::Invalid { expected: 0xa92c }
+        );
+    }
+
+    #[test]
+    fn verify_offloaded() {
+        let segment = hex::decode("900500177214f1140000000060022238d99a0000020405b4").unwrap();
+        assert_eq!(
+            pseudo_header(24).verify(Protocol::TCP, &segment, 0xd99a),
+            ChecksumStatus::Offloaded
+        );
+    }
+
+    #[test]
+    fn verify_truncated() {
+        let segment = hex::decode("900500177214f1140000000060022238a92c").unwrap();
+        assert_eq!(
+            pseudo_header(24).verify(Protocol::TCP, &segment, 0xa92c),
+            ChecksumStatus::Unverified
+        );
+    }
+
+    #[test]
+    fn verify_ipv6() {
+        let pseudo_header = PseudoHeader::new(
+            IpAddr::V6("2001:db8::1".parse::<Ipv6Addr>().unwrap()),
+            IpAddr::V6("2001:db8::2".parse::<Ipv6Addr>().unwrap()),
+            10,
+        );
+        let segment = hex::decode("04d2162e000a20fc6869").unwrap();
+        assert_eq!(
+            pseudo_header.verify(Protocol::UDP, &segment, 0x20fc),
+            ChecksumStatus::Valid
+        );
+    }
+}
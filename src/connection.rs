@@ -0,0 +1,408 @@
+use flow::{Direction, Flow, FlowTable, TcpSegment};
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+
+/// Where a connection is in the TCP state machine, as seen by an observer
+/// between the two endpoints.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TcpState {
+    /// Nothing other than the first packet has been seen.
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+
+    /// One side has sent a FIN.
+    FinWait,
+
+    /// Both sides have sent a FIN but the last one isn't acknowledged yet.
+    Closing,
+    Closed,
+    Reset,
+}
+
+impl fmt::Display for TcpState {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            TcpState::Listen => "LISTEN",
+            TcpState::SynSent => "SYN-SENT",
+            TcpState::SynReceived => "SYN-RECEIVED",
+            TcpState::Established => "ESTABLISHED",
+            TcpState::FinWait => "FIN-WAIT",
+            TcpState::Closing => "CLOSING",
+            TcpState::Closed => "CLOSED",
+            TcpState::Reset => "RESET",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// How a connection ended up by the end of the capture.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The SYN or SYN-ACK was never answered.
+    HalfOpen,
+
+    /// The server answered the SYN with a reset.
+    Refused,
+
+    Reset {
+        by: Direction,
+    },
+
+    /// Both sides sent a FIN and it was acknowledged.
+    Closed,
+
+    /// Still established, or part way through closing, when the capture
+    /// ended.
+    Open,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Outcome::HalfOpen => write!(f, "half-open"),
+            Outcome::Refused => write!(f, "refused"),
+            Outcome::Reset {
+                by: Direction::ClientToServer,
+            } => write!(f, "reset by client"),
+            Outcome::Reset {
+                by: Direction::ServerToClient,
+            } => write!(f, "reset by server"),
+            Outcome::Closed => write!(f, "closed"),
+            Outcome::Open => write!(f, "open"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Connection {
+    flow: Flow,
+    state: TcpState,
+    first_seen: Duration,
+    last_seen: Duration,
+    packets: usize,
+
+    saw_syn: bool,
+    saw_syn_ack: bool,
+    established: bool,
+
+    /// State the connection was in when it was reset.
+    reset: Option<(Direction, TcpState)>,
+
+    /// One past the highest sequence number each side sent, indexed by
+    /// direction.
+    next_sequence: [Option<u32>; 2],
+
+    /// Sequence number just past each side's FIN, indexed by direction.
+    fin_end: [Option<u32>; 2],
+    fin_acknowledged: [bool; 2],
+}
+
+impl Connection {
+    fn new(flow: Flow, timestamp: Duration) -> Connection {
+        Connection {
+            flow,
+            state: TcpState::Listen,
+            first_seen: timestamp,
+            last_seen: timestamp,
+            packets: 0,
+            saw_syn: false,
+            saw_syn_ack: false,
+            established: false,
+            reset: None,
+            next_sequence: [None, None],
+            fin_end: [None, None],
+            fin_acknowledged: [false, false],
+        }
+    }
+
+    fn update(&mut self, direction: Direction, segment: &TcpSegment) {
+        let header = segment.header();
+        self.packets += 1;
+        self.last_seen = segment.timestamp();
+
+        let end = header
+            .sequence_number()
+            .wrapping_add(segment.sequence_length());
+        let next = &mut self.next_sequence[side(direction)];
+        if next.is_none_or(|next| sequence_before(next, end)) {
+            *next = Some(end);
+        }
+
+        if self.state == TcpState::Reset {
+            return;
+        }
+
+        if header.reset() {
+            self.reset = Some((direction, self.state));
+            self.state = TcpState::Reset;
+            return;
+        }
+
+        let side = side(direction);
+        if header.acknowledgment() {
+            if let Some(end) = self.fin_end[1 - side] {
+                if !sequence_before(header.acknowledgment_number(), end) {
+                    self.fin_acknowledged[1 - side] = true;
+                }
+            }
+        }
+
+        if header.fin() && self.fin_end[side].is_none() {
+            let end = header
+                .sequence_number()
+                .wrapping_add(segment.sequence_length());
+            self.fin_end[side] = Some(end);
+        }
+
+        self.state = match (self.state, direction) {
+            _ if header.synchronize() && !header.acknowledgment() => {
+                self.saw_syn = true;
+                match self.state {
+                    TcpState::Listen | TcpState::SynSent => TcpState::SynSent,
+                    state => state,
+                }
+            }
+            _ if header.synchronize() => {
+                self.saw_syn_ack = true;
+                match self.state {
+                    TcpState::Listen | TcpState::SynSent => TcpState::SynReceived,
+                    state => state,
+                }
+            }
+            (TcpState::SynReceived, Direction::ClientToServer) if header.acknowledgment() => {
+                TcpState::Established
+            }
+            (TcpState::Listen, _) => TcpState::Established,
+            (state, _) => state,
+        };
+
+        if self.state == TcpState::Established {
+            self.established = true;
+        }
+
+        if self.established {
+            self.state = match (self.fin_end, self.fin_acknowledged) {
+                ([Some(_), Some(_)], [true, true]) => TcpState::Closed,
+                ([Some(_), Some(_)], _) => TcpState::Closing,
+                ([Some(_), None], _) | ([None, Some(_)], _) => TcpState::FinWait,
+                _ => self.state,
+            };
+        }
+    }
+
+    pub fn flow(&self) -> &Flow {
+        &self.flow
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    pub fn packets(&self) -> usize {
+        self.packets
+    }
+
+    /// One past the highest sequence number sent in `direction`, which is
+    /// where the next new data would start.
+    pub fn next_sequence(&self, direction: Direction) -> Option<u32> {
+        self.next_sequence[side(direction)]
+    }
+
+    /// Time between the first and last packet of the connection.
+    pub fn duration(&self) -> Duration {
+        self.last_seen
+            .checked_sub(self.first_seen)
+            .unwrap_or_default()
+    }
+
+    /// Whether the SYN, SYN-ACK and ACK of the three way handshake were all
+    /// seen.
+    pub fn handshake_complete(&self) -> bool {
+        self.saw_syn && self.saw_syn_ack && self.established
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        match (self.state, self.reset) {
+            (TcpState::Reset, Some((Direction::ServerToClient, TcpState::SynSent))) => {
+                Outcome::Refused
+            }
+            (TcpState::Reset, Some((by, _))) => Outcome::Reset { by },
+            (TcpState::SynSent, _) | (TcpState::SynReceived, _) => Outcome::HalfOpen,
+            (TcpState::Closed, _) => Outcome::Closed,
+            _ => Outcome::Open,
+        }
+    }
+}
+
+impl fmt::Display for Connection {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.flow, self.state, self.outcome())?;
+
+        if self.handshake_complete() {
+            write!(f, ", handshake complete")?;
+        } else if !self.saw_syn {
+            write!(f, ", handshake not captured")?;
+        }
+
+        let duration = self.duration();
+        write!(
+            f,
+            ", {} packets, {}.{:06}s",
+            self.packets,
+            duration.as_secs(),
+            duration.subsec_micros()
+        )
+    }
+}
+
+/// Runs every TCP connection in a capture through the state machine.
+#[derive(Debug, Default)]
+pub struct ConnectionTable {
+    flows: FlowTable,
+    connections: Vec<Connection>,
+}
+
+impl ConnectionTable {
+    pub fn new() -> ConnectionTable {
+        ConnectionTable::default()
+    }
+
+    /// Updates the connection `segment` belongs to, returning its stream
+    /// number and the direction the segment was sent in.
+    pub fn record(&mut self, segment: &TcpSegment) -> (usize, Direction) {
+        let (stream, direction) = self.flows.lookup(segment);
+        if stream == self.connections.len() {
+            let flow = self.flows.flows()[stream].clone();
+            self.connections
+                .push(Connection::new(flow, segment.timestamp()));
+        }
+
+        self.connections[stream].update(direction, segment);
+        (stream, direction)
+    }
+
+    pub fn connections(&self) -> &[Connection] {
+        &self.connections
+    }
+}
+
+fn side(direction: Direction) -> usize {
+    match direction {
+        Direction::ClientToServer => 0,
+        Direction::ServerToClient => 1,
+    }
+}
+
+/// Compares sequence numbers allowing for them wrapping around.
+pub fn sequence_before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+#[cfg(test)]
+mod tests {
+    use connection::{ConnectionTable, Outcome, TcpState};
+    use flow::tests::{ip_header, tcp_header};
+    use flow::{Direction, TcpSegment};
+    use std::time::Duration;
+
+    const SYN: u8 = 0x02;
+    const SYN_ACK: u8 = 0x12;
+    const ACK: u8 = 0x10;
+    const FIN_ACK: u8 = 0x11;
+    const RST: u8 = 0x04;
+    const RST_ACK: u8 = 0x14;
+
+    /// Feeds `(from client, sequence, acknowledgment, flags)` packets one
+    /// millisecond apart into a new table.
+    fn run(packets: &[(bool, u32, u32, u8)]) -> ConnectionTable {
+        let mut table = ConnectionTable::new();
+        for (index, &(from_client, sequence, acknowledgment, flags)) in packets.iter().enumerate() {
+            let ip = ip_header(from_client);
+            let header = if from_client {
+                tcp_header(36869, 23, sequence, acknowledgment, flags)
+            } else {
+                tcp_header(23, 36869, sequence, acknowledgment, flags)
+            };
+            let timestamp = Duration::from_millis(index as u64);
+            table.record(&TcpSegment::new(timestamp, &ip, &header, &[]));
+        }
+
+        table
+    }
+
+    #[test]
+    fn graceful_close() {
+        let table = run(&[
+            (true, 100, 0, SYN),
+            (false, 500, 101, SYN_ACK),
+            (true, 101, 501, ACK),
+            (true, 101, 501, FIN_ACK),
+            (false, 501, 102, FIN_ACK),
+            (true, 102, 502, ACK),
+        ]);
+
+        let connection = &table.connections()[0];
+        assert_eq!(connection.state(), TcpState::Closed);
+        assert_eq!(connection.outcome(), Outcome::Closed);
+        assert!(connection.handshake_complete());
+        assert_eq!(connection.packets(), 6);
+        assert_eq!(connection.duration(), Duration::from_millis(5));
+        assert_eq!(
+            connection.next_sequence(Direction::ClientToServer),
+            Some(102)
+        );
+        assert_eq!(
+            connection.next_sequence(Direction::ServerToClient),
+            Some(502)
+        );
+    }
+
+    #[test]
+    fn unanswered() {
+        let table = run(&[(true, 100, 0, SYN), (true, 100, 0, SYN)]);
+        let connection = &table.connections()[0];
+        assert_eq!(connection.state(), TcpState::SynSent);
+        assert_eq!(connection.outcome(), Outcome::HalfOpen);
+        assert!(!connection.handshake_complete());
+    }
+
+    #[test]
+    fn refused() {
+        let table = run(&[(true, 100, 0, SYN), (false, 0, 101, RST_ACK)]);
+        assert_eq!(table.connections()[0].outcome(), Outcome::Refused);
+    }
+
+    #[test]
+    fn reset() {
+        let table = run(&[
+            (true, 100, 0, SYN),
+            (false, 500, 101, SYN_ACK),
+            (true, 101, 501, ACK),
+            (true, 101, 501, FIN_ACK),
+            (true, 102, 0, RST),
+        ]);
+
+        let connection = &table.connections()[0];
+        assert_eq!(connection.state(), TcpState::Reset);
+        assert_eq!(
+            connection.outcome(),
+            Outcome::Reset {
+                by: Direction::ClientToServer
+            }
+        );
+        assert!(connection.handshake_complete());
+    }
+
+    #[test]
+    fn mid_stream() {
+        let table = run(&[(false, 500, 101, ACK), (true, 101, 501, FIN_ACK)]);
+        let connection = &table.connections()[0];
+        assert_eq!(connection.state(), TcpState::FinWait);
+        assert_eq!(connection.outcome(), Outcome::Open);
+        assert!(!connection.handshake_complete());
+    }
+}
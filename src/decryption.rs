@@ -0,0 +1,639 @@
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes128Gcm, Aes256Gcm};
+use chacha20poly1305::ChaCha20Poly1305;
+use flow::Direction;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use keylog::{KeyLog, SecretLabel};
+use sha2::{Sha256, Sha384};
+use std::time::Duration;
+use tls::{ContentType, TlsRecord, TlsSession};
+
+const TAG_LENGTH: usize = 16;
+const FINISHED: u8 = 20;
+const KEY_UPDATE: u8 = 24;
+
+#[derive(Fail, Debug, Eq, PartialEq)]
+pub enum DecryptionError {
+    #[fail(display = "The TLS handshake wasn't captured")]
+    MissingHandshake,
+
+    #[fail(display = "Cipher suite 0x{:04x} can't be decrypted", _0)]
+    UnsupportedCipherSuite(u16),
+
+    #[fail(display = "No secrets were logged for the session")]
+    MissingSecrets,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Cipher {
+    Aes128Gcm,
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    fn key_length(self) -> usize {
+        match self {
+            Cipher::Aes128Gcm => 16,
+            Cipher::Aes256Gcm | Cipher::ChaCha20Poly1305 => 32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Hash {
+    Sha256,
+    Sha384,
+}
+
+impl Hash {
+    fn length(self) -> usize {
+        match self {
+            Hash::Sha256 => 32,
+            Hash::Sha384 => 48,
+        }
+    }
+
+    fn hmac(self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            Hash::Sha256 => {
+                let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Hash::Sha384 => {
+                let mut mac = <Hmac<Sha384> as Mac>::new_from_slice(key).unwrap();
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+
+    /// HKDF-Expand, or `None` if `secret` is shorter than the hash.
+    fn expand(self, secret: &[u8], info: &[u8], length: usize) -> Option<Vec<u8>> {
+        let mut output = vec![0; length];
+        match self {
+            Hash::Sha256 => Hkdf::<Sha256>::from_prk(secret)
+                .ok()?
+                .expand(info, &mut output)
+                .ok()?,
+            Hash::Sha384 => Hkdf::<Sha384>::from_prk(secret)
+                .ok()?
+                .expand(info, &mut output)
+                .ok()?,
+        }
+
+        Some(output)
+    }
+}
+
+/// The AEAD cipher and hash of the cipher suites which can be decrypted.
+fn cipher_suite(cipher_suite: u16) -> Option<(Cipher, Hash)> {
+    let suite = match cipher_suite {
+        0x1301 | 0x009c | 0x009e | 0xc02b | 0xc02f => (Cipher::Aes128Gcm, Hash::Sha256),
+        0x1302 | 0x009d | 0x009f | 0xc02c | 0xc030 => (Cipher::Aes256Gcm, Hash::Sha384),
+        0x1303 | 0xcca8 | 0xcca9 | 0xccaa => (Cipher::ChaCha20Poly1305, Hash::Sha256),
+        _ => return None,
+    };
+
+    Some(suite)
+}
+
+/// The TLS 1.2 pseudorandom function (RFC 5246 section 5).
+fn prf(hash: Hash, secret: &[u8], label: &[u8], seed: &[u8], length: usize) -> Vec<u8> {
+    let seed = [label, seed].concat();
+    let mut a = hash.hmac(secret, &seed);
+    let mut output = Vec::new();
+    while output.len() < length {
+        output.extend(hash.hmac(secret, &[&a[..], &seed[..]].concat()));
+        a = hash.hmac(secret, &a);
+    }
+
+    output.truncate(length);
+    output
+}
+
+/// HKDF-Expand-Label with an empty context (RFC 8446 section 7.1).
+fn expand_label(hash: Hash, secret: &[u8], label: &str, length: usize) -> Option<Vec<u8>> {
+    let label = format!("tls13 {}", label);
+    let mut info = (length as u16).to_be_bytes().to_vec();
+    info.push(label.len() as u8);
+    info.extend_from_slice(label.as_bytes());
+    info.push(0);
+
+    hash.expand(secret, &info, length)
+}
+
+/// The key and IV protecting the records sent one way, along with the
+/// sequence number of the next record.
+#[derive(Debug)]
+struct RecordProtection {
+    cipher: Cipher,
+    key: Vec<u8>,
+    iv: Vec<u8>,
+    sequence: u64,
+}
+
+impl RecordProtection {
+    fn new(cipher: Cipher, key: &[u8], iv: &[u8]) -> RecordProtection {
+        RecordProtection {
+            cipher,
+            key: key.to_vec(),
+            iv: iv.to_vec(),
+            sequence: 0,
+        }
+    }
+
+    /// The key and IV derived from a TLS 1.3 traffic secret.
+    fn from_secret(cipher: Cipher, hash: Hash, secret: &[u8]) -> Option<RecordProtection> {
+        let key = expand_label(hash, secret, "key", cipher.key_length())?;
+        let iv = expand_label(hash, secret, "iv", 12)?;
+
+        Some(RecordProtection::new(cipher, &key, &iv))
+    }
+
+    /// The IV with the sequence number XORed into its last eight bytes, as
+    /// used by TLS 1.3 and ChaCha20-Poly1305 in TLS 1.2.
+    fn sequence_nonce(&self, sequence: u64) -> Vec<u8> {
+        let mut nonce = self.iv.clone();
+        let offset = nonce.len() - 8;
+        for (byte, mask) in nonce[offset..]
+            .iter_mut()
+            .zip(sequence.to_be_bytes().iter())
+        {
+            *byte ^= mask;
+        }
+
+        nonce
+    }
+
+    fn open(&self, nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let nonce = GenericArray::from_slice(nonce);
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+
+        match self.cipher {
+            Cipher::Aes128Gcm => Aes128Gcm::new_from_slice(&self.key)
+                .ok()?
+                .decrypt(nonce, payload)
+                .ok(),
+            Cipher::Aes256Gcm => Aes256Gcm::new_from_slice(&self.key)
+                .ok()?
+                .decrypt(nonce, payload)
+                .ok(),
+            Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new_from_slice(&self.key)
+                .ok()?
+                .decrypt(nonce, payload)
+                .ok(),
+        }
+    }
+
+    /// Decrypts a TLS 1.2 record. AES-GCM records start with the explicit
+    /// part of the nonce (RFC 5288) while ChaCha20-Poly1305 derives it from
+    /// the sequence number (RFC 7905). The sequence number only moves on
+    /// once a record authenticates, so a record which wasn't encrypted with
+    /// these keys doesn't throw off the ones after it.
+    fn decrypt_tls12(&mut self, record: &TlsRecord) -> Option<Vec<u8>> {
+        let sequence = self.sequence;
+        let fragment = record.fragment();
+        let (nonce, ciphertext) = match self.cipher {
+            Cipher::ChaCha20Poly1305 => (self.sequence_nonce(sequence), fragment),
+            Cipher::Aes128Gcm | Cipher::Aes256Gcm => {
+                if fragment.len() < 8 {
+                    return None;
+                }
+                ([&self.iv[..], &fragment[..8]].concat(), &fragment[8..])
+            }
+        };
+
+        let length = ciphertext.len().checked_sub(TAG_LENGTH)?;
+        let mut aad = sequence.to_be_bytes().to_vec();
+        aad.push(record.content_type().code());
+        aad.extend_from_slice(&record.version().to_be_bytes());
+        aad.extend_from_slice(&(length as u16).to_be_bytes());
+
+        let plaintext = self.open(&nonce, &aad, ciphertext)?;
+        self.sequence += 1;
+        Some(plaintext)
+    }
+
+    /// Decrypts a TLS 1.3 record, returning the real content type from the
+    /// end of the plaintext with the padding removed. Like TLS 1.2, records
+    /// which fail to authenticate, such as 0-RTT data, leave the sequence
+    /// number alone.
+    fn decrypt_tls13(&mut self, record: &TlsRecord) -> Option<(ContentType, Vec<u8>)> {
+        let nonce = self.sequence_nonce(self.sequence);
+        let mut aad = vec![record.content_type().code()];
+        aad.extend_from_slice(&record.version().to_be_bytes());
+        aad.extend_from_slice(&record.length().to_be_bytes());
+
+        let mut plaintext = self.open(&nonce, &aad, record.fragment())?;
+        self.sequence += 1;
+        while plaintext.last() == Some(&0) {
+            plaintext.pop();
+        }
+        let content_type = plaintext.pop()?;
+
+        Some((ContentType::from_code(content_type), plaintext))
+    }
+}
+
+/// What is needed to decrypt the records sent one way in TLS 1.3. The keys
+/// change from the handshake secret to the application secret after the
+/// Finished message and again on each KeyUpdate.
+#[derive(Debug)]
+struct Tls13Side {
+    protection: RecordProtection,
+    secret: Vec<u8>,
+    application_secret: Option<Vec<u8>>,
+
+    /// Bytes of a handshake message split across records.
+    handshake: Vec<u8>,
+}
+
+impl Tls13Side {
+    fn new(
+        cipher: Cipher,
+        hash: Hash,
+        handshake_secret: &[u8],
+        application_secret: Option<&[u8]>,
+    ) -> Option<Tls13Side> {
+        Some(Tls13Side {
+            protection: RecordProtection::from_secret(cipher, hash, handshake_secret)?,
+            secret: handshake_secret.to_vec(),
+            application_secret: application_secret.map(|secret| secret.to_vec()),
+            handshake: Vec::new(),
+        })
+    }
+
+    /// Follows the handshake messages which change the keys.
+    fn read_handshake(&mut self, hash: Hash, data: &[u8]) {
+        self.handshake.extend_from_slice(data);
+        while self.handshake.len() >= 4 {
+            let length = (self.handshake[1] as usize) << 16
+                | (self.handshake[2] as usize) << 8
+                | self.handshake[3] as usize;
+            if self.handshake.len() < 4 + length {
+                break;
+            }
+
+            let message_type = self.handshake[0];
+            self.handshake.drain(..4 + length);
+
+            let next = match message_type {
+                FINISHED => self.application_secret.take(),
+                KEY_UPDATE => expand_label(hash, &self.secret, "traffic upd", hash.length()),
+                _ => None,
+            };
+            if let Some(secret) = next {
+                if let Some(protection) =
+                    RecordProtection::from_secret(self.protection.cipher, hash, &secret)
+                {
+                    self.protection = protection;
+                    self.secret = secret;
+                }
+            }
+        }
+    }
+}
+
+/// A record decrypted with secrets from a key log.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DecryptedRecord {
+    direction: Direction,
+    timestamp: Duration,
+    content_type: ContentType,
+    data: Vec<u8>,
+}
+
+impl DecryptedRecord {
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// The real content type, which TLS 1.3 hides inside the encryption.
+    pub fn content_type(&self) -> ContentType {
+        self.content_type
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// The decrypted records of a session. Records which fail to decrypt, as
+/// with 0-RTT data or secrets from another session, are counted instead.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DecryptedSession {
+    records: Vec<DecryptedRecord>,
+    failures: usize,
+}
+
+impl DecryptedSession {
+    pub fn records(&self) -> &[DecryptedRecord] {
+        &self.records
+    }
+
+    pub fn failures(&self) -> usize {
+        self.failures
+    }
+
+    /// The decrypted application data in the order it was sent.
+    pub fn application_data(&self) -> Vec<&DecryptedRecord> {
+        self.records
+            .iter()
+            .filter(|record| record.content_type == ContentType::ApplicationData)
+            .collect()
+    }
+}
+
+/// Decrypts the records of a TLS 1.2 or 1.3 session which used an AEAD
+/// cipher suite, using the secrets logged for its client random.
+pub fn decrypt(session: &TlsSession, keys: &KeyLog) -> Result<DecryptedSession, DecryptionError> {
+    let (client_hello, server_hello) = match (session.client_hello(), session.server_hello()) {
+        (Some(client_hello), Some(server_hello)) => (client_hello, server_hello),
+        _ => return Err(DecryptionError::MissingHandshake),
+    };
+    let (cipher, hash) = cipher_suite(server_hello.cipher_suite()).ok_or(
+        DecryptionError::UnsupportedCipherSuite(server_hello.cipher_suite()),
+    )?;
+    let client_random = client_hello.random();
+
+    let mut decrypted = DecryptedSession {
+        records: Vec::new(),
+        failures: 0,
+    };
+
+    if server_hello.selected_version() == 0x0304 {
+        let secret = |label| keys.secret(label, client_random);
+        let side = |handshake, application| {
+            Tls13Side::new(cipher, hash, secret(handshake)?, secret(application))
+        };
+        let mut client = side(
+            SecretLabel::ClientHandshakeTrafficSecret,
+            SecretLabel::ClientTrafficSecret0,
+        )
+        .ok_or(DecryptionError::MissingSecrets)?;
+        let mut server = side(
+            SecretLabel::ServerHandshakeTrafficSecret,
+            SecretLabel::ServerTrafficSecret0,
+        )
+        .ok_or(DecryptionError::MissingSecrets)?;
+
+        for (direction, timestamp, record) in session.encrypted_records() {
+            let side = match direction {
+                Direction::ClientToServer => &mut client,
+                Direction::ServerToClient => &mut server,
+            };
+
+            match side.protection.decrypt_tls13(record) {
+                Some((content_type, data)) => {
+                    if content_type == ContentType::Handshake {
+                        side.read_handshake(hash, &data);
+                    }
+                    decrypted.records.push(DecryptedRecord {
+                        direction: *direction,
+                        timestamp: *timestamp,
+                        content_type,
+                        data,
+                    });
+                }
+                None => decrypted.failures += 1,
+            }
+        }
+    } else {
+        let master_secret = keys
+            .secret(SecretLabel::ClientRandom, client_random)
+            .ok_or(DecryptionError::MissingSecrets)?;
+        let iv_length = match cipher {
+            Cipher::ChaCha20Poly1305 => 12,
+            Cipher::Aes128Gcm | Cipher::Aes256Gcm => 4,
+        };
+        let key_length = cipher.key_length();
+
+        let seed = [server_hello.random(), client_random].concat();
+        let block = prf(
+            hash,
+            master_secret,
+            b"key expansion",
+            &seed,
+            2 * (key_length + iv_length),
+        );
+        let (client_key, rest) = block.split_at(key_length);
+        let (server_key, rest) = rest.split_at(key_length);
+        let (client_iv, server_iv) = rest.split_at(iv_length);
+
+        let mut client = RecordProtection::new(cipher, client_key, client_iv);
+        let mut server = RecordProtection::new(cipher, server_key, server_iv);
+
+        for (direction, timestamp, record) in session.encrypted_records() {
+            let protection = match direction {
+                Direction::ClientToServer => &mut client,
+                Direction::ServerToClient => &mut server,
+            };
+
+            match protection.decrypt_tls12(record) {
+                Some(data) => decrypted.records.push(DecryptedRecord {
+                    direction: *direction,
+                    timestamp: *timestamp,
+                    content_type: record.content_type(),
+                    data,
+                }),
+                None => decrypted.failures += 1,
+            }
+        }
+    }
+
+    Ok(decrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use decryption::{decrypt, DecryptionError};
+    use flow::Direction;
+    use keylog::KeyLog;
+    use std::time::Duration;
+    use tls::{ContentType, TlsSession};
+
+    const CLIENT_RANDOM: &str = "0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20";
+
+    fn session(client: &str, server: &str) -> TlsSession {
+        let at = Duration::from_secs(1);
+        let mut session = TlsSession::new();
+        session.push(Direction::ClientToServer, &hex::decode(client).unwrap(), at);
+        session.push(Direction::ServerToClient, &hex::decode(server).unwrap(), at);
+        session
+    }
+
+    fn application_data(session: &TlsSession, keys: &KeyLog) -> Vec<(Direction, Vec<u8>)> {
+        let decrypted = decrypt(session, keys).unwrap();
+        assert_eq!(decrypted.failures(), 0);
+        decrypted
+            .application_data()
+            .iter()
+            .map(|record| (record.direction(), record.data().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn tls12_aes_gcm() {
+        let session = session(
+            "160301002d0100002903030102030405060708090a0b0c0d0e0f10111213141516171819\
+             1a1b1c1d1e1f20000002c02f01001403030001011603030028000000000000100060a83f\
+             d0eb46868b0a449751ead5d33139062551940cd05f625076fff3bf9ed8170303003d0000\
+             00000000100150775dbc1653ed40ef5d8f2738b90e5e508450a52e86772775266a84e8ce\
+             c6b9312d948e51a2299342e7a76409477e7a504b50146e",
+            "160303002a0200002603034142434445464748494a4b4c4d4e4f50515253545556575859\
+             5a5b5c5d5e5f6000c02f0014030300010116030300280000000000001000276fe523728f\
+             c972432210cb0863c95405e374c9dae306aae692c77f70b7351417030300400000000000\
+             0010018cc58643d74095d1f008968dab40502a41fc680c8bc744cdc8b998c18162aefd59\
+             ffdcf910f35e9629ab3c79ab3ae26884fd4e02d5c46d6a",
+        );
+        let keys = KeyLog::parse(&format!(
+            "CLIENT_RANDOM {} {}",
+            CLIENT_RANDOM,
+            "5a".repeat(48)
+        ))
+        .unwrap();
+
+        assert_eq!(
+            application_data(&session, &keys),
+            vec![
+                (
+                    Direction::ClientToServer,
+                    b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n".to_vec()
+                ),
+                (
+                    Direction::ServerToClient,
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi".to_vec()
+                ),
+            ]
+        );
+
+        let decrypted = decrypt(&session, &keys).unwrap();
+        let finished = &decrypted.records()[0];
+        assert_eq!(finished.content_type(), ContentType::Handshake);
+        assert_eq!(finished.data()[0], 20);
+    }
+
+    #[test]
+    fn tls12_chacha20_poly1305() {
+        let session = session(
+            "160301002d0100002903030102030405060708090a0b0c0d0e0f10111213141516171819\
+             1a1b1c1d1e1f20000002cca801001403030001011603030020bb03997d918005482bd5b7\
+             7d75b411490a7952d91d2681be701878a783c963191703030035a60f52ec9271e986e642\
+             96d0f1e20b62f3d796c2164cd138aef060c143e319a233fbf7a636179aad8fd757060a46\
+             cdb54ac711c505",
+            "160303002a0200002603034142434445464748494a4b4c4d4e4f50515253545556575859\
+             5a5b5c5d5e5f6000cca800140303000101160303002024c92cee0150a82ba6979f10c417\
+             371fa0bff26ab81a522df749a847143a3ae417030300383c55025d0db6f38f1daaa5db6e\
+             113fa39c5060207d43214548bf5838bec2451d335d27a4f9eaf64dcb44e4dfcff3301f8b\
+             08c1e1e3523a72",
+        );
+        let keys = KeyLog::parse(&format!(
+            "CLIENT_RANDOM {} {}",
+            CLIENT_RANDOM,
+            "5a".repeat(48)
+        ))
+        .unwrap();
+
+        assert_eq!(application_data(&session, &keys).len(), 2);
+    }
+
+    #[test]
+    fn tls13_key_update() {
+        let session = session(
+            "160301002d0100002903030102030405060708090a0b0c0d0e0f10111213141516171819\
+             1a1b1c1d1e1f200000021301010014030300010117030300358dbedffede873dcf30cbd4\
+             d02194b1a45febfccdc0285d46de03e53ac096955e7a5f134bb539ee29b286e64b2c8fc2\
+             6c5d5b7129ac1703030023328380cdd3688ae3ec340b1c45b95bb7c36f714ed87c3871f3\
+             da6092b0a8f4b3ad8649",
+            "16030300320200002e03034142434445464748494a4b4c4d4e4f50515253545556575859\
+             5a5b5c5d5e5f60001301000006002b00020304140303000101170303003e68a117109e01\
+             a736ab4e6ef993b9a809f9dcd0c708b7fb3978edec8b87aa47111e004eb3e18bd3eda4dd\
+             cd89beb23f3aee08ade357adcedaaa7a7c84ac38170303002253d07fc677b976a12ec9b1\
+             c6bc8bc923f2591e756056d6d19d177facfa48cb2ba4b81703030016ad05ef95c08172e7\
+             8c480d41fc6f688e1120709b71481703030026c2be6de281d234dcf4a8bc87b17b691111\
+             976f3cb97e06b5d0dba3f858436ac0012251947ab6",
+        );
+        let keys = KeyLog::parse(&format!(
+            "CLIENT_HANDSHAKE_TRAFFIC_SECRET {random} {}\n\
+             SERVER_HANDSHAKE_TRAFFIC_SECRET {random} {}\n\
+             CLIENT_TRAFFIC_SECRET_0 {random} {}\n\
+             SERVER_TRAFFIC_SECRET_0 {random} {}\n",
+            "21".repeat(32),
+            "22".repeat(32),
+            "23".repeat(32),
+            "24".repeat(32),
+            random = CLIENT_RANDOM
+        ))
+        .unwrap();
+
+        assert_eq!(
+            application_data(&session, &keys),
+            vec![
+                (
+                    Direction::ClientToServer,
+                    b"GET / HTTP/1.1\r\n\r\n".to_vec()
+                ),
+                (Direction::ServerToClient, b"HTTP/1.1 200 OK\r\n".to_vec()),
+                (
+                    Direction::ServerToClient,
+                    b"Content-Length: 0\r\n\r\n".to_vec()
+                ),
+            ]
+        );
+
+        assert_eq!(
+            decrypt(&session, &KeyLog::new()),
+            Err(DecryptionError::MissingSecrets)
+        );
+    }
+
+    /// A record which doesn't decrypt, like 0-RTT data sent with keys that
+    /// weren't logged, doesn't stop the records after it decrypting.
+    #[test]
+    fn tls13_failed_record() {
+        let session = session(
+            "160301002d0100002903030102030405060708090a0b0c0d0e0f10111213141516171819\
+             1a1b1c1d1e1f20000002130101001403030001011703030015000000000000000000000000\
+             0000000000000000001703030035\
+             8dbedffede873dcf30cbd4d02194b1a45febfccdc0285d46de03e53ac096955e7a5f134b\
+             b539ee29b286e64b2c8fc26c5d5b7129ac1703030023328380cdd3688ae3ec340b1c45b9\
+             5bb7c36f714ed87c3871f3da6092b0a8f4b3ad8649",
+            "16030300320200002e03034142434445464748494a4b4c4d4e4f50515253545556575859\
+             5a5b5c5d5e5f60001301000006002b00020304",
+        );
+        let keys = KeyLog::parse(&format!(
+            "CLIENT_HANDSHAKE_TRAFFIC_SECRET {random} {}\n\
+             SERVER_HANDSHAKE_TRAFFIC_SECRET {random} {}\n\
+             CLIENT_TRAFFIC_SECRET_0 {random} {}\n\
+             SERVER_TRAFFIC_SECRET_0 {random} {}\n",
+            "21".repeat(32),
+            "22".repeat(32),
+            "23".repeat(32),
+            "24".repeat(32),
+            random = CLIENT_RANDOM
+        ))
+        .unwrap();
+
+        let decrypted = decrypt(&session, &keys).unwrap();
+        assert_eq!(decrypted.failures(), 1);
+        let application_data: Vec<&[u8]> = decrypted
+            .application_data()
+            .iter()
+            .map(|record| record.data())
+            .collect();
+        assert_eq!(application_data, [&b"GET / HTTP/1.1\r\n\r\n"[..]]);
+    }
+}
@@ -12,7 +12,7 @@ impl EtherFrame {
     pub fn parse(input: &[u8]) -> IResult<&[u8], EtherFrame> {
         do_parse!(
             input,
-            destination: parse_mac_address >> 
+            destination: parse_mac_address >>
             source: parse_mac_address >>
             ether_type: parse_ether_type >>
 
@@ -23,23 +23,43 @@ impl EtherFrame {
             })
         )
     }
+
+    pub fn source(&self) -> &MacAddress {
+        &self.source
+    }
+
+    pub fn destination(&self) -> &MacAddress {
+        &self.destination
+    }
+
+    pub fn ether_type(&self) -> &EtherType {
+        &self.ether_type
+    }
 }
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum EtherType {
     IP,
+    IPv6,
+    TransparentEthernetBridging,
+    ERSPAN,
+    ERSPANTypeIII,
     Length(u16),
     Other(u16),
 }
 
 named!(
-    parse_ether_type<EtherType>,
+    pub parse_ether_type<EtherType>,
     do_parse!(
         data: be_u16 >>
 
         (match data {
-            0...1500 => EtherType::Length(data),
+            0..=1500 => EtherType::Length(data),
             0x0800 => EtherType::IP,
+            0x86dd => EtherType::IPv6,
+            0x6558 => EtherType::TransparentEthernetBridging,
+            0x88be => EtherType::ERSPAN,
+            0x22eb => EtherType::ERSPANTypeIII,
             _ => EtherType::Other(data),
         })
     )
@@ -0,0 +1,414 @@
+use connection::sequence_before;
+use flow::{Direction, TcpSegment};
+use latency::FlowLatency;
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+
+/// Retransmissions sent sooner than the handshake's round trip time after
+/// the highest segment are taken to be segments which arrived out of order
+/// instead. This is used when the handshake wasn't captured, as Wireshark
+/// does.
+const DEFAULT_OUT_OF_ORDER_THRESHOLD: Duration = Duration::from_millis(3);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Severity {
+    Chat,
+    Note,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Severity::Chat => "Chat",
+            Severity::Note => "Note",
+            Severity::Warning => "Warning",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Something worth pointing out about a TCP segment, following the names
+/// Wireshark's TCP analysis uses.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Annotation {
+    Retransmission,
+
+    /// A retransmission of the segment the receiver sent duplicate ACKs for.
+    FastRetransmission,
+    OutOfOrder,
+
+    /// The segment starts past the highest sequence number seen so far.
+    PreviousSegmentNotCaptured,
+
+    /// The segment acknowledges data which wasn't captured.
+    AckedUnseenSegment,
+
+    /// The nth duplicate of the previous ACK.
+    DuplicateAck(u32),
+    ZeroWindow,
+    ZeroWindowProbe,
+
+    /// The segment fills the receiver's advertised window.
+    WindowFull,
+    WindowUpdate,
+    KeepAlive,
+}
+
+impl Annotation {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Annotation::WindowUpdate => Severity::Chat,
+            Annotation::Retransmission
+            | Annotation::FastRetransmission
+            | Annotation::DuplicateAck(_)
+            | Annotation::ZeroWindowProbe
+            | Annotation::KeepAlive => Severity::Note,
+            Annotation::OutOfOrder
+            | Annotation::PreviousSegmentNotCaptured
+            | Annotation::AckedUnseenSegment
+            | Annotation::ZeroWindow
+            | Annotation::WindowFull => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Annotation::Retransmission => write!(f, "Retransmission"),
+            Annotation::FastRetransmission => write!(f, "Fast retransmission"),
+            Annotation::OutOfOrder => write!(f, "Out-of-order"),
+            Annotation::PreviousSegmentNotCaptured => write!(f, "Previous segment not captured"),
+            Annotation::AckedUnseenSegment => write!(f, "ACKed unseen segment"),
+            Annotation::DuplicateAck(count) => write!(f, "Duplicate ACK #{}", count),
+            Annotation::ZeroWindow => write!(f, "Zero window"),
+            Annotation::ZeroWindowProbe => write!(f, "Zero window probe"),
+            Annotation::WindowFull => write!(f, "Window full"),
+            Annotation::WindowUpdate => write!(f, "Window update"),
+            Annotation::KeepAlive => write!(f, "Keep-alive"),
+        }
+    }
+}
+
+/// What has been seen from one side of a connection.
+#[derive(Clone, Debug, Default)]
+struct Sender {
+    /// One past the highest sequence number sent.
+    next_sequence: Option<u32>,
+
+    /// When `next_sequence` last moved forward.
+    last_advanced: Option<Duration>,
+
+    last_acknowledgment: Option<u32>,
+    last_window: Option<u16>,
+    duplicate_acks: u32,
+    window_scale: Option<u8>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct FlowAnalysis {
+    senders: [Sender; 2],
+
+    /// Times the handshake, for telling out of order segments from
+    /// retransmissions.
+    latency: FlowLatency,
+}
+
+impl FlowAnalysis {
+    /// Shift to apply to windows sent by `side`, which is only used when both
+    /// sides offered the window scale option.
+    fn window_shift(&self, side: usize) -> u32 {
+        match (
+            self.senders[side].window_scale,
+            self.senders[1 - side].window_scale,
+        ) {
+            (Some(shift), Some(_)) => u32::from(shift.min(14)),
+            _ => 0,
+        }
+    }
+
+    /// How soon after the highest segment a retransmission has to be sent
+    /// to be taken as out of order: the round trip time of the handshake,
+    /// from the SYN to the ACK of the SYN-ACK.
+    fn out_of_order_threshold(&self) -> Duration {
+        self.latency
+            .handshake()
+            .total()
+            .unwrap_or(DEFAULT_OUT_OF_ORDER_THRESHOLD)
+    }
+
+    fn analyze(&mut self, direction: Direction, segment: &TcpSegment) -> Vec<Annotation> {
+        self.latency.record(direction, segment);
+        let out_of_order_threshold = self.out_of_order_threshold();
+
+        let header = segment.header();
+        let side = match direction {
+            Direction::ClientToServer => 0,
+            Direction::ServerToClient => 1,
+        };
+
+        let sequence = header.sequence_number();
+        let acknowledgment = header.acknowledgment_number();
+        let window = header.window_size();
+        let length = segment.data().len() as u32;
+        let control = header.synchronize() || header.fin() || header.reset();
+        let sequence_end = sequence.wrapping_add(segment.sequence_length());
+
+        if header.synchronize() {
+            self.senders[side].window_scale = header.window_scale();
+        }
+
+        let receiver = self.senders[1 - side].clone();
+        let sender = &self.senders[side];
+        let mut annotations = Vec::new();
+
+        if window == 0 && !header.reset() {
+            annotations.push(Annotation::ZeroWindow);
+        }
+
+        let keep_alive =
+            length <= 1 && !control && sender.next_sequence == Some(sequence.wrapping_add(1));
+        let probe = length == 1
+            && sender.next_sequence == Some(sequence)
+            && receiver.last_window == Some(0);
+
+        if keep_alive {
+            annotations.push(Annotation::KeepAlive);
+        } else if probe {
+            annotations.push(Annotation::ZeroWindowProbe);
+        }
+
+        if let Some(next) = sender.next_sequence {
+            if !header.reset() && sequence_before(next, sequence) {
+                annotations.push(Annotation::PreviousSegmentNotCaptured);
+            } else if !keep_alive
+                && segment.sequence_length() > 0
+                && sequence_before(sequence, next)
+            {
+                let fast =
+                    receiver.duplicate_acks >= 2 && receiver.last_acknowledgment == Some(sequence);
+                let recent = sender.last_advanced.is_some_and(|last| {
+                    segment
+                        .timestamp()
+                        .checked_sub(last)
+                        .is_some_and(|elapsed| elapsed < out_of_order_threshold)
+                });
+
+                annotations.push(if fast {
+                    Annotation::FastRetransmission
+                } else if recent {
+                    Annotation::OutOfOrder
+                } else {
+                    Annotation::Retransmission
+                });
+            }
+        }
+
+        if let (Some(acknowledged), Some(window)) =
+            (receiver.last_acknowledgment, receiver.last_window)
+        {
+            let edge = acknowledged.wrapping_add(u32::from(window) << self.window_shift(1 - side));
+            if length > 0 && sequence.wrapping_add(length) == edge {
+                annotations.push(Annotation::WindowFull);
+            }
+        }
+
+        let sender = &mut self.senders[side];
+        if header.acknowledgment() {
+            if let Some(next) = receiver.next_sequence {
+                if sequence_before(next, acknowledgment) {
+                    annotations.push(Annotation::AckedUnseenSegment);
+                }
+            }
+
+            let same_ack = sender.last_acknowledgment == Some(acknowledgment);
+            if length == 0 && !control && !keep_alive && same_ack {
+                if sender.last_window == Some(window) && receiver.next_sequence.is_some() {
+                    sender.duplicate_acks += 1;
+                    annotations.push(Annotation::DuplicateAck(sender.duplicate_acks));
+                } else if sender.last_window.is_some_and(|last| window > last) {
+                    annotations.push(Annotation::WindowUpdate);
+                }
+            } else if !same_ack {
+                sender.duplicate_acks = 0;
+            }
+
+            sender.last_acknowledgment = Some(acknowledgment);
+            sender.last_window = Some(window);
+        }
+
+        if !keep_alive && !probe {
+            let advanced = sender
+                .next_sequence
+                .is_none_or(|next| sequence_before(next, sequence_end));
+            if advanced {
+                sender.next_sequence = Some(sequence_end);
+                sender.last_advanced = Some(segment.timestamp());
+            }
+        }
+
+        annotations
+    }
+}
+
+/// Annotates the segments of every TCP connection with the problems Wireshark
+/// calls out in its expert info.
+#[derive(Debug, Default)]
+pub struct ExpertAnalysis {
+    flows: Vec<FlowAnalysis>,
+}
+
+impl ExpertAnalysis {
+    pub fn new() -> ExpertAnalysis {
+        ExpertAnalysis::default()
+    }
+
+    /// Annotates a segment of the connection numbered `stream`. Segments have
+    /// to be passed in the order they were captured.
+    pub fn analyze(
+        &mut self,
+        stream: usize,
+        direction: Direction,
+        segment: &TcpSegment,
+    ) -> Vec<Annotation> {
+        if stream >= self.flows.len() {
+            self.flows.resize(stream + 1, FlowAnalysis::default());
+        }
+
+        self.flows[stream].analyze(direction, segment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expert::{Annotation, ExpertAnalysis, Severity};
+    use flow::tests::{ip_header, tcp_header_with_options};
+    use flow::{FlowTable, TcpSegment};
+    use std::time::Duration;
+
+    const SYN: u8 = 0x02;
+    const SYN_ACK: u8 = 0x12;
+    const ACK: u8 = 0x10;
+
+    /// Runs `(milliseconds, from client, sequence, acknowledgment, flags,
+    /// window, payload length)` packets through the analysis.
+    fn run(packets: &[(u64, bool, u32, u32, u8, u16, usize)]) -> Vec<Vec<Annotation>> {
+        let mut flows = FlowTable::new();
+        let mut analysis = ExpertAnalysis::new();
+        let payload = [0u8; 1500];
+
+        packets
+            .iter()
+            .map(
+                |&(millis, from_client, sequence, acknowledgment, flags, window, length)| {
+                    let ip = ip_header(from_client);
+                    let (source, destination) = if from_client {
+                        (36869, 23)
+                    } else {
+                        (23, 36869)
+                    };
+                    let header = tcp_header_with_options(
+                        source,
+                        destination,
+                        sequence,
+                        acknowledgment,
+                        flags,
+                        window,
+                        &[],
+                    );
+                    let segment = TcpSegment::new(
+                        Duration::from_millis(millis),
+                        &ip,
+                        &header,
+                        &payload[..length],
+                    );
+                    let (stream, direction) = flows.lookup(&segment);
+                    analysis.analyze(stream, direction, &segment)
+                },
+            )
+            .collect()
+    }
+
+    #[test]
+    fn fast_retransmission() {
+        let annotations = run(&[
+            (0, true, 100, 0, SYN, 1000, 0),
+            (10, false, 500, 101, SYN_ACK, 1000, 0),
+            (20, true, 101, 501, ACK, 1000, 0),
+            (30, true, 101, 501, ACK, 1000, 100),
+            (31, true, 201, 501, ACK, 1000, 100),
+            (32, true, 301, 501, ACK, 1000, 100),
+            (40, false, 501, 101, ACK, 1000, 0),
+            (41, false, 501, 201, ACK, 1000, 0),
+            (42, false, 501, 201, ACK, 1000, 0),
+            (43, false, 501, 201, ACK, 1000, 0),
+            (50, true, 201, 501, ACK, 1000, 100),
+        ]);
+
+        assert!(annotations[..4].iter().all(Vec::is_empty));
+        assert_eq!(annotations[4], vec![]);
+        assert_eq!(annotations[7], vec![]);
+        assert_eq!(annotations[8], vec![Annotation::DuplicateAck(1)]);
+        assert_eq!(annotations[9], vec![Annotation::DuplicateAck(2)]);
+        assert_eq!(annotations[10], vec![Annotation::FastRetransmission]);
+        assert_eq!(Annotation::FastRetransmission.severity(), Severity::Note);
+    }
+
+    #[test]
+    fn lost_and_retransmitted() {
+        let annotations = run(&[
+            (0, true, 101, 501, ACK, 1000, 100),
+            (100, true, 301, 501, ACK, 1000, 100),
+            (101, true, 201, 501, ACK, 1000, 100),
+            (500, true, 201, 501, ACK, 1000, 100),
+            (600, false, 501, 501, ACK, 1000, 0),
+        ]);
+
+        assert_eq!(annotations[1], vec![Annotation::PreviousSegmentNotCaptured]);
+        assert_eq!(annotations[2], vec![Annotation::OutOfOrder]);
+        assert_eq!(annotations[3], vec![Annotation::Retransmission]);
+        assert_eq!(annotations[4], vec![Annotation::AckedUnseenSegment]);
+    }
+
+    /// With a 20 ms handshake, segments filling a hole within 20 ms of the
+    /// highest one are out of order rather than retransmitted.
+    #[test]
+    fn out_of_order_threshold_from_handshake() {
+        let annotations = run(&[
+            (0, true, 100, 0, SYN, 1000, 0),
+            (10, false, 500, 101, SYN_ACK, 1000, 0),
+            (20, true, 101, 501, ACK, 1000, 0),
+            (30, true, 201, 501, ACK, 1000, 100),
+            (45, true, 101, 501, ACK, 1000, 100),
+            (50, true, 401, 501, ACK, 1000, 100),
+            (80, true, 301, 501, ACK, 1000, 100),
+        ]);
+
+        assert_eq!(annotations[3], vec![Annotation::PreviousSegmentNotCaptured]);
+        assert_eq!(annotations[4], vec![Annotation::OutOfOrder]);
+        assert_eq!(annotations[5], vec![Annotation::PreviousSegmentNotCaptured]);
+        assert_eq!(annotations[6], vec![Annotation::Retransmission]);
+    }
+
+    #[test]
+    fn windows() {
+        let annotations = run(&[
+            (0, true, 101, 501, ACK, 1000, 0),
+            (10, false, 501, 101, ACK, 200, 0),
+            (20, true, 101, 501, ACK, 1000, 200),
+            (30, false, 501, 301, ACK, 0, 0),
+            (40, true, 301, 501, ACK, 1000, 1),
+            (50, false, 501, 301, ACK, 500, 0),
+            (60, true, 300, 501, ACK, 1000, 0),
+        ]);
+
+        assert_eq!(annotations[2], vec![Annotation::WindowFull]);
+        assert_eq!(annotations[3], vec![Annotation::ZeroWindow]);
+        assert_eq!(annotations[4], vec![Annotation::ZeroWindowProbe]);
+        assert_eq!(annotations[5], vec![Annotation::WindowUpdate]);
+        assert_eq!(annotations[6], vec![Annotation::KeepAlive]);
+    }
+}
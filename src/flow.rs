@@ -0,0 +1,517 @@
+use ip::IpHeader;
+use packet::Packet;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::net::IpAddr;
+use std::time::Duration;
+use tcp::TcpHeader;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct Endpoint {
+    address: IpAddr,
+    port: u16,
+}
+
+impl Endpoint {
+    pub fn new(address: IpAddr, port: u16) -> Endpoint {
+        Endpoint { address, port }
+    }
+
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.address {
+            IpAddr::V4(address) => write!(f, "{}:{}", address, self.port),
+            IpAddr::V6(address) => write!(f, "[{}]:{}", address, self.port),
+        }
+    }
+}
+
+/// A TCP packet along with the time it was captured at.
+#[derive(Clone, Copy, Debug)]
+pub struct TcpSegment<'a> {
+    timestamp: Duration,
+    source: Endpoint,
+    destination: Endpoint,
+    header: &'a TcpHeader,
+    data: &'a [u8],
+}
+
+impl<'a> TcpSegment<'a> {
+    pub fn new(
+        timestamp: Duration,
+        ip_header: &IpHeader,
+        header: &'a TcpHeader,
+        data: &'a [u8],
+    ) -> TcpSegment<'a> {
+        TcpSegment {
+            timestamp,
+            source: Endpoint::new(ip_header.source(), header.source_port()),
+            destination: Endpoint::new(ip_header.destination(), header.destination_port()),
+            header,
+            data,
+        }
+    }
+
+    /// The TCP segment in `packet`, if it holds one.
+    pub fn from_packet(timestamp: Duration, packet: &'a Packet) -> Option<TcpSegment<'a>> {
+        packet
+            .tcp()
+            .map(|(ip_header, header, data)| TcpSegment::new(timestamp, ip_header, header, data))
+    }
+
+    /// Like `from_packet`, but also finds a TCP segment carried through a
+    /// tunnel.
+    pub fn carried_by(timestamp: Duration, packet: &'a Packet) -> Option<TcpSegment<'a>> {
+        packet
+            .carried_tcp()
+            .map(|(ip_header, header, data)| TcpSegment::new(timestamp, ip_header, header, data))
+    }
+
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    pub fn source(&self) -> Endpoint {
+        self.source
+    }
+
+    pub fn destination(&self) -> Endpoint {
+        self.destination
+    }
+
+    pub fn header(&self) -> &'a TcpHeader {
+        self.header
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Number of sequence numbers the segment takes up, counting SYN and FIN.
+    pub fn sequence_length(&self) -> u32 {
+        self.data.len() as u32 + self.header.synchronize() as u32 + self.header.fin() as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Direction {
+    /// From the endpoint which opened the connection, or sent the first
+    /// packet seen when the handshake wasn't captured.
+    ClientToServer,
+    ServerToClient,
+}
+
+impl Direction {
+    pub fn reverse(self) -> Direction {
+        match self {
+            Direction::ClientToServer => Direction::ServerToClient,
+            Direction::ServerToClient => Direction::ClientToServer,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Flow {
+    client: Endpoint,
+    server: Endpoint,
+
+    /// Sequence number of the client's SYN.
+    client_initial_sequence: Option<u32>,
+}
+
+impl Flow {
+    pub fn client(&self) -> Endpoint {
+        self.client
+    }
+
+    pub fn server(&self) -> Endpoint {
+        self.server
+    }
+}
+
+impl fmt::Display for Flow {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} <-> {}", self.client, self.server)
+    }
+}
+
+/// Numbers TCP connections in the order they are first seen, the same way
+/// Wireshark's `tcp.stream` does, and tells which direction each segment
+/// travels in.
+#[derive(Debug, Default)]
+pub struct FlowTable {
+    index: HashMap<(Endpoint, Endpoint), usize>,
+    flows: Vec<Flow>,
+}
+
+impl FlowTable {
+    pub fn new() -> FlowTable {
+        FlowTable::default()
+    }
+
+    /// Finds the connection `segment` belongs to, starting a new one when
+    /// its addresses haven't been seen before or when a SYN starts a new
+    /// connection reusing them.
+    pub fn lookup(&mut self, segment: &TcpSegment) -> (usize, Direction) {
+        let header = segment.header();
+        let is_syn = header.synchronize() && !header.acknowledgment();
+        let key = (segment.source(), segment.destination());
+        let reverse_key = (segment.destination(), segment.source());
+
+        if let Some(&stream) = self.index.get(&key) {
+            let flow = &self.flows[stream];
+            let reused = is_syn
+                && flow
+                    .client_initial_sequence
+                    .is_none_or(|sequence| sequence != header.sequence_number());
+
+            if !reused {
+                let direction = if flow.client == segment.source() {
+                    Direction::ClientToServer
+                } else {
+                    Direction::ServerToClient
+                };
+                return (stream, direction);
+            }
+        }
+
+        let (client, server) = if header.synchronize() && header.acknowledgment() {
+            (segment.destination(), segment.source())
+        } else {
+            (segment.source(), segment.destination())
+        };
+
+        let stream = self.flows.len();
+        self.flows.push(Flow {
+            client,
+            server,
+            client_initial_sequence: if is_syn {
+                Some(header.sequence_number())
+            } else {
+                None
+            },
+        });
+        self.index.insert(key, stream);
+        self.index.insert(reverse_key, stream);
+
+        let direction = if client == segment.source() {
+            Direction::ClientToServer
+        } else {
+            Direction::ServerToClient
+        };
+        (stream, direction)
+    }
+
+    pub fn flows(&self) -> &[Flow] {
+        &self.flows
+    }
+}
+
+/// Works out sequence and acknowledgment numbers relative to the initial
+/// sequence number of each direction of a connection, like Wireshark's
+/// relative sequence numbers. The SYN has relative sequence number 0. When
+/// the handshake wasn't captured the first segment seen in each direction
+/// starts at 1.
+#[derive(Debug, Default)]
+pub struct RelativeNumbers {
+    /// Initial sequence number of each direction, indexed by stream.
+    bases: Vec<[Option<u32>; 2]>,
+}
+
+impl RelativeNumbers {
+    pub fn new() -> RelativeNumbers {
+        RelativeNumbers::default()
+    }
+
+    /// The relative sequence and acknowledgment numbers of a segment of the
+    /// connection numbered `stream`. Segments have to be passed in the order
+    /// they were captured.
+    pub fn relative(
+        &mut self,
+        stream: usize,
+        direction: Direction,
+        segment: &TcpSegment,
+    ) -> (u32, u32) {
+        if stream >= self.bases.len() {
+            self.bases.resize(stream + 1, [None, None]);
+        }
+
+        let header = segment.header();
+        let (side, other) = match direction {
+            Direction::ClientToServer => (0, 1),
+            Direction::ServerToClient => (1, 0),
+        };
+
+        let bases = &mut self.bases[stream];
+        let sequence_base = *bases[side].get_or_insert_with(|| {
+            if header.synchronize() {
+                header.sequence_number()
+            } else {
+                header.sequence_number().wrapping_sub(1)
+            }
+        });
+
+        let acknowledgment = if header.acknowledgment() {
+            let acknowledgment_base =
+                *bases[other].get_or_insert_with(|| header.acknowledgment_number().wrapping_sub(1));
+            header
+                .acknowledgment_number()
+                .wrapping_sub(acknowledgment_base)
+        } else {
+            0
+        };
+
+        (
+            header.sequence_number().wrapping_sub(sequence_base),
+            acknowledgment,
+        )
+    }
+
+    /// A sequence number sent in `direction` of the connection numbered
+    /// `stream`, relative to the start of that direction. Numbers are left
+    /// as they are until a segment sent that way has been seen.
+    pub fn rebase(&self, stream: usize, direction: Direction, sequence: u32) -> u32 {
+        let side = match direction {
+            Direction::ClientToServer => 0,
+            Direction::ServerToClient => 1,
+        };
+        let base = self
+            .bases
+            .get(stream)
+            .and_then(|bases| bases[side])
+            .unwrap_or(0);
+        sequence.wrapping_sub(base)
+    }
+}
+
+/// How sequence and acknowledgment numbers are shown.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Numbering {
+    /// The numbers sent on the wire.
+    Absolute,
+
+    /// Relative to the start of each direction of a connection, as worked
+    /// out by `RelativeNumbers`.
+    Relative,
+}
+
+/// Gives the sequence and acknowledgment numbers to show for each segment
+/// in the chosen numbering.
+#[derive(Debug)]
+pub struct SequenceNumbers {
+    numbering: Numbering,
+    relative: RelativeNumbers,
+}
+
+impl SequenceNumbers {
+    pub fn new(numbering: Numbering) -> SequenceNumbers {
+        SequenceNumbers {
+            numbering,
+            relative: RelativeNumbers::new(),
+        }
+    }
+
+    pub fn numbering(&self) -> Numbering {
+        self.numbering
+    }
+
+    /// The sequence and acknowledgment numbers of a segment of the
+    /// connection numbered `stream`. Segments have to be passed in the order
+    /// they were captured.
+    pub fn numbers(
+        &mut self,
+        stream: usize,
+        direction: Direction,
+        segment: &TcpSegment,
+    ) -> (u32, u32) {
+        let header = segment.header();
+        match self.numbering {
+            Numbering::Absolute => (header.sequence_number(), header.acknowledgment_number()),
+            Numbering::Relative => self.relative.relative(stream, direction, segment),
+        }
+    }
+
+    /// Shows a sequence number sent in `direction` of the connection
+    /// numbered `stream`, after a segment of it has been passed to
+    /// `numbers`.
+    pub fn sequence(&self, stream: usize, direction: Direction, sequence: u32) -> u32 {
+        match self.numbering {
+            Numbering::Absolute => sequence,
+            Numbering::Relative => self.relative.rebase(stream, direction, sequence),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    extern crate hex;
+
+    use flow::{Direction, FlowTable, Numbering, RelativeNumbers, SequenceNumbers, TcpSegment};
+    use ip::{IpHeader, Ipv4Header};
+    use std::time::Duration;
+    use tcp::TcpHeader;
+
+    /// Builds a TCP header between 139.133.217.110 and 139.133.233.2 with the
+    /// given ports, sequence numbers and flags.
+    pub fn tcp_header(
+        source_port: u16,
+        destination_port: u16,
+        sequence_number: u32,
+        acknowledgment_number: u32,
+        flags: u8,
+    ) -> TcpHeader {
+        tcp_header_with_options(
+            source_port,
+            destination_port,
+            sequence_number,
+            acknowledgment_number,
+            flags,
+            0xffff,
+            &[],
+        )
+    }
+
+    /// Like `tcp_header` with a window size and options, which must be
+    /// padded to a multiple of four bytes.
+    pub fn tcp_header_with_options(
+        source_port: u16,
+        destination_port: u16,
+        sequence_number: u32,
+        acknowledgment_number: u32,
+        flags: u8,
+        window_size: u16,
+        options: &[u8],
+    ) -> TcpHeader {
+        let mut raw = Vec::new();
+        raw.extend_from_slice(&source_port.to_be_bytes());
+        raw.extend_from_slice(&destination_port.to_be_bytes());
+        raw.extend_from_slice(&sequence_number.to_be_bytes());
+        raw.extend_from_slice(&acknowledgment_number.to_be_bytes());
+        raw.push((5 + options.len() as u8 / 4) << 4);
+        raw.push(flags);
+        raw.extend_from_slice(&window_size.to_be_bytes());
+        raw.extend_from_slice(&[0, 0, 0, 0]);
+        raw.extend_from_slice(options);
+        TcpHeader::parse(&raw).unwrap().1
+    }
+
+    pub fn ip_header(from_client: bool) -> IpHeader {
+        let raw = if from_client {
+            hex::decode("4500002c08b84000ff0699978b85d96e8b85e902").unwrap()
+        } else {
+            hex::decode("4500002c08b84000ff0699978b85e9028b85d96e").unwrap()
+        };
+        IpHeader::V4(Ipv4Header::parse(&raw).unwrap().1)
+    }
+
+    #[test]
+    fn lookup() {
+        let client = ip_header(true);
+        let server = ip_header(false);
+        let syn = tcp_header(36869, 23, 100, 0, 0x02);
+        let syn_ack = tcp_header(23, 36869, 500, 101, 0x12);
+        let other = tcp_header(36870, 23, 100, 0, 0x02);
+        let reused = tcp_header(36869, 23, 9000, 0, 0x02);
+
+        let mut table = FlowTable::new();
+        let at = Duration::from_secs(0);
+        assert_eq!(
+            table.lookup(&TcpSegment::new(at, &client, &syn, &[])),
+            (0, Direction::ClientToServer)
+        );
+        assert_eq!(
+            table.lookup(&TcpSegment::new(at, &server, &syn_ack, &[])),
+            (0, Direction::ServerToClient)
+        );
+        assert_eq!(
+            table.lookup(&TcpSegment::new(at, &client, &syn, &[])),
+            (0, Direction::ClientToServer)
+        );
+        assert_eq!(
+            table.lookup(&TcpSegment::new(at, &client, &other, &[])),
+            (1, Direction::ClientToServer)
+        );
+        assert_eq!(
+            table.lookup(&TcpSegment::new(at, &client, &reused, &[])),
+            (2, Direction::ClientToServer)
+        );
+        assert_eq!(table.flows().len(), 3);
+    }
+
+    #[test]
+    fn lookup_without_handshake() {
+        let server = ip_header(false);
+        let data = tcp_header(23, 36869, 500, 101, 0x10);
+
+        let mut table = FlowTable::new();
+        let at = Duration::from_secs(0);
+        assert_eq!(
+            table.lookup(&TcpSegment::new(at, &server, &data, &[])),
+            (0, Direction::ClientToServer)
+        );
+        assert_eq!(table.flows()[0].client().port(), 23);
+    }
+
+    #[test]
+    fn relative_numbers() {
+        let client = ip_header(true);
+        let server = ip_header(false);
+        let syn = tcp_header(36869, 23, 1913975060, 0, 0x02);
+        let syn_ack = tcp_header(23, 36869, 0xffff_fff0, 1913975061, 0x12);
+        let ack = tcp_header(36869, 23, 1913975061, 0xffff_fff1, 0x10);
+        let data = tcp_header(23, 36869, 0xffff_fff1, 1913975061, 0x18);
+
+        let mut table = FlowTable::new();
+        let mut relative = RelativeNumbers::new();
+        let at = Duration::from_secs(0);
+        let mut numbers = |ip, header| {
+            let segment = TcpSegment::new(at, ip, header, b"hello");
+            let (stream, direction) = table.lookup(&segment);
+            relative.relative(stream, direction, &segment)
+        };
+
+        assert_eq!(numbers(&client, &syn), (0, 0));
+        assert_eq!(numbers(&server, &syn_ack), (0, 1));
+        assert_eq!(numbers(&client, &ack), (1, 1));
+        assert_eq!(numbers(&server, &data), (1, 1));
+    }
+
+    #[test]
+    fn relative_numbers_without_handshake() {
+        let server = ip_header(false);
+        let data = tcp_header(23, 36869, 500, 101, 0x18);
+
+        let mut table = FlowTable::new();
+        let mut relative = RelativeNumbers::new();
+        let segment = TcpSegment::new(Duration::from_secs(0), &server, &data, &[]);
+        let (stream, direction) = table.lookup(&segment);
+        assert_eq!(relative.relative(stream, direction, &segment), (1, 1));
+    }
+
+    #[test]
+    fn sequence_numbers() {
+        let client = ip_header(true);
+        let syn = tcp_header(36869, 23, 1913975060, 0, 0x02);
+        let segment = TcpSegment::new(Duration::from_secs(0), &client, &syn, &[]);
+        let direction = Direction::ClientToServer;
+
+        let mut absolute = SequenceNumbers::new(Numbering::Absolute);
+        assert_eq!(absolute.numbers(0, direction, &segment), (1913975060, 0));
+        assert_eq!(absolute.sequence(0, direction, 1913975070), 1913975070);
+
+        let mut relative = SequenceNumbers::new(Numbering::Relative);
+        assert_eq!(relative.numbers(0, direction, &segment), (0, 0));
+        assert_eq!(relative.sequence(0, direction, 1913975070), 10);
+        assert_eq!(relative.sequence(0, direction.reverse(), 500), 500);
+    }
+}
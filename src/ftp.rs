@@ -0,0 +1,1143 @@
+use flow::{Direction, Endpoint, Flow, TcpSegment};
+use reassembly::{ReassembledStreams, Reassembler, StreamData, StreamHandler};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use telnet::TelnetMessage;
+
+pub const FTP_PORT: u16 = 21;
+
+/// Lines longer than this are taken to mean the connection isn't FTP.
+const MAXIMUM_LINE_LENGTH: usize = 8192;
+
+/// The reply accepting `AUTH TLS`, after which the control connection is
+/// encrypted.
+const SECURITY_EXCHANGE_COMPLETE: u16 = 234;
+
+/// Commands which send or receive data over a data connection.
+const TRANSFER_COMMANDS: [&str; 7] = ["RETR", "STOR", "STOU", "APPE", "LIST", "NLST", "MLSD"];
+
+#[derive(Fail, Debug, Eq, PartialEq)]
+pub enum FtpError {
+    #[fail(display = "Invalid FTP command")]
+    InvalidCommand,
+
+    #[fail(display = "Invalid FTP reply")]
+    InvalidReply,
+
+    #[fail(display = "FTP line longer than {} bytes", _0)]
+    LineTooLong(usize),
+}
+
+/// Finds the end of the line starting at `start`, returning the line without
+/// its line ending or Telnet commands and where the next line starts.
+fn read_line(input: &[u8], start: usize) -> Result<Option<(String, usize)>, FtpError> {
+    let rest = &input[start..];
+    match rest.iter().position(|&byte| byte == b'\n') {
+        Some(end) => {
+            // Telnet commands can come before a line, such as the interrupt
+            // and synch which RFC 959 sends before ABOR.
+            let line = TelnetMessage::parse(&rest[..end]).data();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches('\r').to_string();
+            Ok(Some((line, start + end + 1)))
+        }
+        None if rest.len() > MAXIMUM_LINE_LENGTH => Err(FtpError::LineTooLong(MAXIMUM_LINE_LENGTH)),
+        None => Ok(None),
+    }
+}
+
+/// A command sent by the client, such as `RETR report.pdf`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FtpCommand {
+    verb: String,
+    argument: Option<String>,
+}
+
+impl FtpCommand {
+    /// Parses the first line of `input`. Returns `None` until the whole line
+    /// has arrived.
+    pub fn parse(input: &[u8]) -> Result<Option<(usize, FtpCommand)>, FtpError> {
+        let (line, length) = match read_line(input, 0)? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+
+        Ok(Some((length, FtpCommand::parse_line(&line)?)))
+    }
+
+    /// Parses a command line without its line ending.
+    fn parse_line(line: &str) -> Result<FtpCommand, FtpError> {
+        let (verb, argument) = match line.find(' ') {
+            Some(index) => (&line[..index], Some(line[index + 1..].to_string())),
+            None => (line, None),
+        };
+        if !(3..=4).contains(&verb.len()) || !verb.bytes().all(|byte| byte.is_ascii_alphabetic()) {
+            return Err(FtpError::InvalidCommand);
+        }
+
+        Ok(FtpCommand {
+            verb: verb.to_ascii_uppercase(),
+            argument,
+        })
+    }
+
+    /// The command name, in upper case.
+    pub fn verb(&self) -> &str {
+        &self.verb
+    }
+
+    pub fn argument(&self) -> Option<&str> {
+        self.argument.as_deref()
+    }
+
+    /// Whether the command sends or receives data over a data connection.
+    pub fn is_transfer(&self) -> bool {
+        TRANSFER_COMMANDS.contains(&self.verb.as_str())
+    }
+}
+
+impl fmt::Display for FtpCommand {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.verb)?;
+        if let Some(argument) = &self.argument {
+            write!(f, " {}", argument)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A reply sent by the server, which may span several lines.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FtpReply {
+    code: u16,
+    lines: Vec<String>,
+}
+
+impl FtpReply {
+    /// Parses the first reply in `input`. Returns `None` until the last line
+    /// of the reply has arrived.
+    pub fn parse(input: &[u8]) -> Result<Option<(usize, FtpReply)>, FtpError> {
+        let (line, mut length) = match read_line(input, 0)? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+
+        let mut reply = FtpReply::parse_first_line(&line)?;
+        if line.as_bytes().get(3) == Some(&b'-') {
+            // The reply carries on until a line starts with the same code
+            // followed by a space.
+            let last = format!("{} ", &line[..3]);
+            loop {
+                let (line, next) = match read_line(input, length)? {
+                    Some(line) => line,
+                    None => return Ok(None),
+                };
+                length = next;
+
+                if line.starts_with(&last) || line == last.trim_end() {
+                    reply
+                        .lines
+                        .push(line.get(4..).unwrap_or_default().to_string());
+                    break;
+                }
+                reply.lines.push(line);
+            }
+        }
+
+        Ok(Some((length, reply)))
+    }
+
+    /// Parses the line a reply starts with, leaving out any lines after it.
+    fn parse_first_line(line: &str) -> Result<FtpReply, FtpError> {
+        let bytes = line.as_bytes();
+        let valid = bytes.len() >= 3
+            && bytes[..3].iter().all(u8::is_ascii_digit)
+            && (b'1'..=b'5').contains(&bytes[0])
+            && (bytes.len() == 3 || bytes[3] == b' ' || bytes[3] == b'-');
+        if !valid {
+            return Err(FtpError::InvalidReply);
+        }
+
+        Ok(FtpReply {
+            code: line[..3].parse().map_err(|_| FtpError::InvalidReply)?,
+            lines: vec![line.get(4..).unwrap_or_default().to_string()],
+        })
+    }
+
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// The text of each line, without the code at the start of the first
+    /// and last lines.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Whether more replies to the same command will follow.
+    pub fn is_preliminary(&self) -> bool {
+        self.code < 200
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.code >= 200 && self.code < 400
+    }
+}
+
+impl fmt::Display for FtpReply {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.code)?;
+        if let Some(text) = self.lines.first().filter(|text| !text.is_empty()) {
+            write!(f, " {}", text)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Either a command or a reply, for showing segments of the control
+/// connection on their own.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FtpMessage {
+    Command(FtpCommand),
+    Reply(FtpReply),
+}
+
+impl FtpMessage {
+    /// Parses the first message in a segment, which is a reply when it was
+    /// sent by the server. Only the first line of a reply is read, since the
+    /// rest may be in later segments.
+    pub fn parse(input: &[u8], from_server: bool) -> Option<FtpMessage> {
+        if from_server {
+            match read_line(input, 0) {
+                Ok(Some((line, _))) => FtpReply::parse_first_line(&line)
+                    .ok()
+                    .map(FtpMessage::Reply),
+                _ => None,
+            }
+        } else {
+            match FtpCommand::parse(input) {
+                Ok(Some((_, command))) => Some(FtpMessage::Command(command)),
+                _ => None,
+            }
+        }
+    }
+}
+
+impl fmt::Display for FtpMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FtpMessage::Command(command) => write!(f, "{}", command),
+            FtpMessage::Reply(reply) => write!(f, "{}", reply),
+        }
+    }
+}
+
+/// Reads the six comma separated numbers of a PORT command or PASV reply,
+/// such as `192,168,1,2,7,138`.
+pub fn parse_host_port(text: &str) -> Option<(IpAddr, u16)> {
+    let numbers = text
+        .trim()
+        .split(',')
+        .map(|number| number.trim().parse::<u8>().ok())
+        .collect::<Option<Vec<u8>>>()?;
+    if numbers.len() != 6 {
+        return None;
+    }
+
+    let address = Ipv4Addr::new(numbers[0], numbers[1], numbers[2], numbers[3]);
+    let port = u16::from(numbers[4]) << 8 | u16::from(numbers[5]);
+    Some((IpAddr::V4(address), port))
+}
+
+/// Reads the argument of an EPRT command, such as `|2|2001:db8::1|5282|`.
+pub fn parse_extended_address(text: &str) -> Option<(IpAddr, u16)> {
+    let delimiter = text.chars().next()?;
+    let fields: Vec<&str> = text[delimiter.len_utf8()..].split(delimiter).collect();
+    if fields.len() != 4 || !fields[3].is_empty() {
+        return None;
+    }
+
+    let address = match fields[0] {
+        "1" => IpAddr::V4(fields[1].parse::<Ipv4Addr>().ok()?),
+        "2" => IpAddr::V6(fields[1].parse::<Ipv6Addr>().ok()?),
+        _ => return None,
+    };
+    Some((address, fields[2].parse().ok()?))
+}
+
+/// Reads the address from the text of a 227 reply, which servers write in
+/// different ways but always as six comma separated numbers.
+pub fn parse_passive_reply(text: &str) -> Option<(IpAddr, u16)> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let rest = &text[start..];
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit() && c != ',')
+        .unwrap_or(rest.len());
+    parse_host_port(&rest[..end])
+}
+
+/// Reads the port from the text of a 229 reply, such as
+/// `Entering Extended Passive Mode (|||6446|)`.
+pub fn parse_extended_passive_reply(text: &str) -> Option<u16> {
+    let start = text.find('(')? + 1;
+    let end = start + text[start..].find(')')?;
+    let inner = &text[start..end];
+    let delimiter = inner.chars().next()?;
+    let fields: Vec<&str> = inner.split(delimiter).collect();
+    if fields.len() != 5 {
+        return None;
+    }
+
+    fields[3].parse().ok()
+}
+
+/// Which side opens a data connection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FtpMode {
+    /// The server connects to an address the client sent with PORT or EPRT.
+    Active,
+
+    /// The client connects to an address the server replied to PASV or
+    /// EPSV with.
+    Passive,
+}
+
+impl fmt::Display for FtpMode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            FtpMode::Active => write!(f, "active"),
+            FtpMode::Passive => write!(f, "passive"),
+        }
+    }
+}
+
+/// A data connection announced on the control connection.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FtpDataChannel {
+    mode: FtpMode,
+
+    /// `None` when the address is the same as the control connection's, as
+    /// with EPSV, or was sent as 0.0.0.0.
+    address: Option<IpAddr>,
+
+    port: u16,
+    announced: Duration,
+
+    /// The index of the exchange whose command used the channel.
+    transfer: Option<usize>,
+}
+
+impl FtpDataChannel {
+    pub fn mode(&self) -> FtpMode {
+        self.mode
+    }
+
+    pub fn address(&self) -> Option<IpAddr> {
+        self.address
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn announced(&self) -> Duration {
+        self.announced
+    }
+
+    pub fn transfer(&self) -> Option<usize> {
+        self.transfer
+    }
+
+    /// The data connection a PORT or EPRT command, or a 227 or 229 reply,
+    /// announces.
+    fn announced_by(message: &FtpMessage, announced: Duration) -> Option<FtpDataChannel> {
+        let (mode, address, port) = match message {
+            FtpMessage::Command(command) => {
+                let (address, port) = match command.verb() {
+                    "PORT" => command.argument().and_then(parse_host_port),
+                    "EPRT" => command.argument().and_then(parse_extended_address),
+                    _ => None,
+                }?;
+                (FtpMode::Active, Some(address), port)
+            }
+            FtpMessage::Reply(reply) => {
+                let text = reply.lines().join(" ");
+                match reply.code() {
+                    227 => {
+                        let (address, port) = parse_passive_reply(&text)?;
+                        (FtpMode::Passive, Some(address), port)
+                    }
+                    229 => (FtpMode::Passive, None, parse_extended_passive_reply(&text)?),
+                    _ => return None,
+                }
+            }
+        };
+
+        Some(FtpDataChannel {
+            mode,
+            address: address.filter(|address| !address.is_unspecified()),
+            port,
+            announced,
+            transfer: None,
+        })
+    }
+
+    /// The endpoint the data connection is made to, given the control
+    /// connection.
+    pub fn endpoint(&self, control: &Flow) -> Endpoint {
+        let address = self.address.unwrap_or_else(|| match self.mode {
+            FtpMode::Active => control.client().address(),
+            FtpMode::Passive => control.server().address(),
+        });
+        Endpoint::new(address, self.port)
+    }
+}
+
+/// A command and the replies to it. The greeting has no command.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FtpExchange {
+    command: Option<FtpCommand>,
+    replies: Vec<FtpReply>,
+    timestamp: Duration,
+}
+
+impl FtpExchange {
+    pub fn command(&self) -> Option<&FtpCommand> {
+        self.command.as_ref()
+    }
+
+    pub fn replies(&self) -> &[FtpReply] {
+        &self.replies
+    }
+
+    /// The time the command, or the first reply, was captured at.
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    fn is_answered(&self) -> bool {
+        self.replies
+            .last()
+            .is_some_and(|reply| !reply.is_preliminary())
+    }
+}
+
+/// Where one side of the control connection is up to.
+#[derive(Debug, Default)]
+struct Side {
+    buffer: Vec<u8>,
+    broken: bool,
+}
+
+/// Reads the commands and replies of an FTP control connection and the data
+/// connections they announce.
+#[derive(Debug, Default)]
+pub struct FtpSession {
+    client: Side,
+    server: Side,
+    exchanges: Vec<FtpExchange>,
+
+    /// The first exchange still waiting for its final reply.
+    unanswered: usize,
+
+    /// Whether the control connection switched to TLS after `AUTH`.
+    encrypted: bool,
+
+    data_channels: Vec<FtpDataChannel>,
+}
+
+impl FtpSession {
+    pub fn new() -> FtpSession {
+        FtpSession::default()
+    }
+
+    pub fn exchanges(&self) -> &[FtpExchange] {
+        &self.exchanges
+    }
+
+    pub fn data_channels(&self) -> &[FtpDataChannel] {
+        &self.data_channels
+    }
+
+    /// Whether the control connection was encrypted after an `AUTH`
+    /// command. Nothing after that point is read.
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// The command which used `channel`, such as `RETR report.pdf`.
+    pub fn transfer_command(&self, channel: &FtpDataChannel) -> Option<&FtpCommand> {
+        channel
+            .transfer
+            .and_then(|index| self.exchanges[index].command())
+    }
+
+    /// Adds bytes sent in `direction`, which must follow on from the last
+    /// bytes sent that way.
+    pub fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration) {
+        let side = match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        };
+        if side.broken || self.encrypted {
+            return;
+        }
+        side.buffer.extend_from_slice(data);
+
+        while !self.encrypted {
+            let side = match direction {
+                Direction::ClientToServer => &mut self.client,
+                Direction::ServerToClient => &mut self.server,
+            };
+            let parsed = match direction {
+                Direction::ClientToServer => FtpCommand::parse(&side.buffer).map(|parsed| {
+                    parsed.map(|(length, command)| (length, FtpMessage::Command(command)))
+                }),
+                Direction::ServerToClient => FtpReply::parse(&side.buffer)
+                    .map(|parsed| parsed.map(|(length, reply)| (length, FtpMessage::Reply(reply)))),
+            };
+
+            let message = match parsed {
+                Ok(Some((length, message))) => {
+                    side.buffer.drain(..length);
+                    message
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    side.broken = true;
+                    side.buffer.clear();
+                    break;
+                }
+            };
+
+            if let Some(channel) = FtpDataChannel::announced_by(&message, timestamp) {
+                self.data_channels.push(channel);
+            }
+            match message {
+                FtpMessage::Command(command) => self.add_command(command, timestamp),
+                FtpMessage::Reply(reply) => self.add_reply(reply, timestamp),
+            }
+        }
+    }
+
+    /// Notes that bytes sent in `direction` weren't captured.
+    pub fn gap(&mut self, direction: Direction) {
+        let side = match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        };
+        side.broken = true;
+        side.buffer.clear();
+    }
+
+    fn add_command(&mut self, command: FtpCommand, timestamp: Duration) {
+        if command.is_transfer() {
+            let index = self.exchanges.len();
+            if let Some(channel) = self.data_channels.last_mut() {
+                if channel.transfer.is_none() {
+                    channel.transfer = Some(index);
+                }
+            }
+        }
+
+        self.exchanges.push(FtpExchange {
+            command: Some(command),
+            replies: Vec::new(),
+            timestamp,
+        });
+    }
+
+    fn add_reply(&mut self, reply: FtpReply, timestamp: Duration) {
+        if reply.code() == SECURITY_EXCHANGE_COMPLETE {
+            self.encrypted = true;
+            self.client.buffer.clear();
+            self.server.buffer.clear();
+        }
+
+        // Replies sent without a command waiting, like the greeting, get an
+        // exchange of their own.
+        if self.unanswered == self.exchanges.len() {
+            self.exchanges.push(FtpExchange {
+                command: None,
+                replies: Vec::new(),
+                timestamp,
+            });
+        }
+
+        let exchange = &mut self.exchanges[self.unanswered];
+        exchange.replies.push(reply);
+        if exchange.is_answered() {
+            self.unanswered += 1;
+        }
+    }
+}
+
+impl StreamHandler for FtpSession {
+    /// Connections to port 21 are read.
+    fn detect(ports: [u16; 2], _data: &[u8]) -> Option<FtpSession> {
+        if ports.contains(&FTP_PORT) {
+            Some(FtpSession::new())
+        } else {
+            None
+        }
+    }
+
+    fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration) {
+        FtpSession::push(self, direction, data, timestamp);
+    }
+
+    fn gap(&mut self, direction: Direction) {
+        FtpSession::gap(self, direction);
+    }
+
+    fn finish(&mut self) {}
+}
+
+/// The bytes sent over an FTP-DATA connection.
+#[derive(Debug)]
+pub struct FtpTransfer {
+    control: usize,
+    channel: usize,
+    mode: FtpMode,
+    reassemblers: [Reassembler; 2],
+    direction: Option<Direction>,
+    data: Vec<u8>,
+    missing: u64,
+    first_seen: Duration,
+    last_seen: Duration,
+}
+
+impl FtpTransfer {
+    fn new(control: usize, channel: usize, mode: FtpMode, timestamp: Duration) -> FtpTransfer {
+        FtpTransfer {
+            control,
+            channel,
+            mode,
+            reassemblers: Default::default(),
+            direction: None,
+            data: Vec::new(),
+            missing: 0,
+            first_seen: timestamp,
+            last_seen: timestamp,
+        }
+    }
+
+    /// The number of the control connection which announced this one.
+    pub fn control(&self) -> usize {
+        self.control
+    }
+
+    /// The index of the announcement in the control connection's data
+    /// channels.
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// The direction data was sent in between the FTP client and server, so
+    /// uploads are `ClientToServer` whichever side opened the connection.
+    /// `None` if nothing was sent.
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+    }
+
+    /// The bytes sent, which make up the file or listing transferred.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The number of bytes missing from `data`, because they weren't captured
+    /// or arrived after later ones at the start of a connection.
+    pub fn missing(&self) -> u64 {
+        self.missing
+    }
+
+    pub fn first_seen(&self) -> Duration {
+        self.first_seen
+    }
+
+    pub fn last_seen(&self) -> Duration {
+        self.last_seen
+    }
+
+    fn record(&mut self, direction: Direction, segment: &TcpSegment) {
+        self.last_seen = segment.timestamp();
+        let bytes = match direction {
+            Direction::ClientToServer => self.reassemblers[0].push(segment),
+            Direction::ServerToClient => self.reassemblers[1].push(segment),
+        };
+        self.append(direction, &bytes);
+    }
+
+    fn append(&mut self, direction: Direction, bytes: &[u8]) {
+        if !bytes.is_empty() {
+            // In active mode the FTP server opens the data connection.
+            let direction = match self.mode {
+                FtpMode::Active => direction.reverse(),
+                FtpMode::Passive => direction,
+            };
+            self.direction.get_or_insert(direction);
+            self.data.extend_from_slice(bytes);
+        }
+    }
+
+    fn finish(&mut self) {
+        let directions = [Direction::ClientToServer, Direction::ServerToClient];
+        for (side, &direction) in directions.iter().enumerate() {
+            self.missing += self.reassemblers[side].skipped();
+            for (data, _) in self.reassemblers[side].finish() {
+                match data {
+                    StreamData::Bytes(bytes) => self.append(direction, &bytes),
+                    StreamData::Missing(length) => self.missing += length,
+                }
+            }
+        }
+    }
+}
+
+/// Reads FTP control connections and tags the connections they announce as
+/// FTP-DATA.
+#[derive(Debug, Default)]
+pub struct FtpAnalysis {
+    control: ReassembledStreams<FtpSession>,
+    transfers: BTreeMap<usize, FtpTransfer>,
+
+    /// The number of connections already checked against the announced
+    /// data channels.
+    checked: usize,
+
+    /// Whether the bytes of data connections are left out.
+    without_contents: bool,
+}
+
+impl FtpAnalysis {
+    pub fn new() -> FtpAnalysis {
+        FtpAnalysis::default()
+    }
+
+    /// Like `new`, but only finds the data connections and doesn't keep the
+    /// files and listings sent over them.
+    pub fn without_contents() -> FtpAnalysis {
+        FtpAnalysis {
+            without_contents: true,
+            ..FtpAnalysis::default()
+        }
+    }
+
+    /// Records a segment of the connection numbered `stream`. Segments have
+    /// to be passed in the order they were captured, along with every
+    /// connection seen so far.
+    pub fn record(
+        &mut self,
+        flows: &[Flow],
+        stream: usize,
+        direction: Direction,
+        segment: &TcpSegment,
+    ) {
+        if stream >= self.checked {
+            self.checked = stream + 1;
+            self.claim(flows, stream, segment.timestamp());
+        }
+
+        match self.transfers.get_mut(&stream) {
+            Some(_) if self.without_contents => {}
+            Some(transfer) => transfer.record(direction, segment),
+            None => self.control.record(stream, direction, segment),
+        }
+    }
+
+    /// Flushes every connection at the end of the capture.
+    pub fn finish(&mut self) {
+        self.control.finish();
+        for transfer in self.transfers.values_mut() {
+            transfer.finish();
+        }
+    }
+
+    /// The control connections along with their numbers.
+    pub fn sessions(&self) -> Vec<(usize, &FtpSession)> {
+        self.control.handlers()
+    }
+
+    /// The data connections along with their numbers.
+    pub fn transfers(&self) -> &BTreeMap<usize, FtpTransfer> {
+        &self.transfers
+    }
+
+    /// Whether the connection numbered `stream` is an FTP-DATA connection.
+    pub fn is_data(&self, stream: usize) -> bool {
+        self.transfers.contains_key(&stream)
+    }
+
+    /// Makes a new connection a data connection if it goes to an endpoint a
+    /// control connection announced and no other connection has used yet.
+    /// When the endpoint was announced more than once, on one control
+    /// connection or several, the latest announcement claims it.
+    fn claim(&mut self, flows: &[Flow], stream: usize, timestamp: Duration) {
+        let server = flows[stream].server();
+        let used = |control: usize, channel: usize| {
+            self.transfers
+                .values()
+                .any(|transfer| transfer.control == control && transfer.channel == channel)
+        };
+
+        let found = self
+            .control
+            .handlers()
+            .into_iter()
+            .flat_map(|(control, session)| {
+                session
+                    .data_channels()
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, channel)| (control, index, channel))
+            })
+            .filter(|&(control, index, channel)| {
+                channel.endpoint(&flows[control]) == server && !used(control, index)
+            })
+            .max_by_key(|&(_, _, channel)| channel.announced())
+            .map(|(control, index, channel)| (control, index, channel.mode()));
+
+        if let Some((control, channel, mode)) = found {
+            let transfer = FtpTransfer::new(control, channel, mode, timestamp);
+            self.transfers.insert(stream, transfer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flow::tests::{ip_header, tcp_header};
+    use flow::{Direction, FlowTable, TcpSegment};
+    use ftp::{
+        parse_extended_address, parse_extended_passive_reply, parse_host_port, parse_passive_reply,
+        FtpAnalysis, FtpCommand, FtpError, FtpMessage, FtpMode, FtpReply, FtpSession,
+    };
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    #[test]
+    fn parse() {
+        let (length, command) = FtpCommand::parse(b"retr report.pdf\r\nQUIT\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(length, 17);
+        assert_eq!(command.verb(), "RETR");
+        assert_eq!(command.argument(), Some("report.pdf"));
+        assert!(command.is_transfer());
+        assert_eq!(command.to_string(), "RETR report.pdf");
+        assert_eq!(FtpCommand::parse(b"PAS"), Ok(None));
+        assert_eq!(
+            FtpCommand::parse(b"HELLO there\r\n"),
+            Err(FtpError::InvalidCommand)
+        );
+
+        let (_, reply) = FtpReply::parse(b"230 Login successful.\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply.code(), 230);
+        assert!(reply.is_success());
+        assert_eq!(reply.to_string(), "230 Login successful.");
+
+        let raw = b"211-Features:\r\n EPSV\r\n PASV\r\n211 End\r\n";
+        assert_eq!(FtpReply::parse(&raw[..25]), Ok(None));
+        let (length, reply) = FtpReply::parse(raw).unwrap().unwrap();
+        assert_eq!(length, raw.len());
+        assert_eq!(reply.lines(), ["Features:", " EPSV", " PASV", "End"]);
+        assert_eq!(
+            FtpMessage::parse(&raw[..25], true).unwrap().to_string(),
+            "211 Features:"
+        );
+
+        assert_eq!(
+            FtpReply::parse(b"SSH-2.0-OpenSSH\r\n"),
+            Err(FtpError::InvalidReply)
+        );
+        assert_eq!(
+            FtpReply::parse(&[b'2'; 9000]),
+            Err(FtpError::LineTooLong(8192))
+        );
+    }
+
+    #[test]
+    fn addresses() {
+        let address = |text: &str| text.parse::<IpAddr>().unwrap();
+
+        assert_eq!(
+            parse_host_port("192,168,1,2,7,138"),
+            Some((address("192.168.1.2"), 1930))
+        );
+        assert_eq!(parse_host_port("192,168,1,2,7"), None);
+        assert_eq!(parse_host_port("192,168,1,2,7,256"), None);
+        assert_eq!(
+            parse_passive_reply("Entering Passive Mode (10,0,0,2,117,48)."),
+            Some((address("10.0.0.2"), 30000))
+        );
+        assert_eq!(
+            parse_passive_reply("Entering Passive Mode 10,0,0,2,117,48"),
+            Some((address("10.0.0.2"), 30000))
+        );
+        assert_eq!(
+            parse_extended_address("|1|132.235.1.2|6275|"),
+            Some((address("132.235.1.2"), 6275))
+        );
+        assert_eq!(
+            parse_extended_address("|2|1080::8:800:200C:417A|5282|"),
+            Some((address("1080::8:800:200c:417a"), 5282))
+        );
+        assert_eq!(parse_extended_address("|3|x|1|"), None);
+        assert_eq!(
+            parse_extended_passive_reply("Entering Extended Passive Mode (|||6446|)"),
+            Some(6446)
+        );
+        assert_eq!(parse_extended_passive_reply("Entering (|||)"), None);
+    }
+
+    #[test]
+    fn session() {
+        let at = Duration::from_secs;
+        let mut session = FtpSession::new();
+
+        session.push(Direction::ServerToClient, b"220 Ready\r\n", at(1));
+        session.push(Direction::ClientToServer, b"USER ftp\r\nPASV\r\n", at(2));
+        session.push(Direction::ServerToClient, b"331 Password?\r\n227 (", at(3));
+        session.push(Direction::ServerToClient, b"0,0,0,0,4,0)\r\n", at(4));
+        session.push(Direction::ClientToServer, b"LIST\r\n", at(5));
+        session.push(
+            Direction::ServerToClient,
+            b"150 Here\r\n226 Done\r\n",
+            at(6),
+        );
+        session.push(
+            Direction::ClientToServer,
+            b"EPRT |1|10.1.1.1|2000|\r\n",
+            at(7),
+        );
+
+        let exchanges = session.exchanges();
+        assert_eq!(exchanges.len(), 5);
+        assert_eq!(exchanges[0].command(), None);
+        assert_eq!(exchanges[0].replies()[0].code(), 220);
+        assert_eq!(exchanges[2].command().unwrap().verb(), "PASV");
+        assert_eq!(exchanges[2].replies()[0].code(), 227);
+        assert_eq!(exchanges[2].timestamp(), at(2));
+        let codes: Vec<u16> = exchanges[3].replies().iter().map(FtpReply::code).collect();
+        assert_eq!(codes, [150, 226]);
+        assert!(exchanges[4].replies().is_empty());
+
+        let channels = session.data_channels();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(channels[0].mode(), FtpMode::Passive);
+        assert_eq!(channels[0].address(), None);
+        assert_eq!(channels[0].port(), 1024);
+        assert_eq!(channels[0].announced(), at(4));
+        assert_eq!(
+            session.transfer_command(&channels[0]).unwrap().verb(),
+            "LIST"
+        );
+        assert_eq!(channels[1].mode(), FtpMode::Active);
+        assert_eq!(channels[1].address(), "10.1.1.1".parse().ok());
+        assert_eq!(session.transfer_command(&channels[1]), None);
+
+        // Telnet commands are left out of command lines.
+        session.push(
+            Direction::ClientToServer,
+            b"\xff\xf4\xff\xf2ABOR\r\n",
+            at(7),
+        );
+        assert_eq!(session.exchanges()[5].command().unwrap().verb(), "ABOR");
+        assert_eq!(
+            FtpCommand::parse(b"\xff\xfa\x18\x01\xff\xf0NOOP\r\n")
+                .unwrap()
+                .unwrap()
+                .1
+                .verb(),
+            "NOOP"
+        );
+        assert_eq!(
+            FtpCommand::parse(b"STOR a\xff\xffb\r\n")
+                .unwrap()
+                .unwrap()
+                .1
+                .argument(),
+            Some("a\u{fffd}b")
+        );
+
+        // Nothing more is read after bytes go missing.
+        session.gap(Direction::ServerToClient);
+        session.push(Direction::ServerToClient, b"200 OK\r\n", at(8));
+        assert!(session.exchanges()[4].replies().is_empty());
+    }
+
+    #[test]
+    fn auth_tls() {
+        let at = Duration::from_secs;
+        let mut session = FtpSession::new();
+
+        session.push(Direction::ServerToClient, b"220 Ready\r\n", at(1));
+        session.push(Direction::ClientToServer, b"AUTH TLS\r\n", at(2));
+        session.push(
+            Direction::ServerToClient,
+            b"234 Proceed with negotiation.\r\n",
+            at(3),
+        );
+        session.push(
+            Direction::ClientToServer,
+            b"\x16\x03\x01\x02\x00\x01",
+            at(4),
+        );
+        session.push(
+            Direction::ServerToClient,
+            b"\x16\x03\x03\x00\x7a\x02",
+            at(4),
+        );
+
+        assert!(session.is_encrypted());
+        let exchanges = session.exchanges();
+        assert_eq!(exchanges.len(), 2);
+        assert_eq!(exchanges[1].command().unwrap().verb(), "AUTH");
+        assert_eq!(exchanges[1].replies()[0].code(), 234);
+    }
+
+    #[test]
+    fn analysis() {
+        let at = Duration::from_secs;
+        let client = ip_header(true);
+        let server = ip_header(false);
+        let mut flows = FlowTable::new();
+        let mut analysis = FtpAnalysis::new();
+        let mut record = |from_client: bool, header, data: &[u8], timestamp| {
+            let ip = if from_client { &client } else { &server };
+            let segment = TcpSegment::new(timestamp, ip, &header, data);
+            let (stream, direction) = flows.lookup(&segment);
+            analysis.record(flows.flows(), stream, direction, &segment);
+        };
+
+        record(true, tcp_header(40000, 21, 100, 0, 0x02), b"", at(1));
+        record(false, tcp_header(21, 40000, 500, 101, 0x12), b"", at(1));
+        record(
+            true,
+            tcp_header(40000, 21, 101, 501, 0x18),
+            b"EPSV\r\n",
+            at(2),
+        );
+        let reply = b"229 Entering Extended Passive Mode (|||30000|)\r\n";
+        record(false, tcp_header(21, 40000, 501, 107, 0x18), reply, at(3));
+
+        // Only connections to the announced port are data connections.
+        record(true, tcp_header(40001, 30001, 700, 0, 0x02), b"", at(4));
+        record(true, tcp_header(40002, 30000, 800, 0, 0x02), b"", at(4));
+        record(
+            true,
+            tcp_header(40000, 21, 107, 550, 0x18),
+            b"STOR a.txt\r\n",
+            at(5),
+        );
+        record(
+            true,
+            tcp_header(40002, 30000, 806, 0, 0x18),
+            b"world",
+            at(6),
+        );
+        record(
+            true,
+            tcp_header(40002, 30000, 801, 0, 0x18),
+            b"hello",
+            at(6),
+        );
+        analysis.finish();
+
+        assert!(!analysis.is_data(0));
+        assert!(!analysis.is_data(1));
+        assert!(analysis.is_data(2));
+
+        let transfer = &analysis.transfers()[&2];
+        assert_eq!(transfer.control(), 0);
+        assert_eq!(transfer.channel(), 0);
+        assert_eq!(transfer.direction(), Some(Direction::ClientToServer));
+        assert_eq!(transfer.data(), b"helloworld");
+        assert_eq!(transfer.missing(), 0);
+        assert_eq!(transfer.first_seen(), at(4));
+
+        let sessions = analysis.sessions();
+        assert_eq!(sessions.len(), 1);
+        let (_, session) = sessions[0];
+        let channel = &session.data_channels()[0];
+        assert_eq!(session.transfer_command(channel).unwrap().verb(), "STOR");
+    }
+
+    #[test]
+    fn reannounced_endpoint() {
+        let at = Duration::from_secs;
+        let client = ip_header(true);
+        let server = ip_header(false);
+        let mut flows = FlowTable::new();
+        let mut analysis = FtpAnalysis::without_contents();
+        let mut record = |from_client: bool, header, data: &[u8], timestamp| {
+            let ip = if from_client { &client } else { &server };
+            let segment = TcpSegment::new(timestamp, ip, &header, data);
+            let (stream, direction) = flows.lookup(&segment);
+            analysis.record(flows.flows(), stream, direction, &segment);
+            stream
+        };
+
+        // Two control connections announce the same endpoint, the second one
+        // in a reply split across two segments.
+        let reply = b"229 Entering Extended Passive Mode (|||30000|)\r\n";
+        assert_eq!(
+            record(true, tcp_header(40000, 21, 100, 0, 0x02), b"", at(1)),
+            0
+        );
+        assert_eq!(
+            record(true, tcp_header(40001, 21, 100, 0, 0x02), b"", at(1)),
+            1
+        );
+        record(false, tcp_header(21, 40000, 501, 101, 0x18), reply, at(2));
+        record(
+            false,
+            tcp_header(21, 40001, 501, 101, 0x18),
+            &reply[..20],
+            at(3),
+        );
+        record(
+            false,
+            tcp_header(21, 40001, 521, 101, 0x18),
+            &reply[20..],
+            at(3),
+        );
+
+        assert_eq!(
+            record(true, tcp_header(40002, 30001, 700, 0, 0x02), b"", at(4)),
+            2
+        );
+        assert_eq!(
+            record(true, tcp_header(40003, 30000, 700, 0, 0x02), b"", at(4)),
+            3
+        );
+        record(
+            true,
+            tcp_header(40003, 30000, 701, 0, 0x18),
+            b"hello",
+            at(4),
+        );
+        assert_eq!(
+            record(true, tcp_header(40004, 30000, 700, 0, 0x02), b"", at(5)),
+            4
+        );
+        assert_eq!(
+            record(true, tcp_header(40005, 30000, 700, 0, 0x02), b"", at(5)),
+            5
+        );
+        analysis.finish();
+
+        let controls: Vec<_> = (0..6)
+            .map(|stream| analysis.transfers().get(&stream).map(|t| t.control()))
+            .collect();
+        assert_eq!(controls, [None, None, None, Some(1), Some(0), None]);
+        assert!(analysis.transfers()[&3].data().is_empty());
+    }
+}
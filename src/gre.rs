@@ -0,0 +1,355 @@
+use ether::{parse_ether_type, EtherType};
+use nom::{be_u16, be_u32, Err, IResult, Needed};
+use std::fmt;
+use std::fmt::Formatter;
+use util::take_bool;
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct GreHeader {
+    flags: GreFlags,
+    protocol_type: EtherType,
+    checksum: Option<u16>,
+    offset: Option<u16>,
+
+    /// For version 1 (PPTP) headers, the payload length followed by the call
+    /// id.
+    key: Option<u32>,
+    sequence_number: Option<u32>,
+    acknowledgment_number: Option<u32>,
+
+    /// Raw source route entries, including the terminating null entry.
+    routing: Option<Vec<u8>>,
+}
+
+impl GreHeader {
+    pub fn parse(input: &[u8]) -> IResult<&[u8], GreHeader> {
+        parse_gre_header(input)
+    }
+
+    pub fn protocol_type(&self) -> &EtherType {
+        &self.protocol_type
+    }
+
+    pub fn sequence_number(&self) -> Option<u32> {
+        self.sequence_number
+    }
+}
+
+impl fmt::Display for GreHeader {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.protocol_type)?;
+
+        if let Some(key) = self.key {
+            write!(f, " Key={:#010x}", key)?;
+        }
+
+        if let Some(sequence_number) = self.sequence_number {
+            write!(f, " Seq={}", sequence_number)?;
+        }
+
+        Ok(())
+    }
+}
+
+named!(
+    pub parse_gre_header<GreHeader>,
+    do_parse!(
+        flags:                 parse_gre_flags >>
+        protocol_type:         parse_ether_type >>
+        checksum:              cond!(
+                                   flags.checksum_present || flags.routing_present,
+                                   tuple!(be_u16, be_u16)
+                               ) >>
+        key:                   cond!(flags.key_present, be_u32) >>
+        sequence_number:       cond!(flags.sequence_present, be_u32) >>
+        acknowledgment_number: cond!(flags.acknowledgment_present, be_u32) >>
+        routing:               cond!(flags.routing_present, parse_source_routes) >>
+
+        (GreHeader {
+            checksum: checksum.map(|(checksum, _)| checksum),
+            offset: checksum.map(|(_, offset)| offset),
+            flags,
+            protocol_type,
+            key,
+            sequence_number,
+            acknowledgment_number,
+            routing,
+        })
+    )
+);
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct GreFlags {
+    checksum_present: bool,
+    routing_present: bool,
+    key_present: bool,
+    sequence_present: bool,
+    strict_source_route: bool,
+    recursion_control: u8,
+    acknowledgment_present: bool,
+    flags: u8,
+    version: u8,
+}
+
+named!(
+    parse_gre_flags<GreFlags>,
+    bits!(do_parse!(
+        checksum_present:       take_bool >>
+        routing_present:        take_bool >>
+        key_present:            take_bool >>
+        sequence_present:       take_bool >>
+        strict_source_route:    take_bool >>
+        recursion_control:      take_bits!(u8, 3) >>
+        acknowledgment_present: take_bool >>
+        flags:                  take_bits!(u8, 4) >>
+        version:                take_bits!(u8, 3) >>
+
+        (GreFlags {
+            checksum_present,
+            routing_present,
+            key_present,
+            sequence_present,
+            strict_source_route,
+            recursion_control,
+            acknowledgment_present,
+            flags,
+            version,
+        })
+    ))
+);
+
+/// Consumes source route entries up to and including the null entry which
+/// terminates the list.
+fn parse_source_routes(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
+    let mut offset = 0;
+
+    loop {
+        if input.len() < offset + 4 {
+            return Err(Err::Incomplete(Needed::Size(offset + 4)));
+        }
+
+        let address_family = (input[offset] as u16) << 8 | input[offset + 1] as u16;
+        let length = input[offset + 3] as usize;
+        offset += 4 + length;
+
+        if address_family == 0 && length == 0 {
+            break;
+        }
+    }
+
+    if input.len() < offset {
+        return Err(Err::Incomplete(Needed::Size(offset)));
+    }
+
+    let (routes, rest) = input.split_at(offset);
+    Ok((rest, routes.to_vec()))
+}
+
+/// The header added by Cisco's Encapsulated Remote SPAN in front of a
+/// mirrored Ethernet frame.
+#[derive(Eq, PartialEq, Debug)]
+pub struct ErspanHeader {
+    vlan: u16,
+    class_of_service: u8,
+    truncated: bool,
+    session_id: u16,
+    details: ErspanDetails,
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub enum ErspanDetails {
+    TypeII {
+        encapsulation_type: u8,
+        index: u32,
+    },
+    TypeIII {
+        bad_or_short: u8,
+        timestamp: u32,
+        security_group_tag: u16,
+        frame_type: u8,
+        hardware_id: u8,
+        egress: bool,
+        timestamp_granularity: u8,
+        platform_specific: Option<Vec<u8>>,
+    },
+}
+
+impl ErspanHeader {
+    pub fn parse_type_ii(input: &[u8]) -> IResult<&[u8], ErspanHeader> {
+        parse_erspan_type_ii_header(input)
+    }
+
+    pub fn parse_type_iii(input: &[u8]) -> IResult<&[u8], ErspanHeader> {
+        parse_erspan_type_iii_header(input)
+    }
+}
+
+named!(
+    pub parse_erspan_type_ii_header<ErspanHeader>,
+    bits!(do_parse!(
+                            tag_bits!(u8, 4, 1) >>
+        vlan:               take_bits!(u16, 12) >>
+        class_of_service:   take_bits!(u8, 3) >>
+        encapsulation_type: take_bits!(u8, 2) >>
+        truncated:          take_bool >>
+        session_id:         take_bits!(u16, 10) >>
+                            take_bits!(u16, 12) >>
+        index:              take_bits!(u32, 20) >>
+
+        (ErspanHeader {
+            vlan,
+            class_of_service,
+            truncated,
+            session_id,
+            details: ErspanDetails::TypeII {
+                encapsulation_type,
+                index,
+            },
+        })
+    ))
+);
+
+named!(
+    pub parse_erspan_type_iii_header<ErspanHeader>,
+    bits!(do_parse!(
+                               tag_bits!(u8, 4, 2) >>
+        vlan:                  take_bits!(u16, 12) >>
+        class_of_service:      take_bits!(u8, 3) >>
+        bad_or_short:          take_bits!(u8, 2) >>
+        truncated:             take_bool >>
+        session_id:            take_bits!(u16, 10) >>
+        timestamp:             bytes!(be_u32) >>
+        security_group_tag:    bytes!(be_u16) >>
+                               take_bool >>
+        frame_type:            take_bits!(u8, 5) >>
+        hardware_id:           take_bits!(u8, 6) >>
+        egress:                take_bool >>
+        timestamp_granularity: take_bits!(u8, 2) >>
+        optional_present:      take_bool >>
+        platform_specific:     bytes!(cond!(optional_present, take!(8))) >>
+
+        (ErspanHeader {
+            vlan,
+            class_of_service,
+            truncated,
+            session_id,
+            details: ErspanDetails::TypeIII {
+                bad_or_short,
+                timestamp,
+                security_group_tag,
+                frame_type,
+                hardware_id,
+                egress,
+                timestamp_granularity,
+                platform_specific: platform_specific.map(|value| value.to_vec()),
+            },
+        })
+    ))
+);
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use ether::EtherType;
+    use gre::{ErspanDetails, ErspanHeader, GreFlags, GreHeader};
+
+    #[test]
+    fn parse() {
+        let raw = hex::decode("00000800").unwrap();
+        let (rest, header) = GreHeader::parse(&raw).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            header,
+            GreHeader {
+                flags: GreFlags {
+                    checksum_present: false,
+                    routing_present: false,
+                    key_present: false,
+                    sequence_present: false,
+                    strict_source_route: false,
+                    recursion_control: 0,
+                    acknowledgment_present: false,
+                    flags: 0,
+                    version: 0,
+                },
+                protocol_type: EtherType::IP,
+                checksum: None,
+                offset: None,
+                key: None,
+                sequence_number: None,
+                acknowledgment_number: None,
+                routing: None,
+            }
+        )
+    }
+
+    #[test]
+    fn parse_with_optional_fields() {
+        let raw = hex::decode("b0006558abcd0000000004d20000002a").unwrap();
+        let (rest, header) = GreHeader::parse(&raw).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(header.protocol_type, EtherType::TransparentEthernetBridging);
+        assert_eq!(header.checksum, Some(0xabcd));
+        assert_eq!(header.offset, Some(0));
+        assert_eq!(header.key, Some(1234));
+        assert_eq!(header.sequence_number, Some(42));
+    }
+
+    #[test]
+    fn parse_source_routes() {
+        let raw = hex::decode("c00008000000000000010304c0a8000100000000").unwrap();
+        let (rest, header) = GreHeader::parse(&raw).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            header.routing,
+            Some(hex::decode("00010304c0a8000100000000").unwrap())
+        );
+    }
+
+    #[test]
+    fn parse_erspan_type_ii() {
+        let raw = hex::decode("100a30640000002a").unwrap();
+        let (rest, header) = ErspanHeader::parse_type_ii(&raw).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            header,
+            ErspanHeader {
+                vlan: 10,
+                class_of_service: 1,
+                truncated: false,
+                session_id: 100,
+                details: ErspanDetails::TypeII {
+                    encapsulation_type: 2,
+                    index: 42,
+                },
+            }
+        )
+    }
+
+    #[test]
+    fn parse_erspan_type_iii() {
+        let raw = hex::decode("20142465000003e800070546").unwrap();
+        let (rest, header) = ErspanHeader::parse_type_iii(&raw).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            header,
+            ErspanHeader {
+                vlan: 20,
+                class_of_service: 1,
+                truncated: true,
+                session_id: 101,
+                details: ErspanDetails::TypeIII {
+                    bad_or_short: 0,
+                    timestamp: 1000,
+                    security_group_tag: 7,
+                    frame_type: 1,
+                    hardware_id: 20,
+                    egress: false,
+                    timestamp_granularity: 3,
+                    platform_specific: None,
+                },
+            }
+        )
+    }
+}
@@ -0,0 +1,396 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flow::Endpoint;
+use http::{HttpHead, HttpMessage, HttpTransaction, StartLine};
+use serde_json::Value;
+use std::str;
+use std::time::Duration;
+use util::format_timestamp;
+
+/// An HTTP transaction along with the connection it was sent over.
+#[derive(Clone, Copy, Debug)]
+pub struct HarEntry<'a> {
+    stream: usize,
+    server: Endpoint,
+
+    /// Whether the transaction was decrypted from TLS, so its URL is https.
+    decrypted: bool,
+    transaction: &'a HttpTransaction,
+}
+
+impl<'a> HarEntry<'a> {
+    pub fn new(
+        stream: usize,
+        server: Endpoint,
+        decrypted: bool,
+        transaction: &'a HttpTransaction,
+    ) -> HarEntry<'a> {
+        HarEntry {
+            stream,
+            server,
+            decrypted,
+            transaction,
+        }
+    }
+}
+
+/// Builds a HAR 1.2 log. Transactions whose request wasn't captured are left
+/// out, and requests without a response get status 0 the way browsers
+/// record failed requests. Bodies are only included when `bodies` is set.
+/// Timings other than send, wait and receive can't be told from the
+/// capture and are -1.
+pub fn to_har(entries: &[HarEntry], bodies: bool) -> Value {
+    let entries: Vec<Value> = entries
+        .iter()
+        .filter_map(|entry| {
+            let request = entry.transaction.request()?;
+            let response = entry.transaction.response();
+
+            let send = between(request.first_seen(), request.last_seen());
+            let wait = response.map_or(0.0, |response| {
+                between(request.last_seen(), response.first_seen())
+            });
+            let receive = response.map_or(0.0, |response| {
+                between(response.first_seen(), response.last_seen())
+            });
+
+            Some(json!({
+                "startedDateTime": format_timestamp(request.first_seen()),
+                "time": send + wait + receive,
+                "request": request_json(request, entry, bodies),
+                "response": response_json(response, bodies),
+                "cache": {},
+                "timings": {
+                    "blocked": -1,
+                    "dns": -1,
+                    "connect": -1,
+                    "ssl": -1,
+                    "send": send,
+                    "wait": wait,
+                    "receive": receive,
+                },
+                "serverIPAddress": entry.server.address().to_string(),
+                "connection": entry.stream.to_string(),
+            }))
+        })
+        .collect();
+
+    json!({
+        "log": {
+            "version": "1.2",
+            "creator": {
+                "name": env!("CARGO_PKG_NAME"),
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "entries": entries,
+        }
+    })
+}
+
+/// Milliseconds from `start` to `end`.
+fn between(start: Duration, end: Duration) -> f64 {
+    let elapsed = end.checked_sub(start).unwrap_or_default();
+    (elapsed.as_secs_f64() * 1_000_000.0).round() / 1000.0
+}
+
+fn request_json(request: &HttpMessage, entry: &HarEntry, bodies: bool) -> Value {
+    let head = request.head();
+    let (method, target, version) = match head.start_line() {
+        StartLine::Request {
+            method,
+            target,
+            version,
+        } => (method.as_str(), target.as_str(), version.as_str()),
+        StartLine::Response { version, .. } => ("", "", version.as_str()),
+    };
+
+    let url = if target.starts_with("http://") || target.starts_with("https://") {
+        target.to_string()
+    } else {
+        let host = head
+            .header("Host")
+            .map(str::to_string)
+            .unwrap_or_else(|| entry.server.to_string());
+        let scheme = if entry.decrypted { "https" } else { "http" };
+        format!("{}://{}{}", scheme, host, target)
+    };
+
+    let query_string: Vec<Value> = target
+        .split_once('?')
+        .map(|(_, query)| {
+            query
+                .split('&')
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                    json!({ "name": name, "value": value })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let cookies: Vec<Value> = head
+        .headers()
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Cookie"))
+        .flat_map(|(_, value)| value.split(';'))
+        .filter_map(cookie_json)
+        .collect();
+
+    let mut json = json!({
+        "method": method,
+        "url": url,
+        "httpVersion": version,
+        "cookies": cookies,
+        "headers": headers_json(head),
+        "queryString": query_string,
+        "headersSize": request.body_start() - request.start(),
+        "bodySize": request.end() - request.body_start(),
+    });
+
+    if bodies && !request.body().is_empty() {
+        let mut post_data = json!({ "mimeType": head.header("Content-Type").unwrap_or_default() });
+        match str::from_utf8(request.body()) {
+            Ok(text) => post_data["text"] = json!(text),
+            Err(_) => {
+                // Unlike content, postData has no encoding field, so binary
+                // bodies go in the comment rather than being taken as text.
+                post_data["text"] = json!("");
+                post_data["comment"] = json!(format!(
+                    "Binary body, base64 encoded: {}",
+                    STANDARD.encode(request.body())
+                ));
+            }
+        }
+        json["postData"] = post_data;
+    }
+
+    json
+}
+
+fn response_json(response: Option<&HttpMessage>, bodies: bool) -> Value {
+    let response = match response {
+        Some(response) => response,
+        None => {
+            return json!({
+                "status": 0,
+                "statusText": "",
+                "httpVersion": "",
+                "cookies": [],
+                "headers": [],
+                "content": { "size": 0, "mimeType": "" },
+                "redirectURL": "",
+                "headersSize": -1,
+                "bodySize": -1,
+            })
+        }
+    };
+
+    let head = response.head();
+    let (version, status, reason) = match head.start_line() {
+        StartLine::Response {
+            version,
+            status,
+            reason,
+        } => (version.as_str(), *status, reason.as_str()),
+        StartLine::Request { version, .. } => (version.as_str(), 0, ""),
+    };
+
+    let cookies: Vec<Value> = head
+        .headers()
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Set-Cookie"))
+        .filter_map(|(_, value)| cookie_json(value.split(';').next().unwrap_or_default()))
+        .collect();
+
+    let mut content = json!({
+        "size": response.body().len(),
+        "mimeType": head.header("Content-Type").unwrap_or_default(),
+    });
+    if bodies && !response.body().is_empty() {
+        match str::from_utf8(response.body()) {
+            Ok(text) => content["text"] = json!(text),
+            Err(_) => {
+                content["text"] = json!(STANDARD.encode(response.body()));
+                content["encoding"] = json!("base64");
+            }
+        }
+    }
+
+    json!({
+        "status": status,
+        "statusText": reason,
+        "httpVersion": version,
+        "cookies": cookies,
+        "headers": headers_json(head),
+        "content": content,
+        "redirectURL": head.header("Location").unwrap_or_default(),
+        "headersSize": response.body_start() - response.start(),
+        "bodySize": response.end() - response.body_start(),
+    })
+}
+
+fn headers_json(head: &HttpHead) -> Vec<Value> {
+    head.headers()
+        .iter()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect()
+}
+
+fn cookie_json(cookie: &str) -> Option<Value> {
+    let (name, value) = cookie.split_once('=')?;
+    Some(json!({ "name": name.trim(), "value": value.trim() }))
+}
+
+#[cfg(test)]
+mod tests {
+    use flow::tests::{ip_header, tcp_header};
+    use flow::{Direction, Endpoint, FlowTable, TcpSegment};
+    use har::{to_har, HarEntry};
+    use http::{HttpAnalysis, HttpConversation};
+    use std::time::Duration;
+
+    #[test]
+    fn export() {
+        let mut conversation = HttpConversation::new();
+        let start = Duration::from_secs(1_248_463_230);
+        conversation.push(
+            Direction::ClientToServer,
+            b"POST /form?a=1&b HTTP/1.1\r\nHost: example.com\r\nCookie: id=7; x=y\r\n\
+              Content-Length: 2\r\n\r\nhi",
+            start,
+        );
+        conversation.push(
+            Direction::ServerToClient,
+            b"HTTP/1.1 302 Found\r\nLocation: /done\r\nSet-Cookie: s=1; Path=/\r\n\
+              Content-Length: 2\r\n\r\n\xff\xfe",
+            start + Duration::from_millis(25),
+        );
+        conversation.push(
+            Direction::ClientToServer,
+            b"GET /other HTTP/1.1\r\n\r\n",
+            start + Duration::from_millis(40),
+        );
+        conversation.finish();
+
+        let server = Endpoint::new("10.0.0.2".parse().unwrap(), 80);
+        let entries: Vec<HarEntry> = conversation
+            .transactions()
+            .iter()
+            .map(|transaction| HarEntry::new(3, server, false, transaction))
+            .collect();
+        let har = to_har(&entries, true);
+
+        let log = &har["log"];
+        assert_eq!(log["version"], "1.2");
+        assert_eq!(log["entries"].as_array().unwrap().len(), 2);
+
+        let entry = &log["entries"][0];
+        assert_eq!(entry["startedDateTime"], "2009-07-24T19:20:30.000Z");
+        assert_eq!(entry["time"], 25.0);
+        assert_eq!(entry["timings"]["wait"], 25.0);
+        assert_eq!(entry["serverIPAddress"], "10.0.0.2");
+        assert_eq!(entry["connection"], "3");
+
+        let request = &entry["request"];
+        assert_eq!(request["url"], "http://example.com/form?a=1&b");
+        assert_eq!(
+            request["queryString"],
+            json!([{ "name": "a", "value": "1" }, { "name": "b", "value": "" }])
+        );
+        assert_eq!(
+            request["cookies"],
+            json!([{ "name": "id", "value": "7" }, { "name": "x", "value": "y" }])
+        );
+        assert_eq!(request["postData"]["text"], "hi");
+        assert_eq!(request["bodySize"], 2);
+
+        let response = &entry["response"];
+        assert_eq!(response["status"], 302);
+        assert_eq!(response["redirectURL"], "/done");
+        assert_eq!(response["cookies"], json!([{ "name": "s", "value": "1" }]));
+        assert_eq!(
+            response["content"],
+            json!({ "size": 2, "mimeType": "", "text": "//4=", "encoding": "base64" })
+        );
+
+        let unanswered = &log["entries"][1];
+        assert_eq!(unanswered["request"]["url"], "http://10.0.0.2:80/other");
+        assert_eq!(unanswered["response"]["status"], 0);
+    }
+
+    #[test]
+    fn export_binary_post() {
+        let mut conversation = HttpConversation::new();
+        let start = Duration::from_secs(1_248_463_230);
+        conversation.push(
+            Direction::ClientToServer,
+            b"POST /upload HTTP/1.1\r\nHost: example.com\r\n\
+              Content-Type: application/octet-stream\r\nContent-Length: 3\r\n\r\n\x00\xff\x10",
+            start,
+        );
+        conversation.finish();
+
+        let server = Endpoint::new("10.0.0.2".parse().unwrap(), 80);
+        let entries: Vec<HarEntry> = conversation
+            .transactions()
+            .iter()
+            .map(|transaction| HarEntry::new(0, server, false, transaction))
+            .collect();
+        let har = to_har(&entries, true);
+
+        assert_eq!(
+            har["log"]["entries"][0]["request"]["postData"],
+            json!({
+                "mimeType": "application/octet-stream",
+                "text": "",
+                "comment": "Binary body, base64 encoded: AP8Q",
+            })
+        );
+    }
+
+    #[test]
+    fn export_decrypted() {
+        let mut flows = FlowTable::new();
+        let syn = tcp_header(40000, 443, 100, 0, 0x02);
+        let at = Duration::from_secs(1_248_463_230);
+        let (stream, _) = flows.lookup(&TcpSegment::new(at, &ip_header(true), &syn, &[]));
+        let flow = &flows.flows()[stream];
+
+        let mut analysis = HttpAnalysis::new();
+        analysis.push_decrypted(
+            stream,
+            flow,
+            Direction::ClientToServer,
+            b"GET /account HTTP/1.1\r\nHost: example.com\r\n\r\n",
+            at,
+        );
+        analysis.push_decrypted(
+            stream,
+            flow,
+            Direction::ServerToClient,
+            b"HTTP/1.1 204 No Content\r\n\r\n",
+            at + Duration::from_millis(10),
+        );
+        analysis.finish();
+        assert!(analysis.is_decrypted(stream));
+
+        let entries: Vec<HarEntry> = analysis
+            .transactions()
+            .into_iter()
+            .map(|(stream, transaction)| {
+                HarEntry::new(
+                    stream,
+                    flow.server(),
+                    analysis.is_decrypted(stream),
+                    transaction,
+                )
+            })
+            .collect();
+        let har = to_har(&entries, false);
+
+        let entry = &har["log"]["entries"][0];
+        assert_eq!(entry["request"]["url"], "https://example.com/account");
+        assert_eq!(entry["response"]["status"], 204);
+    }
+}
@@ -0,0 +1,508 @@
+use std::collections::VecDeque;
+
+/// The size of the dynamic table a decoder starts with.
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+
+/// Each dynamic table entry counts this much on top of its name and value.
+const ENTRY_OVERHEAD: usize = 32;
+
+const EOS: usize = 256;
+
+#[derive(Fail, Debug, Eq, PartialEq)]
+pub enum HpackError {
+    #[fail(display = "Header block ended early")]
+    Truncated,
+
+    #[fail(display = "Integer in header block is too large")]
+    IntegerOverflow,
+
+    #[fail(display = "Invalid header table index {}", _0)]
+    InvalidIndex(usize),
+
+    #[fail(display = "Invalid Huffman encoded string")]
+    InvalidHuffman,
+
+    #[fail(display = "Dynamic table size {} is over the limit", _0)]
+    InvalidTableSize(usize),
+}
+
+/// The static table from RFC 7541 appendix A. Index 1 is the first entry.
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// The Huffman code and its length in bits for each byte, followed by the
+/// end of string symbol, from RFC 7541 appendix B.
+#[rustfmt::skip]
+const HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13), (0x7fffd8, 23), (0xfffffe2, 28), (0xfffffe3, 28), (0xfffffe4, 28),
+    (0xfffffe5, 28), (0xfffffe6, 28), (0xfffffe7, 28), (0xfffffe8, 28), (0xffffea, 24),
+    (0x3ffffffc, 30), (0xfffffe9, 28), (0xfffffea, 28), (0x3ffffffd, 30), (0xfffffeb, 28),
+    (0xfffffec, 28), (0xfffffed, 28), (0xfffffee, 28), (0xfffffef, 28), (0xffffff0, 28),
+    (0xffffff1, 28), (0xffffff2, 28), (0x3ffffffe, 30), (0xffffff3, 28), (0xffffff4, 28),
+    (0xffffff5, 28), (0xffffff6, 28), (0xffffff7, 28), (0xffffff8, 28), (0xffffff9, 28),
+    (0xffffffa, 28), (0xffffffb, 28), (0x14, 6), (0x3f8, 10), (0x3f9, 10), (0xffa, 12),
+    (0x1ff9, 13), (0x15, 6), (0xf8, 8), (0x7fa, 11), (0x3fa, 10), (0x3fb, 10), (0xf9, 8),
+    (0x7fb, 11), (0xfa, 8), (0x16, 6), (0x17, 6), (0x18, 6), (0x0, 5), (0x1, 5), (0x2, 5),
+    (0x19, 6), (0x1a, 6), (0x1b, 6), (0x1c, 6), (0x1d, 6), (0x1e, 6), (0x1f, 6), (0x5c, 7),
+    (0xfb, 8), (0x7ffc, 15), (0x20, 6), (0xffb, 12), (0x3fc, 10), (0x1ffa, 13), (0x21, 6),
+    (0x5d, 7), (0x5e, 7), (0x5f, 7), (0x60, 7), (0x61, 7), (0x62, 7), (0x63, 7), (0x64, 7),
+    (0x65, 7), (0x66, 7), (0x67, 7), (0x68, 7), (0x69, 7), (0x6a, 7), (0x6b, 7), (0x6c, 7),
+    (0x6d, 7), (0x6e, 7), (0x6f, 7), (0x70, 7), (0x71, 7), (0x72, 7), (0xfc, 8), (0x73, 7),
+    (0xfd, 8), (0x1ffb, 13), (0x7fff0, 19), (0x1ffc, 13), (0x3ffc, 14), (0x22, 6), (0x7ffd, 15),
+    (0x3, 5), (0x23, 6), (0x4, 5), (0x24, 6), (0x5, 5), (0x25, 6), (0x26, 6), (0x27, 6), (0x6, 5),
+    (0x74, 7), (0x75, 7), (0x28, 6), (0x29, 6), (0x2a, 6), (0x7, 5), (0x2b, 6), (0x76, 7),
+    (0x2c, 6), (0x8, 5), (0x9, 5), (0x2d, 6), (0x77, 7), (0x78, 7), (0x79, 7), (0x7a, 7), (0x7b, 7),
+    (0x7ffe, 15), (0x7fc, 11), (0x3ffd, 14), (0x1ffd, 13), (0xffffffc, 28), (0xfffe6, 20),
+    (0x3fffd2, 22), (0xfffe7, 20), (0xfffe8, 20), (0x3fffd3, 22), (0x3fffd4, 22), (0x3fffd5, 22),
+    (0x7fffd9, 23), (0x3fffd6, 22), (0x7fffda, 23), (0x7fffdb, 23), (0x7fffdc, 23), (0x7fffdd, 23),
+    (0x7fffde, 23), (0xffffeb, 24), (0x7fffdf, 23), (0xffffec, 24), (0xffffed, 24), (0x3fffd7, 22),
+    (0x7fffe0, 23), (0xffffee, 24), (0x7fffe1, 23), (0x7fffe2, 23), (0x7fffe3, 23), (0x7fffe4, 23),
+    (0x1fffdc, 21), (0x3fffd8, 22), (0x7fffe5, 23), (0x3fffd9, 22), (0x7fffe6, 23), (0x7fffe7, 23),
+    (0xffffef, 24), (0x3fffda, 22), (0x1fffdd, 21), (0xfffe9, 20), (0x3fffdb, 22), (0x3fffdc, 22),
+    (0x7fffe8, 23), (0x7fffe9, 23), (0x1fffde, 21), (0x7fffea, 23), (0x3fffdd, 22), (0x3fffde, 22),
+    (0xfffff0, 24), (0x1fffdf, 21), (0x3fffdf, 22), (0x7fffeb, 23), (0x7fffec, 23), (0x1fffe0, 21),
+    (0x1fffe1, 21), (0x3fffe0, 22), (0x1fffe2, 21), (0x7fffed, 23), (0x3fffe1, 22), (0x7fffee, 23),
+    (0x7fffef, 23), (0xfffea, 20), (0x3fffe2, 22), (0x3fffe3, 22), (0x3fffe4, 22), (0x7ffff0, 23),
+    (0x3fffe5, 22), (0x3fffe6, 22), (0x7ffff1, 23), (0x3ffffe0, 26), (0x3ffffe1, 26), (0xfffeb, 20),
+    (0x7fff1, 19), (0x3fffe7, 22), (0x7ffff2, 23), (0x3fffe8, 22), (0x1ffffec, 25), (0x3ffffe2, 26),
+    (0x3ffffe3, 26), (0x3ffffe4, 26), (0x7ffffde, 27), (0x7ffffdf, 27), (0x3ffffe5, 26),
+    (0xfffff1, 24), (0x1ffffed, 25), (0x7fff2, 19), (0x1fffe3, 21), (0x3ffffe6, 26),
+    (0x7ffffe0, 27), (0x7ffffe1, 27), (0x3ffffe7, 26), (0x7ffffe2, 27), (0xfffff2, 24),
+    (0x1fffe4, 21), (0x1fffe5, 21), (0x3ffffe8, 26), (0x3ffffe9, 26), (0xffffffd, 28),
+    (0x7ffffe3, 27), (0x7ffffe4, 27), (0x7ffffe5, 27), (0xfffec, 20), (0xfffff3, 24), (0xfffed, 20),
+    (0x1fffe6, 21), (0x3fffe9, 22), (0x1fffe7, 21), (0x1fffe8, 21), (0x7ffff3, 23), (0x3fffea, 22),
+    (0x3fffeb, 22), (0x1ffffee, 25), (0x1ffffef, 25), (0xfffff4, 24), (0xfffff5, 24),
+    (0x3ffffea, 26), (0x7ffff4, 23), (0x3ffffeb, 26), (0x7ffffe6, 27), (0x3ffffec, 26),
+    (0x3ffffed, 26), (0x7ffffe7, 27), (0x7ffffe8, 27), (0x7ffffe9, 27), (0x7ffffea, 27),
+    (0x7ffffeb, 27), (0xffffffe, 28), (0x7ffffec, 27), (0x7ffffed, 27), (0x7ffffee, 27),
+    (0x7ffffef, 27), (0x7fffff0, 27), (0x3ffffee, 26), (0x3fffffff, 30),
+];
+
+/// Marks an entry of `HUFFMAN_TREE` as a symbol rather than another node.
+const LEAF: u16 = 0x8000;
+
+/// The Huffman codes as a binary tree, built once at compile time. Each node
+/// holds the entries to follow for a 0 bit and a 1 bit, either the index of
+/// another node or a symbol marked with `LEAF`. Node 0 is the root. The code
+/// is complete, so every entry is filled in.
+static HUFFMAN_TREE: [[u16; 2]; EOS] = build_huffman_tree();
+
+const fn build_huffman_tree() -> [[u16; 2]; EOS] {
+    let mut tree = [[0; 2]; EOS];
+    let mut nodes = 1;
+
+    let mut symbol = 0;
+    while symbol < HUFFMAN_CODES.len() {
+        let (code, length) = HUFFMAN_CODES[symbol];
+        let mut node = 0;
+        let mut bit = length - 1;
+        while bit > 0 {
+            let branch = (code >> bit & 1) as usize;
+            if tree[node][branch] == 0 {
+                tree[node][branch] = nodes;
+                nodes += 1;
+            }
+            node = tree[node][branch] as usize;
+            bit -= 1;
+        }
+        tree[node][(code & 1) as usize] = LEAF | symbol as u16;
+
+        symbol += 1;
+    }
+
+    tree
+}
+
+/// Decodes a Huffman encoded string by walking `HUFFMAN_TREE` one bit at a time.
+fn decode_huffman(input: &[u8]) -> Result<Vec<u8>, HpackError> {
+    let mut output = Vec::new();
+    let mut node = 0;
+    let mut code = 0u32;
+    let mut length = 0u8;
+
+    for &byte in input {
+        for shift in (0..8).rev() {
+            let bit = byte >> shift & 1;
+            code = code << 1 | u32::from(bit);
+            length += 1;
+
+            let next = HUFFMAN_TREE[node][usize::from(bit)];
+            if next & LEAF == 0 {
+                node = usize::from(next);
+                continue;
+            }
+
+            match usize::from(next & !LEAF) {
+                EOS => return Err(HpackError::InvalidHuffman),
+                symbol => output.push(symbol as u8),
+            }
+            node = 0;
+            code = 0;
+            length = 0;
+        }
+    }
+
+    // Padding is the start of the end of string code, so it's all ones and
+    // shorter than a byte.
+    if length >= 8 || code != (1 << length) - 1 {
+        return Err(HpackError::InvalidHuffman);
+    }
+
+    Ok(output)
+}
+
+/// Reads the primitives of a header block.
+struct BlockReader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> BlockReader<'a> {
+    fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    /// Reads an integer with an `prefix` bit prefix, returning the bits of
+    /// the first byte above the prefix too.
+    fn integer(&mut self, prefix: u8) -> Result<(u8, usize), HpackError> {
+        let (&first, rest) = self.input.split_first().ok_or(HpackError::Truncated)?;
+        self.input = rest;
+
+        let mask = (1u16 << prefix) as u8 - 1;
+        let flags = first & !mask;
+        let mut value = (first & mask) as usize;
+        if value < mask as usize {
+            return Ok((flags, value));
+        }
+
+        let mut shift = 0;
+        loop {
+            let (&byte, rest) = self.input.split_first().ok_or(HpackError::Truncated)?;
+            self.input = rest;
+            if shift > 28 {
+                return Err(HpackError::IntegerOverflow);
+            }
+
+            value += ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok((flags, value));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, HpackError> {
+        let (flags, length) = self.integer(7)?;
+        if self.input.len() < length {
+            return Err(HpackError::Truncated);
+        }
+
+        let (bytes, rest) = self.input.split_at(length);
+        self.input = rest;
+        let bytes = if flags & 0x80 != 0 {
+            decode_huffman(bytes)?
+        } else {
+            bytes.to_vec()
+        };
+
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+/// Decompresses the header blocks sent in one direction of an HTTP/2
+/// connection. The dynamic table is carried from one block to the next, so
+/// every block has to be decoded in the order it was sent.
+#[derive(Debug)]
+pub struct Decoder {
+    /// The dynamic table, with the newest entry first.
+    table: VecDeque<(String, String)>,
+    size: usize,
+    maximum_size: usize,
+
+    /// The largest size the encoder is allowed to pick.
+    limit: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Decoder {
+        Decoder {
+            table: VecDeque::new(),
+            size: 0,
+            maximum_size: DEFAULT_TABLE_SIZE,
+            limit: DEFAULT_TABLE_SIZE,
+        }
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Sets the largest dynamic table the encoder may use, which the peer
+    /// decoding its headers announces in SETTINGS_HEADER_TABLE_SIZE.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// Decodes a whole header block into names and values.
+    pub fn decode(&mut self, block: &[u8]) -> Result<Vec<(String, String)>, HpackError> {
+        let mut reader = BlockReader { input: block };
+        let mut headers = Vec::new();
+
+        while !reader.is_empty() {
+            let first = reader.input[0];
+            if first & 0x80 != 0 {
+                let (_, index) = reader.integer(7)?;
+                headers.push(self.entry(index)?);
+            } else if first & 0xc0 == 0x40 {
+                let header = self.literal(&mut reader, 6)?;
+                self.insert(header.clone());
+                headers.push(header);
+            } else if first & 0xe0 == 0x20 {
+                let (_, size) = reader.integer(5)?;
+                if size > self.limit {
+                    return Err(HpackError::InvalidTableSize(size));
+                }
+                self.maximum_size = size;
+                self.evict(0);
+            } else {
+                // Literals without indexing and never indexed literals only
+                // differ in what intermediaries may do with them.
+                headers.push(self.literal(&mut reader, 4)?);
+            }
+        }
+
+        Ok(headers)
+    }
+
+    fn entry(&self, index: usize) -> Result<(String, String), HpackError> {
+        match index {
+            0 => Err(HpackError::InvalidIndex(index)),
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Ok((name.to_string(), value.to_string()))
+            }
+            _ => self
+                .table
+                .get(index - 62)
+                .cloned()
+                .ok_or(HpackError::InvalidIndex(index)),
+        }
+    }
+
+    /// Reads a literal header whose name is indexed with a `prefix` bit
+    /// integer, or follows as a string when the index is 0.
+    fn literal(
+        &self,
+        reader: &mut BlockReader,
+        prefix: u8,
+    ) -> Result<(String, String), HpackError> {
+        let (_, index) = reader.integer(prefix)?;
+        let name = match index {
+            0 => reader.string()?,
+            _ => self.entry(index)?.0,
+        };
+
+        Ok((name, reader.string()?))
+    }
+
+    fn insert(&mut self, header: (String, String)) {
+        let size = header.0.len() + header.1.len() + ENTRY_OVERHEAD;
+        self.evict(size);
+
+        // An entry larger than the whole table just empties it.
+        if size <= self.maximum_size {
+            self.size += size;
+            self.table.push_front(header);
+        }
+    }
+
+    /// Drops the oldest entries until there is room for `space` more bytes.
+    fn evict(&mut self, space: usize) {
+        while self.size + space > self.maximum_size {
+            match self.table.pop_back() {
+                Some((name, value)) => self.size -= name.len() + value.len() + ENTRY_OVERHEAD,
+                None => break,
+            }
+        }
+    }
+
+    /// The size of the dynamic table as HPACK counts it.
+    pub fn table_size(&self) -> usize {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use hpack::{decode_huffman, Decoder, HpackError, EOS, HUFFMAN_CODES};
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// The requests with Huffman encoding from RFC 7541 appendix C.4.
+    #[test]
+    fn decode() {
+        let mut decoder = Decoder::new();
+
+        let block = hex::decode("828684418cf1e3c2e5f23a6ba0ab90f4ff").unwrap();
+        assert_eq!(
+            decoder.decode(&block),
+            Ok(headers(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ]))
+        );
+        assert_eq!(decoder.table_size(), 57);
+
+        let block = hex::decode("828684be5886a8eb10649cbf").unwrap();
+        assert_eq!(
+            decoder.decode(&block),
+            Ok(headers(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache"),
+            ]))
+        );
+        assert_eq!(decoder.table_size(), 110);
+
+        let block = hex::decode("828785bf408825a849e95ba97d7f8925a849e95bb8e8b4bf").unwrap();
+        assert_eq!(
+            decoder.decode(&block),
+            Ok(headers(&[
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value"),
+            ]))
+        );
+        assert_eq!(decoder.table_size(), 164);
+
+        // Shrinking the table to nothing evicts every entry.
+        assert_eq!(decoder.decode(&[0x20]), Ok(Vec::new()));
+        assert_eq!(decoder.table_size(), 0);
+        assert_eq!(decoder.decode(&[0xbe]), Err(HpackError::InvalidIndex(62)));
+    }
+
+    /// Every byte value, Huffman encoded in one string with the codes from
+    /// the table, decodes back to itself.
+    #[test]
+    fn decode_every_huffman_code() {
+        let mut encoded = Vec::new();
+        let mut bits = 0u64;
+        let mut length = 0;
+        for &(code, code_length) in &HUFFMAN_CODES[..EOS] {
+            bits = bits << code_length | u64::from(code);
+            length += code_length;
+            while length >= 8 {
+                length -= 8;
+                encoded.push((bits >> length) as u8);
+            }
+        }
+        if length > 0 {
+            let padding = (1 << (8 - length)) - 1;
+            encoded.push((bits << (8 - length) | padding) as u8);
+        }
+
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode_huffman(&encoded), Ok(bytes));
+    }
+
+    #[test]
+    fn invalid() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.decode(&[0x80]), Err(HpackError::InvalidIndex(0)));
+        assert_eq!(
+            decoder.decode(&[0x41, 0x05, b'a']),
+            Err(HpackError::Truncated)
+        );
+        assert_eq!(
+            decoder.decode(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            Err(HpackError::Truncated)
+        );
+
+        // A string padded with more than seven bits.
+        assert_eq!(
+            decoder.decode(&[0x41, 0x82, 0xff, 0xff]),
+            Err(HpackError::InvalidHuffman)
+        );
+
+        decoder.set_limit(100);
+        assert_eq!(
+            decoder.decode(&[0x3f, 0x46]),
+            Err(HpackError::InvalidTableSize(101))
+        );
+    }
+}
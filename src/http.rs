@@ -0,0 +1,1171 @@
+use flow::{Direction, Flow, TcpSegment};
+use http2::looks_like_http2;
+use reassembly::{ReassembledStreams, StreamHandler};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Formatter;
+use std::mem;
+use std::time::Duration;
+use tls::looks_like_tls;
+use websocket::WebSocketConnection;
+
+/// Ports HTTP is assumed to run on. Connections on other ports are decoded
+/// when they start with something that looks like HTTP.
+pub const HTTP_PORTS: [u16; 5] = [80, 3128, 8000, 8008, 8080];
+
+const METHODS: [&str; 9] = [
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+/// Heads longer than this are taken to be something other than HTTP.
+const MAXIMUM_HEAD_LENGTH: usize = 64 * 1024;
+
+#[derive(Fail, Debug, Eq, PartialEq)]
+pub enum HttpError {
+    #[fail(display = "Invalid HTTP start line")]
+    InvalidStartLine,
+
+    #[fail(display = "Invalid HTTP header")]
+    InvalidHeader,
+
+    #[fail(display = "HTTP head is too long")]
+    HeadTooLong,
+
+    #[fail(display = "Invalid Content-Length")]
+    InvalidContentLength,
+
+    #[fail(display = "Invalid chunk size")]
+    InvalidChunkSize,
+}
+
+/// Whether `data` starts with an HTTP/1.x request or status line.
+pub fn looks_like_http(data: &[u8]) -> bool {
+    data.starts_with(b"HTTP/1.")
+        || METHODS.iter().any(|method| {
+            data.starts_with(method.as_bytes()) && data.get(method.len()) == Some(&b' ')
+        })
+}
+
+/// Whether `data` starts with a whole HTTP/1.x request or status line.
+fn starts_with_start_line(data: &[u8]) -> bool {
+    let end = match data.iter().position(|&byte| byte == b'\n') {
+        Some(end) => end,
+        None => return false,
+    };
+    let line = String::from_utf8_lossy(&data[..end]);
+    let version = match StartLine::parse(line.trim_end_matches('\r')) {
+        Ok(StartLine::Request { version, .. }) | Ok(StartLine::Response { version, .. }) => version,
+        Err(_) => return false,
+    };
+
+    looks_like_http(data) && version.starts_with("HTTP/1.")
+}
+
+/// Whether `data` could be the start of an HTTP/1.x request or status line
+/// whose end hasn't arrived yet.
+fn could_start_http(data: &[u8]) -> bool {
+    let candidates = METHODS.iter().map(|method| format!("{} ", method));
+    let prefix_of_start = candidates
+        .chain(Some("HTTP/1.".to_string()))
+        .any(|start| start.as_bytes().starts_with(data));
+
+    !data.contains(&b'\n')
+        && data.len() < MAXIMUM_HEAD_LENGTH
+        && (prefix_of_start || looks_like_http(data))
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum StartLine {
+    Request {
+        method: String,
+        target: String,
+        version: String,
+    },
+    Response {
+        version: String,
+        status: u16,
+        reason: String,
+    },
+}
+
+impl StartLine {
+    fn parse(line: &str) -> Result<StartLine, HttpError> {
+        if line.starts_with("HTTP/") {
+            let mut parts = line.splitn(3, ' ');
+            let version = parts.next().unwrap_or_default();
+            let status = parts
+                .next()
+                .filter(|status| status.len() == 3)
+                .and_then(|status| status.parse().ok())
+                .ok_or(HttpError::InvalidStartLine)?;
+            let reason = parts.next().unwrap_or_default();
+
+            return Ok(StartLine::Response {
+                version: version.to_string(),
+                status,
+                reason: reason.to_string(),
+            });
+        }
+
+        let parts: Vec<&str> = line.split(' ').collect();
+        match parts.as_slice() {
+            [method, target, version]
+                if is_token(method) && !target.is_empty() && version.starts_with("HTTP/") =>
+            {
+                Ok(StartLine::Request {
+                    method: method.to_string(),
+                    target: target.to_string(),
+                    version: version.to_string(),
+                })
+            }
+            _ => Err(HttpError::InvalidStartLine),
+        }
+    }
+}
+
+impl fmt::Display for StartLine {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            StartLine::Request {
+                method,
+                target,
+                version,
+            } => write!(f, "{} {} {}", method, target, version),
+            StartLine::Response {
+                version,
+                status,
+                reason,
+            } => write!(f, "{} {} {}", version, status, reason),
+        }
+    }
+}
+
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+}
+
+/// The start line and headers of a request or response.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct HttpHead {
+    start_line: StartLine,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpHead {
+    /// Parses a head, returning its length in bytes along with it. Returns
+    /// `None` when the input ends before the head does.
+    pub fn parse(input: &[u8]) -> Result<Option<(usize, HttpHead)>, HttpError> {
+        if let Some(line_end) = input.iter().position(|&byte| byte == b'\n') {
+            let line = String::from_utf8_lossy(&input[..line_end]);
+            StartLine::parse(line.trim_end_matches('\r'))?;
+        }
+
+        let length = match head_length(input) {
+            Some(length) => length,
+            None if input.len() > MAXIMUM_HEAD_LENGTH => return Err(HttpError::HeadTooLong),
+            None => return Ok(None),
+        };
+
+        let text = String::from_utf8_lossy(&input[..length]);
+        let mut lines = text
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line));
+        let start_line = StartLine::parse(lines.next().unwrap_or_default())?;
+
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in lines.take_while(|line| !line.is_empty()) {
+            if line.starts_with(' ') || line.starts_with('\t') {
+                // A value continued on the next line, which RFC 7230 has
+                // deprecated.
+                let (_, value) = headers.last_mut().ok_or(HttpError::InvalidHeader)?;
+                value.push(' ');
+                value.push_str(line.trim());
+                continue;
+            }
+
+            let colon = line.find(':').ok_or(HttpError::InvalidHeader)?;
+            let name = &line[..colon];
+            if !is_token(name) {
+                return Err(HttpError::InvalidHeader);
+            }
+
+            headers.push((name.to_string(), line[colon + 1..].trim().to_string()));
+        }
+
+        Ok(Some((
+            length,
+            HttpHead {
+                start_line,
+                headers,
+            },
+        )))
+    }
+
+    pub fn start_line(&self) -> &StartLine {
+        &self.start_line
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn method(&self) -> Option<&str> {
+        match &self.start_line {
+            StartLine::Request { method, .. } => Some(method),
+            StartLine::Response { .. } => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        match self.start_line {
+            StartLine::Response { status, .. } => Some(status),
+            StartLine::Request { .. } => None,
+        }
+    }
+
+    fn is_chunked(&self) -> bool {
+        self.header("Transfer-Encoding").is_some_and(|codings| {
+            codings
+                .rsplit(',')
+                .next()
+                .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+        })
+    }
+
+    fn content_length(&self) -> Result<Option<u64>, HttpError> {
+        match self.header("Content-Length") {
+            // Repeated values are allowed as long as they agree.
+            Some(value) => {
+                let mut lengths = value.split(',').map(|length| length.trim().parse::<u64>());
+                let first = lengths.next().unwrap_or(Ok(0));
+                match first {
+                    Ok(first) if lengths.all(|length| length == Ok(first)) => Ok(Some(first)),
+                    _ => Err(HttpError::InvalidContentLength),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// How the body following this head ends, following RFC 7230 section
+    /// 3.3.3. `request_method` is the method of the request a response
+    /// answers.
+    fn framing(&self, request_method: Option<&str>) -> Result<Framing, HttpError> {
+        if let Some(status) = self.status() {
+            let bodiless = request_method == Some("HEAD")
+                || status < 200
+                || status == 204
+                || status == 304
+                || (request_method == Some("CONNECT") && status < 300);
+            if bodiless {
+                return Ok(Framing::None);
+            }
+        }
+
+        if self.is_chunked() {
+            return Ok(Framing::Chunked);
+        }
+
+        match (self.content_length()?, self.status()) {
+            (Some(length), _) => Ok(Framing::Length(length)),
+            (None, Some(_)) => Ok(Framing::UntilClose),
+            (None, None) => Ok(Framing::None),
+        }
+    }
+}
+
+impl fmt::Display for HttpHead {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "HTTP {}", self.start_line)
+    }
+}
+
+/// Length of the head at the start of `input`, including the empty line.
+/// Bare line feeds are accepted as line endings.
+fn head_length(input: &[u8]) -> Option<usize> {
+    input
+        .iter()
+        .enumerate()
+        .filter(|&(_, &byte)| byte == b'\n')
+        .find_map(|(index, _)| {
+            let rest = &input[index + 1..];
+            if rest.starts_with(b"\r\n") {
+                Some(index + 3)
+            } else if rest.starts_with(b"\n") {
+                Some(index + 2)
+            } else {
+                None
+            }
+        })
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Framing {
+    None,
+    Length(u64),
+    Chunked,
+    UntilClose,
+}
+
+/// A request or response read from a reassembled stream.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct HttpMessage {
+    head: HttpHead,
+
+    /// The body with any chunked encoding removed.
+    body: Vec<u8>,
+
+    /// Offsets in the stream of the start of the head, the start of the body
+    /// and the end of the message.
+    start: u64,
+    body_start: u64,
+    end: u64,
+
+    /// When the first and last bytes of the message were captured.
+    first_seen: Duration,
+    last_seen: Duration,
+
+    /// Whether the message ended before the stream did.
+    complete: bool,
+}
+
+impl HttpMessage {
+    pub fn head(&self) -> &HttpHead {
+        &self.head
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    pub fn body_start(&self) -> u64 {
+        self.body_start
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn first_seen(&self) -> Duration {
+        self.first_seen
+    }
+
+    pub fn last_seen(&self) -> Duration {
+        self.last_seen
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ParserState {
+    Head,
+    Body(u64),
+    ChunkSize,
+    ChunkData(u64),
+    ChunkEnd,
+    Trailers,
+    UntilClose,
+
+    /// Stopped after something which isn't HTTP, or after a gap.
+    Broken,
+
+    /// The connection switched to another protocol.
+    Upgraded,
+}
+
+/// Reads messages out of one direction of a connection.
+#[derive(Debug)]
+struct MessageParser {
+    is_response: bool,
+    state: ParserState,
+    buffer: Vec<u8>,
+
+    /// Stream offset of the first byte in `buffer`.
+    offset: u64,
+    head_started: Option<Duration>,
+    current: Option<HttpMessage>,
+
+    /// The number of heads read, including that of `current`.
+    heads: usize,
+
+    /// Methods of requests which have been read, for the request parser, or
+    /// which are waiting for a response, for the response parser.
+    methods: VecDeque<String>,
+
+    messages: Vec<HttpMessage>,
+}
+
+impl MessageParser {
+    fn new(is_response: bool) -> MessageParser {
+        MessageParser {
+            is_response,
+            state: ParserState::Head,
+            buffer: Vec::new(),
+            offset: 0,
+            head_started: None,
+            current: None,
+            heads: 0,
+            methods: VecDeque::new(),
+            messages: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, data: &[u8], timestamp: Duration) {
+        if self.state == ParserState::Head && self.buffer.is_empty() && !data.is_empty() {
+            self.head_started = Some(timestamp);
+        }
+
+        self.buffer.extend_from_slice(data);
+        while let Some(state) = self.step(timestamp) {
+            self.state = state;
+        }
+    }
+
+    /// Reads the next part of a message, returning the state to move to or
+    /// `None` when more input is needed.
+    fn step(&mut self, timestamp: Duration) -> Option<ParserState> {
+        match self.state {
+            ParserState::Head => {
+                let (length, head) = match HttpHead::parse(&self.buffer) {
+                    Ok(Some(parsed)) => parsed,
+                    Ok(None) => return None,
+                    Err(_) => return Some(ParserState::Broken),
+                };
+
+                let request_method = if self.is_response {
+                    let answered = head
+                        .status()
+                        .is_some_and(|status| status >= 200 || status == 101);
+                    if answered {
+                        self.methods.pop_front()
+                    } else {
+                        self.methods.front().cloned()
+                    }
+                } else {
+                    self.methods.extend(head.method().map(str::to_string));
+                    None
+                };
+
+                let framing = match head.framing(request_method.as_deref()) {
+                    Ok(framing) => framing,
+                    Err(_) => return Some(ParserState::Broken),
+                };
+
+                let start = self.offset;
+                self.consume(length);
+                self.heads += 1;
+                self.current = Some(HttpMessage {
+                    head,
+                    body: Vec::new(),
+                    start,
+                    body_start: self.offset,
+                    end: self.offset,
+                    first_seen: self.head_started.unwrap_or(timestamp),
+                    last_seen: timestamp,
+                    complete: false,
+                });
+
+                Some(match framing {
+                    Framing::None | Framing::Length(0) => self.complete(timestamp),
+                    Framing::Length(length) => ParserState::Body(length),
+                    Framing::Chunked => ParserState::ChunkSize,
+                    Framing::UntilClose => ParserState::UntilClose,
+                })
+            }
+            ParserState::Body(remaining) => {
+                let remaining = remaining - self.take_body(remaining);
+                if remaining == 0 {
+                    Some(self.complete(timestamp))
+                } else {
+                    self.state = ParserState::Body(remaining);
+                    None
+                }
+            }
+            ParserState::ChunkSize => {
+                let line = self.take_line()?;
+                let size = line.split(';').next().unwrap_or_default().trim();
+                match u64::from_str_radix(size, 16) {
+                    Ok(0) => Some(ParserState::Trailers),
+                    Ok(size) => Some(ParserState::ChunkData(size)),
+                    Err(_) => Some(ParserState::Broken),
+                }
+            }
+            ParserState::ChunkData(remaining) => {
+                let remaining = remaining - self.take_body(remaining);
+                if remaining == 0 {
+                    Some(ParserState::ChunkEnd)
+                } else {
+                    self.state = ParserState::ChunkData(remaining);
+                    None
+                }
+            }
+            ParserState::ChunkEnd => {
+                let line = self.take_line()?;
+                if line.is_empty() {
+                    Some(ParserState::ChunkSize)
+                } else {
+                    Some(ParserState::Broken)
+                }
+            }
+            ParserState::Trailers => {
+                let line = self.take_line()?;
+                if line.is_empty() {
+                    Some(self.complete(timestamp))
+                } else {
+                    Some(ParserState::Trailers)
+                }
+            }
+            ParserState::UntilClose => {
+                let length = self.buffer.len() as u64;
+                self.take_body(length);
+                if let Some(message) = self.current.as_mut() {
+                    message.last_seen = timestamp;
+                }
+                None
+            }
+            ParserState::Broken | ParserState::Upgraded => None,
+        }
+    }
+
+    /// Moves up to `limit` bytes from the buffer to the current body,
+    /// returning how many were moved.
+    fn take_body(&mut self, limit: u64) -> u64 {
+        let length = (self.buffer.len() as u64).min(limit) as usize;
+        if let Some(message) = self.current.as_mut() {
+            message.body.extend_from_slice(&self.buffer[..length]);
+        }
+        self.consume(length);
+        length as u64
+    }
+
+    /// Takes a line from the buffer without its line ending.
+    fn take_line(&mut self) -> Option<String> {
+        let end = self.buffer.iter().position(|&byte| byte == b'\n')?;
+        let line = String::from_utf8_lossy(&self.buffer[..end])
+            .trim_end_matches('\r')
+            .to_string();
+        self.consume(end + 1);
+        Some(line)
+    }
+
+    fn consume(&mut self, length: usize) {
+        self.buffer.drain(..length);
+        self.offset += length as u64;
+    }
+
+    fn complete(&mut self, timestamp: Duration) -> ParserState {
+        if let Some(mut message) = self.current.take() {
+            message.end = self.offset;
+            message.last_seen = timestamp;
+            message.complete = true;
+            self.messages.push(message);
+        }
+
+        self.head_started = if self.buffer.is_empty() {
+            None
+        } else {
+            Some(timestamp)
+        };
+        ParserState::Head
+    }
+
+    /// Stops parsing at a gap in the stream.
+    fn gap(&mut self) {
+        if self.state != ParserState::Upgraded {
+            self.state = ParserState::Broken;
+        }
+    }
+
+    /// Ends the stream, which completes bodies read until the connection
+    /// closes and keeps whatever was read of any other message.
+    fn finish(&mut self) {
+        if let Some(mut message) = self.current.take() {
+            message.end = self.offset;
+            message.complete = self.state == ParserState::UntilClose;
+            self.messages.push(message);
+        }
+    }
+}
+
+/// A request and the response to it. Either can be missing when the
+/// capture started or ended part way through.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct HttpTransaction {
+    request: Option<HttpMessage>,
+    response: Option<HttpMessage>,
+}
+
+impl HttpTransaction {
+    pub fn request(&self) -> Option<&HttpMessage> {
+        self.request.as_ref()
+    }
+
+    pub fn response(&self) -> Option<&HttpMessage> {
+        self.response.as_ref()
+    }
+}
+
+impl fmt::Display for HttpTransaction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match &self.request {
+            Some(request) => {
+                write!(f, "{}", request.head().start_line())?;
+                if let Some(host) = request.head().header("Host") {
+                    write!(f, " (Host: {})", host)?;
+                }
+            }
+            None => write!(f, "(request not captured)")?,
+        }
+
+        match &self.response {
+            Some(response) => write!(
+                f,
+                " -> {}, {} bytes",
+                response.head().start_line(),
+                response.body().len()
+            )?,
+            None => write!(f, " -> (no response)")?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the requests and responses of one connection and pairs them up.
+#[derive(Debug)]
+pub struct HttpConversation {
+    client: MessageParser,
+    server: MessageParser,
+    transactions: Vec<HttpTransaction>,
+
+    /// The number of request heads given a transaction.
+    requests: usize,
+
+    /// Transactions whose request head has been read but whose request
+    /// hasn't been completed, oldest first.
+    unfinished_requests: VecDeque<usize>,
+
+    /// The frames sent after an upgrade to WebSocket.
+    websocket: Option<WebSocketConnection>,
+}
+
+impl Default for HttpConversation {
+    fn default() -> HttpConversation {
+        HttpConversation {
+            client: MessageParser::new(false),
+            server: MessageParser::new(true),
+            transactions: Vec::new(),
+            requests: 0,
+            unfinished_requests: VecDeque::new(),
+            websocket: None,
+        }
+    }
+}
+
+impl HttpConversation {
+    pub fn new() -> HttpConversation {
+        HttpConversation::default()
+    }
+
+    /// Adds bytes sent in `direction`, which must follow on from the last
+    /// bytes sent that way.
+    pub fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration) {
+        if let Some(websocket) = self.websocket.as_mut() {
+            websocket.push(direction, data, timestamp);
+            return;
+        }
+
+        match direction {
+            Direction::ClientToServer => {
+                self.client.push(data, timestamp);
+                let methods: Vec<String> = self.client.methods.drain(..).collect();
+                self.server.methods.extend(methods);
+            }
+            Direction::ServerToClient => self.server.push(data, timestamp),
+        }
+
+        self.pair();
+    }
+
+    /// Notes that bytes sent in `direction` weren't captured.
+    pub fn gap(&mut self, direction: Direction) {
+        if let Some(websocket) = self.websocket.as_mut() {
+            websocket.gap(direction);
+            return;
+        }
+
+        match direction {
+            Direction::ClientToServer => self.client.gap(),
+            Direction::ServerToClient => self.server.gap(),
+        }
+    }
+
+    /// Ends both directions, keeping any messages cut short.
+    pub fn finish(&mut self) {
+        self.client.finish();
+        self.server.finish();
+        self.pair();
+        if let Some(websocket) = self.websocket.as_mut() {
+            websocket.finish();
+        }
+    }
+
+    pub fn transactions(&self) -> &[HttpTransaction] {
+        &self.transactions
+    }
+
+    /// The WebSocket connection the conversation was upgraded to.
+    pub fn websocket(&self) -> Option<&WebSocketConnection> {
+        self.websocket.as_ref()
+    }
+
+    fn pair(&mut self) {
+        // Requests are registered as soon as their head has been read, so
+        // that a response sent before the body, such as a 417 refusing
+        // `Expect: 100-continue`, pairs with its request.
+        while self.requests < self.client.heads {
+            self.requests += 1;
+            self.unfinished_requests.push_back(self.transactions.len());
+            self.transactions.push(HttpTransaction {
+                request: None,
+                response: None,
+            });
+        }
+
+        for request in self.client.messages.drain(..) {
+            match self.unfinished_requests.pop_front() {
+                Some(index) => self.transactions[index].request = Some(request),
+                None => self.transactions.push(HttpTransaction {
+                    request: Some(request),
+                    response: None,
+                }),
+            }
+        }
+
+        for response in self.server.messages.drain(..) {
+            let status = response.head().status().unwrap_or_default();
+            if status < 200 && status != 101 {
+                continue;
+            }
+
+            if status == 101 {
+                self.client.state = ParserState::Upgraded;
+                self.server.state = ParserState::Upgraded;
+
+                // Frames sent straight after the heads are still buffered.
+                let upgrade = response.head().header("upgrade");
+                if upgrade.is_some_and(|protocol| protocol.eq_ignore_ascii_case("websocket")) {
+                    let mut websocket = WebSocketConnection::new();
+                    let timestamp = response.last_seen();
+                    let client = mem::take(&mut self.client.buffer);
+                    websocket.push(Direction::ClientToServer, &client, timestamp);
+                    let server = mem::take(&mut self.server.buffer);
+                    websocket.push(Direction::ServerToClient, &server, timestamp);
+                    self.websocket = Some(websocket);
+                }
+            }
+
+            let unanswered = self
+                .transactions
+                .iter_mut()
+                .find(|transaction| transaction.response.is_none());
+            match unanswered {
+                Some(transaction) => transaction.response = Some(response),
+                None => self.transactions.push(HttpTransaction {
+                    request: None,
+                    response: Some(response),
+                }),
+            }
+        }
+    }
+}
+
+impl StreamHandler for HttpConversation {
+    /// Connections on the usual HTTP ports or starting with an HTTP/1.x
+    /// request or status line are read, unless they start with TLS or HTTP/2.
+    fn detect(ports: [u16; 2], data: &[u8]) -> Option<HttpConversation> {
+        if looks_like_tls(data) || looks_like_http2(data) {
+            return None;
+        }
+
+        if ports.iter().any(|port| HTTP_PORTS.contains(port)) || starts_with_start_line(data) {
+            Some(HttpConversation::new())
+        } else {
+            None
+        }
+    }
+
+    /// Waits for the whole first line on other ports, so that a request line
+    /// split between segments is still recognised.
+    fn needs_more(_ports: [u16; 2], data: &[u8]) -> bool {
+        !looks_like_tls(data) && !looks_like_http2(data) && could_start_http(data)
+    }
+
+    fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration) {
+        HttpConversation::push(self, direction, data, timestamp);
+    }
+
+    fn gap(&mut self, direction: Direction) {
+        HttpConversation::gap(self, direction);
+    }
+
+    fn finish(&mut self) {
+        HttpConversation::finish(self);
+    }
+}
+
+/// Finds the HTTP/1.x requests and responses in every TCP connection.
+#[derive(Debug, Default)]
+pub struct HttpAnalysis {
+    streams: ReassembledStreams<HttpConversation>,
+}
+
+/// Wraps connections which were already reassembled and finished.
+impl From<ReassembledStreams<HttpConversation>> for HttpAnalysis {
+    fn from(streams: ReassembledStreams<HttpConversation>) -> HttpAnalysis {
+        HttpAnalysis { streams }
+    }
+}
+
+impl HttpAnalysis {
+    pub fn new() -> HttpAnalysis {
+        HttpAnalysis::default()
+    }
+
+    /// Records a segment of the connection numbered `stream`. Segments have
+    /// to be passed in the order they were captured.
+    pub fn record(&mut self, stream: usize, direction: Direction, segment: &TcpSegment) {
+        self.streams.record(stream, direction, segment);
+    }
+
+    /// Adds bytes decrypted from the connection numbered `stream`, such as
+    /// the application data of a TLS session.
+    pub fn push_decrypted(
+        &mut self,
+        stream: usize,
+        flow: &Flow,
+        direction: Direction,
+        data: &[u8],
+        timestamp: Duration,
+    ) {
+        self.streams
+            .push_decrypted(stream, flow, direction, data, timestamp);
+    }
+
+    /// Flushes every connection at the end of the capture.
+    pub fn finish(&mut self) {
+        self.streams.finish();
+    }
+
+    /// Whether the requests of the connection numbered `stream` were read
+    /// from decrypted bytes, as with HTTPS.
+    pub fn is_decrypted(&self, stream: usize) -> bool {
+        self.streams.is_decrypted(stream)
+    }
+
+    /// Every connection upgraded to WebSocket along with its number.
+    pub fn websockets(&self) -> Vec<(usize, &WebSocketConnection)> {
+        self.streams
+            .handlers()
+            .into_iter()
+            .filter_map(|(stream, conversation)| {
+                conversation
+                    .websocket()
+                    .map(|websocket| (stream, websocket))
+            })
+            .collect()
+    }
+
+    /// Every transaction along with the number of its connection.
+    pub fn transactions(&self) -> Vec<(usize, &HttpTransaction)> {
+        self.streams
+            .handlers()
+            .into_iter()
+            .flat_map(|(stream, conversation)| {
+                conversation
+                    .transactions()
+                    .iter()
+                    .map(move |transaction| (stream, transaction))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flow::tests::{ip_header, tcp_header};
+    use flow::{Direction, FlowTable, TcpSegment};
+    use http::{looks_like_http, HttpAnalysis, HttpConversation, HttpError, HttpHead, StartLine};
+    use std::time::Duration;
+
+    #[test]
+    fn parse_head() {
+        let raw = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nX-Long: a\r\n  b\r\n\r\nbody";
+        let (length, head) = HttpHead::parse(raw).unwrap().unwrap();
+
+        assert_eq!(length, raw.len() - 4);
+        assert_eq!(
+            head,
+            HttpHead {
+                start_line: StartLine::Request {
+                    method: "GET".to_string(),
+                    target: "/index.html".to_string(),
+                    version: "HTTP/1.1".to_string(),
+                },
+                headers: vec![
+                    ("Host".to_string(), "example.com".to_string()),
+                    ("X-Long".to_string(), "a b".to_string()),
+                ],
+            }
+        );
+        assert_eq!(head.header("host"), Some("example.com"));
+        assert_eq!(head.to_string(), "HTTP GET /index.html HTTP/1.1");
+
+        assert_eq!(HttpHead::parse(b"HTTP/1.1 200 OK\r\nServer: x"), Ok(None));
+        assert_eq!(
+            HttpHead::parse(b"SSH-2.0-OpenSSH_7.4\r\n"),
+            Err(HttpError::InvalidStartLine)
+        );
+        assert_eq!(
+            HttpHead::parse(b"HTTP/1.1 200 OK\r\nbad header\r\n\r\n"),
+            Err(HttpError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn detect() {
+        assert!(looks_like_http(b"POST /api HTTP/1.1\r\n"));
+        assert!(looks_like_http(b"HTTP/1.0 404 Not Found\r\n"));
+        assert!(!looks_like_http(b"GETTING"));
+    }
+
+    #[test]
+    fn conversation() {
+        let mut conversation = HttpConversation::new();
+        let client = Direction::ClientToServer;
+        let server = Direction::ServerToClient;
+        let at = Duration::from_millis;
+
+        conversation.push(
+            client,
+            b"HEAD / HTTP/1.1\r\n\r\nPOST /upload HTTP/1.1\r\nExpect: 100-continue\r\n",
+            at(0),
+        );
+        conversation.push(client, b"Content-Length: 5\r\n\r\n", at(1));
+        conversation.push(
+            server,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n",
+            at(10),
+        );
+        conversation.push(server, b"HTTP/1.1 100 Continue\r\n\r\n", at(11));
+        conversation.push(client, b"hello", at(20));
+        conversation.push(
+            server,
+            b"HTTP/1.1 201 Created\r\nTransfer-Encoding: chunked\r\n\r\n3;x=y\r\nabc",
+            at(30),
+        );
+        conversation.push(server, b"\r\n2\r\nde\r\n0\r\nTrailer: 1\r\n\r\n", at(31));
+        conversation.push(client, b"GET /stream HTTP/1.0\r\n\r\n", at(40));
+        conversation.push(server, b"HTTP/1.0 200 OK\r\n\r\nuntil close", at(50));
+        conversation.finish();
+
+        let transactions = conversation.transactions();
+        assert_eq!(transactions.len(), 3);
+
+        let head = &transactions[0];
+        assert_eq!(head.response().unwrap().head().status(), Some(200));
+        assert!(head.response().unwrap().body().is_empty());
+
+        let post = &transactions[1];
+        let request = post.request().unwrap();
+        assert_eq!(request.body(), b"hello");
+        assert_eq!(
+            (request.start(), request.body_start(), request.end()),
+            (19, 85, 90)
+        );
+        assert_eq!((request.first_seen(), request.last_seen()), (at(0), at(20)));
+
+        let response = post.response().unwrap();
+        assert_eq!(response.head().status(), Some(201));
+        assert_eq!(response.body(), b"abcde");
+        assert!(response.is_complete());
+        assert_eq!(
+            (response.first_seen(), response.last_seen()),
+            (at(30), at(31))
+        );
+
+        let stream = transactions[2].response().unwrap();
+        assert_eq!(stream.body(), b"until close");
+        assert!(stream.is_complete());
+        assert_eq!(
+            transactions[2].to_string(),
+            "GET /stream HTTP/1.0 -> HTTP/1.0 200 OK, 11 bytes"
+        );
+    }
+
+    #[test]
+    fn websocket() {
+        let mut conversation = HttpConversation::new();
+        let at = Duration::from_millis;
+
+        conversation.push(
+            Direction::ClientToServer,
+            b"GET /chat HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n",
+            at(0),
+        );
+        conversation.push(
+            Direction::ServerToClient,
+            b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n\x81\x02hi",
+            at(10),
+        );
+        conversation.push(
+            Direction::ClientToServer,
+            b"\x81\x82\x00\x01\x00\x00ho",
+            at(20),
+        );
+        conversation.finish();
+
+        assert_eq!(conversation.transactions().len(), 1);
+        let websocket = conversation.websocket().unwrap();
+        assert_eq!(websocket.frames().len(), 2);
+        assert_eq!(websocket.frames()[0].1, at(10));
+        let texts: Vec<Option<&str>> = websocket
+            .messages()
+            .iter()
+            .map(|message| message.text())
+            .collect();
+        assert_eq!(texts, [Some("hi"), Some("hn")]);
+    }
+
+    #[test]
+    fn decrypted() {
+        let at = Duration::from_secs(0);
+        let client = ip_header(true);
+        let header = tcp_header(36869, 8080, 1, 1, 0x18);
+        let segment = TcpSegment::new(at, &client, &header, b"\x17\x03\x03\x00\x01\xff");
+
+        let mut flows = FlowTable::new();
+        let mut analysis = HttpAnalysis::new();
+        let (stream, direction) = flows.lookup(&segment);
+        analysis.record(stream, direction, &segment);
+
+        let flow = &flows.flows()[stream];
+        analysis.push_decrypted(stream, flow, direction, b"GET / HTTP/1.1\r\n\r\n", at);
+        analysis.push_decrypted(
+            stream,
+            flow,
+            direction.reverse(),
+            b"HTTP/1.1 204 No Content\r\n\r\n",
+            at,
+        );
+        analysis.finish();
+
+        let transactions = analysis.transactions();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            transactions[0].1.to_string(),
+            "GET / HTTP/1.1 -> HTTP/1.1 204 No Content, 0 bytes"
+        );
+    }
+
+    #[test]
+    fn expectation_failed() {
+        let mut conversation = HttpConversation::new();
+        let at = Duration::from_millis;
+
+        conversation.push(
+            Direction::ClientToServer,
+            b"PUT /big HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n",
+            at(0),
+        );
+        conversation.push(
+            Direction::ServerToClient,
+            b"HTTP/1.1 417 Expectation Failed\r\nContent-Length: 0\r\n\r\n",
+            at(10),
+        );
+        conversation.finish();
+
+        let transactions = conversation.transactions();
+        assert_eq!(transactions.len(), 1);
+        assert!(!transactions[0].request().unwrap().is_complete());
+        assert_eq!(
+            transactions[0].to_string(),
+            "PUT /big HTTP/1.1 -> HTTP/1.1 417 Expectation Failed, 0 bytes"
+        );
+    }
+
+    /// A request line split between segments on a port HTTP isn't assumed to
+    /// run on is still recognised.
+    #[test]
+    fn detect_split_request_line() {
+        let at = Duration::from_secs(0);
+        let client = ip_header(true);
+        let server = ip_header(false);
+        let segments = [
+            (&client, tcp_header(36869, 9000, 1, 1, 0x18), &b"GE"[..]),
+            (
+                &client,
+                tcp_header(36869, 9000, 3, 1, 0x18),
+                &b"T / HTTP/1.1\r"[..],
+            ),
+            (
+                &client,
+                tcp_header(36869, 9000, 16, 1, 0x18),
+                &b"\n\r\n"[..],
+            ),
+            (
+                &server,
+                tcp_header(9000, 36869, 1, 19, 0x18),
+                &b"HTTP/1.1 204 OK\r\n\r\n"[..],
+            ),
+        ];
+
+        let mut flows = FlowTable::new();
+        let mut analysis = HttpAnalysis::new();
+        for (ip, header, data) in segments.iter() {
+            let segment = TcpSegment::new(at, ip, header, data);
+            let (stream, direction) = flows.lookup(&segment);
+            analysis.record(stream, direction, &segment);
+        }
+        analysis.finish();
+
+        let transactions = analysis.transactions();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(
+            transactions[0].1.to_string(),
+            "GET / HTTP/1.1 -> HTTP/1.1 204 OK, 0 bytes"
+        );
+
+        // Anything which can't become a request or status line is turned
+        // down straight away.
+        let header = tcp_header(36870, 9000, 1, 1, 0x18);
+        let segment = TcpSegment::new(at, &client, &header, b"GX");
+        let mut flows = FlowTable::new();
+        let mut analysis = HttpAnalysis::new();
+        let (stream, direction) = flows.lookup(&segment);
+        analysis.record(stream, direction, &segment);
+        let header = tcp_header(36870, 9000, 3, 1, 0x18);
+        let segment = TcpSegment::new(at, &client, &header, b"ET / HTTP/1.1\r\n\r\n");
+        analysis.record(stream, direction, &segment);
+        analysis.finish();
+        assert!(analysis.transactions().is_empty());
+    }
+}
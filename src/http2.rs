@@ -0,0 +1,1001 @@
+use flow::Direction;
+use hpack::Decoder;
+use http::{HttpHead, StartLine};
+use nom::{be_u24, be_u32, be_u8};
+use reassembly::{ReassembledStreams, StreamHandler};
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+use tls::looks_like_tls;
+use util::to_hex;
+
+/// What a client sends first, so servers which also speak HTTP/1.x can tell
+/// it apart.
+pub const CONNECTION_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const FRAME_HEADER_LENGTH: usize = 9;
+
+const END_STREAM: u8 = 0x01;
+const ACK: u8 = 0x01;
+const END_HEADERS: u8 = 0x04;
+const PADDED: u8 = 0x08;
+const PRIORITY: u8 = 0x20;
+
+const HEADER_TABLE_SIZE: u16 = 1;
+
+#[derive(Fail, Debug, Eq, PartialEq)]
+pub enum Http2Error {
+    #[fail(display = "Invalid HTTP/2 {} frame", _0)]
+    InvalidFrame(FrameType),
+}
+
+/// Whether `data` starts with the client connection preface or with the
+/// SETTINGS frame a server opens with.
+pub fn looks_like_http2(data: &[u8]) -> bool {
+    if data.starts_with(CONNECTION_PREFACE) {
+        return true;
+    }
+
+    match parse_frame_header(data) {
+        Ok((_, (length, frame_type, flags, stream_id))) => {
+            frame_type == 4 && flags == 0 && stream_id == 0 && length % 6 == 0
+        }
+        Err(_) => false,
+    }
+}
+
+/// The name of an error code sent in RST_STREAM and GOAWAY frames.
+pub fn error_code_name(code: u32) -> String {
+    let name = match code {
+        0x0 => "NO_ERROR",
+        0x1 => "PROTOCOL_ERROR",
+        0x2 => "INTERNAL_ERROR",
+        0x3 => "FLOW_CONTROL_ERROR",
+        0x4 => "SETTINGS_TIMEOUT",
+        0x5 => "STREAM_CLOSED",
+        0x6 => "FRAME_SIZE_ERROR",
+        0x7 => "REFUSED_STREAM",
+        0x8 => "CANCEL",
+        0x9 => "COMPRESSION_ERROR",
+        0xa => "CONNECT_ERROR",
+        0xb => "ENHANCE_YOUR_CALM",
+        0xc => "INADEQUATE_SECURITY",
+        0xd => "HTTP_1_1_REQUIRED",
+        _ => return format!("{:#x}", code),
+    };
+
+    name.to_string()
+}
+
+/// The name of a parameter of a SETTINGS frame.
+pub fn setting_name(identifier: u16) -> String {
+    let name = match identifier {
+        0x1 => "HEADER_TABLE_SIZE",
+        0x2 => "ENABLE_PUSH",
+        0x3 => "MAX_CONCURRENT_STREAMS",
+        0x4 => "INITIAL_WINDOW_SIZE",
+        0x5 => "MAX_FRAME_SIZE",
+        0x6 => "MAX_HEADER_LIST_SIZE",
+        0x8 => "ENABLE_CONNECT_PROTOCOL",
+        _ => return format!("{:#x}", identifier),
+    };
+
+    name.to_string()
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum FrameType {
+    Data,
+    Headers,
+    Priority,
+    RstStream,
+    Settings,
+    PushPromise,
+    Ping,
+    GoAway,
+    WindowUpdate,
+    Continuation,
+    Other(u8),
+}
+
+impl FrameType {
+    pub fn from_code(code: u8) -> FrameType {
+        match code {
+            0 => FrameType::Data,
+            1 => FrameType::Headers,
+            2 => FrameType::Priority,
+            3 => FrameType::RstStream,
+            4 => FrameType::Settings,
+            5 => FrameType::PushPromise,
+            6 => FrameType::Ping,
+            7 => FrameType::GoAway,
+            8 => FrameType::WindowUpdate,
+            9 => FrameType::Continuation,
+            other => FrameType::Other(other),
+        }
+    }
+
+    /// The names of the flags this type of frame defines which are set.
+    fn flag_names(self, flags: u8) -> Vec<&'static str> {
+        let defined: &[(u8, &str)] = match self {
+            FrameType::Data => &[(END_STREAM, "END_STREAM"), (PADDED, "PADDED")],
+            FrameType::Headers => &[
+                (END_STREAM, "END_STREAM"),
+                (END_HEADERS, "END_HEADERS"),
+                (PADDED, "PADDED"),
+                (PRIORITY, "PRIORITY"),
+            ],
+            FrameType::Settings | FrameType::Ping => &[(ACK, "ACK")],
+            FrameType::PushPromise => &[(END_HEADERS, "END_HEADERS"), (PADDED, "PADDED")],
+            FrameType::Continuation => &[(END_HEADERS, "END_HEADERS")],
+            _ => &[],
+        };
+
+        defined
+            .iter()
+            .filter(|(flag, _)| flags & flag != 0)
+            .map(|&(_, name)| name)
+            .collect()
+    }
+}
+
+impl fmt::Display for FrameType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            FrameType::Data => "DATA",
+            FrameType::Headers => "HEADERS",
+            FrameType::Priority => "PRIORITY",
+            FrameType::RstStream => "RST_STREAM",
+            FrameType::Settings => "SETTINGS",
+            FrameType::PushPromise => "PUSH_PROMISE",
+            FrameType::Ping => "PING",
+            FrameType::GoAway => "GOAWAY",
+            FrameType::WindowUpdate => "WINDOW_UPDATE",
+            FrameType::Continuation => "CONTINUATION",
+            FrameType::Other(code) => return write!(f, "Frame Type {:#04x}", code),
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+named!(
+    parse_frame_header<(u32, u8, u8, u32)>,
+    do_parse!(
+        length:     be_u24 >>
+        frame_type: be_u8 >>
+        flags:      be_u8 >>
+        stream_id:  be_u32 >>
+
+        (length, frame_type, flags, stream_id & 0x7fff_ffff)
+    )
+);
+
+/// What a frame carries, with any padding removed.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum FramePayload {
+    Data(Vec<u8>),
+
+    /// The start of a header block, which is only complete with the
+    /// END_HEADERS flag.
+    Headers(Vec<u8>),
+
+    RstStream(u32),
+    Settings(Vec<(u16, u32)>),
+    PushPromise {
+        promised_stream_id: u32,
+        block: Vec<u8>,
+    },
+    Ping([u8; 8]),
+    GoAway {
+        last_stream_id: u32,
+        error_code: u32,
+        debug_data: Vec<u8>,
+    },
+    WindowUpdate(u32),
+    Continuation(Vec<u8>),
+
+    /// PRIORITY frames and frame types which aren't read.
+    Other(Vec<u8>),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Frame {
+    frame_type: FrameType,
+    flags: u8,
+    stream_id: u32,
+    length: u32,
+    payload: FramePayload,
+}
+
+/// Removes the padding of a frame with the PADDED flag.
+fn unpad(flags: u8, payload: &[u8]) -> Option<&[u8]> {
+    if flags & PADDED == 0 {
+        return Some(payload);
+    }
+
+    let (&padding, rest) = payload.split_first()?;
+    rest.get(..rest.len().checked_sub(padding as usize)?)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+impl Frame {
+    /// Parses the frame at the start of `input`, returning how many bytes it
+    /// took up. `None` means more bytes are needed.
+    pub fn parse(input: &[u8]) -> Result<Option<(usize, Frame)>, Http2Error> {
+        let (length, frame_type, flags, stream_id) = match parse_frame_header(input) {
+            Ok((_, header)) => header,
+            Err(_) => return Ok(None),
+        };
+        let end = FRAME_HEADER_LENGTH + length as usize;
+        let payload = match input.get(FRAME_HEADER_LENGTH..end) {
+            Some(payload) => payload,
+            None => return Ok(None),
+        };
+
+        let frame_type = FrameType::from_code(frame_type);
+        let payload = Frame::parse_payload(frame_type, flags, payload)
+            .ok_or(Http2Error::InvalidFrame(frame_type))?;
+
+        Ok(Some((
+            end,
+            Frame {
+                frame_type,
+                flags,
+                stream_id,
+                length,
+                payload,
+            },
+        )))
+    }
+
+    fn parse_payload(frame_type: FrameType, flags: u8, payload: &[u8]) -> Option<FramePayload> {
+        let payload = match frame_type {
+            FrameType::Data => FramePayload::Data(unpad(flags, payload)?.to_vec()),
+            FrameType::Headers => {
+                let block = unpad(flags, payload)?;
+                let block = if flags & PRIORITY != 0 {
+                    block.get(5..)?
+                } else {
+                    block
+                };
+                FramePayload::Headers(block.to_vec())
+            }
+            FrameType::RstStream if payload.len() == 4 => {
+                FramePayload::RstStream(read_u32(payload))
+            }
+            FrameType::Settings if payload.len() % 6 == 0 => FramePayload::Settings(
+                payload
+                    .chunks(6)
+                    .map(|setting| {
+                        (
+                            u16::from_be_bytes([setting[0], setting[1]]),
+                            read_u32(&setting[2..]),
+                        )
+                    })
+                    .collect(),
+            ),
+            FrameType::PushPromise => {
+                let payload = unpad(flags, payload)?;
+                FramePayload::PushPromise {
+                    promised_stream_id: read_u32(payload.get(..4)?) & 0x7fff_ffff,
+                    block: payload[4..].to_vec(),
+                }
+            }
+            FrameType::Ping if payload.len() == 8 => {
+                let mut data = [0; 8];
+                data.copy_from_slice(payload);
+                FramePayload::Ping(data)
+            }
+            FrameType::GoAway if payload.len() >= 8 => FramePayload::GoAway {
+                last_stream_id: read_u32(payload) & 0x7fff_ffff,
+                error_code: read_u32(&payload[4..]),
+                debug_data: payload[8..].to_vec(),
+            },
+            FrameType::WindowUpdate if payload.len() == 4 => {
+                FramePayload::WindowUpdate(read_u32(payload) & 0x7fff_ffff)
+            }
+            FrameType::Continuation => FramePayload::Continuation(payload.to_vec()),
+            FrameType::Priority | FrameType::Other(_) => FramePayload::Other(payload.to_vec()),
+            _ => return None,
+        };
+
+        Some(payload)
+    }
+
+    /// Parses the frames in a segment, after the connection preface if it
+    /// starts with one. Only whole frames are returned.
+    pub fn parse_segment(input: &[u8]) -> Vec<Frame> {
+        let mut input = input.strip_prefix(CONNECTION_PREFACE).unwrap_or(input);
+        let mut frames = Vec::new();
+        while let Ok(Some((length, frame))) = Frame::parse(input) {
+            frames.push(frame);
+            input = &input[length..];
+        }
+
+        frames
+    }
+
+    pub fn frame_type(&self) -> FrameType {
+        self.frame_type
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// The stream the frame belongs to, or 0 for the whole connection.
+    pub fn stream_id(&self) -> u32 {
+        self.stream_id
+    }
+
+    /// The length of the payload, including any padding.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    pub fn payload(&self) -> &FramePayload {
+        &self.payload
+    }
+
+    pub fn is_end_stream(&self) -> bool {
+        match self.frame_type {
+            FrameType::Data | FrameType::Headers => self.flags & END_STREAM != 0,
+            _ => false,
+        }
+    }
+
+    pub fn is_end_headers(&self) -> bool {
+        match self.frame_type {
+            FrameType::Headers | FrameType::PushPromise | FrameType::Continuation => {
+                self.flags & END_HEADERS != 0
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.frame_type)?;
+        if self.stream_id != 0 {
+            write!(f, " stream {}", self.stream_id)?;
+        }
+        for name in self.frame_type.flag_names(self.flags) {
+            write!(f, " {}", name)?;
+        }
+
+        match &self.payload {
+            FramePayload::Data(data) => write!(f, ", {} bytes", data.len()),
+            FramePayload::Headers(block) | FramePayload::Continuation(block) => {
+                write!(f, ", {} bytes of headers", block.len())
+            }
+            FramePayload::RstStream(error_code) => write!(f, ", {}", error_code_name(*error_code)),
+            FramePayload::Settings(settings) => {
+                for (index, (identifier, value)) in settings.iter().enumerate() {
+                    let separator = if index == 0 { " " } else { ", " };
+                    write!(f, "{}{}={}", separator, setting_name(*identifier), value)?;
+                }
+                Ok(())
+            }
+            FramePayload::PushPromise {
+                promised_stream_id,
+                block,
+            } => write!(
+                f,
+                ", promised stream {}, {} bytes of headers",
+                promised_stream_id,
+                block.len()
+            ),
+            FramePayload::Ping(data) => write!(f, " {}", to_hex(data)),
+            FramePayload::GoAway {
+                last_stream_id,
+                error_code,
+                debug_data,
+            } => {
+                write!(
+                    f,
+                    ", last stream {}, {}",
+                    last_stream_id,
+                    error_code_name(*error_code)
+                )?;
+                if !debug_data.is_empty() {
+                    write!(f, ", \"{}\"", String::from_utf8_lossy(debug_data))?;
+                }
+                Ok(())
+            }
+            FramePayload::WindowUpdate(increment) => write!(f, ", increment {}", increment),
+            FramePayload::Other(payload) => write!(f, ", {} bytes", payload.len()),
+        }
+    }
+}
+
+/// What one side sent on a stream.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Http2Message {
+    headers: Vec<(String, String)>,
+    trailers: Vec<(String, String)>,
+    data: Vec<u8>,
+    ended: bool,
+}
+
+impl Http2Message {
+    /// The decompressed headers, including pseudo-headers like `:path`.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// The value of the first header named `name`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Headers sent after the data, such as the status of a gRPC call.
+    pub fn trailers(&self) -> &[(String, String)] {
+        &self.trailers
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Whether the side closed the stream with END_STREAM.
+    pub fn is_ended(&self) -> bool {
+        self.ended
+    }
+
+    fn add_headers(&mut self, headers: Vec<(String, String)>) {
+        // Informational responses come before the final headers.
+        let informational = self
+            .header(":status")
+            .is_some_and(|status| status.starts_with('1'));
+        if self.headers.is_empty() || informational {
+            self.headers = headers;
+        } else {
+            self.trailers.extend(headers);
+        }
+    }
+}
+
+/// A request and its response, which share a stream identifier.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Http2Stream {
+    id: u32,
+    request: Http2Message,
+    response: Http2Message,
+    reset: Option<u32>,
+    frames: usize,
+    first_seen: Duration,
+    last_seen: Duration,
+}
+
+impl Http2Stream {
+    fn new(id: u32, timestamp: Duration) -> Http2Stream {
+        Http2Stream {
+            id,
+            request: Http2Message::default(),
+            response: Http2Message::default(),
+            reset: None,
+            frames: 0,
+            first_seen: timestamp,
+            last_seen: timestamp,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn request(&self) -> &Http2Message {
+        &self.request
+    }
+
+    pub fn response(&self) -> &Http2Message {
+        &self.response
+    }
+
+    /// The error code of the RST_STREAM frame which ended the stream.
+    pub fn reset(&self) -> Option<u32> {
+        self.reset
+    }
+
+    /// The number of frames sent on the stream.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn first_seen(&self) -> Duration {
+        self.first_seen
+    }
+
+    pub fn last_seen(&self) -> Duration {
+        self.last_seen
+    }
+
+    fn message(&mut self, direction: Direction) -> &mut Http2Message {
+        match direction {
+            Direction::ClientToServer => &mut self.request,
+            Direction::ServerToClient => &mut self.response,
+        }
+    }
+}
+
+impl fmt::Display for Http2Stream {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "stream {}: ", self.id)?;
+        match (self.request.header(":method"), self.request.header(":path")) {
+            (Some(method), Some(path)) => write!(f, "{} {}", method, path)?,
+            (Some(method), None) => write!(f, "{}", method)?,
+            _ => write!(f, "(no request)")?,
+        }
+        write!(f, ", {} bytes", self.request.data.len())?;
+
+        match self.response.header(":status") {
+            Some(status) => write!(f, " -> {}, {} bytes", status, self.response.data.len())?,
+            None => write!(f, " -> (no response)")?,
+        }
+
+        if let Some(error_code) = self.reset {
+            write!(f, ", reset with {}", error_code_name(error_code))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Where one side of the connection is up to.
+#[derive(Debug, Default)]
+struct Side {
+    buffer: Vec<u8>,
+
+    /// Whether an HTTP/1.1 head asking for or agreeing to an upgrade to h2c
+    /// comes first.
+    upgrade: bool,
+
+    /// Whether the client connection preface is still to come.
+    preface: bool,
+
+    /// Whether bytes went missing or something couldn't be parsed, after
+    /// which frame boundaries are unknown.
+    broken: bool,
+
+    /// Decompresses the headers this side sends.
+    decoder: Decoder,
+
+    /// A header block waiting for CONTINUATION frames, along with the stream
+    /// it's for.
+    block: Option<(u32, Vec<u8>)>,
+}
+
+/// Reads the frames of an HTTP/2 connection and groups them by stream.
+#[derive(Debug, Default)]
+pub struct Http2Connection {
+    client: Side,
+    server: Side,
+    frames: Vec<(Direction, Duration, Frame)>,
+    streams: Vec<Http2Stream>,
+
+    /// The index in `streams` of each stream ID.
+    stream_indices: HashMap<u32, usize>,
+}
+
+impl Http2Connection {
+    /// A connection which starts with the client connection preface, as h2
+    /// over TLS and h2c with prior knowledge do.
+    pub fn new() -> Http2Connection {
+        let mut connection = Http2Connection::default();
+        connection.client.preface = true;
+        connection
+    }
+
+    /// A connection which starts as HTTP/1.1 and is upgraded to h2c.
+    pub fn upgrade() -> Http2Connection {
+        let mut connection = Http2Connection::new();
+        connection.client.upgrade = true;
+        connection.server.upgrade = true;
+        connection
+    }
+
+    /// Every frame, in the order it was sent, along with the time the end of
+    /// each was captured.
+    pub fn frames(&self) -> &[(Direction, Duration, Frame)] {
+        &self.frames
+    }
+
+    /// The streams in the order they were first seen.
+    pub fn streams(&self) -> &[Http2Stream] {
+        &self.streams
+    }
+
+    /// Adds bytes sent in `direction`, which must follow on from the last
+    /// bytes sent that way.
+    pub fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration) {
+        let side = self.side(direction);
+        if side.broken {
+            return;
+        }
+        side.buffer.extend_from_slice(data);
+
+        if side.upgrade {
+            match HttpHead::parse(&side.buffer) {
+                Ok(Some((length, head))) => {
+                    side.buffer.drain(..length);
+                    side.upgrade = false;
+                    self.read_upgrade(head, timestamp);
+                }
+                Ok(None) => return,
+                Err(_) => side.broken = true,
+            }
+        }
+
+        let side = self.side(direction);
+        if side.preface && !side.broken {
+            let length = side.buffer.len().min(CONNECTION_PREFACE.len());
+            if side.buffer[..length] != CONNECTION_PREFACE[..length] {
+                side.broken = true;
+            } else if length < CONNECTION_PREFACE.len() {
+                return;
+            } else {
+                side.buffer.drain(..length);
+                side.preface = false;
+            }
+        }
+
+        let mut frames = Vec::new();
+        while !side.broken {
+            match Frame::parse(&side.buffer) {
+                Ok(Some((length, frame))) => {
+                    side.buffer.drain(..length);
+                    frames.push(frame);
+                }
+                Ok(None) => break,
+                Err(_) => side.broken = true,
+            }
+        }
+
+        for frame in frames {
+            self.read_frame(direction, frame, timestamp);
+        }
+    }
+
+    /// Notes that bytes sent in `direction` weren't captured.
+    pub fn gap(&mut self, direction: Direction) {
+        let side = self.side(direction);
+        side.broken = true;
+        side.buffer.clear();
+    }
+
+    fn side(&mut self, direction: Direction) -> &mut Side {
+        match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        }
+    }
+
+    fn stream(&mut self, id: u32, timestamp: Duration) -> &mut Http2Stream {
+        let streams = &mut self.streams;
+        let index = *self.stream_indices.entry(id).or_insert_with(|| {
+            streams.push(Http2Stream::new(id, timestamp));
+            streams.len() - 1
+        });
+
+        &mut self.streams[index]
+    }
+
+    /// Handles the HTTP/1.1 request asking for h2c, which becomes stream 1,
+    /// or the response to it.
+    fn read_upgrade(&mut self, head: HttpHead, timestamp: Duration) {
+        match head.start_line() {
+            StartLine::Request { method, target, .. } => {
+                let mut headers = vec![
+                    (":method".to_string(), method.clone()),
+                    (":path".to_string(), target.clone()),
+                ];
+                headers.extend(
+                    head.headers()
+                        .iter()
+                        .map(|(name, value)| (name.to_lowercase(), value.clone())),
+                );
+
+                let request = &mut self.stream(1, timestamp).request;
+                request.headers = headers;
+                request.ended = true;
+            }
+            StartLine::Response { status: 101, .. } => {}
+            StartLine::Response { .. } => {
+                // The server carried on with HTTP/1.1.
+                self.client.broken = true;
+                self.server.broken = true;
+            }
+        }
+    }
+
+    fn read_frame(&mut self, direction: Direction, frame: Frame, timestamp: Duration) {
+        if frame.stream_id != 0 {
+            let stream = self.stream(frame.stream_id, timestamp);
+            stream.frames += 1;
+            stream.last_seen = timestamp;
+        }
+
+        match &frame.payload {
+            FramePayload::Data(data) => {
+                let message = self.stream(frame.stream_id, timestamp).message(direction);
+                message.data.extend_from_slice(data);
+            }
+            FramePayload::Headers(block) => {
+                self.side(direction).block = Some((frame.stream_id, block.clone()));
+            }
+            FramePayload::PushPromise {
+                promised_stream_id,
+                block,
+            } => {
+                self.side(direction).block = Some((*promised_stream_id, block.clone()));
+            }
+            FramePayload::Continuation(fragment) => {
+                if let Some((_, block)) = &mut self.side(direction).block {
+                    block.extend_from_slice(fragment);
+                }
+            }
+            FramePayload::RstStream(error_code) => {
+                self.stream(frame.stream_id, timestamp).reset = Some(*error_code);
+            }
+            FramePayload::Settings(settings) if frame.flags & ACK == 0 => {
+                // The table size a side announces limits the other's encoder.
+                let peer = match direction {
+                    Direction::ClientToServer => Direction::ServerToClient,
+                    Direction::ServerToClient => Direction::ClientToServer,
+                };
+                for &(identifier, value) in settings {
+                    if identifier == HEADER_TABLE_SIZE {
+                        self.side(peer).decoder.set_limit(value as usize);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if frame.is_end_headers() {
+            self.read_headers(direction, frame.frame_type, timestamp);
+        }
+        if frame.is_end_stream() {
+            self.stream(frame.stream_id, timestamp)
+                .message(direction)
+                .ended = true;
+        }
+
+        self.frames.push((direction, timestamp, frame));
+    }
+
+    /// Decompresses a finished header block. PUSH_PROMISE frames carry the
+    /// request of the stream they promise.
+    fn read_headers(&mut self, direction: Direction, frame_type: FrameType, timestamp: Duration) {
+        let side = self.side(direction);
+        let (stream_id, block) = match side.block.take() {
+            Some(block) => block,
+            None => return,
+        };
+
+        let headers = match side.decoder.decode(&block) {
+            Ok(headers) => headers,
+            Err(_) => {
+                // The dynamic table is out of step, so later blocks can't
+                // be trusted either.
+                side.broken = true;
+                return;
+            }
+        };
+
+        let stream = self.stream(stream_id, timestamp);
+        match frame_type {
+            FrameType::PushPromise => stream.request.add_headers(headers),
+            _ => stream.message(direction).add_headers(headers),
+        }
+    }
+}
+
+impl StreamHandler for Http2Connection {
+    /// Connections starting with the client preface or a server's SETTINGS
+    /// frame are read, as are HTTP/1.1 requests to upgrade to h2c.
+    fn detect(_ports: [u16; 2], data: &[u8]) -> Option<Http2Connection> {
+        if looks_like_tls(data) {
+            return None;
+        }
+
+        if looks_like_http2(data) {
+            return Some(Http2Connection::new());
+        }
+
+        match HttpHead::parse(data) {
+            Ok(Some((_, head)))
+                if head
+                    .header("upgrade")
+                    .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("h2c")) =>
+            {
+                Some(Http2Connection::upgrade())
+            }
+            _ => None,
+        }
+    }
+
+    fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration) {
+        Http2Connection::push(self, direction, data, timestamp);
+    }
+
+    fn gap(&mut self, direction: Direction) {
+        Http2Connection::gap(self, direction);
+    }
+
+    fn finish(&mut self) {}
+}
+
+/// Finds the HTTP/2 connections in a capture.
+pub type Http2Analysis = ReassembledStreams<Http2Connection>;
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use flow::Direction;
+    use http2::{
+        looks_like_http2, Frame, FramePayload, FrameType, Http2Connection, Http2Error,
+        CONNECTION_PREFACE,
+    };
+    use reassembly::StreamHandler;
+    use std::time::Duration;
+
+    /// The first request of RFC 7541 appendix C.4.
+    const REQUEST_BLOCK: &str = "828684418cf1e3c2e5f23a6ba0ab90f4ff";
+
+    #[test]
+    fn parse_frame() {
+        // A padded HEADERS frame with a priority.
+        let raw = hex::decode(format!(
+            "000019012d00000001020000000010{}0000ffff",
+            REQUEST_BLOCK
+        ))
+        .unwrap();
+        let (length, frame) = Frame::parse(&raw).unwrap().unwrap();
+
+        assert_eq!(length, raw.len() - 2);
+        assert_eq!(frame.frame_type(), FrameType::Headers);
+        assert_eq!(frame.stream_id(), 1);
+        assert_eq!(frame.length(), 25);
+        assert!(frame.is_end_stream());
+        assert!(frame.is_end_headers());
+        assert_eq!(
+            frame.payload(),
+            &FramePayload::Headers(hex::decode(REQUEST_BLOCK).unwrap())
+        );
+        assert_eq!(
+            frame.to_string(),
+            "HEADERS stream 1 END_STREAM END_HEADERS PADDED PRIORITY, 17 bytes of headers"
+        );
+
+        let raw = hex::decode("00000c040000000000000100001000000300000064").unwrap();
+        let (_, frame) = Frame::parse(&raw).unwrap().unwrap();
+        assert_eq!(
+            frame.to_string(),
+            "SETTINGS HEADER_TABLE_SIZE=4096, MAX_CONCURRENT_STREAMS=100"
+        );
+        assert!(looks_like_http2(&raw));
+
+        let raw = hex::decode("00000a070000000000000000030000000b6869").unwrap();
+        let (_, frame) = Frame::parse(&raw).unwrap().unwrap();
+        assert_eq!(
+            frame.to_string(),
+            "GOAWAY, last stream 3, ENHANCE_YOUR_CALM, \"hi\""
+        );
+        assert!(!looks_like_http2(&raw));
+
+        let raw = hex::decode("000004060000000000").unwrap();
+        assert_eq!(Frame::parse(&raw), Ok(None));
+        let raw = hex::decode("00000406000000000001020304").unwrap();
+        assert_eq!(
+            Frame::parse(&raw),
+            Err(Http2Error::InvalidFrame(FrameType::Ping))
+        );
+    }
+
+    #[test]
+    fn connection() {
+        let block = hex::decode(REQUEST_BLOCK).unwrap();
+        let mut client = CONNECTION_PREFACE.to_vec();
+        client.extend_from_slice(&hex::decode("000000040000000000").unwrap());
+
+        // The header block is split between HEADERS and CONTINUATION.
+        client.extend_from_slice(&hex::decode("000005010000000001").unwrap());
+        client.extend_from_slice(&block[..5]);
+        client.extend_from_slice(&hex::decode("00000c090400000001").unwrap());
+        client.extend_from_slice(&block[5..]);
+        client.extend_from_slice(&hex::decode("000002000100000001").unwrap());
+        client.extend_from_slice(b"hi");
+        client.extend_from_slice(&hex::decode("00000403000000000300000008").unwrap());
+
+        let server = hex::decode(
+            "000000040000000000\
+             00000101040000000188\
+             00000300000000000168690a\
+             00000f010500000001400b677270632d7374617475730130",
+        )
+        .unwrap();
+
+        let at = Duration::from_secs(1);
+        let mut connection = StreamHandler::detect([40000, 50051], &client).unwrap();
+        Http2Connection::push(
+            &mut connection,
+            Direction::ClientToServer,
+            &client[..10],
+            at,
+        );
+        Http2Connection::push(
+            &mut connection,
+            Direction::ClientToServer,
+            &client[10..],
+            at,
+        );
+        Http2Connection::push(&mut connection, Direction::ServerToClient, &server, at);
+
+        assert_eq!(connection.frames().len(), 9);
+        let streams = connection.streams();
+        assert_eq!(streams.len(), 2);
+
+        let request = streams[0].request();
+        assert_eq!(request.header(":authority"), Some("www.example.com"));
+        assert_eq!(request.headers().len(), 4);
+        assert_eq!(request.data(), b"hi");
+        assert!(request.is_ended());
+
+        let response = streams[0].response();
+        assert_eq!(response.header(":status"), Some("200"));
+        assert_eq!(response.data(), b"hi\n");
+        assert_eq!(
+            response.trailers(),
+            &[("grpc-status".to_string(), "0".to_string())][..]
+        );
+        assert!(response.is_ended());
+        assert_eq!(streams[0].frames(), 6);
+        assert_eq!(
+            streams[0].to_string(),
+            "stream 1: GET /, 2 bytes -> 200, 3 bytes"
+        );
+
+        assert_eq!(streams[1].reset(), Some(8));
+        assert_eq!(
+            streams[1].to_string(),
+            "stream 3: (no request), 0 bytes -> (no response), reset with CANCEL"
+        );
+    }
+
+    #[test]
+    fn upgrade() {
+        let request = b"GET /chat HTTP/1.1\r\nHost: example.com\r\nUpgrade: h2c\r\n\r\n";
+        assert!(
+            <Http2Connection as StreamHandler>::detect([40000, 80], b"GET / HTTP/1.1\r\n\r\n")
+                .is_none()
+        );
+        let mut connection = StreamHandler::detect([40000, 80], request).unwrap();
+
+        let mut client = request.to_vec();
+        client.extend_from_slice(CONNECTION_PREFACE);
+        let mut server = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: h2c\r\n\r\n".to_vec();
+        server.extend_from_slice(&hex::decode("00000101050000000188").unwrap());
+
+        let at = Duration::from_secs(1);
+        Http2Connection::push(&mut connection, Direction::ClientToServer, &client, at);
+        Http2Connection::push(&mut connection, Direction::ServerToClient, &server, at);
+
+        let streams = connection.streams();
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].request().header(":path"), Some("/chat"));
+        assert_eq!(streams[0].request().header("host"), Some("example.com"));
+        assert_eq!(streams[0].response().header(":status"), Some("200"));
+        assert!(streams[0].response().is_ended());
+    }
+}
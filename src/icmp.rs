@@ -1,4 +1,6 @@
 use nom::{be_u16, be_u8, IResult};
+use std::fmt;
+use std::fmt::Formatter;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct IcmpHeader {
@@ -13,6 +15,21 @@ impl IcmpHeader {
     }
 }
 
+impl fmt::Display for IcmpHeader {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.packet_type.nice_name() {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "Type {}", self.packet_type.value)?,
+        }
+
+        if self.code != 0 {
+            write!(f, " Code={}", self.code)?;
+        }
+
+        Ok(())
+    }
+}
+
 named!(pub parse_icmp_header<IcmpHeader>, do_parse!(
     packet_type: be_u8 >>
     code:        be_u8 >>
@@ -35,7 +52,7 @@ impl PacketType {
         PacketType { value }
     }
 
-    fn nice_name(&self) -> Option<&'static str> {
+    pub fn nice_name(&self) -> Option<&'static str> {
         match self.value {
             0 => Some("Echo reply"),
             3 => Some("Destination unreachable"),
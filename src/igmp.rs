@@ -0,0 +1,449 @@
+use ip::parse_ip_addr;
+use nom::{be_u16, be_u8, rest, IResult};
+use std::fmt;
+use std::fmt::Formatter;
+use std::net::Ipv4Addr;
+use util::take_bool;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct IgmpPacket {
+    checksum: u16,
+    message: IgmpMessage,
+}
+
+impl IgmpPacket {
+    pub fn parse(input: &[u8]) -> IResult<&[u8], IgmpPacket> {
+        parse_igmp_packet(input)
+    }
+
+    pub fn message(&self) -> &IgmpMessage {
+        &self.message
+    }
+
+    /// The version of IGMP the message belongs to. Queries are told apart by
+    /// their length and maximum response time, as described in RFC 3376
+    /// section 7.1.
+    pub fn version(&self) -> u8 {
+        match self.message {
+            IgmpMessage::Query {
+                max_response_code: 0,
+                details: None,
+                ..
+            } => 1,
+            IgmpMessage::Query { details: None, .. } => 2,
+            IgmpMessage::Query { .. } => 3,
+            IgmpMessage::ReportV1 { .. } => 1,
+            IgmpMessage::ReportV2 { .. } | IgmpMessage::LeaveV2 { .. } => 2,
+            IgmpMessage::ReportV3 { .. } => 3,
+            IgmpMessage::Other { .. } => 0,
+        }
+    }
+
+    /// The groups the sending host joined or left with this message.
+    pub fn membership_changes(&self) -> Vec<MembershipChange> {
+        match self.message {
+            IgmpMessage::ReportV1 { group } | IgmpMessage::ReportV2 { group } => {
+                vec![MembershipChange::new(Membership::Joined, group, Vec::new())]
+            }
+            IgmpMessage::LeaveV2 { group } => {
+                vec![MembershipChange::new(Membership::Left, group, Vec::new())]
+            }
+            IgmpMessage::ReportV3 { ref records } => {
+                records.iter().map(GroupRecord::membership_change).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for IgmpPacket {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "v{} ", self.version())?;
+
+        match self.message {
+            IgmpMessage::Query { group, .. } if group == Ipv4Addr::UNSPECIFIED => {
+                return write!(f, "General query")
+            }
+            IgmpMessage::Query { group, .. } => return write!(f, "Query for {}", group),
+            IgmpMessage::Other { message_type, .. } => {
+                return write!(f, "Type {:#04x}", message_type)
+            }
+            IgmpMessage::LeaveV2 { .. } => write!(f, "Leave")?,
+            _ => write!(f, "Report")?,
+        }
+
+        let changes: Vec<String> = self
+            .membership_changes()
+            .iter()
+            .map(MembershipChange::to_string)
+            .collect();
+        write!(f, ": {}", changes.join(", "))
+    }
+}
+
+named!(
+    pub parse_igmp_packet<IgmpPacket>,
+    do_parse!(
+        message_type: be_u8 >>
+        code:         be_u8 >>
+        checksum:     be_u16 >>
+        message:      call!(parse_igmp_message, message_type, code) >>
+
+        (IgmpPacket {
+            checksum,
+            message,
+        })
+    )
+);
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum IgmpMessage {
+    /// A membership query. `group` is unspecified for general queries.
+    /// Version 3 queries carry `details`.
+    Query {
+        max_response_code: u8,
+        group: Ipv4Addr,
+        details: Option<QueryDetails>,
+    },
+
+    ReportV1 {
+        group: Ipv4Addr,
+    },
+
+    ReportV2 {
+        group: Ipv4Addr,
+    },
+
+    LeaveV2 {
+        group: Ipv4Addr,
+    },
+
+    ReportV3 {
+        records: Vec<GroupRecord>,
+    },
+
+    Other {
+        message_type: u8,
+        data: Vec<u8>,
+    },
+}
+
+fn parse_igmp_message(input: &[u8], message_type: u8, code: u8) -> IResult<&[u8], IgmpMessage> {
+    match message_type {
+        0x11 if input.len() >= 8 => do_parse!(
+            input,
+            group:   parse_ip_addr >>
+            details: parse_query_details >>
+            (IgmpMessage::Query {
+                max_response_code: code,
+                group,
+                details: Some(details),
+            })
+        ),
+        0x11 => map!(input, parse_ip_addr, |group| IgmpMessage::Query {
+            max_response_code: code,
+            group,
+            details: None,
+        }),
+        0x12 => map!(input, parse_ip_addr, |group| IgmpMessage::ReportV1 {
+            group
+        }),
+        0x16 => map!(input, parse_ip_addr, |group| IgmpMessage::ReportV2 {
+            group
+        }),
+        0x17 => map!(input, parse_ip_addr, |group| IgmpMessage::LeaveV2 { group }),
+        0x22 => do_parse!(
+            input,
+                           be_u16 >>
+            record_count:  be_u16 >>
+            records:       count!(parse_group_record, record_count as usize) >>
+            (IgmpMessage::ReportV3 { records })
+        ),
+        _ => map!(input, rest, |data| IgmpMessage::Other {
+            message_type,
+            data: data.to_vec(),
+        }),
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct QueryDetails {
+    suppress_router_processing: bool,
+    robustness_variable: u8,
+    query_interval_code: u8,
+    sources: Vec<Ipv4Addr>,
+}
+
+named!(
+    parse_query_details<QueryDetails>,
+    do_parse!(
+        flags:               bits!(tuple!(
+                                 take_bits!(u8, 4),
+                                 take_bool,
+                                 take_bits!(u8, 3)
+                             )) >>
+        query_interval_code: be_u8 >>
+        source_count:        be_u16 >>
+        sources:             count!(parse_ip_addr, source_count as usize) >>
+
+        (QueryDetails {
+            suppress_router_processing: flags.1,
+            robustness_variable: flags.2,
+            query_interval_code,
+            sources,
+        })
+    )
+);
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct GroupRecord {
+    record_type: RecordType,
+    group: Ipv4Addr,
+    sources: Vec<Ipv4Addr>,
+    auxiliary_data: Vec<u8>,
+}
+
+impl GroupRecord {
+    /// Interprets the record following the host behaviour in RFC 3376
+    /// section 5. A change to include mode with no sources is how version 3
+    /// hosts leave a group, and blocking sources leaves those sources.
+    /// Records of unknown type are reported as such.
+    fn membership_change(&self) -> MembershipChange {
+        let membership = match self.record_type {
+            RecordType::ModeIsInclude | RecordType::ChangeToIncludeMode
+                if self.sources.is_empty() =>
+            {
+                Membership::Left
+            }
+            RecordType::ModeIsInclude
+            | RecordType::ChangeToIncludeMode
+            | RecordType::ModeIsExclude
+            | RecordType::ChangeToExcludeMode
+            | RecordType::AllowNewSources => Membership::Joined,
+            RecordType::BlockOldSources => Membership::Left,
+            RecordType::Other(record_type) => Membership::Unknown(record_type),
+        };
+
+        let sources = match self.record_type {
+            RecordType::ModeIsExclude | RecordType::ChangeToExcludeMode => Vec::new(),
+            _ => self.sources.clone(),
+        };
+
+        MembershipChange::new(membership, self.group, sources)
+    }
+}
+
+named!(
+    parse_group_record<GroupRecord>,
+    do_parse!(
+        record_type:      be_u8 >>
+        auxiliary_length: be_u8 >>
+        source_count:     be_u16 >>
+        group:            parse_ip_addr >>
+        sources:          count!(parse_ip_addr, source_count as usize) >>
+        auxiliary_data:   take!(auxiliary_length as usize * 4) >>
+
+        (GroupRecord {
+            record_type: RecordType::new(record_type),
+            group,
+            sources,
+            auxiliary_data: auxiliary_data.to_vec(),
+        })
+    )
+);
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum RecordType {
+    ModeIsInclude,
+    ModeIsExclude,
+    ChangeToIncludeMode,
+    ChangeToExcludeMode,
+    AllowNewSources,
+    BlockOldSources,
+    Other(u8),
+}
+
+impl RecordType {
+    pub fn new(value: u8) -> RecordType {
+        match value {
+            1 => RecordType::ModeIsInclude,
+            2 => RecordType::ModeIsExclude,
+            3 => RecordType::ChangeToIncludeMode,
+            4 => RecordType::ChangeToExcludeMode,
+            5 => RecordType::AllowNewSources,
+            6 => RecordType::BlockOldSources,
+            _ => RecordType::Other(value),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Membership {
+    Joined,
+    Left,
+
+    /// A group record of a type RFC 3376 doesn't define, which says nothing
+    /// about whether the host is a member.
+    Unknown(u8),
+}
+
+/// A group joined or left by a host. `sources` lists the sources the change
+/// applies to for source-specific membership; it is empty when the change
+/// applies to every source.
+#[derive(Debug, Eq, PartialEq)]
+pub struct MembershipChange {
+    membership: Membership,
+    group: Ipv4Addr,
+    sources: Vec<Ipv4Addr>,
+}
+
+impl MembershipChange {
+    fn new(membership: Membership, group: Ipv4Addr, sources: Vec<Ipv4Addr>) -> MembershipChange {
+        MembershipChange {
+            membership,
+            group,
+            sources,
+        }
+    }
+}
+
+impl fmt::Display for MembershipChange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.membership {
+            Membership::Joined => write!(f, "joined {}", self.group)?,
+            Membership::Left => write!(f, "left {}", self.group)?,
+            Membership::Unknown(record_type) => {
+                write!(f, "sent record type {} for {}", record_type, self.group)?
+            }
+        }
+
+        if !self.sources.is_empty() {
+            let sources: Vec<String> = self.sources.iter().map(Ipv4Addr::to_string).collect();
+            write!(f, " for sources {}", sources.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use igmp::{
+        GroupRecord, IgmpMessage, IgmpPacket, Membership, MembershipChange, QueryDetails,
+        RecordType,
+    };
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn parse_v2_query() {
+        let raw = hex::decode("1164ee9b00000000").unwrap();
+        let (_, packet) = IgmpPacket::parse(&raw).unwrap();
+        assert_eq!(
+            packet,
+            IgmpPacket {
+                checksum: 0xee9b,
+                message: IgmpMessage::Query {
+                    max_response_code: 100,
+                    group: Ipv4Addr::new(0, 0, 0, 0),
+                    details: None,
+                },
+            }
+        );
+        assert_eq!(packet.version(), 2);
+    }
+
+    #[test]
+    fn parse_v3_query() {
+        let raw = hex::decode("11640000e901010102140001c0a80001").unwrap();
+        let (_, packet) = IgmpPacket::parse(&raw).unwrap();
+        assert_eq!(
+            packet.message,
+            IgmpMessage::Query {
+                max_response_code: 100,
+                group: Ipv4Addr::new(233, 1, 1, 1),
+                details: Some(QueryDetails {
+                    suppress_router_processing: false,
+                    robustness_variable: 2,
+                    query_interval_code: 20,
+                    sources: vec![Ipv4Addr::new(192, 168, 0, 1)],
+                }),
+            }
+        );
+        assert_eq!(packet.version(), 3);
+    }
+
+    #[test]
+    fn parse_v2_leave() {
+        let raw = hex::decode("17000000ef010203").unwrap();
+        let (_, packet) = IgmpPacket::parse(&raw).unwrap();
+        assert_eq!(
+            packet.membership_changes(),
+            vec![MembershipChange {
+                membership: Membership::Left,
+                group: Ipv4Addr::new(239, 1, 2, 3),
+                sources: Vec::new(),
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_v3_report() {
+        let raw = hex::decode(
+            "2200000000000004\
+             04000000ef010101\
+             03000000ef020202\
+             01000002e8010101c0a80001c0a80002\
+             09000000ef030303",
+        )
+        .unwrap();
+        let (_, packet) = IgmpPacket::parse(&raw).unwrap();
+        assert_eq!(
+            packet.message,
+            IgmpMessage::ReportV3 {
+                records: vec![
+                    GroupRecord {
+                        record_type: RecordType::ChangeToExcludeMode,
+                        group: Ipv4Addr::new(239, 1, 1, 1),
+                        sources: Vec::new(),
+                        auxiliary_data: Vec::new(),
+                    },
+                    GroupRecord {
+                        record_type: RecordType::ChangeToIncludeMode,
+                        group: Ipv4Addr::new(239, 2, 2, 2),
+                        sources: Vec::new(),
+                        auxiliary_data: Vec::new(),
+                    },
+                    GroupRecord {
+                        record_type: RecordType::ModeIsInclude,
+                        group: Ipv4Addr::new(232, 1, 1, 1),
+                        sources: vec![Ipv4Addr::new(192, 168, 0, 1), Ipv4Addr::new(192, 168, 0, 2)],
+                        auxiliary_data: Vec::new(),
+                    },
+                    GroupRecord {
+                        record_type: RecordType::Other(9),
+                        group: Ipv4Addr::new(239, 3, 3, 3),
+                        sources: Vec::new(),
+                        auxiliary_data: Vec::new(),
+                    },
+                ],
+            }
+        );
+
+        let changes: Vec<String> = packet
+            .membership_changes()
+            .iter()
+            .map(MembershipChange::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                "joined 239.1.1.1",
+                "left 239.2.2.2",
+                "joined 232.1.1.1 for sources 192.168.0.1, 192.168.0.2",
+                "sent record type 9 for 239.3.3.3",
+            ]
+        );
+    }
+}
@@ -1,5 +1,8 @@
+use ipv6::Ipv6Header;
 use nom::{be_u16, be_u8, IResult};
-use std::net::Ipv4Addr;
+use std::fmt;
+use std::fmt::Formatter;
+use std::net::{IpAddr, Ipv4Addr};
 use util::take_bool;
 
 #[derive(Debug, Eq, PartialEq)]
@@ -30,13 +33,111 @@ impl Ipv4Header {
     pub fn protocol(&self) -> &Protocol {
         &self.protocol
     }
+
+    pub fn source(&self) -> &Ipv4Addr {
+        &self.source
+    }
+
+    pub fn destination(&self) -> &Ipv4Addr {
+        &self.destination
+    }
+
+    /// Length of the header in bytes, including options.
+    pub fn header_length(&self) -> u8 {
+        self.internet_header_length
+    }
+
+    pub fn total_length(&self) -> u16 {
+        self.total_length
+    }
+
+    pub fn more_fragments(&self) -> bool {
+        self.more_fragments
+    }
+
+    /// Where the payload starts in the original datagram, in bytes.
+    pub fn fragment_offset(&self) -> usize {
+        self.fragment_offset as usize * 8
+    }
+
+    /// Whether the packet carries only part of a datagram.
+    pub fn is_fragment(&self) -> bool {
+        self.more_fragments || self.fragment_offset != 0
+    }
+
+    /// Number of payload bytes following the header according to
+    /// `total_length`. `None` when `total_length` is zero, which happens for
+    /// packets captured on the sending host before segmentation offload
+    /// fills the field in.
+    pub fn payload_length(&self) -> Option<usize> {
+        if self.total_length == 0 {
+            return None;
+        }
+
+        Some((self.total_length as usize).saturating_sub(self.internet_header_length as usize))
+    }
+}
+
+/// The header of either version of IP.
+#[derive(Debug, Eq, PartialEq)]
+pub enum IpHeader {
+    V4(Ipv4Header),
+    V6(Ipv6Header),
+}
+
+impl IpHeader {
+    /// The protocol of the payload following the header.
+    pub fn protocol(&self) -> &Protocol {
+        match self {
+            IpHeader::V4(header) => header.protocol(),
+            IpHeader::V6(header) => header.next_header(),
+        }
+    }
+
+    /// Whether the packet carries only part of a datagram. IPv6 fragment
+    /// headers aren't decoded, so this is only ever true for IPv4.
+    pub fn is_fragment(&self) -> bool {
+        match self {
+            IpHeader::V4(header) => header.is_fragment(),
+            IpHeader::V6(_) => false,
+        }
+    }
+
+    /// Where the payload starts in the original datagram, in bytes.
+    pub fn fragment_offset(&self) -> usize {
+        match self {
+            IpHeader::V4(header) => header.fragment_offset(),
+            IpHeader::V6(_) => 0,
+        }
+    }
+
+    pub fn payload_length(&self) -> Option<usize> {
+        match self {
+            IpHeader::V4(header) => header.payload_length(),
+            IpHeader::V6(header) => header.payload_length(),
+        }
+    }
+
+    pub fn source(&self) -> IpAddr {
+        match self {
+            IpHeader::V4(header) => IpAddr::V4(*header.source()),
+            IpHeader::V6(header) => IpAddr::V6(*header.source()),
+        }
+    }
+
+    pub fn destination(&self) -> IpAddr {
+        match self {
+            IpHeader::V4(header) => IpAddr::V4(*header.destination()),
+            IpHeader::V6(header) => IpAddr::V6(*header.destination()),
+        }
+    }
 }
 
 named!(
     pub parse_ip_header<Ipv4Header>,
     bits!(do_parse!(
                                 tag_bits!(u8, 4, 4) >>
-        internet_header_length: take_bits!(u8, 4) >>
+        internet_header_length: verify!(take_bits!(u8, 4), |length| length >= 5) >>
         type_of_service:        bytes!(parse_ip_tos) >>
         total_length:           bytes!(be_u16) >>
         identification:         bytes!(be_u16) >>
@@ -112,25 +213,208 @@ named!(pub parse_ip_tos<TypeOfService>,
     )
 );
 
-#[derive(Debug, Eq, PartialEq)]
-pub enum Protocol {
-    ICMP,
-    TCP,
-    UDP,
-    Other(u8),
+/// Generates the `Protocol` enum along with the conversions to and from the
+/// assigned number and the keyword from the IANA registry.
+macro_rules! protocols {
+    ($($number:expr => $variant:ident, $name:expr;)*) => {
+        #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+        pub enum Protocol {
+            $($variant,)*
+            Other(u8),
+        }
+
+        impl Protocol {
+            pub fn from_number(num: u8) -> Protocol {
+                match num {
+                    $($number => Protocol::$variant,)*
+                    _ => Protocol::Other(num),
+                }
+            }
+
+            pub fn number(&self) -> u8 {
+                match *self {
+                    $(Protocol::$variant => $number,)*
+                    Protocol::Other(num) => num,
+                }
+            }
+
+            /// The keyword assigned to the protocol by IANA. `None` for
+            /// unassigned, experimental and reserved numbers.
+            pub fn name(&self) -> Option<&'static str> {
+                match *self {
+                    $(Protocol::$variant => Some($name),)*
+                    Protocol::Other(_) => None,
+                }
+            }
+        }
+    };
+}
+
+// From: https://www.iana.org/assignments/protocol-numbers/protocol-numbers.xhtml
+protocols! {
+    0x00 => HOPOPT, "HOPOPT";
+    0x01 => ICMP, "ICMP";
+    0x02 => IGMP, "IGMP";
+    0x03 => GGP, "GGP";
+    0x04 => IPv4, "IPv4";
+    0x05 => ST, "ST";
+    0x06 => TCP, "TCP";
+    0x07 => CBT, "CBT";
+    0x08 => EGP, "EGP";
+    0x09 => IGP, "IGP";
+    0x0a => BbnRccMon, "BBN-RCC-MON";
+    0x0b => NvpII, "NVP-II";
+    0x0c => PUP, "PUP";
+    0x0d => ARGUS, "ARGUS";
+    0x0e => EMCON, "EMCON";
+    0x0f => XNET, "XNET";
+    0x10 => CHAOS, "CHAOS";
+    0x11 => UDP, "UDP";
+    0x12 => MUX, "MUX";
+    0x13 => DcnMeas, "DCN-MEAS";
+    0x14 => HMP, "HMP";
+    0x15 => PRM, "PRM";
+    0x16 => XnsIdp, "XNS-IDP";
+    0x17 => Trunk1, "TRUNK-1";
+    0x18 => Trunk2, "TRUNK-2";
+    0x19 => Leaf1, "LEAF-1";
+    0x1a => Leaf2, "LEAF-2";
+    0x1b => RDP, "RDP";
+    0x1c => IRTP, "IRTP";
+    0x1d => IsoTp4, "ISO-TP4";
+    0x1e => NETBLT, "NETBLT";
+    0x1f => MfeNsp, "MFE-NSP";
+    0x20 => MeritInp, "MERIT-INP";
+    0x21 => DCCP, "DCCP";
+    0x22 => ThreePC, "3PC";
+    0x23 => IDPR, "IDPR";
+    0x24 => XTP, "XTP";
+    0x25 => DDP, "DDP";
+    0x26 => IdprCmtp, "IDPR-CMTP";
+    0x27 => TPPlusPlus, "TP++";
+    0x28 => IL, "IL";
+    0x29 => IPv6, "IPv6";
+    0x2a => SDRP, "SDRP";
+    0x2b => IPv6Route, "IPv6-Route";
+    0x2c => IPv6Frag, "IPv6-Frag";
+    0x2d => IDRP, "IDRP";
+    0x2e => RSVP, "RSVP";
+    0x2f => GRE, "GRE";
+    0x30 => DSR, "DSR";
+    0x31 => BNA, "BNA";
+    0x32 => ESP, "ESP";
+    0x33 => AH, "AH";
+    0x34 => INLSP, "I-NLSP";
+    0x35 => SWIPE, "SWIPE";
+    0x36 => NARP, "NARP";
+    0x37 => MinIPv4, "Min-IPv4";
+    0x38 => TLSP, "TLSP";
+    0x39 => SKIP, "SKIP";
+    0x3a => IPv6ICMP, "IPv6-ICMP";
+    0x3b => IPv6NoNxt, "IPv6-NoNxt";
+    0x3c => IPv6Opts, "IPv6-Opts";
+    0x3d => HostInternal, "Any host internal protocol";
+    0x3e => CFTP, "CFTP";
+    0x3f => LocalNetwork, "Any local network";
+    0x40 => SatExpak, "SAT-EXPAK";
+    0x41 => KRYPTOLAN, "KRYPTOLAN";
+    0x42 => RVD, "RVD";
+    0x43 => IPPC, "IPPC";
+    0x44 => DistributedFileSystem, "Any distributed file system";
+    0x45 => SatMon, "SAT-MON";
+    0x46 => VISA, "VISA";
+    0x47 => IPCV, "IPCV";
+    0x48 => CPNX, "CPNX";
+    0x49 => CPHB, "CPHB";
+    0x4a => WSN, "WSN";
+    0x4b => PVP, "PVP";
+    0x4c => BrSatMon, "BR-SAT-MON";
+    0x4d => SunNd, "SUN-ND";
+    0x4e => WbMon, "WB-MON";
+    0x4f => WbExpak, "WB-EXPAK";
+    0x50 => IsoIp, "ISO-IP";
+    0x51 => VMTP, "VMTP";
+    0x52 => SecureVmtp, "SECURE-VMTP";
+    0x53 => VINES, "VINES";
+    0x54 => IPTM, "IPTM";
+    0x55 => NsfnetIgp, "NSFNET-IGP";
+    0x56 => DGP, "DGP";
+    0x57 => TCF, "TCF";
+    0x58 => EIGRP, "EIGRP";
+    0x59 => OSPF, "OSPFIGP";
+    0x5a => SpriteRpc, "Sprite-RPC";
+    0x5b => LARP, "LARP";
+    0x5c => MTP, "MTP";
+    0x5d => AX25, "AX.25";
+    0x5e => IPIP, "IPIP";
+    0x5f => MICP, "MICP";
+    0x60 => SccSp, "SCC-SP";
+    0x61 => ETHERIP, "ETHERIP";
+    0x62 => ENCAP, "ENCAP";
+    0x63 => PrivateEncryption, "Any private encryption scheme";
+    0x64 => GMTP, "GMTP";
+    0x65 => IFMP, "IFMP";
+    0x66 => PNNI, "PNNI";
+    0x67 => PIM, "PIM";
+    0x68 => ARIS, "ARIS";
+    0x69 => SCPS, "SCPS";
+    0x6a => QNX, "QNX";
+    0x6b => ActiveNetworks, "A/N";
+    0x6c => IPComp, "IPComp";
+    0x6d => SNP, "SNP";
+    0x6e => CompaqPeer, "Compaq-Peer";
+    0x6f => IPXInIP, "IPX-in-IP";
+    0x70 => VRRP, "VRRP";
+    0x71 => PGM, "PGM";
+    0x72 => ZeroHop, "Any 0-hop protocol";
+    0x73 => L2TP, "L2TP";
+    0x74 => DDX, "DDX";
+    0x75 => IATP, "IATP";
+    0x76 => STP, "STP";
+    0x77 => SRP, "SRP";
+    0x78 => UTI, "UTI";
+    0x79 => SMP, "SMP";
+    0x7a => SM, "SM";
+    0x7b => PTP, "PTP";
+    0x7c => ISISOverIPv4, "ISIS over IPv4";
+    0x7d => FIRE, "FIRE";
+    0x7e => CRTP, "CRTP";
+    0x7f => CRUDP, "CRUDP";
+    0x80 => SSCOPMCE, "SSCOPMCE";
+    0x81 => IPLT, "IPLT";
+    0x82 => SPS, "SPS";
+    0x83 => PIPE, "PIPE";
+    0x84 => SCTP, "SCTP";
+    0x85 => FC, "FC";
+    0x86 => RsvpE2eIgnore, "RSVP-E2E-IGNORE";
+    0x87 => MobilityHeader, "Mobility Header";
+    0x88 => UDPLite, "UDPLite";
+    0x89 => MPLSInIP, "MPLS-in-IP";
+    0x8a => MANET, "manet";
+    0x8b => HIP, "HIP";
+    0x8c => Shim6, "Shim6";
+    0x8d => WESP, "WESP";
+    0x8e => ROHC, "ROHC";
+    0x8f => Ethernet, "Ethernet";
+    0x90 => AGGFRAG, "AGGFRAG";
+    0x91 => NSH, "NSH";
 }
 
-impl Protocol {
-    pub fn from_number(num: u8) -> Protocol {
-        match num {
-            0x01 => Protocol::ICMP,
-            0x06 => Protocol::TCP,
-            0x11 => Protocol::UDP,
-            _ => Protocol::Other(num),
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "Other({})", self.number()),
         }
     }
 }
 
+impl fmt::Debug for Protocol {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     extern crate hex;
@@ -186,4 +470,20 @@ mod tests {
             },
         )
     }
+
+    #[test]
+    fn protocol_numbers_round_trip() {
+        for num in 0..=255u8 {
+            assert_eq!(Protocol::from_number(num).number(), num);
+        }
+    }
+
+    #[test]
+    fn protocol_names() {
+        assert_eq!(Protocol::from_number(47), Protocol::GRE);
+        assert_eq!(format!("{:?}", Protocol::from_number(2)), "IGMP");
+        assert_eq!(format!("{}", Protocol::from_number(89)), "OSPFIGP");
+        assert_eq!(format!("{}", Protocol::from_number(58)), "IPv6-ICMP");
+        assert_eq!(format!("{}", Protocol::from_number(200)), "Other(200)");
+    }
 }
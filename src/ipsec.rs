@@ -0,0 +1,304 @@
+use ip::Protocol;
+use nom::{be_u16, be_u32, be_u8, IResult};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Formatter;
+use std::net::IpAddr;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct EspHeader {
+    security_parameters_index: u32,
+    sequence_number: u32,
+}
+
+impl EspHeader {
+    pub fn parse(input: &[u8]) -> IResult<&[u8], EspHeader> {
+        parse_esp_header(input)
+    }
+
+    pub fn security_parameters_index(&self) -> u32 {
+        self.security_parameters_index
+    }
+
+    pub fn sequence_number(&self) -> u32 {
+        self.sequence_number
+    }
+}
+
+impl fmt::Display for EspHeader {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SPI={:#010x} Seq={}",
+            self.security_parameters_index, self.sequence_number
+        )
+    }
+}
+
+named!(
+    pub parse_esp_header<EspHeader>,
+    do_parse!(
+        security_parameters_index: be_u32 >>
+        sequence_number:           be_u32 >>
+
+        (EspHeader {
+            security_parameters_index,
+            sequence_number,
+        })
+    )
+);
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct AhHeader {
+    next_header: Protocol,
+    payload_length: u8,
+    security_parameters_index: u32,
+    sequence_number: u32,
+    integrity_check_value: Vec<u8>,
+}
+
+impl AhHeader {
+    pub fn parse(input: &[u8]) -> IResult<&[u8], AhHeader> {
+        parse_ah_header(input)
+    }
+
+    pub fn next_header(&self) -> &Protocol {
+        &self.next_header
+    }
+
+    pub fn security_parameters_index(&self) -> u32 {
+        self.security_parameters_index
+    }
+
+    pub fn sequence_number(&self) -> u32 {
+        self.sequence_number
+    }
+}
+
+impl fmt::Display for AhHeader {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SPI={:#010x} Seq={}",
+            self.security_parameters_index, self.sequence_number
+        )
+    }
+}
+
+named!(
+    pub parse_ah_header<AhHeader>,
+    do_parse!(
+        next_header:               be_u8 >>
+        payload_length:            verify!(be_u8, |length| length >= 1) >>
+                                   be_u16 >>
+        security_parameters_index: be_u32 >>
+        sequence_number:           be_u32 >>
+        integrity_check_value:     take!((payload_length as usize + 2) * 4 - 12) >>
+
+        (AhHeader {
+            next_header: Protocol::from_number(next_header),
+            payload_length,
+            security_parameters_index,
+            sequence_number,
+            integrity_check_value: integrity_check_value.to_vec(),
+        })
+    )
+);
+
+/// One direction of an IPsec connection, identified by the protocol, the
+/// endpoints and the security parameters index.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct SecurityAssociation {
+    protocol: Protocol,
+    source: IpAddr,
+    destination: IpAddr,
+    security_parameters_index: u32,
+}
+
+impl SecurityAssociation {
+    pub fn new(
+        protocol: Protocol,
+        source: IpAddr,
+        destination: IpAddr,
+        security_parameters_index: u32,
+    ) -> SecurityAssociation {
+        SecurityAssociation {
+            protocol,
+            source,
+            destination,
+            security_parameters_index,
+        }
+    }
+}
+
+impl fmt::Display for SecurityAssociation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} SPI {:#010x} {} -> {}",
+            self.protocol, self.security_parameters_index, self.source, self.destination
+        )
+    }
+}
+
+/// Collects the sequence numbers seen for each security association so gaps
+/// and replayed packets can be reported.
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    associations: BTreeMap<SecurityAssociation, SequenceReport>,
+}
+
+impl SequenceTracker {
+    pub fn new() -> SequenceTracker {
+        SequenceTracker::default()
+    }
+
+    pub fn record(&mut self, association: SecurityAssociation, sequence_number: u32) {
+        self.associations
+            .entry(association)
+            .or_default()
+            .record(sequence_number);
+    }
+
+    pub fn reports(&self) -> impl Iterator<Item = (&SecurityAssociation, &SequenceReport)> {
+        self.associations.iter()
+    }
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct SequenceReport {
+    packets: u64,
+    highest: Option<u32>,
+    seen: BTreeSet<u32>,
+
+    /// Packets whose sequence number was already seen.
+    replayed: u64,
+
+    /// Packets which arrived after one with a higher sequence number.
+    reordered: u64,
+}
+
+impl SequenceReport {
+    fn record(&mut self, sequence_number: u32) {
+        self.packets += 1;
+
+        if !self.seen.insert(sequence_number) {
+            self.replayed += 1;
+            return;
+        }
+
+        match self.highest {
+            Some(highest) if sequence_number < highest => self.reordered += 1,
+            _ => self.highest = Some(sequence_number),
+        }
+    }
+
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    pub fn replayed(&self) -> u64 {
+        self.replayed
+    }
+
+    pub fn reordered(&self) -> u64 {
+        self.reordered
+    }
+
+    pub fn first(&self) -> Option<u32> {
+        self.seen.iter().next().cloned()
+    }
+
+    pub fn last(&self) -> Option<u32> {
+        self.highest
+    }
+
+    /// Ranges of sequence numbers, inclusive, which were never seen between
+    /// the lowest and highest ones which were.
+    pub fn gaps(&self) -> Vec<(u32, u32)> {
+        let mut gaps = Vec::new();
+        let mut previous: Option<u32> = None;
+
+        for &sequence_number in &self.seen {
+            if let Some(previous) = previous {
+                if sequence_number > previous + 1 {
+                    gaps.push((previous + 1, sequence_number - 1));
+                }
+            }
+            previous = Some(sequence_number);
+        }
+
+        gaps
+    }
+
+    pub fn missing(&self) -> u64 {
+        self.gaps()
+            .iter()
+            .map(|&(start, end)| u64::from(end - start) + 1)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use ip::Protocol;
+    use ipsec::{AhHeader, EspHeader, SecurityAssociation, SequenceTracker};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn parse_esp() {
+        let raw = hex::decode("0000100100000005deadbeef").unwrap();
+        let (rest, header) = EspHeader::parse(&raw).unwrap();
+        assert_eq!(rest, &[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(
+            header,
+            EspHeader {
+                security_parameters_index: 0x1001,
+                sequence_number: 5,
+            }
+        )
+    }
+
+    #[test]
+    fn parse_ah() {
+        let raw = hex::decode("0604000000001002000000070102030405060708090a0b0c").unwrap();
+        let (rest, header) = AhHeader::parse(&raw).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            header,
+            AhHeader {
+                next_header: Protocol::TCP,
+                payload_length: 4,
+                security_parameters_index: 0x1002,
+                sequence_number: 7,
+                integrity_check_value: hex::decode("0102030405060708090a0b0c").unwrap(),
+            }
+        )
+    }
+
+    #[test]
+    fn sequence_report() {
+        let association = SecurityAssociation::new(
+            Protocol::ESP,
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            0x1001,
+        );
+
+        let mut tracker = SequenceTracker::new();
+        for &sequence_number in &[1, 2, 3, 6, 5, 5, 9] {
+            tracker.record(association, sequence_number);
+        }
+
+        let (_, report) = tracker.reports().next().unwrap();
+        assert_eq!(report.packets(), 7);
+        assert_eq!(report.first(), Some(1));
+        assert_eq!(report.last(), Some(9));
+        assert_eq!(report.gaps(), vec![(4, 4), (7, 8)]);
+        assert_eq!(report.missing(), 3);
+        assert_eq!(report.replayed(), 1);
+        assert_eq!(report.reordered(), 1);
+    }
+}
@@ -0,0 +1,109 @@
+use ip::Protocol;
+use nom::{be_u16, be_u8, IResult};
+use std::net::Ipv6Addr;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Ipv6Header {
+    traffic_class: u8,
+    flow_label: u32,
+    payload_length: u16,
+    next_header: Protocol,
+    hop_limit: u8,
+    source: Ipv6Addr,
+    destination: Ipv6Addr,
+}
+
+impl Ipv6Header {
+    pub fn parse(input: &[u8]) -> IResult<&[u8], Ipv6Header> {
+        parse_ipv6_header(input)
+    }
+
+    pub fn next_header(&self) -> &Protocol {
+        &self.next_header
+    }
+
+    pub fn source(&self) -> &Ipv6Addr {
+        &self.source
+    }
+
+    pub fn destination(&self) -> &Ipv6Addr {
+        &self.destination
+    }
+
+    /// Number of payload bytes following the header. `None` when
+    /// `payload_length` is zero, which is used by jumbograms and by packets
+    /// captured before segmentation offload fills the field in.
+    pub fn payload_length(&self) -> Option<usize> {
+        if self.payload_length == 0 {
+            return None;
+        }
+
+        Some(self.payload_length as usize)
+    }
+}
+
+named!(
+    pub parse_ipv6_header<Ipv6Header>,
+    bits!(do_parse!(
+                        tag_bits!(u8, 4, 6) >>
+        traffic_class:  take_bits!(u8, 8) >>
+        flow_label:     take_bits!(u32, 20) >>
+        payload_length: bytes!(be_u16) >>
+        next_header:    bytes!(be_u8) >>
+        hop_limit:      bytes!(be_u8) >>
+        source:         bytes!(parse_ipv6_addr) >>
+        destination:    bytes!(parse_ipv6_addr) >>
+
+        (Ipv6Header {
+            traffic_class,
+            flow_label,
+            payload_length,
+            next_header: Protocol::from_number(next_header),
+            hop_limit,
+            source,
+            destination,
+        })
+    ))
+);
+
+named!(
+    pub parse_ipv6_addr<Ipv6Addr>,
+    do_parse!(
+        octets: take!(16) >>
+        ({
+            let mut value = [0; 16];
+            value.copy_from_slice(octets);
+            Ipv6Addr::from(value)
+        })
+    )
+);
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use ip::Protocol;
+    use ipv6::Ipv6Header;
+    use std::net::Ipv6Addr;
+
+    #[test]
+    fn parse_header() {
+        let raw = hex::decode(
+            "6e000001002c3a4020010db8000000000000000000000001ff0200000000000000000000000000fb",
+        )
+        .unwrap();
+        let (_, header) = Ipv6Header::parse(&raw).unwrap();
+        assert_eq!(
+            header,
+            Ipv6Header {
+                traffic_class: 0xe0,
+                flow_label: 1,
+                payload_length: 44,
+                next_header: Protocol::IPv6ICMP,
+                hop_limit: 64,
+                source: "2001:db8::1".parse::<Ipv6Addr>().unwrap(),
+                destination: "ff02::fb".parse::<Ipv6Addr>().unwrap(),
+            }
+        )
+    }
+}
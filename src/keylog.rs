@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use util::from_hex;
+
+#[derive(Fail, Debug, Eq, PartialEq)]
+pub enum KeyLogError {
+    #[fail(display = "Invalid key log entry on line {}", _0)]
+    InvalidEntry(usize),
+}
+
+/// The kinds of secret in a key log which are used for decryption.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum SecretLabel {
+    /// The TLS 1.2 master secret.
+    ClientRandom,
+    ClientHandshakeTrafficSecret,
+    ServerHandshakeTrafficSecret,
+    ClientTrafficSecret0,
+    ServerTrafficSecret0,
+}
+
+impl SecretLabel {
+    pub fn from_name(name: &str) -> Option<SecretLabel> {
+        match name {
+            "CLIENT_RANDOM" => Some(SecretLabel::ClientRandom),
+            "CLIENT_HANDSHAKE_TRAFFIC_SECRET" => Some(SecretLabel::ClientHandshakeTrafficSecret),
+            "SERVER_HANDSHAKE_TRAFFIC_SECRET" => Some(SecretLabel::ServerHandshakeTrafficSecret),
+            "CLIENT_TRAFFIC_SECRET_0" => Some(SecretLabel::ClientTrafficSecret0),
+            "SERVER_TRAFFIC_SECRET_0" => Some(SecretLabel::ServerTrafficSecret0),
+            _ => None,
+        }
+    }
+}
+
+/// Secrets in the NSS key log format, which browsers, curl and programs
+/// using OpenSSL write to the file named by `SSLKEYLOGFILE`. Each line holds
+/// a label, the client random of the session in hex and the secret in hex.
+#[derive(Debug, Default)]
+pub struct KeyLog {
+    secrets: HashMap<(SecretLabel, Vec<u8>), Vec<u8>>,
+}
+
+impl KeyLog {
+    pub fn new() -> KeyLog {
+        KeyLog::default()
+    }
+
+    /// Reads a key log. Comments, blank lines and labels that aren't used
+    /// for decryption are skipped.
+    pub fn parse(text: &str) -> Result<KeyLog, KeyLogError> {
+        let mut log = KeyLog::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(KeyLogError::InvalidEntry(index + 1));
+            }
+
+            let label = match SecretLabel::from_name(fields[0]) {
+                Some(label) => label,
+                None => continue,
+            };
+            let (client_random, secret) = match (from_hex(fields[1]), from_hex(fields[2])) {
+                (Some(client_random), Some(secret)) => (client_random, secret),
+                _ => return Err(KeyLogError::InvalidEntry(index + 1)),
+            };
+
+            log.insert(label, client_random, secret);
+        }
+
+        Ok(log)
+    }
+
+    pub fn insert(&mut self, label: SecretLabel, client_random: Vec<u8>, secret: Vec<u8>) {
+        self.secrets.insert((label, client_random), secret);
+    }
+
+    /// The secret logged for the session with `client_random`.
+    pub fn secret(&self, label: SecretLabel, client_random: &[u8]) -> Option<&[u8]> {
+        self.secrets
+            .get(&(label, client_random.to_vec()))
+            .map(|secret| &secret[..])
+    }
+
+    pub fn len(&self) -> usize {
+        self.secrets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use keylog::{KeyLog, KeyLogError, SecretLabel};
+
+    #[test]
+    fn parse() {
+        let log = KeyLog::parse(
+            "# SSL/TLS secrets log file, generated by NSS\n\
+             \n\
+             CLIENT_RANDOM 0a0b 0102\n\
+             SERVER_TRAFFIC_SECRET_0 0A0B 0304\n\
+             EXPORTER_SECRET 0a0b 0506\n",
+        )
+        .unwrap();
+
+        assert_eq!(log.len(), 2);
+        assert_eq!(
+            log.secret(SecretLabel::ClientRandom, &[0x0a, 0x0b]),
+            Some(&[0x01, 0x02][..])
+        );
+        assert_eq!(
+            log.secret(SecretLabel::ServerTrafficSecret0, &[0x0a, 0x0b]),
+            Some(&[0x03, 0x04][..])
+        );
+        assert_eq!(
+            log.secret(SecretLabel::ClientTrafficSecret0, &[0x0a, 0x0b]),
+            None
+        );
+
+        assert_eq!(
+            KeyLog::parse("CLIENT_RANDOM 0a0b\n").unwrap_err(),
+            KeyLogError::InvalidEntry(1)
+        );
+        assert_eq!(
+            KeyLog::parse("\nCLIENT_RANDOM 0a0b xyz\n").unwrap_err(),
+            KeyLogError::InvalidEntry(2)
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use connection::sequence_before;
+use flow::{Direction, TcpSegment};
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+
+/// Writes a duration as milliseconds with microsecond precision.
+fn write_millis(f: &mut Formatter, duration: Duration) -> fmt::Result {
+    write!(
+        f,
+        "{}.{:03}ms",
+        duration.as_millis(),
+        duration.subsec_micros() % 1000
+    )
+}
+
+/// Summary of a set of samples. The 95th percentile uses the nearest rank
+/// method.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Statistics {
+    count: usize,
+    minimum: Duration,
+    average: Duration,
+    maximum: Duration,
+    percentile_95: Duration,
+}
+
+impl Statistics {
+    pub fn new(samples: &[Duration]) -> Option<Statistics> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+
+        let count = sorted.len();
+        let total: Duration = sorted.iter().sum();
+        let rank = (count * 95).div_ceil(100);
+
+        Some(Statistics {
+            count,
+            minimum: sorted[0],
+            average: total / count as u32,
+            maximum: sorted[count - 1],
+            percentile_95: sorted[rank.max(1) - 1],
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn minimum(&self) -> Duration {
+        self.minimum
+    }
+
+    pub fn average(&self) -> Duration {
+        self.average
+    }
+
+    pub fn maximum(&self) -> Duration {
+        self.maximum
+    }
+
+    pub fn percentile_95(&self) -> Duration {
+        self.percentile_95
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} samples, min ", self.count)?;
+        write_millis(f, self.minimum)?;
+        write!(f, ", avg ")?;
+        write_millis(f, self.average)?;
+        write!(f, ", max ")?;
+        write_millis(f, self.maximum)?;
+        write!(f, ", p95 ")?;
+        write_millis(f, self.percentile_95)
+    }
+}
+
+/// When each packet of the three way handshake was seen.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Handshake {
+    /// The last SYN sent before the SYN-ACK, so that retransmitted SYNs
+    /// don't count the retransmission timeout.
+    syn: Option<Duration>,
+    syn_ack: Option<Duration>,
+    ack: Option<Duration>,
+    server_initial_sequence: Option<u32>,
+}
+
+impl Handshake {
+    /// Time the server took to answer the SYN, which is the round trip
+    /// between the capture point and the server.
+    pub fn syn_to_syn_ack(&self) -> Option<Duration> {
+        between(self.syn, self.syn_ack)
+    }
+
+    /// Time the client took to answer the SYN-ACK, which is the round trip
+    /// between the capture point and the client.
+    pub fn syn_ack_to_ack(&self) -> Option<Duration> {
+        between(self.syn_ack, self.ack)
+    }
+
+    pub fn total(&self) -> Option<Duration> {
+        between(self.syn, self.ack)
+    }
+}
+
+impl fmt::Display for Handshake {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let steps = [
+            ("SYN->SYN-ACK", self.syn_to_syn_ack()),
+            ("SYN-ACK->ACK", self.syn_ack_to_ack()),
+            ("total", self.total()),
+        ];
+
+        for (index, (name, duration)) in steps.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{} ", name)?;
+            match duration {
+                Some(duration) => write_millis(f, *duration)?,
+                None => write!(f, "-")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn between(start: Option<Duration>, end: Option<Duration>) -> Option<Duration> {
+    end?.checked_sub(start?)
+}
+
+/// A segment waiting to be acknowledged.
+#[derive(Clone, Copy, Debug)]
+struct Outstanding {
+    end: u32,
+    sent: Duration,
+    retransmitted: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FlowLatency {
+    handshake: Handshake,
+
+    /// Round trip times of data sent in each direction.
+    samples: [Vec<Duration>; 2],
+
+    outstanding: [Vec<Outstanding>; 2],
+    highest_end: [Option<u32>; 2],
+}
+
+impl FlowLatency {
+    /// Records a segment of the connection. Segments have to be passed in
+    /// the order they were captured.
+    pub fn record(&mut self, direction: Direction, segment: &TcpSegment) {
+        let header = segment.header();
+        let timestamp = segment.timestamp();
+        let (side, other) = match direction {
+            Direction::ClientToServer => (0, 1),
+            Direction::ServerToClient => (1, 0),
+        };
+
+        if header.synchronize() && !header.acknowledgment() {
+            if self.handshake.syn_ack.is_none() {
+                self.handshake.syn = Some(timestamp);
+            }
+        } else if header.synchronize() {
+            if self.handshake.syn_ack.is_none() {
+                self.handshake.syn_ack = Some(timestamp);
+                self.handshake.server_initial_sequence = Some(header.sequence_number());
+            }
+        } else if direction == Direction::ClientToServer
+            && header.acknowledgment()
+            && self.handshake.ack.is_none()
+            && self
+                .handshake
+                .server_initial_sequence
+                .map(|sequence| sequence.wrapping_add(1))
+                == Some(header.acknowledgment_number())
+        {
+            self.handshake.ack = Some(timestamp);
+        }
+
+        if header.acknowledgment() {
+            self.acknowledge(other, header.acknowledgment_number(), timestamp);
+        }
+
+        // The handshake is measured separately, and RST segments are never
+        // acknowledged.
+        if header.synchronize() || header.reset() || segment.sequence_length() == 0 {
+            return;
+        }
+
+        let start = header.sequence_number();
+        let end = start.wrapping_add(segment.sequence_length());
+        match self.highest_end[side] {
+            Some(highest) if sequence_before(start, highest) => {
+                // Karn's algorithm: an ACK for retransmitted data can't be
+                // matched to the transmission it answers.
+                for outstanding in &mut self.outstanding[side] {
+                    if sequence_before(start, outstanding.end) {
+                        outstanding.retransmitted = true;
+                    }
+                }
+            }
+            _ => {
+                self.outstanding[side].push(Outstanding {
+                    end,
+                    sent: timestamp,
+                    retransmitted: false,
+                });
+                self.highest_end[side] = Some(end);
+            }
+        }
+    }
+
+    /// Takes a sample from the latest segment covered by an ACK for data
+    /// sent by `side`.
+    fn acknowledge(&mut self, side: usize, acknowledgment: u32, timestamp: Duration) {
+        let covered = self.outstanding[side]
+            .iter()
+            .take_while(|outstanding| !sequence_before(acknowledgment, outstanding.end))
+            .count();
+        if covered == 0 {
+            return;
+        }
+
+        let latest = self.outstanding[side][covered - 1];
+        if !latest.retransmitted {
+            if let Some(rtt) = timestamp.checked_sub(latest.sent) {
+                self.samples[side].push(rtt);
+            }
+        }
+
+        self.outstanding[side].drain(..covered);
+    }
+
+    pub fn handshake(&self) -> &Handshake {
+        &self.handshake
+    }
+
+    /// Round trip times from data sent in `direction` to its ACK.
+    pub fn samples(&self, direction: Direction) -> &[Duration] {
+        match direction {
+            Direction::ClientToServer => &self.samples[0],
+            Direction::ServerToClient => &self.samples[1],
+        }
+    }
+
+    /// Statistics over the round trip times of both directions.
+    pub fn statistics(&self) -> Option<Statistics> {
+        let samples: Vec<Duration> = self.samples.concat();
+        Statistics::new(&samples)
+    }
+}
+
+/// Measures handshake latency and round trip times from data segments to
+/// the ACKs covering them, for every TCP connection.
+#[derive(Debug, Default)]
+pub struct LatencyAnalysis {
+    flows: Vec<FlowLatency>,
+}
+
+impl LatencyAnalysis {
+    pub fn new() -> LatencyAnalysis {
+        LatencyAnalysis::default()
+    }
+
+    /// Records a segment of the connection numbered `stream`. Segments have
+    /// to be passed in the order they were captured.
+    pub fn record(&mut self, stream: usize, direction: Direction, segment: &TcpSegment) {
+        if stream >= self.flows.len() {
+            self.flows.resize(stream + 1, FlowLatency::default());
+        }
+
+        self.flows[stream].record(direction, segment);
+    }
+
+    pub fn flows(&self) -> &[FlowLatency] {
+        &self.flows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flow::tests::{ip_header, tcp_header};
+    use flow::{Direction, FlowTable, TcpSegment};
+    use latency::{LatencyAnalysis, Statistics};
+    use std::time::Duration;
+
+    const SYN: u8 = 0x02;
+    const SYN_ACK: u8 = 0x12;
+    const ACK: u8 = 0x10;
+
+    #[test]
+    fn statistics() {
+        let samples: Vec<Duration> = (1..=20).map(Duration::from_millis).collect();
+        let statistics = Statistics::new(&samples).unwrap();
+        assert_eq!(statistics.count(), 20);
+        assert_eq!(statistics.minimum(), Duration::from_millis(1));
+        assert_eq!(statistics.average(), Duration::from_micros(10500));
+        assert_eq!(statistics.maximum(), Duration::from_millis(20));
+        assert_eq!(statistics.percentile_95(), Duration::from_millis(19));
+        assert_eq!(
+            statistics.to_string(),
+            "20 samples, min 1.000ms, avg 10.500ms, max 20.000ms, p95 19.000ms"
+        );
+        assert_eq!(Statistics::new(&[]), None);
+    }
+
+    #[test]
+    fn measure() {
+        // (milliseconds, from client, sequence, acknowledgment, flags,
+        // payload length)
+        let packets = [
+            (0, true, 100, 0, SYN, 0),
+            (1000, true, 100, 0, SYN, 0),
+            (1030, false, 500, 101, SYN_ACK, 0),
+            (1032, true, 101, 501, ACK, 0),
+            (1040, true, 101, 501, ACK, 10),
+            (1041, true, 111, 501, ACK, 10),
+            (1060, false, 501, 121, ACK, 0),
+            (1070, true, 121, 501, ACK, 10),
+            (1300, true, 121, 501, ACK, 10),
+            (1330, false, 501, 131, ACK, 5),
+            (1350, true, 131, 506, ACK, 0),
+        ];
+
+        let mut flows = FlowTable::new();
+        let mut analysis = LatencyAnalysis::new();
+        let payload = [0u8; 10];
+        for &(millis, from_client, sequence, acknowledgment, flags, length) in packets.iter() {
+            let ip = ip_header(from_client);
+            let header = if from_client {
+                tcp_header(36869, 23, sequence, acknowledgment, flags)
+            } else {
+                tcp_header(23, 36869, sequence, acknowledgment, flags)
+            };
+            let segment = TcpSegment::new(
+                Duration::from_millis(millis),
+                &ip,
+                &header,
+                &payload[..length],
+            );
+            let (stream, direction) = flows.lookup(&segment);
+            analysis.record(stream, direction, &segment);
+        }
+
+        let flow = &analysis.flows()[0];
+        assert_eq!(
+            flow.handshake().syn_to_syn_ack(),
+            Some(Duration::from_millis(30))
+        );
+        assert_eq!(
+            flow.handshake().syn_ack_to_ack(),
+            Some(Duration::from_millis(2))
+        );
+        assert_eq!(flow.handshake().total(), Some(Duration::from_millis(32)));
+        assert_eq!(
+            flow.samples(Direction::ClientToServer),
+            &[Duration::from_millis(19)]
+        );
+        assert_eq!(
+            flow.samples(Direction::ServerToClient),
+            &[Duration::from_millis(20)]
+        );
+        assert_eq!(flow.statistics().unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,52 @@
+#![allow(clippy::upper_case_acronyms, non_local_definitions)]
+
+#[macro_use]
+extern crate nom;
+
+#[macro_use]
+extern crate failure_derive;
+
+extern crate failure;
+
+extern crate aes_gcm;
+extern crate base64;
+extern crate chacha20poly1305;
+extern crate hkdf;
+extern crate hmac;
+extern crate md5;
+extern crate sha2;
+
+#[macro_use]
+extern crate serde_json;
+
+pub mod checksum;
+pub mod connection;
+pub mod decryption;
+pub mod ether;
+pub mod expert;
+pub mod flow;
+pub mod ftp;
+pub mod gre;
+pub mod har;
+pub mod hpack;
+pub mod http;
+pub mod http2;
+pub mod icmp;
+pub mod igmp;
+pub mod ip;
+pub mod ipsec;
+pub mod ipv6;
+pub mod keylog;
+pub mod latency;
+pub mod mac_address;
+pub mod packet;
+pub mod reassembly;
+pub mod ssh;
+pub mod tcp;
+pub mod telnet;
+pub mod tls;
+pub mod trace;
+pub mod udp;
+mod util;
+pub mod websocket;
+pub mod x509;
@@ -1,5 +1,4 @@
-#[macro_use]
-extern crate nom;
+#![allow(non_local_definitions)]
 
 #[macro_use]
 extern crate failure_derive;
@@ -8,26 +7,49 @@ extern crate failure;
 
 extern crate pcap_file;
 
-mod ether;
-mod icmp;
-mod ip;
-mod mac_address;
-mod packet;
-mod tcp;
-mod udp;
-mod util;
+extern crate wirebug;
 
+use std::collections::HashMap;
 use std::env;
+use std::fs;
 use std::fs::File;
 use std::io;
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::time::Duration;
 
 use pcap_file::PcapReader;
 
+use wirebug::connection::ConnectionTable;
+use wirebug::decryption::{decrypt, DecryptedRecord};
+use wirebug::expert::{Annotation, ExpertAnalysis};
+use wirebug::flow::{Direction, Flow, FlowTable, Numbering, SequenceNumbers, TcpSegment};
+use wirebug::ftp::FtpAnalysis;
+use wirebug::har::{to_har, HarEntry};
+use wirebug::http::HttpAnalysis;
+use wirebug::http2::Http2Analysis;
+use wirebug::igmp::IgmpMessage;
+use wirebug::ipsec::{SecurityAssociation, SequenceTracker};
+use wirebug::keylog::{KeyLog, KeyLogError};
+use wirebug::latency::LatencyAnalysis;
+use wirebug::packet;
+use wirebug::packet::{Application, PacketContents};
+use wirebug::reassembly::{
+    write_chunks, FollowFormat, FollowStream, ReassembledStreams, StreamHandler,
+};
+use wirebug::ssh::{message_name, KexInit, SshAnalysis};
+use wirebug::tls::TlsAnalysis;
+use wirebug::trace::{write_points, TraceFormat, TraceRecorder};
+use wirebug::x509::{write_pem, write_report, CertificateInventory};
+
 #[derive(Fail, Debug)]
 enum ErrorKind {
     #[fail(display = "No arguments were passed to the program.")]
     ArgumentMissing,
 
+    #[fail(display = "Invalid argument: {}.", _0)]
+    InvalidArgument(String),
+
     #[fail(display = "Failed to access file.")]
     FileError(#[cause] io::Error),
 
@@ -39,27 +61,856 @@ enum ErrorKind {
 
     #[fail(display = "Failed to parse packet.")]
     ParseError(#[cause] packet::ParseError),
+
+    #[fail(display = "Failed to read the key log.")]
+    KeyLogError(#[cause] KeyLogError),
 }
 
 fn main() {
     handle_main().unwrap();
 }
 
+/// Options accepted anywhere on the command line. Those ending in `=` take a
+/// value.
+const FLAGS: [&str; 4] = ["--relative", "--absolute", "--bodies", "--keylog="];
+
 fn handle_main() -> Result<(), ErrorKind> {
-    let arg = env::args()
-        .nth(1)
-        .map_or(Err(ErrorKind::ArgumentMissing), Ok)?;
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let known = |flag: &String| {
+        FLAGS.iter().any(|name| {
+            flag == name
+                || (name.ends_with('=') && flag.starts_with(name) && flag.len() > name.len())
+        })
+    };
+    if let Some(flag) = flags.iter().find(|flag| !known(flag)) {
+        return Err(ErrorKind::InvalidArgument(flag.clone()));
+    }
+    let flag = |name: &str| flags.iter().any(|flag| flag == name);
+    // Each command has its own default numbering when neither is passed.
+    let numbering = match (flag("--relative"), flag("--absolute")) {
+        (true, true) => return Err(ErrorKind::InvalidArgument("--absolute".to_string())),
+        (true, false) => Some(Numbering::Relative),
+        (false, true) => Some(Numbering::Absolute),
+        (false, false) => None,
+    };
+    let absolute = numbering.unwrap_or(Numbering::Absolute);
+    let relative = numbering.unwrap_or(Numbering::Relative);
+    let keys = match flags.iter().find_map(|flag| flag.strip_prefix("--keylog=")) {
+        Some(path) => Some(read_keylog(path)?),
+        None => None,
+    };
+    let keys = keys.as_ref();
+    let path = |index: usize| args.get(index).ok_or(ErrorKind::ArgumentMissing);
+
+    match args.first().map(String::as_str) {
+        Some("summary") => print_summary(path(1)?, absolute),
+        Some("igmp") => print_igmp(path(1)?),
+        Some("ipsec") => print_ipsec(path(1)?),
+        Some("connections") => print_connections(path(1)?, absolute),
+        Some("expert") => print_expert(path(1)?, absolute),
+        Some("rtt") => print_rtt(path(1)?),
+        Some("trace") => print_trace(path(1)?, args.get(2), relative),
+        Some("http") => print_http(path(1)?, keys),
+        Some("http2") => print_http2(path(1)?, keys),
+        Some("websocket") => print_websocket(path(1)?, keys),
+        Some("har") => print_har(path(1)?, flag("--bodies"), keys),
+        Some("tls") => print_tls(path(1)?, keys),
+        Some("certs") => print_certificates(path(1)?, args.get(2)),
+        Some("ssh") => print_ssh(path(1)?),
+        Some("ftp") => print_ftp(path(1)?, args.get(2)),
+        Some("follow") => print_follow(path(1)?, args.get(2), args.get(3), relative, keys),
+        Some(_) => print_first_packet(path(0)?, absolute),
+        None => Err(ErrorKind::ArgumentMissing),
+    }
+}
+
+fn open_capture(path: &str) -> Result<PcapReader<File>, ErrorKind> {
+    let file = File::open(path).map_err(ErrorKind::FileError)?;
+    PcapReader::new(file).map_err(|_e| ErrorKind::PcapError)
+}
 
-    let file = File::open(arg).map_err(ErrorKind::FileError)?;
-    let mut reader = PcapReader::new(file).map_err(|_e| ErrorKind::PcapError)?;
+/// Parses every packet in the capture, handing each one to `handle` along
+/// with the time it was captured at. Packets which fail to parse are reported
+/// and skipped so one malformed packet doesn't hide the rest of the capture.
+fn for_each_packet<F>(path: &str, mut handle: F) -> Result<(), ErrorKind>
+where
+    F: FnMut(Duration, packet::Packet),
+{
+    for (index, pcap_packet) in open_capture(path)?.enumerate() {
+        let pcap_packet = pcap_packet.map_err(|_e| ErrorKind::PcapError)?;
+        let timestamp = Duration::from_secs(u64::from(pcap_packet.header.ts_sec))
+            + Duration::from_micros(u64::from(pcap_packet.header.ts_usec));
+
+        match packet::Packet::parse(&pcap_packet.data) {
+            Ok(pkt) => handle(timestamp, pkt),
+            Err(error) => eprintln!("Skipping packet {}: {}", index + 1, error),
+        }
+    }
+
+    Ok(())
+}
+
+fn print_first_packet(path: &str, numbering: Numbering) -> Result<(), ErrorKind> {
+    let mut reader = open_capture(path)?;
 
     let pcap_packet = reader
-        .nth(0)
-        .map_or(Err(ErrorKind::NoPacket), Ok)?
+        .next()
+        .ok_or(ErrorKind::NoPacket)?
         .map_err(|_e| ErrorKind::PcapError)?;
     let pkt = packet::Packet::parse(&pcap_packet.data).map_err(ErrorKind::ParseError)?;
 
     println!("{:#?}", pkt);
 
+    // The dump always has the raw numbers, so relative ones go after it.
+    if let Some(segment) = TcpSegment::carried_by(Duration::default(), &pkt) {
+        if numbering == Numbering::Relative {
+            let (stream, direction) = FlowTable::new().lookup(&segment);
+            let (sequence_number, acknowledgment_number) =
+                SequenceNumbers::new(numbering).numbers(stream, direction, &segment);
+            println!(
+                "Relative sequence number {}, acknowledgment number {}.",
+                sequence_number, acknowledgment_number
+            );
+        }
+    }
+
+    if let Some(truncation) = pkt.truncation() {
+        println!("Packet {}.", truncation);
+    }
+
+    Ok(())
+}
+
+/// Prints a line for every packet with the time since the first packet and a
+/// summary of its contents.
+fn print_summary(path: &str, numbering: Numbering) -> Result<(), ErrorKind> {
+    let mut index = 0;
+    let mut start = None;
+    let mut flows = FlowTable::new();
+    let mut sequence_numbers = SequenceNumbers::new(numbering);
+    let mut ftp = FtpAnalysis::without_contents();
+
+    for_each_packet(path, |timestamp, mut pkt| {
+        let numbers = match TcpSegment::carried_by(timestamp, &pkt) {
+            Some(segment) => {
+                let (stream, direction) = flows.lookup(&segment);
+                ftp.record(flows.flows(), stream, direction, &segment);
+                let numbers = sequence_numbers.numbers(stream, direction, &segment);
+                Some((stream, numbers))
+            }
+            None => None,
+        };
+        if numbers.is_some_and(|(stream, _)| ftp.is_data(stream)) {
+            pkt.set_application(Application::FtpData);
+        }
+
+        index += 1;
+        let start = *start.get_or_insert(timestamp);
+        let elapsed = timestamp.checked_sub(start).unwrap_or_default();
+
+        let summary = match numbers {
+            Some((_, (sequence_number, acknowledgment_number))) => pkt
+                .with_numbers(sequence_number, acknowledgment_number)
+                .to_string(),
+            None => pkt.to_string(),
+        };
+        println!(
+            "{} {}.{:06} {}",
+            index,
+            elapsed.as_secs(),
+            elapsed.subsec_micros(),
+            summary
+        );
+    })
+}
+
+/// Prints the queries sent and the groups joined or left by each host.
+fn print_igmp(path: &str) -> Result<(), ErrorKind> {
+    for_each_packet(path, |_, pkt| {
+        let (ip_header, igmp) = match (pkt.ip_header(), pkt.contents()) {
+            (Some(ip_header), Some(PacketContents::IGMP { packet })) => (ip_header, packet),
+            _ => return,
+        };
+
+        let host = ip_header.source();
+        let version = igmp.version();
+
+        match igmp.message() {
+            IgmpMessage::Query { group, .. } if *group == Ipv4Addr::UNSPECIFIED => {
+                println!("{} IGMPv{} general query", host, version)
+            }
+            IgmpMessage::Query { group, .. } => {
+                println!("{} IGMPv{} query for {}", host, version, group)
+            }
+            IgmpMessage::Other { message_type, .. } => {
+                println!("{} IGMP message type {:#04x}", host, message_type)
+            }
+            _ => {
+                for change in igmp.membership_changes() {
+                    println!("{} IGMPv{} {}", host, version, change);
+                }
+            }
+        }
+    })
+}
+
+/// Prints the sequence number gaps and replays seen for each IPsec security
+/// association.
+fn print_ipsec(path: &str) -> Result<(), ErrorKind> {
+    let mut tracker = SequenceTracker::new();
+
+    for_each_packet(path, |_, pkt| {
+        let ip_header = match pkt.ip_header() {
+            Some(ip_header) => ip_header,
+            None => return,
+        };
+
+        let (spi, sequence_number) = match pkt.contents() {
+            Some(PacketContents::ESP { header, .. }) => {
+                (header.security_parameters_index(), header.sequence_number())
+            }
+            Some(PacketContents::AH { header, .. }) => {
+                (header.security_parameters_index(), header.sequence_number())
+            }
+            _ => return,
+        };
+
+        let association = SecurityAssociation::new(
+            *ip_header.protocol(),
+            ip_header.source(),
+            ip_header.destination(),
+            spi,
+        );
+        tracker.record(association, sequence_number);
+    })?;
+
+    for (association, report) in tracker.reports() {
+        println!("{}", association);
+        println!("  packets:   {}", report.packets());
+        if let (Some(first), Some(last)) = (report.first(), report.last()) {
+            println!("  sequence:  {}-{}", first, last);
+        }
+        println!("  missing:   {}", report.missing());
+        for (start, end) in report.gaps() {
+            if start == end {
+                println!("    {}", start);
+            } else {
+                println!("    {}-{}", start, end);
+            }
+        }
+        println!("  replayed:  {}", report.replayed());
+        println!("  reordered: {}", report.reordered());
+    }
+
+    Ok(())
+}
+
+/// Prints the reassembled contents of a TCP connection, or lists the
+/// connections in the capture when none is chosen.
+fn print_follow(
+    path: &str,
+    stream: Option<&String>,
+    format: Option<&String>,
+    numbering: Numbering,
+    keys: Option<&KeyLog>,
+) -> Result<(), ErrorKind> {
+    let stream = match stream {
+        Some(stream) => stream
+            .parse::<usize>()
+            .map_err(|_e| ErrorKind::InvalidArgument(stream.clone()))?,
+        None => return print_streams(path),
+    };
+
+    let format = match format {
+        Some(name) => {
+            FollowFormat::from_name(name).ok_or_else(|| ErrorKind::InvalidArgument(name.clone()))?
+        }
+        None => FollowFormat::Ascii,
+    };
+
+    let mut flows = FlowTable::new();
+    let mut follow = FollowStream::new();
+    let mut sessions = TlsAnalysis::new();
+
+    for_each_packet(path, |timestamp, pkt| {
+        if let Some(segment) = TcpSegment::from_packet(timestamp, &pkt) {
+            let (index, direction) = flows.lookup(&segment);
+            if index == stream {
+                follow.push(direction, &segment);
+                if keys.is_some() {
+                    sessions.record(index, direction, &segment);
+                }
+            }
+        }
+    })?;
+
+    if stream >= flows.flows().len() {
+        return Err(ErrorKind::InvalidArgument(stream.to_string()));
+    }
+
+    // Show what was sent inside TLS instead when it can be decrypted.
+    if let Some(keys) = keys {
+        sessions.finish();
+        if let Some((_, session)) = sessions.handlers().pop() {
+            if let Ok(decrypted) = decrypt(session, keys) {
+                follow = FollowStream::new();
+                for record in decrypted.application_data() {
+                    follow.push_bytes(record.direction(), record.data());
+                }
+            }
+        }
+    }
+
+    // Decrypted data has no sequence numbers, so it keeps its offsets.
+    let first_sequence_numbers = match (numbering, follow.first_sequence_numbers()) {
+        (Numbering::Absolute, [client, server]) if client.is_some() || server.is_some() => {
+            Some([client.unwrap_or(0), server.unwrap_or(0)])
+        }
+        _ => None,
+    };
+
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    write_chunks(
+        &mut output,
+        &follow.finish(),
+        format,
+        first_sequence_numbers,
+    )
+    .map_err(ErrorKind::FileError)?;
+    output.flush().map_err(ErrorKind::FileError)
+}
+
+fn print_streams(path: &str) -> Result<(), ErrorKind> {
+    let mut flows = FlowTable::new();
+
+    for_each_packet(path, |timestamp, pkt| {
+        if let Some(segment) = TcpSegment::from_packet(timestamp, &pkt) {
+            flows.lookup(&segment);
+        }
+    })?;
+
+    for (index, flow) in flows.flows().iter().enumerate() {
+        println!("{} {}", index, flow);
+    }
+
+    Ok(())
+}
+
+/// Prints the state each TCP connection reached and how it ended.
+fn print_connections(path: &str, numbering: Numbering) -> Result<(), ErrorKind> {
+    let mut table = ConnectionTable::new();
+    let mut numbers = SequenceNumbers::new(numbering);
+
+    for_each_packet(path, |timestamp, pkt| {
+        if let Some(segment) = TcpSegment::from_packet(timestamp, &pkt) {
+            let (stream, direction) = table.record(&segment);
+            numbers.numbers(stream, direction, &segment);
+        }
+    })?;
+
+    for (index, connection) in table.connections().iter().enumerate() {
+        print!("{} {}", index, connection);
+        let sides = [
+            ("client", Direction::ClientToServer),
+            ("server", Direction::ServerToClient),
+        ];
+        for &(side, direction) in sides.iter() {
+            if let Some(next) = connection.next_sequence(direction) {
+                print!(
+                    ", {} next Seq={}",
+                    side,
+                    numbers.sequence(index, direction, next)
+                );
+            }
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+/// Prints the TCP packets which have something worth pointing out, like
+/// retransmissions or zero windows, followed by how often each came up.
+fn print_expert(path: &str, numbering: Numbering) -> Result<(), ErrorKind> {
+    let mut flows = FlowTable::new();
+    let mut numbers = SequenceNumbers::new(numbering);
+    let mut analysis = ExpertAnalysis::new();
+    let mut counts = HashMap::new();
+    let mut index = 0;
+    let mut start = None;
+
+    for_each_packet(path, |timestamp, pkt| {
+        index += 1;
+        let start = *start.get_or_insert(timestamp);
+        let segment = match TcpSegment::from_packet(timestamp, &pkt) {
+            Some(segment) => segment,
+            None => return,
+        };
+
+        let (stream, direction) = flows.lookup(&segment);
+        let (sequence_number, acknowledgment_number) = numbers.numbers(stream, direction, &segment);
+        let elapsed = timestamp.checked_sub(start).unwrap_or_default();
+        for annotation in analysis.analyze(stream, direction, &segment) {
+            print!(
+                "{} {}.{:06} {} -> {} Seq={}",
+                index,
+                elapsed.as_secs(),
+                elapsed.subsec_micros(),
+                segment.source(),
+                segment.destination(),
+                sequence_number
+            );
+            if segment.header().acknowledgment() {
+                print!(" Ack={}", acknowledgment_number);
+            }
+            println!(" {}: {}", annotation.severity(), annotation);
+
+            let name = match annotation {
+                Annotation::DuplicateAck(_) => "Duplicate ACK".to_string(),
+                annotation => annotation.to_string(),
+            };
+            *counts.entry((annotation.severity(), name)).or_insert(0) += 1;
+        }
+    })?;
+
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(a, _), (b, _)| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    for ((severity, name), count) in counts {
+        println!("{}: {} x{}", severity, name, count);
+    }
+
+    Ok(())
+}
+
+/// Prints the handshake timings and round trip time statistics of each TCP
+/// connection.
+fn print_rtt(path: &str) -> Result<(), ErrorKind> {
+    let mut flows = FlowTable::new();
+    let mut analysis = LatencyAnalysis::new();
+
+    for_each_packet(path, |timestamp, pkt| {
+        if let Some(segment) = TcpSegment::from_packet(timestamp, &pkt) {
+            let (stream, direction) = flows.lookup(&segment);
+            analysis.record(stream, direction, &segment);
+        }
+    })?;
+
+    for (index, (flow, latency)) in flows.flows().iter().zip(analysis.flows()).enumerate() {
+        println!("{} {}", index, flow);
+        println!("  handshake: {}", latency.handshake());
+        match latency.statistics() {
+            Some(statistics) => println!("  rtt:       {}", statistics),
+            None => println!("  rtt:       no samples"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the time sequence graph data of every TCP connection as CSV or
+/// JSON.
+fn print_trace(path: &str, format: Option<&String>, numbering: Numbering) -> Result<(), ErrorKind> {
+    let format = match format {
+        Some(name) => {
+            TraceFormat::from_name(name).ok_or_else(|| ErrorKind::InvalidArgument(name.clone()))?
+        }
+        None => TraceFormat::Csv,
+    };
+
+    let mut flows = FlowTable::new();
+    let mut recorder = TraceRecorder::new(numbering);
+
+    for_each_packet(path, |timestamp, pkt| {
+        if let Some(segment) = TcpSegment::from_packet(timestamp, &pkt) {
+            let (stream, direction) = flows.lookup(&segment);
+            recorder.record(stream, direction, &segment);
+        }
+    })?;
+
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    write_points(&mut output, recorder.points(), format).map_err(ErrorKind::FileError)?;
+    output.flush().map_err(ErrorKind::FileError)
+}
+
+/// Reads the HTTP/1.x transactions out of every TCP connection. With a key
+/// log, the application data of TLS connections is decrypted and read too.
+fn read_http(path: &str, keys: Option<&KeyLog>) -> Result<(FlowTable, HttpAnalysis), ErrorKind> {
+    let (flows, streams) = run_stream_analysis(path, keys)?;
+    Ok((flows, HttpAnalysis::from(streams)))
+}
+
+/// Reassembles every TCP connection and hands those `T` recognises to it.
+/// With a key log, the application data of TLS connections is decrypted and
+/// handed over too.
+fn run_stream_analysis<T: StreamHandler>(
+    path: &str,
+    keys: Option<&KeyLog>,
+) -> Result<(FlowTable, ReassembledStreams<T>), ErrorKind> {
+    let mut flows = FlowTable::new();
+    let mut analysis = ReassembledStreams::new();
+    let mut sessions = TlsAnalysis::new();
+
+    for_each_packet(path, |timestamp, pkt| {
+        if let Some(segment) = TcpSegment::from_packet(timestamp, &pkt) {
+            let (stream, direction) = flows.lookup(&segment);
+            analysis.record(stream, direction, &segment);
+            if keys.is_some() {
+                sessions.record(stream, direction, &segment);
+            }
+        }
+    })?;
+
+    if let Some(keys) = keys {
+        push_decrypted(&flows, &mut sessions, keys, |stream, flow, record| {
+            analysis.push_decrypted(
+                stream,
+                flow,
+                record.direction(),
+                record.data(),
+                record.timestamp(),
+            )
+        });
+    }
+    analysis.finish();
+
+    Ok((flows, analysis))
+}
+
+/// Decrypts every TLS connection the key log has secrets for, handing the
+/// application data records to `push` with the number of their connection.
+fn push_decrypted<F>(flows: &FlowTable, sessions: &mut TlsAnalysis, keys: &KeyLog, mut push: F)
+where
+    F: FnMut(usize, &Flow, &DecryptedRecord),
+{
+    sessions.finish();
+    for (stream, session) in sessions.handlers() {
+        if let Ok(decrypted) = decrypt(session, keys) {
+            for record in decrypted.application_data() {
+                push(stream, &flows.flows()[stream], record);
+            }
+        }
+    }
+}
+
+fn read_keylog(path: &str) -> Result<KeyLog, ErrorKind> {
+    let text = fs::read_to_string(path).map_err(ErrorKind::FileError)?;
+    KeyLog::parse(&text).map_err(ErrorKind::KeyLogError)
+}
+
+/// Prints every HTTP/1.x request in the capture with the response to it.
+fn print_http(path: &str, keys: Option<&KeyLog>) -> Result<(), ErrorKind> {
+    let (_, analysis) = read_http(path, keys)?;
+
+    for (stream, transaction) in analysis.transactions() {
+        println!("{} {}", stream, transaction);
+    }
+
+    Ok(())
+}
+
+/// Marks what the client sent with `>` and what the server sent with `<`.
+fn arrow(direction: Direction) -> &'static str {
+    match direction {
+        Direction::ClientToServer => ">",
+        Direction::ServerToClient => "<",
+    }
+}
+
+/// Prints the frames of every HTTP/2 connection, then the headers and the
+/// amount of data sent on each of its streams.
+fn print_http2(path: &str, keys: Option<&KeyLog>) -> Result<(), ErrorKind> {
+    let (flows, analysis): (_, Http2Analysis) = run_stream_analysis(path, keys)?;
+
+    for (stream, connection) in analysis.handlers() {
+        println!("{} {}", stream, flows.flows()[stream]);
+        for (direction, _, frame) in connection.frames() {
+            println!("  {} {}", arrow(*direction), frame);
+        }
+
+        for http2_stream in connection.streams() {
+            println!("  {}", http2_stream);
+            let messages = [
+                (Direction::ClientToServer, http2_stream.request()),
+                (Direction::ServerToClient, http2_stream.response()),
+            ];
+            for (direction, message) in messages.iter() {
+                for (name, value) in message.headers().iter().chain(message.trailers()) {
+                    println!("    {} {}: {}", arrow(*direction), name, value);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the frames of every connection upgraded to WebSocket, then the
+/// messages they make up.
+fn print_websocket(path: &str, keys: Option<&KeyLog>) -> Result<(), ErrorKind> {
+    let (flows, analysis) = read_http(path, keys)?;
+
+    for (stream, websocket) in analysis.websockets() {
+        println!("{} {}", stream, flows.flows()[stream]);
+        for (direction, _, frame) in websocket.frames() {
+            println!("  {} {}", arrow(*direction), frame);
+        }
+
+        println!("  messages:");
+        for message in websocket.messages() {
+            println!("    {} {}", arrow(message.direction()), message);
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the HTTP/1.x transactions in the capture as a HAR 1.2 log.
+fn print_har(path: &str, bodies: bool, keys: Option<&KeyLog>) -> Result<(), ErrorKind> {
+    let (flows, analysis) = read_http(path, keys)?;
+    let entries: Vec<HarEntry> = analysis
+        .transactions()
+        .into_iter()
+        .map(|(stream, transaction)| {
+            let server = flows.flows()[stream].server();
+            HarEntry::new(stream, server, analysis.is_decrypted(stream), transaction)
+        })
+        .collect();
+
+    println!("{:#}", to_har(&entries, bodies));
+    Ok(())
+}
+
+/// Prints the hellos and fingerprints of every TLS connection, and how much
+/// could be decrypted when there's a key log.
+fn print_tls(path: &str, keys: Option<&KeyLog>) -> Result<(), ErrorKind> {
+    // The key log is used below, not to decrypt the sessions into themselves.
+    let (flows, analysis): (_, TlsAnalysis) = run_stream_analysis(path, None)?;
+
+    for (stream, session) in analysis.handlers() {
+        println!("{} {}", stream, flows.flows()[stream]);
+        if let Some(hello) = session.client_hello() {
+            println!("  client hello: {}", hello);
+            println!("  ja3:          {}", hello.ja3());
+            println!("  ja4:          {}", hello.ja4());
+        }
+        if let Some(hello) = session.server_hello() {
+            println!("  server hello: {}", hello);
+            println!("  ja3s:         {}", hello.ja3s());
+        }
+        if !session.certificates().is_empty() {
+            println!("  certificates: {}", session.certificates().len());
+        }
+        if let Some(keys) = keys {
+            match decrypt(session, keys) {
+                Ok(decrypted) => {
+                    let application_data = decrypted.application_data();
+                    println!(
+                        "  decrypted:    {} records, {} bytes of application data, {} failed",
+                        decrypted.records().len(),
+                        application_data
+                            .iter()
+                            .map(|record| record.data().len())
+                            .sum::<usize>(),
+                        decrypted.failures()
+                    );
+                }
+                Err(error) => println!("  decrypted:    {}", error),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the certificates servers sent in the clear, grouped by server, or
+/// writes them out in PEM.
+fn print_certificates(path: &str, format: Option<&String>) -> Result<(), ErrorKind> {
+    let pem = match format.map(String::as_str) {
+        Some("pem") => true,
+        Some(name) => return Err(ErrorKind::InvalidArgument(name.to_string())),
+        None => false,
+    };
+
+    let (flows, analysis): (_, TlsAnalysis) = run_stream_analysis(path, None)?;
+
+    let mut inventory = CertificateInventory::new();
+    for (stream, session) in analysis.handlers() {
+        if let Some(seen) = session.certificates_seen() {
+            inventory.add(flows.flows()[stream].server(), session.certificates(), seen);
+        }
+    }
+
+    let entries = inventory.entries();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    if pem {
+        write_pem(&mut output, &entries).map_err(ErrorKind::FileError)?;
+    } else {
+        write_report(&mut output, &entries).map_err(ErrorKind::FileError)?;
+        if inventory.invalid() > 0 {
+            writeln!(
+                output,
+                "{} certificates couldn't be parsed",
+                inventory.invalid()
+            )
+            .map_err(ErrorKind::FileError)?;
+        }
+    }
+    output.flush().map_err(ErrorKind::FileError)
+}
+
+/// Prints the identification lines, fingerprints and negotiated algorithms of
+/// every SSH connection, and the algorithms each side offered that are weak.
+fn print_ssh(path: &str) -> Result<(), ErrorKind> {
+    let (flows, analysis): (_, SshAnalysis) = run_stream_analysis(path, None)?;
+
+    for (stream, session) in analysis.handlers() {
+        println!("{} {}", stream, flows.flows()[stream]);
+        if let Some(banner) = session.client_banner() {
+            println!("  client:       {}", banner);
+        }
+        if let Some(banner) = session.server_banner() {
+            println!("  server:       {}", banner);
+        }
+        if let Some(kex_init) = session.client_kex_init() {
+            println!("  hassh:        {}", kex_init.hassh());
+        }
+        if let Some(kex_init) = session.server_kex_init() {
+            println!("  hassh server: {}", kex_init.hassh_server());
+        }
+        if let (Some(kex), Some(kex_type)) = (session.kex_algorithm(), session.kex_type()) {
+            println!("  kex:          {} ({})", kex, kex_type);
+        }
+        if let Some(host_key) = session.host_key_algorithm() {
+            println!("  host key:     {}", host_key);
+        }
+        for &direction in &[Direction::ClientToServer, Direction::ServerToClient] {
+            if let Some(cipher) = session.encryption_algorithm(direction) {
+                let mac = session.mac_algorithm(direction).unwrap_or("-");
+                println!("  cipher {}:     {}, {}", arrow(direction), cipher, mac);
+            }
+        }
+
+        for (direction, _, number) in session.messages() {
+            println!(
+                "  {} {}",
+                arrow(*direction),
+                message_name(*number, session.kex_type())
+            );
+        }
+
+        let offers = [
+            ("client", session.client_kex_init()),
+            ("server", session.server_kex_init()),
+        ];
+        for (side, kex_init) in offers.iter() {
+            let weak = kex_init.map(KexInit::weak_algorithms).unwrap_or_default();
+            if !weak.is_empty() {
+                println!("  weak {} offers: {}", side, weak.join(", "));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the commands and replies of every FTP control connection and the
+/// data connections they announced, or writes out what was sent over one
+/// data connection.
+fn print_ftp(path: &str, stream: Option<&String>) -> Result<(), ErrorKind> {
+    let stream = match stream {
+        Some(stream) => Some(
+            stream
+                .parse::<usize>()
+                .map_err(|_e| ErrorKind::InvalidArgument(stream.clone()))?,
+        ),
+        None => None,
+    };
+
+    let mut flows = FlowTable::new();
+    let mut analysis = FtpAnalysis::new();
+
+    for_each_packet(path, |timestamp, pkt| {
+        if let Some(segment) = TcpSegment::from_packet(timestamp, &pkt) {
+            let (stream, direction) = flows.lookup(&segment);
+            analysis.record(flows.flows(), stream, direction, &segment);
+        }
+    })?;
+    analysis.finish();
+
+    if let Some(stream) = stream {
+        let transfer = analysis
+            .transfers()
+            .get(&stream)
+            .ok_or_else(|| ErrorKind::InvalidArgument(stream.to_string()))?;
+        let stdout = io::stdout();
+        let mut output = stdout.lock();
+        output
+            .write_all(transfer.data())
+            .map_err(ErrorKind::FileError)?;
+        return output.flush().map_err(ErrorKind::FileError);
+    }
+
+    for (control, session) in analysis.sessions() {
+        let flow = &flows.flows()[control];
+        println!("{} {}", control, flow);
+        for exchange in session.exchanges() {
+            if let Some(command) = exchange.command() {
+                println!("  > {}", command);
+            }
+            for reply in exchange.replies() {
+                println!("  < {}", reply);
+                for line in reply.lines().iter().skip(1) {
+                    println!("      {}", line);
+                }
+            }
+        }
+        if session.is_encrypted() {
+            println!("  (control connection encrypted)");
+        }
+
+        if session.data_channels().is_empty() {
+            continue;
+        }
+        println!("  data connections:");
+        for (index, channel) in session.data_channels().iter().enumerate() {
+            let command = session
+                .transfer_command(channel)
+                .map_or_else(|| "no transfer".to_string(), |command| command.to_string());
+            let transfer = analysis
+                .transfers()
+                .iter()
+                .find(|(_, transfer)| transfer.control() == control && transfer.channel() == index);
+            match transfer {
+                Some((stream, transfer)) => {
+                    print!(
+                        "    {} {} {}, {}: {} bytes",
+                        stream,
+                        channel.mode(),
+                        channel.endpoint(flow),
+                        command,
+                        transfer.data().len()
+                    );
+                    if let Some(direction) = transfer.direction() {
+                        print!(" {}", arrow(direction));
+                    }
+                    if transfer.missing() > 0 {
+                        print!(", {} bytes missing", transfer.missing());
+                    }
+                    println!();
+                }
+                None => println!(
+                    "    - {} {}, {}: not captured",
+                    channel.mode(),
+                    channel.endpoint(flow),
+                    command
+                ),
+            }
+        }
+    }
+
     Ok(())
 }
@@ -1,10 +1,26 @@
-use ether::EtherFrame;
+use nom::{Err, IResult};
+use std::cmp;
+use std::fmt;
+use std::fmt::Formatter;
+
+use checksum::{ChecksumStatus, PseudoHeader};
+use ether::{EtherFrame, EtherType};
+use ftp::{FtpMessage, FTP_PORT};
+use gre::{ErspanHeader, GreHeader};
+use http::{looks_like_http, HttpHead, HTTP_PORTS};
+use http2::{looks_like_http2, Frame, CONNECTION_PREFACE};
 use icmp::IcmpHeader;
-use ip::Ipv4Header;
-use ip::Protocol;
-use tcp::TcpHeader;
+use igmp::IgmpPacket;
+use ip::{IpHeader, Ipv4Header, Protocol};
+use ipsec::{AhHeader, EspHeader};
+use ipv6::Ipv6Header;
+use ssh::{looks_like_ssh, SshBanner};
+use tcp::{TcpHeader, TruncatedTcpHeader};
+use telnet::TelnetMessage;
+use tls::{looks_like_tls, TlsRecord};
 use udp::UdpPacket;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Fail, Debug)]
 pub enum ParseError {
     #[fail(display = "Ethernet frame invalid.")]
@@ -21,50 +37,443 @@ pub enum ParseError {
 
     #[fail(display = "Failed to parse UDP packet")]
     InvalidUdpPacket,
+
+    #[fail(display = "Failed to parse IGMP packet")]
+    InvalidIgmpPacket,
+
+    #[fail(display = "Failed to parse ESP header")]
+    InvalidEspHeader,
+
+    #[fail(display = "Failed to parse AH header")]
+    InvalidAhHeader,
+
+    #[fail(display = "Failed to parse GRE header")]
+    InvalidGreHeader,
+
+    #[fail(display = "Failed to parse ERSPAN header")]
+    InvalidErspanHeader,
 }
 
+/// How many packets may be nested inside each other through tunnels and
+/// authentication headers, so that a crafted packet can't recurse without
+/// limit. Packets nested deeper aren't decoded, and the packet carrying them
+/// is marked as truncated.
+pub const MAXIMUM_NESTING_DEPTH: usize = 8;
+
 #[derive(Eq, PartialEq, Debug)]
 pub struct Packet {
-    frame: EtherFrame,
-    ip_header: Ipv4Header,
-    contents: PacketContents,
+    /// `None` for packets which were not carried in an Ethernet frame, such
+    /// as the inner packet of an IP-in-IP tunnel.
+    frame: Option<EtherFrame>,
+    ip_header: Option<IpHeader>,
+    contents: Option<PacketContents>,
+
+    /// Bytes following the IP packet in the frame, usually padding added to
+    /// reach the minimum Ethernet frame size.
+    trailer: Vec<u8>,
+
+    /// Where the captured data ran out, when fewer bytes were captured than
+    /// the headers say the packet holds, for example because of the snapshot
+    /// length, or where decoding stopped at `MAXIMUM_NESTING_DEPTH`. Layers
+    /// after this point are `None`.
+    truncation: Option<Truncation>,
 }
 
 impl Packet {
+    /// Parses an Ethernet frame and the IP packet it carries.
     pub fn parse(input: &[u8]) -> Result<Packet, ParseError> {
+        Packet::parse_frame(input, 0)
+    }
+
+    /// Parses an IPv4 packet which isn't carried in an Ethernet frame.
+    pub fn parse_ipv4_packet(input: &[u8]) -> Result<Packet, ParseError> {
+        Packet::empty().parse_ipv4(input, 0)
+    }
+
+    /// Parses an IPv6 packet which isn't carried in an Ethernet frame.
+    pub fn parse_ipv6_packet(input: &[u8]) -> Result<Packet, ParseError> {
+        Packet::empty().parse_ipv6(input, 0)
+    }
+
+    /// Parses a frame found `depth` packets deep.
+    fn parse_frame(input: &[u8], depth: usize) -> Result<Packet, ParseError> {
+        let packet = Packet::empty();
+
         let (after_ether_header, ether_frame) =
-            EtherFrame::parse(input).map_err(|_e| ParseError::InvalidEthernetFrame)?;
+            match decode(EtherFrame::parse(input), ParseError::InvalidEthernetFrame)? {
+                Some(decoded) => decoded,
+                None => return Ok(packet.truncated_at(Layer::Ethernet, input)),
+            };
 
-        let (after_ip_header, ip_header) =
-            Ipv4Header::parse(after_ether_header).map_err(|_e| ParseError::InvalidIpHeader)?;
+        let ether_type = *ether_frame.ether_type();
+        let packet = Packet {
+            frame: Some(ether_frame),
+            ..packet
+        };
 
-        let contents = PacketContents::parse(ip_header.protocol(), after_ip_header)?;
+        match ether_type {
+            EtherType::IP => packet.parse_ipv4(after_ether_header, depth),
+            EtherType::IPv6 => packet.parse_ipv6(after_ether_header, depth),
+            _ => Ok(packet),
+        }
+    }
 
-        Ok(Packet {
-            frame: ether_frame,
-            ip_header,
+    pub fn ip_header(&self) -> Option<&IpHeader> {
+        self.ip_header.as_ref()
+    }
+
+    pub fn contents(&self) -> Option<&PacketContents> {
+        self.contents.as_ref()
+    }
+
+    /// The IP header, TCP header and payload of a TCP packet.
+    pub fn tcp(&self) -> Option<(&IpHeader, &TcpHeader, &[u8])> {
+        match (&self.ip_header, &self.contents) {
+            (Some(ip_header), Some(PacketContents::TCP { header, data, .. })) => {
+                Some((ip_header, header, data))
+            }
+            _ => None,
+        }
+    }
+
+    /// Like `tcp`, but also finds a TCP packet carried through tunnels or
+    /// behind an authentication header. A packet holds at most one.
+    pub fn carried_tcp(&self) -> Option<(&IpHeader, &TcpHeader, &[u8])> {
+        let ip_header = self.ip_header.as_ref()?;
+        let mut contents = self.contents.as_ref()?;
+        loop {
+            contents = match contents {
+                PacketContents::TCP { header, data, .. } => return Some((ip_header, header, data)),
+                PacketContents::AH {
+                    contents: Some(contents),
+                    ..
+                } => contents,
+                PacketContents::Tunnel { packet }
+                | PacketContents::GRE {
+                    payload: Some(GrePayload::Packet(packet)),
+                    ..
+                }
+                | PacketContents::GRE {
+                    payload: Some(GrePayload::ERSPAN { frame: packet, .. }),
+                    ..
+                } => return packet.carried_tcp(),
+                _ => return None,
+            };
+        }
+    }
+
+    /// Shows the packet with other sequence and acknowledgment numbers in
+    /// place of those in the TCP header it carries, such as relative ones.
+    pub fn with_numbers(
+        &self,
+        sequence_number: u32,
+        acknowledgment_number: u32,
+    ) -> PacketDisplay<'_> {
+        PacketDisplay {
+            packet: self,
+            numbers: Some((sequence_number, acknowledgment_number)),
+        }
+    }
+
+    /// Replaces what a TCP segment was recognised as, for protocols that can
+    /// only be recognised from earlier packets.
+    pub fn set_application(&mut self, new_application: Application) {
+        if let Some(PacketContents::TCP {
+            ref mut application,
+            ..
+        }) = self.contents
+        {
+            *application = Some(new_application);
+        }
+    }
+
+    pub fn truncation(&self) -> Option<&Truncation> {
+        self.truncation.as_ref()
+    }
+
+    fn empty() -> Packet {
+        Packet {
+            frame: None,
+            ip_header: None,
+            contents: None,
+            trailer: Vec::new(),
+            truncation: None,
+        }
+    }
+
+    fn parse_ipv4(self, input: &[u8], depth: usize) -> Result<Packet, ParseError> {
+        match decode(Ipv4Header::parse(input), ParseError::InvalidIpHeader)? {
+            Some((after_ip_header, header)) => {
+                self.parse_ip_payload(IpHeader::V4(header), after_ip_header, depth)
+            }
+            None => Ok(self.truncated_at(Layer::IPv4, input)),
+        }
+    }
+
+    fn parse_ipv6(self, input: &[u8], depth: usize) -> Result<Packet, ParseError> {
+        match decode(Ipv6Header::parse(input), ParseError::InvalidIpHeader)? {
+            Some((after_ip_header, header)) => {
+                self.parse_ip_payload(IpHeader::V6(header), after_ip_header, depth)
+            }
+            None => Ok(self.truncated_at(Layer::IPv6, input)),
+        }
+    }
+
+    fn parse_ip_payload(
+        self,
+        ip_header: IpHeader,
+        input: &[u8],
+        depth: usize,
+    ) -> Result<Packet, ParseError> {
+        let payload_length = ip_header.payload_length().unwrap_or(input.len());
+        let (payload, trailer) = input.split_at(cmp::min(payload_length, input.len()));
+
+        let protocol = *ip_header.protocol();
+        let mut pseudo_header =
+            PseudoHeader::new(ip_header.source(), ip_header.destination(), payload_length);
+        let contents = if ip_header.fragment_offset() != 0 {
+            // Only the first fragment starts with the protocol's header.
+            Some(PacketContents::Fragment {
+                offset: ip_header.fragment_offset(),
+                data: payload.to_vec(),
+            })
+        } else {
+            if ip_header.is_fragment() {
+                pseudo_header = pseudo_header.first_fragment();
+            }
+            PacketContents::parse(&protocol, &pseudo_header, payload, depth)?
+        };
+        let packet = Packet {
+            ip_header: Some(ip_header),
             contents,
-        })
+            trailer: trailer.to_vec(),
+            ..self
+        };
+
+        let complete = packet
+            .contents
+            .as_ref()
+            .is_some_and(PacketContents::is_complete);
+        if !complete || payload.len() < payload_length {
+            return Ok(packet.truncated_at(Layer::Protocol(protocol), payload));
+        }
+
+        Ok(packet)
+    }
+
+    fn truncated_at(self, layer: Layer, remaining: &[u8]) -> Packet {
+        Packet {
+            truncation: Some(Truncation {
+                layer,
+                offset: remaining.len(),
+            }),
+            ..self
+        }
+    }
+}
+
+/// A one line summary of the packet, listing the addresses and protocol
+/// followed by the details of each layer. Encapsulated packets are separated
+/// by `|`.
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        PacketDisplay {
+            packet: self,
+            numbers: None,
+        }
+        .fmt(f)
+    }
+}
+
+/// A packet shown with the TCP numbers passed to `Packet::with_numbers`.
+pub struct PacketDisplay<'a> {
+    packet: &'a Packet,
+    numbers: Option<(u32, u32)>,
+}
+
+impl<'a> fmt::Display for PacketDisplay<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let packet = self.packet;
+        match (&packet.frame, &packet.ip_header) {
+            (_, Some(ip_header)) => write!(
+                f,
+                "{} -> {} {}",
+                ip_header.source(),
+                ip_header.destination(),
+                ip_header.protocol()
+            )?,
+            (Some(frame), None) => write!(
+                f,
+                "{} -> {} {:?}",
+                frame.source(),
+                frame.destination(),
+                frame.ether_type()
+            )?,
+            (None, None) => {}
+        }
+
+        match packet.contents {
+            Some(PacketContents::Other) | None => {}
+            Some(ref contents) => {
+                write!(f, " ")?;
+                contents.fmt_with_numbers(f, self.numbers)?;
+            }
+        }
+
+        if let Some(ref truncation) = packet.truncation {
+            write!(f, " [{}]", truncation)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The depth of a packet nested one level below `depth`, or `None` when that
+/// would be deeper than `MAXIMUM_NESTING_DEPTH` and the packet is left
+/// undecoded.
+fn nested(depth: usize) -> Option<usize> {
+    if depth >= MAXIMUM_NESTING_DEPTH {
+        return None;
+    }
+
+    Some(depth + 1)
+}
+
+/// Runs a header parser over possibly truncated input. Running out of input
+/// is reported as `None` rather than as an error so the layers decoded before
+/// it are kept.
+fn decode<T>(
+    result: IResult<&[u8], T>,
+    error: ParseError,
+) -> Result<Option<(&[u8], T)>, ParseError> {
+    match result {
+        Ok(decoded) => Ok(Some(decoded)),
+        Err(Err::Incomplete(_)) => Ok(None),
+        Err(_) => Err(error),
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Layer {
+    Ethernet,
+    IPv4,
+    IPv6,
+
+    /// The payload of an IP packet.
+    Protocol(Protocol),
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Layer::Ethernet => write!(f, "Ethernet"),
+            Layer::IPv4 => write!(f, "IPv4"),
+            Layer::IPv6 => write!(f, "IPv6"),
+            Layer::Protocol(protocol) => write!(f, "{}", protocol),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug)]
+pub struct Truncation {
+    layer: Layer,
+
+    /// Number of bytes of the layer which were captured.
+    offset: usize,
+}
+
+impl Truncation {
+    pub fn layer(&self) -> Layer {
+        self.layer
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for Truncation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "truncated at layer {}, byte {}", self.layer, self.offset)
     }
 }
 
 #[derive(Eq, PartialEq, Debug)]
 pub enum PacketContents {
-    ICMP { header: IcmpHeader, data: Vec<u8> },
+    ICMP {
+        header: IcmpHeader,
+        data: Vec<u8>,
+    },
+
+    TCP {
+        header: TcpHeader,
+        data: Vec<u8>,
+        checksum: ChecksumStatus,
+        application: Option<Application>,
+    },
+
+    /// The fields of a TCP header which the capture ends partway through.
+    TruncatedTCP {
+        header: TruncatedTcpHeader,
+    },
 
-    TCP { header: TcpHeader, data: Vec<u8> },
+    UDP {
+        packet: UdpPacket,
+        checksum: ChecksumStatus,
+    },
 
-    UDP { packet: UdpPacket },
+    IGMP {
+        packet: IgmpPacket,
+    },
+
+    ESP {
+        header: EspHeader,
+        data: Vec<u8>,
+    },
+
+    /// An authentication header followed by the packet it authenticates.
+    AH {
+        header: AhHeader,
+        contents: Option<Box<PacketContents>>,
+    },
+
+    /// A packet encapsulated directly in IP, as with IP-in-IP and 6in4.
+    Tunnel {
+        packet: Box<Packet>,
+    },
+
+    GRE {
+        header: GreHeader,
+        payload: Option<GrePayload>,
+    },
+
+    /// A fragment of a datagram other than the first, which carries the
+    /// bytes at `offset` of the protocol's packet.
+    Fragment {
+        offset: usize,
+        data: Vec<u8>,
+    },
 
     Other,
 }
 
 impl PacketContents {
-    fn parse(proto: &Protocol, input: &[u8]) -> Result<PacketContents, ParseError> {
+    /// Parses the payload of an IP packet. Returns `None` when the input ends
+    /// before the protocol's header does, except for TCP, where the fields
+    /// which were captured are kept.
+    fn parse(
+        proto: &Protocol,
+        pseudo_header: &PseudoHeader,
+        input: &[u8],
+        depth: usize,
+    ) -> Result<Option<PacketContents>, ParseError> {
         let contents = match proto {
             Protocol::ICMP => {
                 let (after_header, header) =
-                    IcmpHeader::parse(input).map_err(|_e| ParseError::InvalidIcmpPacket)?;
+                    match decode(IcmpHeader::parse(input), ParseError::InvalidIcmpPacket)? {
+                        Some(decoded) => decoded,
+                        None => return Ok(None),
+                    };
 
                 let data = after_header.to_vec();
 
@@ -72,21 +481,373 @@ impl PacketContents {
             }
             Protocol::TCP => {
                 let (after_header, header) =
-                    TcpHeader::parse(input).map_err(|_e| ParseError::InvalidTcpHeader)?;
+                    match decode(TcpHeader::parse(input), ParseError::InvalidTcpHeader)? {
+                        Some(decoded) => decoded,
+                        None => {
+                            return Ok(TruncatedTcpHeader::parse(input)
+                                .map(|header| PacketContents::TruncatedTCP { header }))
+                        }
+                    };
                 let data = after_header.to_vec();
+                let checksum = pseudo_header.verify(Protocol::TCP, input, header.checksum());
+                let application = Application::parse(&header, &data);
 
-                PacketContents::TCP { header, data }
+                PacketContents::TCP {
+                    header,
+                    data,
+                    checksum,
+                    application,
+                }
             }
             Protocol::UDP => {
                 let (_, packet) =
-                    UdpPacket::parse(input).map_err(|_e| ParseError::InvalidUdpPacket)?;
+                    match decode(UdpPacket::parse(input), ParseError::InvalidUdpPacket)? {
+                        Some(decoded) => decoded,
+                        None => return Ok(None),
+                    };
+
+                let checksum = if packet.checksum() == 0 && pseudo_header.is_ipv4() {
+                    ChecksumStatus::NotPresent
+                } else {
+                    pseudo_header.verify(Protocol::UDP, input, packet.checksum())
+                };
+
+                PacketContents::UDP { packet, checksum }
+            }
+            Protocol::IGMP => {
+                let (_, packet) =
+                    match decode(IgmpPacket::parse(input), ParseError::InvalidIgmpPacket)? {
+                        Some(decoded) => decoded,
+                        None => return Ok(None),
+                    };
+
+                PacketContents::IGMP { packet }
+            }
+            Protocol::ESP => {
+                let (after_header, header) =
+                    match decode(EspHeader::parse(input), ParseError::InvalidEspHeader)? {
+                        Some(decoded) => decoded,
+                        None => return Ok(None),
+                    };
+                let data = after_header.to_vec();
+
+                PacketContents::ESP { header, data }
+            }
+            Protocol::AH => {
+                let (after_header, header) =
+                    match decode(AhHeader::parse(input), ParseError::InvalidAhHeader)? {
+                        Some(decoded) => decoded,
+                        None => return Ok(None),
+                    };
+                let pseudo_header = pseudo_header.after_header(input.len() - after_header.len());
+                let contents = match nested(depth) {
+                    Some(depth) => PacketContents::parse(
+                        header.next_header(),
+                        &pseudo_header,
+                        after_header,
+                        depth,
+                    )?,
+                    None => None,
+                };
+
+                PacketContents::AH {
+                    header,
+                    contents: contents.map(Box::new),
+                }
+            }
+            Protocol::IPv4 => match nested(depth) {
+                Some(depth) => PacketContents::Tunnel {
+                    packet: Box::new(Packet::empty().parse_ipv4(input, depth)?),
+                },
+                None => return Ok(None),
+            },
+            Protocol::IPv6 => match nested(depth) {
+                Some(depth) => PacketContents::Tunnel {
+                    packet: Box::new(Packet::empty().parse_ipv6(input, depth)?),
+                },
+                None => return Ok(None),
+            },
+            Protocol::GRE => {
+                let (after_header, header) =
+                    match decode(GreHeader::parse(input), ParseError::InvalidGreHeader)? {
+                        Some(decoded) => decoded,
+                        None => return Ok(None),
+                    };
+                let payload = match nested(depth) {
+                    Some(depth) => GrePayload::parse(&header, after_header, depth)?,
+                    None => None,
+                };
 
-                PacketContents::UDP { packet }
+                PacketContents::GRE { header, payload }
             }
             _ => PacketContents::Other,
         };
 
-        Ok(contents)
+        Ok(Some(contents))
+    }
+
+    /// Whether every layer the contents hold was decoded. The packet an
+    /// authentication header or GRE header carries may be missing.
+    fn is_complete(&self) -> bool {
+        match self {
+            PacketContents::AH {
+                contents: Some(contents),
+                ..
+            } => contents.is_complete(),
+            PacketContents::AH { contents: None, .. }
+            | PacketContents::GRE { payload: None, .. } => false,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for PacketContents {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.fmt_with_numbers(f, None)
+    }
+}
+
+impl PacketContents {
+    /// Writes the contents, showing `numbers` as the sequence and
+    /// acknowledgment numbers of the TCP header when given.
+    fn fmt_with_numbers(&self, f: &mut Formatter, numbers: Option<(u32, u32)>) -> fmt::Result {
+        match self {
+            PacketContents::ICMP { header, .. } => write!(f, "{}", header),
+            PacketContents::TCP {
+                header,
+                data,
+                checksum,
+                application,
+            } => {
+                let (sequence_number, acknowledgment_number) =
+                    numbers.unwrap_or((header.sequence_number(), header.acknowledgment_number()));
+                write!(
+                    f,
+                    "{} Len={}",
+                    header.with_numbers(sequence_number, acknowledgment_number),
+                    data.len()
+                )?;
+                write_checksum_status(f, checksum)?;
+                match application {
+                    Some(application) => write!(f, " | {}", application),
+                    None => Ok(()),
+                }
+            }
+            PacketContents::TruncatedTCP { header } => write!(f, "{}", header),
+            PacketContents::UDP { packet, checksum } => {
+                write!(f, "{}", packet)?;
+                write_checksum_status(f, checksum)
+            }
+            PacketContents::IGMP { packet } => write!(f, "{}", packet),
+            PacketContents::ESP { header, .. } => write!(f, "{}", header),
+            PacketContents::AH { header, contents } => {
+                write!(f, "{} | {}", header, header.next_header())?;
+                match contents {
+                    Some(contents) => {
+                        write!(f, " ")?;
+                        contents.fmt_with_numbers(f, numbers)
+                    }
+                    None => Ok(()),
+                }
+            }
+            PacketContents::Tunnel { packet } => {
+                write!(f, "| {}", PacketDisplay { packet, numbers })
+            }
+            PacketContents::GRE { header, payload } => {
+                write!(f, "{}", header)?;
+                match payload {
+                    Some(GrePayload::Packet(packet))
+                    | Some(GrePayload::ERSPAN { frame: packet, .. }) => {
+                        write!(f, " | {}", PacketDisplay { packet, numbers })
+                    }
+                    _ => Ok(()),
+                }
+            }
+            PacketContents::Fragment { offset, data } => {
+                write!(f, "Fragment Offset={} Len={}", offset, data.len())
+            }
+            PacketContents::Other => Ok(()),
+        }
+    }
+}
+
+fn write_checksum_status(f: &mut Formatter, checksum: &ChecksumStatus) -> fmt::Result {
+    if checksum.is_notable() {
+        write!(f, " [checksum {}]", checksum)?;
+    }
+
+    Ok(())
+}
+
+/// The application protocol carried by a TCP segment, recognised by port or
+/// by how the segment starts.
+#[derive(Eq, PartialEq, Debug)]
+pub enum Application {
+    Telnet(TelnetMessage),
+
+    /// A segment starting with a whole HTTP/1.x head.
+    HTTP(HttpHead),
+
+    /// The TLS records in a segment which starts on a record boundary.
+    TLS(Vec<TlsRecord>),
+
+    /// The whole HTTP/2 frames in a segment starting with the connection
+    /// preface or a SETTINGS frame. Headers stay compressed, since that
+    /// needs the rest of the connection.
+    HTTP2 {
+        preface: bool,
+        frames: Vec<Frame>,
+    },
+
+    /// A segment starting with an SSH identification line.
+    SSH(SshBanner),
+
+    /// The first command or reply in a segment of an FTP control connection.
+    FTP(FtpMessage),
+
+    /// A segment of a connection an FTP control connection announced. These
+    /// are only recognised when the whole capture is read in order.
+    FtpData,
+}
+
+impl Application {
+    fn parse(header: &TcpHeader, data: &[u8]) -> Option<Application> {
+        if data.is_empty() {
+            return None;
+        }
+
+        let ports = [header.source_port(), header.destination_port()];
+        if ports.contains(&23) {
+            return Some(Application::Telnet(TelnetMessage::parse(data)));
+        }
+
+        if ports.contains(&FTP_PORT) {
+            let from_server = header.source_port() == FTP_PORT;
+            return FtpMessage::parse(data, from_server).map(Application::FTP);
+        }
+
+        if looks_like_http2(data) {
+            return Some(Application::HTTP2 {
+                preface: data.starts_with(CONNECTION_PREFACE),
+                frames: Frame::parse_segment(data),
+            });
+        }
+
+        if ports.iter().any(|port| HTTP_PORTS.contains(port)) || looks_like_http(data) {
+            if let Ok(Some((_, head))) = HttpHead::parse(data) {
+                return Some(Application::HTTP(head));
+            }
+        }
+
+        if looks_like_tls(data) {
+            return Some(Application::TLS(TlsRecord::parse_segment(data)));
+        }
+
+        if looks_like_ssh(data) {
+            let line = data.split(|&byte| byte == b'\n').next().unwrap_or_default();
+            if let Ok(banner) = SshBanner::parse(line) {
+                return Some(Application::SSH(banner));
+            }
+        }
+
+        None
+    }
+}
+
+impl fmt::Display for Application {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Application::Telnet(message) => write!(f, "{}", message),
+            Application::HTTP(head) => write!(f, "{}", head),
+            Application::TLS(records) => {
+                write!(f, "TLS")?;
+                for (index, record) in records.iter().enumerate() {
+                    let separator = if index == 0 { " " } else { "; " };
+                    write!(f, "{}{}", separator, record)?;
+                }
+
+                Ok(())
+            }
+            Application::HTTP2 { preface, frames } => {
+                write!(f, "HTTP/2")?;
+                let mut separator = " ";
+                if *preface {
+                    write!(f, " Connection Preface")?;
+                    separator = "; ";
+                }
+                for frame in frames {
+                    write!(f, "{}{}", separator, frame)?;
+                    separator = "; ";
+                }
+
+                Ok(())
+            }
+            Application::SSH(banner) => write!(f, "{}", banner),
+            Application::FTP(message) => write!(f, "FTP {}", message),
+            Application::FtpData => write!(f, "FTP-DATA"),
+        }
+    }
+}
+
+/// The packet carried by GRE.
+#[derive(Eq, PartialEq, Debug)]
+pub enum GrePayload {
+    Packet(Box<Packet>),
+
+    /// A frame mirrored by ERSPAN. Type I sessions carry the frame without an
+    /// ERSPAN header.
+    ERSPAN {
+        header: Option<ErspanHeader>,
+        frame: Box<Packet>,
+    },
+
+    Other(Vec<u8>),
+}
+
+impl GrePayload {
+    /// Parses the payload following a GRE header. Returns `None` when the
+    /// input ends before the ERSPAN header does.
+    fn parse(
+        header: &GreHeader,
+        input: &[u8],
+        depth: usize,
+    ) -> Result<Option<GrePayload>, ParseError> {
+        let payload = match header.protocol_type() {
+            EtherType::IP => {
+                GrePayload::Packet(Box::new(Packet::empty().parse_ipv4(input, depth)?))
+            }
+            EtherType::IPv6 => {
+                GrePayload::Packet(Box::new(Packet::empty().parse_ipv6(input, depth)?))
+            }
+            EtherType::TransparentEthernetBridging => {
+                GrePayload::Packet(Box::new(Packet::parse_frame(input, depth)?))
+            }
+            EtherType::ERSPAN if header.sequence_number().is_none() => GrePayload::ERSPAN {
+                header: None,
+                frame: Box::new(Packet::parse_frame(input, depth)?),
+            },
+            EtherType::ERSPAN | EtherType::ERSPANTypeIII => {
+                let parsed = if *header.protocol_type() == EtherType::ERSPAN {
+                    ErspanHeader::parse_type_ii(input)
+                } else {
+                    ErspanHeader::parse_type_iii(input)
+                };
+
+                let (after_header, erspan_header) =
+                    match decode(parsed, ParseError::InvalidErspanHeader)? {
+                        Some(decoded) => decoded,
+                        None => return Ok(None),
+                    };
+
+                GrePayload::ERSPAN {
+                    header: Some(erspan_header),
+                    frame: Box::new(Packet::parse_frame(after_header, depth)?),
+                }
+            }
+            _ => GrePayload::Other(input.to_vec()),
+        };
+
+        Ok(Some(payload))
     }
 }
 
@@ -94,11 +855,18 @@ impl PacketContents {
 mod tests {
     extern crate hex;
 
+    use checksum::ChecksumStatus;
     use ether::EtherFrame;
+    use gre::GreHeader;
     use icmp::IcmpHeader;
-    use ip::Ipv4Header;
-    use packet::{Packet, PacketContents};
-    use tcp::TcpHeader;
+    use igmp::IgmpPacket;
+    use ip::{IpHeader, Ipv4Header, Protocol};
+    use ipsec::AhHeader;
+    use packet::{
+        Application, GrePayload, Layer, Packet, PacketContents, Truncation, MAXIMUM_NESTING_DEPTH,
+    };
+    use tcp::{TcpHeader, TruncatedTcpHeader};
+    use telnet::TelnetMessage;
     use udp::UdpPacket;
 
     #[test]
@@ -119,12 +887,14 @@ mod tests {
         assert_eq!(
             packet,
             Packet {
-                frame: EtherFrame::parse(&raw_ether_frame).unwrap().1,
-                ip_header: Ipv4Header::parse(&raw_ip_header).unwrap().1,
-                contents: PacketContents::ICMP {
+                frame: Some(EtherFrame::parse(&raw_ether_frame).unwrap().1),
+                ip_header: Some(IpHeader::V4(Ipv4Header::parse(&raw_ip_header).unwrap().1)),
+                contents: Some(PacketContents::ICMP {
                     header: IcmpHeader::parse(&raw_icmp_header).unwrap().1,
                     data: raw_icmp_data,
-                }
+                }),
+                trailer: Vec::new(),
+                truncation: None,
             }
         )
     }
@@ -146,12 +916,16 @@ mod tests {
         assert_eq!(
             packet,
             Packet {
-                frame: EtherFrame::parse(&raw_ether_frame).unwrap().1,
-                ip_header: Ipv4Header::parse(&raw_ip_header).unwrap().1,
-                contents: PacketContents::TCP {
+                frame: Some(EtherFrame::parse(&raw_ether_frame).unwrap().1),
+                ip_header: Some(IpHeader::V4(Ipv4Header::parse(&raw_ip_header).unwrap().1)),
+                contents: Some(PacketContents::TCP {
                     header: TcpHeader::parse(&raw_tcp_packet).unwrap().1,
                     data: Vec::new(),
-                },
+                    checksum: ChecksumStatus::Valid,
+                    application: None,
+                }),
+                trailer: Vec::new(),
+                truncation: None,
             },
         )
     }
@@ -172,12 +946,373 @@ mod tests {
         assert_eq!(
             packet,
             Packet {
-                frame: EtherFrame::parse(&raw_ether_frame).unwrap().1,
-                ip_header: Ipv4Header::parse(&raw_ip_header).unwrap().1,
-                contents: PacketContents::UDP {
-                    packet: UdpPacket::parse(&raw_udp_packet).unwrap().1
-                },
+                frame: Some(EtherFrame::parse(&raw_ether_frame).unwrap().1),
+                ip_header: Some(IpHeader::V4(Ipv4Header::parse(&raw_ip_header).unwrap().1)),
+                contents: Some(PacketContents::UDP {
+                    packet: UdpPacket::parse(&raw_udp_packet).unwrap().1,
+                    checksum: ChecksumStatus::Valid,
+                }),
+                trailer: Vec::new(),
+                truncation: None,
             }
         )
     }
+
+    #[test]
+    fn parse_telnet() {
+        let raw_ether_frame = hex::decode("00e0f7263fe908002086354b0800").unwrap();
+        let raw_ip_header = hex::decode("4500002f08b84000ff0699948b85d96e8b85e902").unwrap();
+        let raw_tcp_packet =
+            hex::decode("900500177214f1140000000060182238a92c0000020405b4").unwrap();
+        let raw_telnet = hex::decode("fffd01").unwrap();
+
+        let mut raw = raw_ether_frame.clone();
+        raw.append(&mut raw_ip_header.clone());
+        raw.append(&mut raw_tcp_packet.clone());
+        raw.append(&mut raw_telnet.clone());
+
+        let packet = Packet::parse(&raw).unwrap();
+
+        match packet.contents {
+            Some(PacketContents::TCP {
+                application: Some(Application::Telnet(ref message)),
+                ..
+            }) => assert_eq!(message, &TelnetMessage::parse(&raw_telnet)),
+            ref contents => panic!("expected Telnet, got {:?}", contents),
+        }
+        assert!(packet.to_string().ends_with("| Telnet Do Echo"));
+    }
+
+    #[test]
+    fn parse_tcp_with_ethernet_padding() {
+        let raw_ether_frame = hex::decode("00e0f7263fe908002086354b0800").unwrap();
+        let raw_ip_header = hex::decode("4500002c08b84000ff0699978b85d96e8b85e902").unwrap();
+        let raw_tcp_packet =
+            hex::decode("900500177214f1140000000060022238a92c0000020405b4").unwrap();
+
+        let mut raw = raw_ether_frame.clone();
+        raw.append(&mut raw_ip_header.clone());
+        raw.append(&mut raw_tcp_packet.clone());
+        raw.append(&mut vec![0, 0]);
+
+        let packet = Packet::parse(&raw).unwrap();
+
+        assert_eq!(
+            packet,
+            Packet {
+                frame: Some(EtherFrame::parse(&raw_ether_frame).unwrap().1),
+                ip_header: Some(IpHeader::V4(Ipv4Header::parse(&raw_ip_header).unwrap().1)),
+                contents: Some(PacketContents::TCP {
+                    header: TcpHeader::parse(&raw_tcp_packet).unwrap().1,
+                    data: Vec::new(),
+                    checksum: ChecksumStatus::Valid,
+                    application: None,
+                }),
+                trailer: vec![0, 0],
+                truncation: None,
+            },
+        )
+    }
+
+    #[test]
+    fn parse_truncated_udp() {
+        let raw_ether_frame = hex::decode("00e0f7263fe908002086354b0800").unwrap();
+        let raw_ip_header = hex::decode("45000026ab494000ff11f7008b85d96e8b85e902").unwrap();
+        let raw_udp_packet = hex::decode("99d0043f0012722868656c6c6f68").unwrap();
+
+        let mut raw = raw_ether_frame.clone();
+        raw.append(&mut raw_ip_header.clone());
+        raw.append(&mut raw_udp_packet.clone());
+
+        let packet = Packet::parse(&raw).unwrap();
+
+        assert_eq!(
+            packet.contents,
+            Some(PacketContents::UDP {
+                packet: UdpPacket::parse(&raw_udp_packet).unwrap().1,
+                checksum: ChecksumStatus::Unverified,
+            }),
+        );
+        assert_eq!(
+            packet.truncation,
+            Some(Truncation {
+                layer: Layer::Protocol(Protocol::UDP),
+                offset: 14,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_first_fragment() {
+        let raw_ether_frame = hex::decode("00e0f7263fe908002086354b0800").unwrap();
+        let raw_ip_header = hex::decode("45000020ab492000ff11f7008b85d96e8b85e902").unwrap();
+        let raw_udp_packet = hex::decode("99d0043f0012722868656c6c").unwrap();
+
+        let mut raw = raw_ether_frame.clone();
+        raw.append(&mut raw_ip_header.clone());
+        raw.append(&mut raw_udp_packet.clone());
+
+        let packet = Packet::parse(&raw).unwrap();
+
+        // The checksum covers the rest of the payload in the next fragment.
+        assert_eq!(
+            packet.contents,
+            Some(PacketContents::UDP {
+                packet: UdpPacket::parse(&raw_udp_packet).unwrap().1,
+                checksum: ChecksumStatus::Unverified,
+            }),
+        );
+        assert_eq!(packet.truncation, None);
+    }
+
+    #[test]
+    fn parse_later_fragment() {
+        let raw_ether_frame = hex::decode("00e0f7263fe908002086354b0800").unwrap();
+        let raw_ip_header = hex::decode("4500001aab490001ff11f7008b85d96e8b85e902").unwrap();
+        let raw_data = hex::decode("6f68656c6c6f").unwrap();
+
+        let mut raw = raw_ether_frame.clone();
+        raw.append(&mut raw_ip_header.clone());
+        raw.append(&mut raw_data.clone());
+
+        let packet = Packet::parse(&raw).unwrap();
+
+        assert_eq!(
+            packet.contents,
+            Some(PacketContents::Fragment {
+                offset: 8,
+                data: raw_data,
+            }),
+        );
+        assert_eq!(
+            packet.to_string(),
+            "139.133.217.110 -> 139.133.233.2 UDP Fragment Offset=8 Len=6"
+        );
+    }
+
+    #[test]
+    fn parse_truncated_tcp_header() {
+        let raw_ether_frame = hex::decode("00e0f7263fe908002086354b0800").unwrap();
+        let raw_ip_header = hex::decode("4500002c08b84000ff0699978b85d96e8b85e902").unwrap();
+        let raw_tcp_packet = hex::decode("900500177214f1140000").unwrap();
+
+        let mut raw = raw_ether_frame.clone();
+        raw.append(&mut raw_ip_header.clone());
+        raw.append(&mut raw_tcp_packet.clone());
+
+        let packet = Packet::parse(&raw).unwrap();
+
+        assert_eq!(
+            packet,
+            Packet {
+                frame: Some(EtherFrame::parse(&raw_ether_frame).unwrap().1),
+                ip_header: Some(IpHeader::V4(Ipv4Header::parse(&raw_ip_header).unwrap().1)),
+                contents: Some(PacketContents::TruncatedTCP {
+                    header: TruncatedTcpHeader::parse(&raw_tcp_packet).unwrap(),
+                }),
+                trailer: Vec::new(),
+                truncation: Some(Truncation {
+                    layer: Layer::Protocol(Protocol::TCP),
+                    offset: 10,
+                }),
+            },
+        );
+        assert_eq!(
+            packet.truncation().unwrap().to_string(),
+            "truncated at layer TCP, byte 10"
+        );
+        assert_eq!(
+            packet.to_string(),
+            "139.133.217.110 -> 139.133.233.2 TCP 36869 -> 23 Seq=1913975060 \
+             [truncated at layer TCP, byte 10]"
+        );
+
+        // Not even the ports fit in two bytes.
+        let mut raw = raw_ether_frame.clone();
+        raw.append(&mut raw_ip_header.clone());
+        raw.extend_from_slice(&raw_tcp_packet[..2]);
+
+        assert_eq!(Packet::parse(&raw).unwrap().contents, None);
+    }
+
+    #[test]
+    fn parse_ip_in_ip() {
+        let raw_ether_frame = hex::decode("00e0f7263fe908002086354b0800").unwrap();
+        let raw_outer_ip_header = hex::decode("4500003a0000400040040000c0a80001c0a80002").unwrap();
+        let raw_ip_header = hex::decode("45000026ab494000ff11f7008b85d96e8b85e902").unwrap();
+        let raw_udp_packet = hex::decode("99d0043f0012722868656c6c6f68656c6c6f").unwrap();
+
+        let mut raw = raw_ether_frame.clone();
+        raw.append(&mut raw_outer_ip_header.clone());
+        raw.append(&mut raw_ip_header.clone());
+        raw.append(&mut raw_udp_packet.clone());
+
+        let packet = Packet::parse(&raw).unwrap();
+
+        assert_eq!(
+            packet.contents,
+            Some(PacketContents::Tunnel {
+                packet: Box::new(Packet {
+                    frame: None,
+                    ip_header: Some(IpHeader::V4(Ipv4Header::parse(&raw_ip_header).unwrap().1)),
+                    contents: Some(PacketContents::UDP {
+                        packet: UdpPacket::parse(&raw_udp_packet).unwrap().1,
+                        checksum: ChecksumStatus::Valid,
+                    }),
+                    trailer: Vec::new(),
+                    truncation: None,
+                }),
+            }),
+        )
+    }
+
+    #[test]
+    fn display_tunnelled_tcp_with_numbers() {
+        let raw = hex::decode(
+            "45000040000040004004 0000c0a80001c0a80002\
+             4500002c08b84000ff0699978b85d96e8b85e902\
+             900500177214f1140000000060022238a92c0000020405b4"
+                .replace(' ', ""),
+        )
+        .unwrap();
+        let packet = Packet::parse_ipv4_packet(&raw).unwrap();
+
+        assert!(packet.tcp().is_none());
+        let (ip_header, header, _) = packet.carried_tcp().unwrap();
+        assert_eq!(ip_header.source().to_string(), "139.133.217.110");
+        assert_eq!(header.sequence_number(), 1913975060);
+        assert_eq!(
+            packet.with_numbers(0, 0).to_string(),
+            "192.168.0.1 -> 192.168.0.2 IPv4 | 139.133.217.110 -> 139.133.233.2 TCP \
+             36869 -> 23 [SYN] Seq=0 Win=8760 Len=0"
+        );
+    }
+
+    #[test]
+    fn parse_deeply_nested_ip_in_ip() {
+        // IPv4 headers each carrying the next, the innermost one an empty
+        // UDP packet.
+        let nested = |levels: usize| {
+            let mut raw = Vec::new();
+            for level in 1..=levels {
+                let total_length = 20 * (levels - level + 1) + 8;
+                let protocol = if level == levels { 0x11 } else { 0x04 };
+                raw.extend_from_slice(&[0x45, 0x00, 0x00, total_length as u8]);
+                raw.extend_from_slice(&[0x00, 0x00, 0x40, 0x00, 0x40, protocol, 0x00, 0x00]);
+                raw.extend(hex::decode("c0a80001c0a80002").unwrap());
+            }
+            raw.extend(hex::decode("99d0043f00080000").unwrap());
+            raw
+        };
+        let innermost = |mut packet: Packet| {
+            for _ in 0..MAXIMUM_NESTING_DEPTH {
+                assert_eq!(packet.truncation, None);
+                packet = match packet.contents {
+                    Some(PacketContents::Tunnel { packet }) => *packet,
+                    contents => panic!("expected a tunnel, found {:?}", contents),
+                };
+            }
+            packet
+        };
+
+        let packet = Packet::parse_ipv4_packet(&nested(MAXIMUM_NESTING_DEPTH + 1)).unwrap();
+        let packet = innermost(packet);
+        assert!(matches!(packet.contents, Some(PacketContents::UDP { .. })));
+        assert_eq!(packet.truncation, None);
+
+        // One level deeper, the innermost tunnel isn't decoded.
+        let packet = Packet::parse_ipv4_packet(&nested(MAXIMUM_NESTING_DEPTH + 2)).unwrap();
+        let packet = innermost(packet);
+        assert_eq!(packet.contents, None);
+        assert_eq!(
+            packet.truncation,
+            Some(Truncation {
+                layer: Layer::Protocol(Protocol::IPv4),
+                offset: 28,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_gre_transparent_ethernet_bridging() {
+        let raw_ether_frame = hex::decode("00e0f7263fe908002086354b0800").unwrap();
+        let raw_outer_ip_header = hex::decode("4500004c00004000402f0000c0a80001c0a80002").unwrap();
+        let raw_gre_header = hex::decode("00006558").unwrap();
+        let raw_ip_header = hex::decode("45000026ab494000ff11f7008b85d96e8b85e902").unwrap();
+        let raw_udp_packet = hex::decode("99d0043f0012722868656c6c6f68656c6c6f").unwrap();
+
+        let mut raw_inner_frame = raw_ether_frame.clone();
+        raw_inner_frame.append(&mut raw_ip_header.clone());
+        raw_inner_frame.append(&mut raw_udp_packet.clone());
+
+        let mut raw = raw_ether_frame.clone();
+        raw.append(&mut raw_outer_ip_header.clone());
+        raw.append(&mut raw_gre_header.clone());
+        raw.append(&mut raw_inner_frame.clone());
+
+        let packet = Packet::parse(&raw).unwrap();
+
+        assert_eq!(packet.truncation, None);
+        assert_eq!(
+            packet.contents,
+            Some(PacketContents::GRE {
+                header: GreHeader::parse(&raw_gre_header).unwrap().1,
+                payload: Some(GrePayload::Packet(Box::new(
+                    Packet::parse(&raw_inner_frame).unwrap()
+                ))),
+            }),
+        )
+    }
+
+    #[test]
+    fn parse_igmp() {
+        let raw_ether_frame = hex::decode("01005e010203002086354b000800").unwrap();
+        let raw_ip_header = hex::decode("4600002000004000010200000a000006e0000002").unwrap();
+        let raw_ip_options = hex::decode("94040000").unwrap();
+        let raw_igmp_packet = hex::decode("17000000ef010203").unwrap();
+
+        let mut raw = raw_ether_frame.clone();
+        raw.append(&mut raw_ip_header.clone());
+        raw.append(&mut raw_ip_options.clone());
+        raw.append(&mut raw_igmp_packet.clone());
+
+        let packet = Packet::parse(&raw).unwrap();
+
+        assert_eq!(
+            packet.contents,
+            Some(PacketContents::IGMP {
+                packet: IgmpPacket::parse(&raw_igmp_packet).unwrap().1
+            }),
+        )
+    }
+
+    #[test]
+    fn parse_ah() {
+        let raw_ether_frame = hex::decode("00e0f7263fe908002086354b0800").unwrap();
+        let raw_ip_header = hex::decode("450000440000400040330000c0a80001c0a80002").unwrap();
+        let raw_ah_header =
+            hex::decode("0604000000001002000000070102030405060708090a0b0c").unwrap();
+        let raw_tcp_packet =
+            hex::decode("900500177214f1140000000060022238a92c0000020405b4").unwrap();
+
+        let mut raw = raw_ether_frame.clone();
+        raw.append(&mut raw_ip_header.clone());
+        raw.append(&mut raw_ah_header.clone());
+        raw.append(&mut raw_tcp_packet.clone());
+
+        let packet = Packet::parse(&raw).unwrap();
+
+        assert_eq!(
+            packet.contents,
+            Some(PacketContents::AH {
+                header: AhHeader::parse(&raw_ah_header).unwrap().1,
+                contents: Some(Box::new(PacketContents::TCP {
+                    header: TcpHeader::parse(&raw_tcp_packet).unwrap().1,
+                    data: Vec::new(),
+                    checksum: ChecksumStatus::Invalid { expected: 0x0155 },
+                    application: None,
+                })),
+            }),
+        )
+    }
 }
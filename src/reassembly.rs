@@ -0,0 +1,755 @@
+use flow::{Direction, Flow, TcpSegment};
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::time::Duration;
+
+/// Offsets further than this past the next expected byte are taken to be
+/// from before the start of the stream, after wrapping around.
+const MAXIMUM_WINDOW: u32 = 1 << 30;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StreamData {
+    Bytes(Vec<u8>),
+
+    /// Bytes which were never captured.
+    Missing(u64),
+}
+
+/// Puts the segments sent in one direction of a TCP connection back into
+/// order. Retransmitted bytes are dropped, overlapping segments are trimmed
+/// to the part not yet seen and segments after a gap are held back until
+/// the gap is filled or the stream is finished.
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    /// The sequence number of the first byte of the stream.
+    base: Option<u32>,
+
+    /// Offset of the next byte to deliver.
+    delivered: u64,
+
+    /// Out of order segments keyed by offset, along with the time each was
+    /// captured.
+    pending: BTreeMap<u64, (Vec<u8>, Duration)>,
+
+    /// How many bytes before `base` have been seen.
+    skipped: u64,
+}
+
+impl Reassembler {
+    pub fn new() -> Reassembler {
+        Reassembler::default()
+    }
+
+    /// The sequence number of the first byte of the stream, once a segment
+    /// has been seen.
+    pub fn base(&self) -> Option<u32> {
+        self.base
+    }
+
+    /// The number of bytes before the first one delivered which arrived
+    /// after it. Without a SYN the stream starts at the first segment seen,
+    /// so earlier bytes captured out of order can't be put in front of it.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Adds a segment, returning the bytes it lets through in order.
+    pub fn push(&mut self, segment: &TcpSegment) -> Vec<u8> {
+        let header = segment.header();
+        let mut sequence = header.sequence_number();
+        if header.synchronize() {
+            sequence = sequence.wrapping_add(1);
+        }
+
+        let base = *self.base.get_or_insert(sequence);
+        let data = segment.data();
+        if data.is_empty() {
+            return Vec::new();
+        }
+
+        let relative = sequence.wrapping_sub(base);
+        let next = self.delivered as u32;
+        if relative.wrapping_sub(next) > MAXIMUM_WINDOW
+            && next.wrapping_sub(relative) > MAXIMUM_WINDOW
+        {
+            return Vec::new();
+        }
+
+        // Relative to the last delivered byte rather than the base so that
+        // streams longer than 4GiB keep working.
+        let (offset, data) = if relative.wrapping_sub(next) <= MAXIMUM_WINDOW {
+            (
+                self.delivered + u64::from(relative.wrapping_sub(next)),
+                data,
+            )
+        } else {
+            let behind = u64::from(next.wrapping_sub(relative));
+            if behind > self.delivered {
+                // Only the part from the start of the stream on can be kept.
+                let before_base = behind - self.delivered;
+                self.skipped = cmp::max(self.skipped, before_base);
+                (
+                    0,
+                    &data[cmp::min(before_base, data.len() as u64) as usize..],
+                )
+            } else {
+                (self.delivered - behind, data)
+            }
+        };
+
+        self.insert(offset, data, segment.timestamp());
+
+        let mut output = Vec::new();
+        while let Some((bytes, _)) = self.take_next() {
+            output.extend_from_slice(&bytes);
+        }
+
+        output
+    }
+
+    /// Flushes every held back segment, marking the bytes that never arrived.
+    /// Each segment's bytes are returned separately, with the time the
+    /// segment was captured. Missing bytes have the time of the segment
+    /// after them.
+    pub fn finish(&mut self) -> Vec<(StreamData, Duration)> {
+        let mut output = Vec::new();
+        while let Some((&offset, &(_, timestamp))) = self.pending.iter().next() {
+            if offset > self.delivered {
+                output.push((StreamData::Missing(offset - self.delivered), timestamp));
+                self.delivered = offset;
+            }
+
+            while let Some((bytes, timestamp)) = self.take_next() {
+                if !bytes.is_empty() {
+                    output.push((StreamData::Bytes(bytes), timestamp));
+                }
+            }
+        }
+
+        output
+    }
+
+    fn insert(&mut self, offset: u64, data: &[u8], timestamp: Duration) {
+        let end = offset + data.len() as u64;
+        if end <= self.delivered {
+            return;
+        }
+
+        let keep = self
+            .pending
+            .get(&offset)
+            .is_none_or(|(existing, _)| existing.len() < data.len());
+        if keep {
+            self.pending.insert(offset, (data.to_vec(), timestamp));
+        }
+    }
+
+    /// Removes the first held back segment if it follows on from the bytes
+    /// delivered so far, returning the part of it not yet delivered and the
+    /// time it was captured.
+    fn take_next(&mut self) -> Option<(Vec<u8>, Duration)> {
+        let offset = *self.pending.keys().next()?;
+        if offset > self.delivered {
+            return None;
+        }
+
+        let (mut data, timestamp) = self.pending.remove(&offset).unwrap();
+        let end = offset + data.len() as u64;
+        if end <= self.delivered {
+            return Some((Vec::new(), timestamp));
+        }
+
+        data.drain(..(self.delivered - offset) as usize);
+        self.delivered = end;
+        Some((data, timestamp))
+    }
+}
+
+/// Reads the reassembled bytes of one TCP connection for an application
+/// protocol.
+pub trait StreamHandler: Sized {
+    /// Starts reading a connection given the ports of both ends and the
+    /// first bytes delivered in order, or returns `None` if the connection
+    /// doesn't carry this protocol.
+    fn detect(ports: [u16; 2], data: &[u8]) -> Option<Self>;
+
+    /// Whether the first bytes of a connection, which `detect` turned down,
+    /// are too few to tell yet. Detection is tried again with the bytes sent
+    /// so far each time more arrive, until this returns false.
+    fn needs_more(_ports: [u16; 2], _data: &[u8]) -> bool {
+        false
+    }
+
+    /// Adds bytes sent in `direction`, which follow on from the last bytes
+    /// sent that way.
+    fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration);
+
+    /// Notes that bytes sent in `direction` weren't captured.
+    fn gap(&mut self, direction: Direction);
+
+    /// Ends the connection.
+    fn finish(&mut self);
+}
+
+#[derive(Debug)]
+enum Stream<T> {
+    Undecided {
+        reassemblers: [Reassembler; 2],
+
+        /// Bytes delivered while waiting for `StreamHandler::needs_more` to
+        /// be satisfied, with their direction and capture time.
+        held: Vec<(Direction, Vec<u8>, Duration)>,
+    },
+    Handled {
+        reassemblers: [Reassembler; 2],
+        handler: Box<T>,
+
+        /// Whether the handler was given decrypted bytes rather than the
+        /// bytes of the segments.
+        decrypted: bool,
+    },
+    Other,
+
+    /// Decrypted bytes which weren't recognised either.
+    OtherDecrypted,
+}
+
+/// Reassembles every TCP connection and passes the bytes of those a
+/// `StreamHandler` recognises on to it.
+#[derive(Debug)]
+pub struct ReassembledStreams<T> {
+    streams: Vec<Stream<T>>,
+}
+
+impl<T> Default for ReassembledStreams<T> {
+    fn default() -> ReassembledStreams<T> {
+        ReassembledStreams {
+            streams: Vec::new(),
+        }
+    }
+}
+
+impl<T: StreamHandler> ReassembledStreams<T> {
+    pub fn new() -> ReassembledStreams<T> {
+        ReassembledStreams::default()
+    }
+
+    /// Records a segment of the connection numbered `stream`. Segments have
+    /// to be passed in the order they were captured.
+    pub fn record(&mut self, stream: usize, direction: Direction, segment: &TcpSegment) {
+        while self.streams.len() <= stream {
+            self.streams.push(Stream::Undecided {
+                reassemblers: [Reassembler::new(), Reassembler::new()],
+                held: Vec::new(),
+            });
+        }
+
+        let side = match direction {
+            Direction::ClientToServer => 0,
+            Direction::ServerToClient => 1,
+        };
+
+        let entry = &mut self.streams[stream];
+        let data = match entry {
+            Stream::Undecided { reassemblers, .. } | Stream::Handled { reassemblers, .. } => {
+                reassemblers[side].push(segment)
+            }
+            Stream::Other | Stream::OtherDecrypted => return,
+        };
+
+        if let Stream::Undecided { reassemblers, held } = entry {
+            if data.is_empty() {
+                return;
+            }
+            held.push((direction, data, segment.timestamp()));
+
+            // Protocols are told apart by what the side which spoke first
+            // sent.
+            let first = held[0].0;
+            let start: Vec<u8> = held
+                .iter()
+                .filter(|(direction, _, _)| *direction == first)
+                .flat_map(|(_, bytes, _)| bytes.iter().cloned())
+                .collect();
+
+            let ports = [segment.source().port(), segment.destination().port()];
+            match T::detect(ports, &start) {
+                Some(mut handler) => {
+                    for (direction, bytes, timestamp) in held.drain(..) {
+                        handler.push(direction, &bytes, timestamp);
+                    }
+                    let reassemblers = mem::take(reassemblers);
+                    *entry = Stream::Handled {
+                        reassemblers,
+                        handler: Box::new(handler),
+                        decrypted: false,
+                    };
+                }
+                None if T::needs_more(ports, &start) => {}
+                None => *entry = Stream::Other,
+            }
+            return;
+        }
+
+        if let Stream::Handled { handler, .. } = entry {
+            if !data.is_empty() {
+                handler.push(direction, &data, segment.timestamp());
+            }
+        }
+    }
+
+    /// Adds bytes which were decrypted from the connection numbered `stream`
+    /// rather than read straight from its segments. The decrypted bytes are
+    /// recognised separately from the encrypted ones.
+    pub fn push_decrypted(
+        &mut self,
+        stream: usize,
+        flow: &Flow,
+        direction: Direction,
+        data: &[u8],
+        timestamp: Duration,
+    ) {
+        while self.streams.len() <= stream {
+            self.streams.push(Stream::Other);
+        }
+
+        let entry = &mut self.streams[stream];
+        match entry {
+            Stream::Handled { handler, .. } => handler.push(direction, data, timestamp),
+            Stream::OtherDecrypted => {}
+            Stream::Undecided { .. } | Stream::Other => {
+                let ports = [flow.client().port(), flow.server().port()];
+                *entry = match T::detect(ports, data) {
+                    Some(mut handler) => {
+                        handler.push(direction, data, timestamp);
+                        Stream::Handled {
+                            reassemblers: Default::default(),
+                            handler: Box::new(handler),
+                            decrypted: true,
+                        }
+                    }
+                    None => Stream::OtherDecrypted,
+                };
+            }
+        }
+    }
+
+    /// Flushes every connection at the end of the capture.
+    pub fn finish(&mut self) {
+        for entry in &mut self.streams {
+            if let Stream::Handled {
+                reassemblers,
+                handler,
+                ..
+            } = entry
+            {
+                let directions = [Direction::ClientToServer, Direction::ServerToClient];
+                for (reassembler, &direction) in reassemblers.iter_mut().zip(directions.iter()) {
+                    for (data, timestamp) in reassembler.finish() {
+                        match data {
+                            StreamData::Bytes(bytes) => handler.push(direction, &bytes, timestamp),
+                            StreamData::Missing(_) => handler.gap(direction),
+                        }
+                    }
+                }
+
+                handler.finish();
+            }
+        }
+    }
+
+    /// Whether the connection numbered `stream` was recognised from bytes
+    /// decrypted from it.
+    pub fn is_decrypted(&self, stream: usize) -> bool {
+        match self.streams.get(stream) {
+            Some(Stream::Handled { decrypted, .. }) => *decrypted,
+            _ => false,
+        }
+    }
+
+    /// The handler of every recognised connection along with its number.
+    pub fn handlers(&self) -> Vec<(usize, &T)> {
+        self.streams
+            .iter()
+            .enumerate()
+            .filter_map(|(stream, entry)| match entry {
+                Stream::Handled { handler, .. } => Some((stream, &**handler)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The reassembled contents of both directions of a connection, in the
+/// order they were sent.
+#[derive(Debug, Default)]
+pub struct FollowStream {
+    client: Reassembler,
+    server: Reassembler,
+    chunks: Vec<(Direction, StreamData)>,
+}
+
+impl FollowStream {
+    pub fn new() -> FollowStream {
+        FollowStream::default()
+    }
+
+    pub fn push(&mut self, direction: Direction, segment: &TcpSegment) {
+        let bytes = match direction {
+            Direction::ClientToServer => self.client.push(segment),
+            Direction::ServerToClient => self.server.push(segment),
+        };
+
+        if !bytes.is_empty() {
+            self.append(direction, StreamData::Bytes(bytes));
+        }
+    }
+
+    /// Adds bytes which are already in order, such as decrypted TLS
+    /// application data.
+    pub fn push_bytes(&mut self, direction: Direction, bytes: &[u8]) {
+        if !bytes.is_empty() {
+            self.append(direction, StreamData::Bytes(bytes.to_vec()));
+        }
+    }
+
+    /// The sequence numbers of the first byte sent in each direction,
+    /// client first. This includes bytes skipped at the start, so offsets
+    /// after the missing bytes `finish` puts in front still match.
+    pub fn first_sequence_numbers(&self) -> [Option<u32>; 2] {
+        let first = |reassembler: &Reassembler| {
+            reassembler
+                .base()
+                .map(|base| base.wrapping_sub(reassembler.skipped() as u32))
+        };
+        [first(&self.client), first(&self.server)]
+    }
+
+    /// Flushes both directions and returns every chunk of the conversation.
+    /// Bytes skipped at the start of a direction are shown as missing before
+    /// its first chunk.
+    pub fn finish(mut self) -> Vec<(Direction, StreamData)> {
+        for (data, _) in self.client.finish() {
+            self.append(Direction::ClientToServer, data);
+        }
+
+        for (data, _) in self.server.finish() {
+            self.append(Direction::ServerToClient, data);
+        }
+
+        let skipped = [
+            (Direction::ClientToServer, self.client.skipped()),
+            (Direction::ServerToClient, self.server.skipped()),
+        ];
+        for &(direction, skipped) in skipped.iter().filter(|&&(_, skipped)| skipped > 0) {
+            let first = self
+                .chunks
+                .iter()
+                .position(|&(chunk_direction, _)| chunk_direction == direction)
+                .unwrap_or(self.chunks.len());
+            self.chunks
+                .insert(first, (direction, StreamData::Missing(skipped)));
+        }
+
+        self.chunks
+    }
+
+    fn append(&mut self, direction: Direction, data: StreamData) {
+        if let Some((last_direction, StreamData::Bytes(ref mut last))) = self.chunks.last_mut() {
+            if let StreamData::Bytes(ref bytes) = data {
+                if *last_direction == direction {
+                    last.extend_from_slice(bytes);
+                    return;
+                }
+            }
+        }
+
+        self.chunks.push((direction, data));
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FollowFormat {
+    Raw,
+    Hex,
+    Ascii,
+}
+
+impl FollowFormat {
+    pub fn from_name(name: &str) -> Option<FollowFormat> {
+        match name {
+            "raw" => Some(FollowFormat::Raw),
+            "hex" => Some(FollowFormat::Hex),
+            "ascii" => Some(FollowFormat::Ascii),
+            _ => None,
+        }
+    }
+}
+
+/// Writes out the chunks of a followed stream. Data sent by the server is
+/// indented in the hex format, like Wireshark's hex dump view. Lines of the
+/// hex format start with the offset into the stream, or with the sequence
+/// number when the sequence numbers of the first byte sent each way are
+/// passed in `first_sequence_numbers`.
+pub fn write_chunks<W: Write>(
+    output: &mut W,
+    chunks: &[(Direction, StreamData)],
+    format: FollowFormat,
+    first_sequence_numbers: Option<[u32; 2]>,
+) -> io::Result<()> {
+    let mut offsets = [0u64; 2];
+    for (direction, data) in chunks {
+        let index = match direction {
+            Direction::ClientToServer => 0,
+            Direction::ServerToClient => 1,
+        };
+
+        let bytes = match data {
+            StreamData::Bytes(bytes) => bytes,
+            StreamData::Missing(length) => {
+                offsets[index] += length;
+                if format != FollowFormat::Raw {
+                    writeln!(output, "[{} bytes missing]", length)?;
+                }
+                continue;
+            }
+        };
+
+        match format {
+            FollowFormat::Raw => output.write_all(bytes)?,
+            FollowFormat::Ascii => {
+                let text: String = bytes
+                    .iter()
+                    .map(|&byte| match byte {
+                        b'\r' | b'\n' | b'\t' | 0x20..=0x7e => byte as char,
+                        _ => '.',
+                    })
+                    .collect();
+                output.write_all(text.as_bytes())?;
+                if !text.ends_with('\n') {
+                    writeln!(output)?;
+                }
+            }
+            FollowFormat::Hex => {
+                let indent = if index == 0 { "" } else { "    " };
+                for (line, row) in bytes.chunks(16).enumerate() {
+                    let offset = offsets[index] + line as u64 * 16;
+                    let position = match first_sequence_numbers {
+                        Some(first) => u64::from(first[index].wrapping_add(offset as u32)),
+                        None => offset,
+                    };
+                    write!(output, "{}{:08x}  ", indent, position)?;
+                    for column in 0..16 {
+                        match row.get(column) {
+                            Some(byte) => write!(output, "{:02x} ", byte)?,
+                            None => write!(output, "   ")?,
+                        }
+                        if column == 7 {
+                            write!(output, " ")?;
+                        }
+                    }
+
+                    let text: String = row
+                        .iter()
+                        .map(|&byte| match byte {
+                            0x20..=0x7e => byte as char,
+                            _ => '.',
+                        })
+                        .collect();
+                    writeln!(output, " {}", text)?;
+                }
+            }
+        }
+
+        offsets[index] += bytes.len() as u64;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use flow::tests::{ip_header, tcp_header};
+    use flow::{Direction, TcpSegment};
+    use reassembly::{write_chunks, FollowFormat, FollowStream, Reassembler, StreamData};
+    use std::time::Duration;
+
+    #[test]
+    fn reassemble() {
+        let at = Duration::from_secs(0);
+        let ip = ip_header(true);
+        let syn = tcp_header(36869, 23, 0xffff_fffe, 0, 0x02);
+        let first = tcp_header(36869, 23, 0xffff_ffff, 0, 0x18);
+        let second = tcp_header(36869, 23, 2, 0, 0x18);
+        let overlap = tcp_header(36869, 23, 0, 0, 0x18);
+
+        let mut reassembler = Reassembler::new();
+        assert!(reassembler
+            .push(&TcpSegment::new(at, &ip, &syn, &[]))
+            .is_empty());
+        assert!(reassembler
+            .push(&TcpSegment::new(at, &ip, &second, b"ld"))
+            .is_empty());
+        assert_eq!(
+            reassembler.push(&TcpSegment::new(at, &ip, &first, b"wo")),
+            b"wo".to_vec()
+        );
+        assert_eq!(
+            reassembler.push(&TcpSegment::new(at, &ip, &overlap, b"orl")),
+            b"rld".to_vec()
+        );
+        assert!(reassembler
+            .push(&TcpSegment::new(at, &ip, &first, b"wo"))
+            .is_empty());
+        assert!(reassembler.finish().is_empty());
+    }
+
+    #[test]
+    fn reassemble_gap() {
+        let at = Duration::from_secs;
+        let ip = ip_header(true);
+        let first = tcp_header(36869, 23, 100, 0, 0x18);
+        let after_gap = tcp_header(36869, 23, 105, 0, 0x18);
+        let next = tcp_header(36869, 23, 107, 0, 0x18);
+
+        let mut reassembler = Reassembler::new();
+        assert_eq!(
+            reassembler.push(&TcpSegment::new(at(0), &ip, &first, b"ab")),
+            b"ab".to_vec()
+        );
+        assert!(reassembler
+            .push(&TcpSegment::new(at(1), &ip, &after_gap, b"fg"))
+            .is_empty());
+        assert!(reassembler
+            .push(&TcpSegment::new(at(2), &ip, &next, b"hi"))
+            .is_empty());
+        assert_eq!(
+            reassembler.finish(),
+            vec![
+                (StreamData::Missing(3), at(1)),
+                (StreamData::Bytes(b"fg".to_vec()), at(1)),
+                (StreamData::Bytes(b"hi".to_vec()), at(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reassemble_reordered_start() {
+        let at = Duration::from_secs;
+        let ip = ip_header(true);
+        let later = tcp_header(36869, 23, 105, 0, 0x18);
+        let earlier = tcp_header(36869, 23, 100, 0, 0x18);
+        let straddling = tcp_header(36869, 23, 103, 0, 0x18);
+
+        // The capture starts mid-stream, with the first two segments swapped.
+        let mut reassembler = Reassembler::new();
+        assert_eq!(
+            reassembler.push(&TcpSegment::new(at(0), &ip, &later, b"fg")),
+            b"fg".to_vec()
+        );
+        assert!(reassembler
+            .push(&TcpSegment::new(at(1), &ip, &earlier, b"abcde"))
+            .is_empty());
+        assert_eq!(
+            reassembler.push(&TcpSegment::new(at(2), &ip, &straddling, b"defghi")),
+            b"hi".to_vec()
+        );
+        assert_eq!(reassembler.skipped(), 5);
+        assert_eq!(reassembler.base(), Some(105));
+        assert!(reassembler.finish().is_empty());
+
+        let mut follow = FollowStream::new();
+        follow.push(
+            Direction::ServerToClient,
+            &TcpSegment::new(
+                at(0),
+                &ip_header(false),
+                &tcp_header(23, 36869, 1, 0, 0x18),
+                b"ok",
+            ),
+        );
+        follow.push(
+            Direction::ClientToServer,
+            &TcpSegment::new(at(1), &ip, &later, b"fg"),
+        );
+        follow.push(
+            Direction::ClientToServer,
+            &TcpSegment::new(at(2), &ip, &earlier, b"abcde"),
+        );
+        assert_eq!(follow.first_sequence_numbers(), [Some(100), Some(1)]);
+        assert_eq!(
+            follow.finish(),
+            vec![
+                (Direction::ServerToClient, StreamData::Bytes(b"ok".to_vec())),
+                (Direction::ClientToServer, StreamData::Missing(5)),
+                (Direction::ClientToServer, StreamData::Bytes(b"fg".to_vec())),
+            ]
+        );
+    }
+
+    #[test]
+    fn follow() {
+        let at = Duration::from_secs(0);
+        let client = ip_header(true);
+        let server = ip_header(false);
+        let request = tcp_header(36869, 23, 1, 1, 0x18);
+        let response = tcp_header(23, 36869, 1, 4, 0x18);
+        let rest = tcp_header(23, 36869, 3, 4, 0x18);
+
+        let mut follow = FollowStream::new();
+        follow.push(
+            Direction::ClientToServer,
+            &TcpSegment::new(at, &client, &request, b"hi\n"),
+        );
+        follow.push(
+            Direction::ServerToClient,
+            &TcpSegment::new(at, &server, &response, b"ok"),
+        );
+        follow.push(
+            Direction::ServerToClient,
+            &TcpSegment::new(at, &server, &rest, b"\x00\n"),
+        );
+        assert_eq!(follow.first_sequence_numbers(), [Some(1), Some(1)]);
+        let chunks = follow.finish();
+        assert_eq!(
+            chunks,
+            vec![
+                (
+                    Direction::ClientToServer,
+                    StreamData::Bytes(b"hi\n".to_vec())
+                ),
+                (
+                    Direction::ServerToClient,
+                    StreamData::Bytes(b"ok\x00\n".to_vec())
+                ),
+            ]
+        );
+
+        let mut ascii = Vec::new();
+        write_chunks(&mut ascii, &chunks, FollowFormat::Ascii, None).unwrap();
+        assert_eq!(String::from_utf8(ascii).unwrap(), "hi\nok.\n");
+
+        let mut hex = Vec::new();
+        write_chunks(&mut hex, &chunks, FollowFormat::Hex, None).unwrap();
+        assert_eq!(
+            String::from_utf8(hex).unwrap(),
+            "00000000  68 69 0a                                          hi.\n    \
+             00000000  6f 6b 00 0a                                       ok..\n"
+        );
+
+        let mut hex = Vec::new();
+        write_chunks(&mut hex, &chunks, FollowFormat::Hex, Some([0xfffffffe, 1])).unwrap();
+        assert_eq!(
+            String::from_utf8(hex).unwrap(),
+            "fffffffe  68 69 0a                                          hi.\n    \
+             00000001  6f 6b 00 0a                                       ok..\n"
+        );
+
+        let mut raw = Vec::new();
+        write_chunks(&mut raw, &chunks, FollowFormat::Raw, None).unwrap();
+        assert_eq!(raw, b"hi\nok\x00\n".to_vec());
+    }
+}
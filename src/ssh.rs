@@ -0,0 +1,822 @@
+use flow::Direction;
+use md5::{Digest, Md5};
+use reassembly::{ReassembledStreams, StreamHandler};
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+use tls::looks_like_tls;
+use util::to_hex;
+
+pub const SSH_PORT: u16 = 22;
+
+/// Identification lines can't be longer than this, including CR LF.
+const MAXIMUM_BANNER_LENGTH: usize = 255;
+
+/// Packets must be accepted up to this length, and nothing longer is sent
+/// before keys are exchanged.
+const MAXIMUM_PACKET_LENGTH: u32 = 35_000;
+
+const KEXINIT: u8 = 20;
+const NEWKEYS: u8 = 21;
+
+/// Algorithms which are broken or rely on SHA-1 or small groups, and which
+/// OpenSSH no longer enables by default.
+const WEAK_ALGORITHMS: [&str; 22] = [
+    "diffie-hellman-group1-sha1",
+    "diffie-hellman-group14-sha1",
+    "diffie-hellman-group-exchange-sha1",
+    "ssh-dss",
+    "ssh-rsa",
+    "3des-cbc",
+    "aes128-cbc",
+    "aes192-cbc",
+    "aes256-cbc",
+    "rijndael-cbc@lysator.liu.se",
+    "blowfish-cbc",
+    "cast128-cbc",
+    "arcfour",
+    "arcfour128",
+    "arcfour256",
+    "des-cbc",
+    "none",
+    "hmac-md5",
+    "hmac-md5-96",
+    "hmac-sha1-96",
+    "hmac-md5-etm@openssh.com",
+    "hmac-md5-96-etm@openssh.com",
+];
+
+#[derive(Fail, Debug, Eq, PartialEq)]
+pub enum SshError {
+    #[fail(display = "Invalid SSH identification line")]
+    InvalidBanner,
+
+    #[fail(display = "Invalid SSH packet")]
+    InvalidPacket,
+
+    #[fail(display = "Invalid SSH KEXINIT message")]
+    InvalidKexInit,
+}
+
+/// Whether `data` starts with an SSH identification line.
+pub fn looks_like_ssh(data: &[u8]) -> bool {
+    data.starts_with(b"SSH-")
+}
+
+/// The identification line each side sends first, such as
+/// `SSH-2.0-OpenSSH_9.6p1 Ubuntu-3`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SshBanner {
+    protocol_version: String,
+    software_version: String,
+    comments: Option<String>,
+}
+
+impl SshBanner {
+    /// Parses an identification line, with or without its line ending.
+    pub fn parse(line: &[u8]) -> Result<SshBanner, SshError> {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches('\n').trim_end_matches('\r');
+        let rest = line.strip_prefix("SSH-").ok_or(SshError::InvalidBanner)?;
+
+        let (version, comments) = match rest.find(' ') {
+            Some(index) => (&rest[..index], Some(rest[index + 1..].to_string())),
+            None => (rest, None),
+        };
+        let (protocol_version, software_version) = match version.find('-') {
+            Some(index) => (&version[..index], &version[index + 1..]),
+            None => return Err(SshError::InvalidBanner),
+        };
+        if protocol_version.is_empty() || software_version.is_empty() {
+            return Err(SshError::InvalidBanner);
+        }
+
+        Ok(SshBanner {
+            protocol_version: protocol_version.to_string(),
+            software_version: software_version.to_string(),
+            comments,
+        })
+    }
+
+    pub fn protocol_version(&self) -> &str {
+        &self.protocol_version
+    }
+
+    pub fn software_version(&self) -> &str {
+        &self.software_version
+    }
+
+    pub fn comments(&self) -> Option<&str> {
+        self.comments.as_deref()
+    }
+
+    /// Whether only the SSH-1 protocol is spoken. `1.99` means SSH-2 is
+    /// supported as well.
+    pub fn is_ssh1(&self) -> bool {
+        self.protocol_version.starts_with("1.") && self.protocol_version != "1.99"
+    }
+}
+
+impl fmt::Display for SshBanner {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "SSH-{}-{}", self.protocol_version, self.software_version)?;
+        if let Some(comments) = &self.comments {
+            write!(f, " {}", comments)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The family of key exchange method, which decides what messages 30 to 49
+/// mean.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum KexType {
+    DiffieHellman,
+    DiffieHellmanGroupExchange,
+    EllipticCurveDiffieHellman,
+
+    /// A post-quantum key encapsulation combined with X25519 or ECDH.
+    Hybrid,
+
+    Other,
+}
+
+impl KexType {
+    pub fn from_algorithm(algorithm: &str) -> KexType {
+        if algorithm.starts_with("diffie-hellman-group-exchange-") {
+            KexType::DiffieHellmanGroupExchange
+        } else if algorithm.starts_with("diffie-hellman-group") {
+            KexType::DiffieHellman
+        } else if algorithm.starts_with("ecdh-sha2-") || algorithm.starts_with("curve") {
+            KexType::EllipticCurveDiffieHellman
+        } else if algorithm.starts_with("sntrup") || algorithm.starts_with("mlkem") {
+            KexType::Hybrid
+        } else {
+            KexType::Other
+        }
+    }
+}
+
+impl fmt::Display for KexType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            KexType::DiffieHellman => "Diffie-Hellman",
+            KexType::DiffieHellmanGroupExchange => "Diffie-Hellman group exchange",
+            KexType::EllipticCurveDiffieHellman => "ECDH",
+            KexType::Hybrid => "post-quantum hybrid",
+            KexType::Other => "unknown",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// The name of a message number. Numbers 30 to 49 are reused by each kind of
+/// key exchange.
+pub fn message_name(number: u8, kex_type: Option<KexType>) -> String {
+    let name = match (number, kex_type) {
+        (1, _) => "SSH_MSG_DISCONNECT",
+        (2, _) => "SSH_MSG_IGNORE",
+        (3, _) => "SSH_MSG_UNIMPLEMENTED",
+        (4, _) => "SSH_MSG_DEBUG",
+        (5, _) => "SSH_MSG_SERVICE_REQUEST",
+        (6, _) => "SSH_MSG_SERVICE_ACCEPT",
+        (7, _) => "SSH_MSG_EXT_INFO",
+        (KEXINIT, _) => "SSH_MSG_KEXINIT",
+        (NEWKEYS, _) => "SSH_MSG_NEWKEYS",
+        (30, Some(KexType::DiffieHellman)) => "SSH_MSG_KEXDH_INIT",
+        (31, Some(KexType::DiffieHellman)) => "SSH_MSG_KEXDH_REPLY",
+        (30, Some(KexType::EllipticCurveDiffieHellman)) => "SSH_MSG_KEX_ECDH_INIT",
+        (31, Some(KexType::EllipticCurveDiffieHellman)) => "SSH_MSG_KEX_ECDH_REPLY",
+        (30, Some(KexType::Hybrid)) => "SSH_MSG_KEX_HYBRID_INIT",
+        (31, Some(KexType::Hybrid)) => "SSH_MSG_KEX_HYBRID_REPLY",
+        (30, Some(KexType::DiffieHellmanGroupExchange)) => "SSH_MSG_KEX_DH_GEX_REQUEST_OLD",
+        (31, Some(KexType::DiffieHellmanGroupExchange)) => "SSH_MSG_KEX_DH_GEX_GROUP",
+        (32, Some(KexType::DiffieHellmanGroupExchange)) => "SSH_MSG_KEX_DH_GEX_INIT",
+        (33, Some(KexType::DiffieHellmanGroupExchange)) => "SSH_MSG_KEX_DH_GEX_REPLY",
+        (34, Some(KexType::DiffieHellmanGroupExchange)) => "SSH_MSG_KEX_DH_GEX_REQUEST",
+        _ => return format!("SSH message {}", number),
+    };
+
+    name.to_string()
+}
+
+/// Reads the integers and name-lists SSH messages are made of.
+struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        if self.input.len() < length {
+            return None;
+        }
+
+        let (taken, rest) = self.input.split_at(length);
+        self.input = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn name_list(&mut self) -> Option<Vec<String>> {
+        let length = self.u32()? as usize;
+        let names = std::str::from_utf8(self.take(length)?).ok()?;
+        if names.is_empty() {
+            return Some(Vec::new());
+        }
+
+        Some(names.split(',').map(str::to_string).collect())
+    }
+}
+
+/// The algorithms a side supports, in order of preference.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct KexInit {
+    cookie: [u8; 16],
+    kex_algorithms: Vec<String>,
+    server_host_key_algorithms: Vec<String>,
+    encryption_algorithms: [Vec<String>; 2],
+    mac_algorithms: [Vec<String>; 2],
+    compression_algorithms: [Vec<String>; 2],
+    languages: [Vec<String>; 2],
+    first_kex_packet_follows: bool,
+}
+
+impl KexInit {
+    /// Parses the payload of a KEXINIT message, including the message
+    /// number.
+    pub fn parse(payload: &[u8]) -> Result<KexInit, SshError> {
+        KexInit::parse_fields(payload).ok_or(SshError::InvalidKexInit)
+    }
+
+    fn parse_fields(payload: &[u8]) -> Option<KexInit> {
+        let mut reader = Reader { input: payload };
+        if reader.u8()? != KEXINIT {
+            return None;
+        }
+
+        let mut cookie = [0; 16];
+        cookie.copy_from_slice(reader.take(16)?);
+
+        Some(KexInit {
+            cookie,
+            kex_algorithms: reader.name_list()?,
+            server_host_key_algorithms: reader.name_list()?,
+            encryption_algorithms: [reader.name_list()?, reader.name_list()?],
+            mac_algorithms: [reader.name_list()?, reader.name_list()?],
+            compression_algorithms: [reader.name_list()?, reader.name_list()?],
+            languages: [reader.name_list()?, reader.name_list()?],
+            first_kex_packet_follows: reader.u8()? != 0,
+        })
+    }
+
+    pub fn cookie(&self) -> [u8; 16] {
+        self.cookie
+    }
+
+    pub fn kex_algorithms(&self) -> &[String] {
+        &self.kex_algorithms
+    }
+
+    pub fn server_host_key_algorithms(&self) -> &[String] {
+        &self.server_host_key_algorithms
+    }
+
+    /// The ciphers for data sent in `direction`.
+    pub fn encryption_algorithms(&self, direction: Direction) -> &[String] {
+        &self.encryption_algorithms[index(direction)]
+    }
+
+    pub fn mac_algorithms(&self, direction: Direction) -> &[String] {
+        &self.mac_algorithms[index(direction)]
+    }
+
+    pub fn compression_algorithms(&self, direction: Direction) -> &[String] {
+        &self.compression_algorithms[index(direction)]
+    }
+
+    pub fn languages(&self, direction: Direction) -> &[String] {
+        &self.languages[index(direction)]
+    }
+
+    pub fn first_kex_packet_follows(&self) -> bool {
+        self.first_kex_packet_follows
+    }
+
+    /// The algorithms offered which are considered weak, in the order they
+    /// appear.
+    pub fn weak_algorithms(&self) -> Vec<&str> {
+        let mut weak = Vec::new();
+        let lists = [
+            &self.kex_algorithms,
+            &self.server_host_key_algorithms,
+            &self.encryption_algorithms[0],
+            &self.encryption_algorithms[1],
+            &self.mac_algorithms[0],
+            &self.mac_algorithms[1],
+        ];
+        for algorithm in lists.iter().flat_map(|list| list.iter()) {
+            if WEAK_ALGORITHMS.contains(&algorithm.as_str()) && !weak.contains(&algorithm.as_str())
+            {
+                weak.push(algorithm.as_str());
+            }
+        }
+
+        weak
+    }
+
+    /// The key exchange, encryption, MAC and compression algorithms for
+    /// data sent in `direction`, joined as HASSH does.
+    fn hassh_algorithms(&self, direction: Direction) -> String {
+        let index = index(direction);
+        [
+            &self.kex_algorithms,
+            &self.encryption_algorithms[index],
+            &self.mac_algorithms[index],
+            &self.compression_algorithms[index],
+        ]
+        .iter()
+        .map(|list| list.join(","))
+        .collect::<Vec<String>>()
+        .join(";")
+    }
+
+    /// The string HASSH hashes to fingerprint a client.
+    pub fn hassh_string(&self) -> String {
+        self.hassh_algorithms(Direction::ClientToServer)
+    }
+
+    /// The HASSH fingerprint of a client's KEXINIT.
+    pub fn hassh(&self) -> String {
+        to_hex(&Md5::digest(self.hassh_string().as_bytes()))
+    }
+
+    /// The string HASSHServer hashes to fingerprint a server.
+    pub fn hassh_server_string(&self) -> String {
+        self.hassh_algorithms(Direction::ServerToClient)
+    }
+
+    /// The HASSHServer fingerprint of a server's KEXINIT.
+    pub fn hassh_server(&self) -> String {
+        to_hex(&Md5::digest(self.hassh_server_string().as_bytes()))
+    }
+}
+
+fn index(direction: Direction) -> usize {
+    match direction {
+        Direction::ClientToServer => 0,
+        Direction::ServerToClient => 1,
+    }
+}
+
+/// The first of the client's algorithms which the server supports too, which
+/// is how SSH picks each algorithm.
+pub fn negotiate<'a>(client: &'a [String], server: &[String]) -> Option<&'a str> {
+    client
+        .iter()
+        .find(|algorithm| server.contains(algorithm))
+        .map(String::as_str)
+}
+
+/// Reads a binary packet from before keys are exchanged, when there is no
+/// MAC. Returns how many bytes it took up and its payload.
+pub fn parse_packet(input: &[u8]) -> Result<Option<(usize, &[u8])>, SshError> {
+    let mut reader = Reader { input };
+    let (length, padding) = match (reader.u32(), reader.u8()) {
+        (Some(length), Some(padding)) => (length, padding),
+        _ => return Ok(None),
+    };
+
+    if length > MAXIMUM_PACKET_LENGTH || length < u32::from(padding) + 1 {
+        return Err(SshError::InvalidPacket);
+    }
+
+    let end = 4 + length as usize;
+    match input.get(5..end - padding as usize) {
+        Some(payload) if input.len() >= end => Ok(Some((end, payload))),
+        _ => Ok(None),
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum SideState {
+    Banner,
+    Packets,
+
+    /// After NEWKEYS, or SSH-1, where packets can't be read.
+    Encrypted,
+
+    Broken,
+}
+
+/// Where one side of the connection is up to.
+#[derive(Debug)]
+struct Side {
+    state: SideState,
+    buffer: Vec<u8>,
+    banner: Option<SshBanner>,
+    kex_init: Option<KexInit>,
+    encrypted_bytes: u64,
+}
+
+impl Default for Side {
+    fn default() -> Side {
+        Side {
+            state: SideState::Banner,
+            buffer: Vec::new(),
+            banner: None,
+            kex_init: None,
+            encrypted_bytes: 0,
+        }
+    }
+}
+
+/// Reads the identification lines and the cleartext key exchange of an SSH
+/// connection.
+#[derive(Debug, Default)]
+pub struct SshSession {
+    client: Side,
+    server: Side,
+
+    /// The number of every message read before NEWKEYS.
+    messages: Vec<(Direction, Duration, u8)>,
+}
+
+impl SshSession {
+    pub fn new() -> SshSession {
+        SshSession::default()
+    }
+
+    pub fn client_banner(&self) -> Option<&SshBanner> {
+        self.client.banner.as_ref()
+    }
+
+    pub fn server_banner(&self) -> Option<&SshBanner> {
+        self.server.banner.as_ref()
+    }
+
+    pub fn client_kex_init(&self) -> Option<&KexInit> {
+        self.client.kex_init.as_ref()
+    }
+
+    pub fn server_kex_init(&self) -> Option<&KexInit> {
+        self.server.kex_init.as_ref()
+    }
+
+    /// The message numbers read before each side sent NEWKEYS, along with
+    /// the time the end of each was captured.
+    pub fn messages(&self) -> &[(Direction, Duration, u8)] {
+        &self.messages
+    }
+
+    /// The number of bytes sent in `direction` after NEWKEYS.
+    pub fn encrypted_bytes(&self, direction: Direction) -> u64 {
+        match direction {
+            Direction::ClientToServer => self.client.encrypted_bytes,
+            Direction::ServerToClient => self.server.encrypted_bytes,
+        }
+    }
+
+    /// The key exchange method both sides settled on.
+    pub fn kex_algorithm(&self) -> Option<&str> {
+        let (client, server) = (self.client_kex_init()?, self.server_kex_init()?);
+        negotiate(&client.kex_algorithms, &server.kex_algorithms)
+    }
+
+    pub fn kex_type(&self) -> Option<KexType> {
+        self.kex_algorithm().map(KexType::from_algorithm)
+    }
+
+    pub fn host_key_algorithm(&self) -> Option<&str> {
+        let (client, server) = (self.client_kex_init()?, self.server_kex_init()?);
+        negotiate(
+            &client.server_host_key_algorithms,
+            &server.server_host_key_algorithms,
+        )
+    }
+
+    /// The cipher chosen for data sent in `direction`.
+    pub fn encryption_algorithm(&self, direction: Direction) -> Option<&str> {
+        let (client, server) = (self.client_kex_init()?, self.server_kex_init()?);
+        negotiate(
+            client.encryption_algorithms(direction),
+            server.encryption_algorithms(direction),
+        )
+    }
+
+    /// The MAC chosen for data sent in `direction`. AEAD ciphers don't use
+    /// one.
+    pub fn mac_algorithm(&self, direction: Direction) -> Option<&str> {
+        let (client, server) = (self.client_kex_init()?, self.server_kex_init()?);
+        negotiate(
+            client.mac_algorithms(direction),
+            server.mac_algorithms(direction),
+        )
+    }
+
+    /// Adds bytes sent in `direction`, which must follow on from the last
+    /// bytes sent that way.
+    pub fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration) {
+        let side = match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        };
+
+        match side.state {
+            SideState::Broken => return,
+            SideState::Encrypted => {
+                side.encrypted_bytes += data.len() as u64;
+                return;
+            }
+            SideState::Banner | SideState::Packets => side.buffer.extend_from_slice(data),
+        }
+
+        // Servers may send other lines before their identification line. None
+        // of the lines may be longer than the identification line may be,
+        // whether or not their end has arrived yet.
+        while side.state == SideState::Banner {
+            let end = side.buffer.iter().position(|&byte| byte == b'\n');
+            let length = end.map_or(side.buffer.len(), |end| end + 1);
+            if length > MAXIMUM_BANNER_LENGTH {
+                side.state = SideState::Broken;
+                return;
+            }
+            let end = match end {
+                Some(end) => end + 1,
+                None => return,
+            };
+
+            let line: Vec<u8> = side.buffer.drain(..end).collect();
+            if !looks_like_ssh(&line) {
+                continue;
+            }
+            match SshBanner::parse(&line) {
+                Ok(banner) => {
+                    side.state = if banner.is_ssh1() {
+                        SideState::Encrypted
+                    } else {
+                        SideState::Packets
+                    };
+                    side.banner = Some(banner);
+                }
+                Err(_) => side.state = SideState::Broken,
+            }
+        }
+
+        while side.state == SideState::Packets {
+            let (length, number) = match parse_packet(&side.buffer) {
+                Ok(Some((length, payload))) => {
+                    let number = payload.first().cloned().unwrap_or_default();
+                    if number == KEXINIT && side.kex_init.is_none() {
+                        side.kex_init = KexInit::parse(payload).ok();
+                    }
+                    (length, number)
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    side.state = SideState::Broken;
+                    break;
+                }
+            };
+
+            side.buffer.drain(..length);
+            self.messages.push((direction, timestamp, number));
+            if number == NEWKEYS {
+                side.state = SideState::Encrypted;
+                side.encrypted_bytes = side.buffer.len() as u64;
+                side.buffer.clear();
+            }
+        }
+    }
+
+    /// Notes that bytes sent in `direction` weren't captured.
+    pub fn gap(&mut self, direction: Direction) {
+        let side = match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        };
+        if side.state != SideState::Encrypted {
+            side.state = SideState::Broken;
+            side.buffer.clear();
+        }
+    }
+}
+
+impl StreamHandler for SshSession {
+    /// Connections to port 22 or starting with an identification line are
+    /// read.
+    fn detect(ports: [u16; 2], data: &[u8]) -> Option<SshSession> {
+        if looks_like_tls(data) {
+            return None;
+        }
+
+        if ports.contains(&SSH_PORT) || looks_like_ssh(data) {
+            Some(SshSession::new())
+        } else {
+            None
+        }
+    }
+
+    fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration) {
+        SshSession::push(self, direction, data, timestamp);
+    }
+
+    fn gap(&mut self, direction: Direction) {
+        SshSession::gap(self, direction);
+    }
+
+    fn finish(&mut self) {}
+}
+
+/// Finds the SSH connections in a capture.
+pub type SshAnalysis = ReassembledStreams<SshSession>;
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use flow::Direction;
+    use ssh::{message_name, parse_packet, KexInit, KexType, SshBanner, SshError, SshSession};
+    use std::time::Duration;
+
+    const CLIENT_KEXINIT: &str = "000000b40714000102030405060708090a0b0c0d0e0f0000002d6375727665\
+        32353531392d7368613235362c6469666669652d68656c6c6d616e2d67726f757031342d736861310000\
+        000b7373682d656432353531390000000a6165733132382d6374720000000a6165733132382d63747200\
+        00000d686d61632d736861322d3235360000000d686d61632d736861322d323536000000046e6f6e6500\
+        0000046e6f6e650000000000000000000000000000000000000000";
+
+    const SERVER_KEXINIT: &str = "000000d40714000102030405060708090a0b0c0d0e0f0000002d6469666669\
+        652d68656c6c6d616e2d67726f757031342d736861312c637572766532353531392d7368613235360000\
+        00137373682d7273612c7373682d65643235353139000000156165733132382d6362632c616573313238\
+        2d6374720000000a6165733132382d6374720000000d686d61632d736861322d3235360000001a686d61\
+        632d736861312d39362c686d61632d736861322d323536000000046e6f6e65000000046e6f6e65000000\
+        0000000000000000000000000000000000";
+
+    #[test]
+    fn banner() {
+        let banner = SshBanner::parse(b"SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13\r\n").unwrap();
+        assert_eq!(banner.protocol_version(), "2.0");
+        assert_eq!(banner.software_version(), "OpenSSH_9.6p1");
+        assert_eq!(banner.comments(), Some("Ubuntu-3ubuntu13"));
+        assert!(!banner.is_ssh1());
+        assert_eq!(banner.to_string(), "SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13");
+
+        let banner = SshBanner::parse(b"SSH-1.99-Cisco-1.25").unwrap();
+        assert_eq!(banner.software_version(), "Cisco-1.25");
+        assert_eq!(banner.comments(), None);
+        assert!(!banner.is_ssh1());
+        assert!(SshBanner::parse(b"SSH-1.5-OldServer").unwrap().is_ssh1());
+
+        assert_eq!(SshBanner::parse(b"SSH-2.0"), Err(SshError::InvalidBanner));
+        assert_eq!(SshBanner::parse(b"HTTP/1.1"), Err(SshError::InvalidBanner));
+    }
+
+    #[test]
+    fn kex_init() {
+        let raw = hex::decode(CLIENT_KEXINIT).unwrap();
+        assert_eq!(parse_packet(&raw[..100]), Ok(None));
+        let (length, payload) = parse_packet(&raw).unwrap().unwrap();
+        assert_eq!(length, raw.len());
+
+        let client = KexInit::parse(payload).unwrap();
+        assert_eq!(client.cookie()[15], 15);
+        assert_eq!(
+            client.kex_algorithms(),
+            ["curve25519-sha256", "diffie-hellman-group14-sha1"]
+        );
+        assert_eq!(
+            client.compression_algorithms(Direction::ServerToClient),
+            ["none"]
+        );
+        assert!(client.languages(Direction::ClientToServer).is_empty());
+        assert!(!client.first_kex_packet_follows());
+        assert_eq!(
+            client.hassh_string(),
+            "curve25519-sha256,diffie-hellman-group14-sha1;aes128-ctr;hmac-sha2-256;none"
+        );
+        assert_eq!(client.hassh(), "c10186e7085a862e2fddf54d4e7d61fa");
+        assert_eq!(client.weak_algorithms(), ["diffie-hellman-group14-sha1"]);
+
+        let raw = hex::decode(SERVER_KEXINIT).unwrap();
+        let (_, payload) = parse_packet(&raw).unwrap().unwrap();
+        let server = KexInit::parse(payload).unwrap();
+        assert_eq!(server.hassh_server(), "65e6f080c636f56dfad27ab1c1fb0a6b");
+        assert_eq!(
+            server.weak_algorithms(),
+            [
+                "diffie-hellman-group14-sha1",
+                "ssh-rsa",
+                "aes128-cbc",
+                "hmac-sha1-96"
+            ]
+        );
+
+        assert_eq!(
+            KexInit::parse(&payload[..40]),
+            Err(SshError::InvalidKexInit)
+        );
+        assert_eq!(
+            parse_packet(&hex::decode("0000ffff04").unwrap()),
+            Err(SshError::InvalidPacket)
+        );
+    }
+
+    #[test]
+    fn session() {
+        let at = Duration::from_secs;
+        let mut session = SshSession::new();
+
+        let mut server = b"Please be nice\r\nSSH-2.0-OpenSSH_8.9p1\r\n".to_vec();
+        server.extend(hex::decode(SERVER_KEXINIT).unwrap());
+        session.push(Direction::ServerToClient, &server[..30], at(1));
+        session.push(Direction::ServerToClient, &server[30..], at(1));
+
+        let mut client = b"SSH-2.0-PuTTY_Release_0.80\r\n".to_vec();
+        client.extend(hex::decode(CLIENT_KEXINIT).unwrap());
+        client.extend(hex::decode("0000000c061e11111111000000000000").unwrap());
+        session.push(Direction::ClientToServer, &client, at(2));
+
+        let mut reply = hex::decode("0000000c061f22222222000000000000").unwrap();
+        reply.extend(hex::decode("0000000c0a1500000000000000000000").unwrap());
+        reply.extend_from_slice(&[0xbb; 20]);
+        session.push(Direction::ServerToClient, &reply, at(3));
+        session.push(Direction::ServerToClient, &[0xbb; 10], at(4));
+
+        assert_eq!(
+            session.server_banner().unwrap().software_version(),
+            "OpenSSH_8.9p1"
+        );
+        assert_eq!(
+            session.client_banner().unwrap().software_version(),
+            "PuTTY_Release_0.80"
+        );
+        assert_eq!(session.kex_algorithm(), Some("curve25519-sha256"));
+        assert_eq!(
+            session.kex_type(),
+            Some(KexType::EllipticCurveDiffieHellman)
+        );
+        assert_eq!(session.host_key_algorithm(), Some("ssh-ed25519"));
+        assert_eq!(
+            session.encryption_algorithm(Direction::ServerToClient),
+            Some("aes128-ctr")
+        );
+        assert_eq!(
+            session.mac_algorithm(Direction::ServerToClient),
+            Some("hmac-sha2-256")
+        );
+        assert_eq!(
+            session.messages(),
+            [
+                (Direction::ServerToClient, at(1), 20),
+                (Direction::ClientToServer, at(2), 20),
+                (Direction::ClientToServer, at(2), 30),
+                (Direction::ServerToClient, at(3), 31),
+                (Direction::ServerToClient, at(3), 21),
+            ]
+        );
+        assert_eq!(session.encrypted_bytes(Direction::ServerToClient), 30);
+        assert_eq!(
+            message_name(31, session.kex_type()),
+            "SSH_MSG_KEX_ECDH_REPLY"
+        );
+        assert_eq!(
+            message_name(31, Some(KexType::DiffieHellmanGroupExchange)),
+            "SSH_MSG_KEX_DH_GEX_GROUP"
+        );
+        assert_eq!(message_name(90, None), "SSH message 90");
+    }
+
+    #[test]
+    fn banner_length() {
+        let banner = |length: usize| {
+            let mut line = b"SSH-2.0-".to_vec();
+            line.resize(length - 2, b'a');
+            line.extend_from_slice(b"\r\n");
+            line
+        };
+
+        let mut session = SshSession::new();
+        session.push(
+            Direction::ClientToServer,
+            &banner(255),
+            Duration::from_secs(1),
+        );
+        assert!(session.client_banner().is_some());
+
+        // A line which is too long is rejected even when its end arrives in
+        // the same segment, and nothing after it is read.
+        let mut server = banner(256);
+        server.extend(banner(255));
+        session.push(Direction::ServerToClient, &server, Duration::from_secs(1));
+        assert!(session.server_banner().is_none());
+    }
+}
@@ -1,4 +1,6 @@
 use nom::{be_u16, be_u32, IResult};
+use std::fmt;
+use std::fmt::Formatter;
 use util::take_bool;
 
 #[derive(Eq, PartialEq, Debug)]
@@ -9,6 +11,13 @@ pub struct TcpHeader {
     acknowledgment_number: u32,
     data_offset: u8,
 
+    /// The three bits between the data offset and the flags. Always zero in
+    /// well formed headers, kept so nonzero values can be reported.
+    reserved: u8,
+
+    nonce_sum: bool,
+    congestion_window_reduced: bool,
+    ecn_echo: bool,
     urgent: bool,
     acknowledgment: bool,
     push: bool,
@@ -19,12 +28,288 @@ pub struct TcpHeader {
     window_size: u16,
     checksum: u16,
     urgent_pointer: u16,
+
+    options: Vec<TcpOption>,
 }
 
 impl TcpHeader {
     pub fn parse(input: &[u8]) -> IResult<&[u8], TcpHeader> {
         parse_tcp_header(input)
     }
+
+    pub fn source_port(&self) -> u16 {
+        self.source_port
+    }
+
+    pub fn destination_port(&self) -> u16 {
+        self.destination_port
+    }
+
+    pub fn sequence_number(&self) -> u32 {
+        self.sequence_number
+    }
+
+    pub fn acknowledgment_number(&self) -> u32 {
+        self.acknowledgment_number
+    }
+
+    /// Shows the header with other sequence and acknowledgment numbers in
+    /// place of its own, such as relative ones worked out from the rest of
+    /// the connection.
+    pub fn with_numbers(
+        &self,
+        sequence_number: u32,
+        acknowledgment_number: u32,
+    ) -> TcpHeaderDisplay<'_> {
+        TcpHeaderDisplay {
+            header: self,
+            sequence_number,
+            acknowledgment_number,
+        }
+    }
+
+    /// The three reserved bits, which are zero in well formed headers.
+    pub fn reserved(&self) -> u8 {
+        self.reserved
+    }
+
+    pub fn nonce_sum(&self) -> bool {
+        self.nonce_sum
+    }
+
+    pub fn congestion_window_reduced(&self) -> bool {
+        self.congestion_window_reduced
+    }
+
+    pub fn ecn_echo(&self) -> bool {
+        self.ecn_echo
+    }
+
+    pub fn urgent(&self) -> bool {
+        self.urgent
+    }
+
+    pub fn acknowledgment(&self) -> bool {
+        self.acknowledgment
+    }
+
+    pub fn push(&self) -> bool {
+        self.push
+    }
+
+    pub fn reset(&self) -> bool {
+        self.reset
+    }
+
+    pub fn synchronize(&self) -> bool {
+        self.synchronize
+    }
+
+    pub fn fin(&self) -> bool {
+        self.fin
+    }
+
+    pub fn window_size(&self) -> u16 {
+        self.window_size
+    }
+
+    pub fn checksum(&self) -> u16 {
+        self.checksum
+    }
+
+    pub fn options(&self) -> &[TcpOption] {
+        &self.options
+    }
+
+    /// The shift count from the window scale option, if present.
+    pub fn window_scale(&self) -> Option<u8> {
+        self.options.iter().find_map(|option| match option {
+            TcpOption::WindowScale(shift) => Some(*shift),
+            _ => None,
+        })
+    }
+
+    /// Names of the flags which are set, in the order they are shown in.
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        let flags = [
+            self.fin,
+            self.synchronize,
+            self.reset,
+            self.push,
+            self.acknowledgment,
+            self.urgent,
+            self.ecn_echo,
+            self.congestion_window_reduced,
+            self.nonce_sum,
+        ];
+        let bits = flags
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (bit, &set)| bits | u16::from(set) << bit);
+
+        flag_names(bits)
+    }
+
+    /// Whether the segment is a SYN offering ECN, which sets both ECE and
+    /// CWR as described in RFC 3168 section 6.1.1.
+    pub fn is_ecn_setup_syn(&self) -> bool {
+        self.synchronize && !self.acknowledgment && self.ecn_echo && self.congestion_window_reduced
+    }
+
+    /// Whether the segment is a SYN-ACK accepting ECN, which sets ECE but not
+    /// CWR.
+    pub fn is_ecn_setup_syn_ack(&self) -> bool {
+        self.synchronize && self.acknowledgment && self.ecn_echo && !self.congestion_window_reduced
+    }
+}
+
+impl fmt::Display for TcpHeader {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.with_numbers(self.sequence_number, self.acknowledgment_number)
+            .fmt(f)
+    }
+}
+
+/// A header shown with the sequence and acknowledgment numbers passed to
+/// `TcpHeader::with_numbers`.
+pub struct TcpHeaderDisplay<'a> {
+    header: &'a TcpHeader,
+    sequence_number: u32,
+    acknowledgment_number: u32,
+}
+
+impl<'a> fmt::Display for TcpHeaderDisplay<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let header = self.header;
+        write!(
+            f,
+            "{} -> {} [{}] Seq={}",
+            header.source_port,
+            header.destination_port,
+            header.flag_names().join(", "),
+            self.sequence_number
+        )?;
+
+        if header.acknowledgment {
+            write!(f, " Ack={}", self.acknowledgment_number)?;
+        }
+
+        write!(f, " Win={}", header.window_size)?;
+
+        if header.is_ecn_setup_syn() {
+            write!(f, " (ECN-setup SYN)")?;
+        } else if header.is_ecn_setup_syn_ack() {
+            write!(f, " (ECN-setup SYN-ACK)")?;
+        }
+
+        if header.reserved != 0 {
+            write!(f, " Reserved={:#05b}", header.reserved)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Names of the flags set in the nine flag bits of a header, FIN being the
+/// lowest, in the order they are shown in.
+fn flag_names(bits: u16) -> Vec<&'static str> {
+    const NAMES: [&str; 9] = ["FIN", "SYN", "RST", "PSH", "ACK", "URG", "ECE", "CWR", "NS"];
+
+    NAMES
+        .iter()
+        .enumerate()
+        .filter(|&(bit, _)| bits & 1 << bit != 0)
+        .map(|(_, &name)| name)
+        .collect()
+}
+
+/// The start of a TCP header which the capture cut short. Fields are only
+/// kept when all of their bytes were captured.
+#[derive(Eq, PartialEq, Debug)]
+pub struct TruncatedTcpHeader {
+    source_port: u16,
+    destination_port: u16,
+    sequence_number: Option<u32>,
+    acknowledgment_number: Option<u32>,
+
+    /// The nine flag bits, FIN being the lowest.
+    flags: Option<u16>,
+    window_size: Option<u16>,
+}
+
+impl TruncatedTcpHeader {
+    /// Reads the fields which fit in `input`. Returns `None` when not even
+    /// the ports were captured.
+    pub fn parse(input: &[u8]) -> Option<TruncatedTcpHeader> {
+        let u16_at = |offset: usize| {
+            input
+                .get(offset..offset + 2)
+                .map(|bytes| u16::from(bytes[0]) << 8 | u16::from(bytes[1]))
+        };
+        let u32_at =
+            |offset: usize| Some(u32::from(u16_at(offset)?) << 16 | u32::from(u16_at(offset + 2)?));
+
+        Some(TruncatedTcpHeader {
+            source_port: u16_at(0)?,
+            destination_port: u16_at(2)?,
+            sequence_number: u32_at(4),
+            acknowledgment_number: u32_at(8),
+            flags: u16_at(12).map(|bits| bits & 0x01ff),
+            window_size: u16_at(14),
+        })
+    }
+
+    pub fn source_port(&self) -> u16 {
+        self.source_port
+    }
+
+    pub fn destination_port(&self) -> u16 {
+        self.destination_port
+    }
+
+    pub fn sequence_number(&self) -> Option<u32> {
+        self.sequence_number
+    }
+
+    pub fn acknowledgment_number(&self) -> Option<u32> {
+        self.acknowledgment_number
+    }
+
+    /// Names of the flags which are set, if they were captured.
+    pub fn flag_names(&self) -> Option<Vec<&'static str>> {
+        self.flags.map(flag_names)
+    }
+
+    pub fn window_size(&self) -> Option<u16> {
+        self.window_size
+    }
+}
+
+/// Shows the fields which were captured, in the same way as a whole header.
+impl fmt::Display for TruncatedTcpHeader {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} -> {}", self.source_port, self.destination_port)?;
+        if let Some(names) = self.flag_names() {
+            write!(f, " [{}]", names.join(", "))?;
+        }
+        if let Some(sequence_number) = self.sequence_number {
+            write!(f, " Seq={}", sequence_number)?;
+        }
+
+        // The acknowledgment number only counts when the ACK flag is set.
+        let acknowledgment = self.flags.is_some_and(|bits| bits & 0x0010 != 0);
+        match self.acknowledgment_number {
+            Some(acknowledgment_number) if acknowledgment => {
+                write!(f, " Ack={}", acknowledgment_number)?
+            }
+            _ => {}
+        }
+        if let Some(window_size) = self.window_size {
+            write!(f, " Win={}", window_size)?;
+        }
+
+        Ok(())
+    }
 }
 
 named!(
@@ -35,9 +320,11 @@ named!(
             destination_port:      bytes!(be_u16) >>
             sequence_number:       bytes!(be_u32) >>
             acknowledgment_number: bytes!(be_u32) >>
-            data_offset:           take_bits!(u8, 4) >>
-                                   tag_bits!(u8, 3, 0) >>
-                                   take_bits!(u8, 3) >>
+            data_offset:           verify!(take_bits!(u8, 4), |offset| offset >= 5) >>
+            reserved:              take_bits!(u8, 3) >>
+            nonce_sum:             take_bool >>
+            congestion_window_reduced: take_bool >>
+            ecn_echo:              take_bool >>
             urgent:                take_bool >>
             acknowledgment:        take_bool >>
             push:                  take_bool >>
@@ -48,7 +335,10 @@ named!(
             window_size:           bytes!(be_u16) >>
             checksum:              bytes!(be_u16) >>
             urgent_pointer:        bytes!(be_u16) >>
-                                   bytes!(take!((data_offset - 5) * 4)) >>
+            options:               bytes!(map!(
+                                       take!((data_offset - 5) * 4),
+                                       parse_tcp_options
+                                   )) >>
 
             (TcpHeader {
                 source_port,
@@ -56,6 +346,10 @@ named!(
                 sequence_number,
                 acknowledgment_number,
                 data_offset: data_offset * 4,
+                reserved,
+                nonce_sum,
+                congestion_window_reduced,
+                ecn_echo,
                 urgent,
                 acknowledgment,
                 push,
@@ -65,15 +359,173 @@ named!(
                 window_size,
                 checksum,
                 urgent_pointer,
+                options,
             })
         )
     )
 );
 
+#[derive(Eq, PartialEq, Debug)]
+pub enum TcpOption {
+    EndOfOptionList,
+    NoOperation,
+    MaximumSegmentSize(u16),
+    WindowScale(u8),
+    SackPermitted,
+
+    /// Left and right edges of the blocks of data received out of order.
+    Sack(Vec<(u32, u32)>),
+
+    Timestamps {
+        value: u32,
+        echo_reply: u32,
+    },
+
+    /// A TCP Fast Open cookie, either the IANA assigned option or the
+    /// experimental one used before it. An empty cookie is a cookie request.
+    FastOpen {
+        cookie: Vec<u8>,
+    },
+
+    MultipathTcp {
+        subtype: MptcpSubtype,
+        data: Vec<u8>,
+    },
+
+    /// An option of a kind which isn't decoded, with the bytes following its
+    /// kind and length.
+    Unknown {
+        kind: u8,
+        data: Vec<u8>,
+    },
+
+    /// An option whose length is missing, below two or past the end of the
+    /// options area. `data` holds the rest of the area after the kind,
+    /// starting with the length byte when there is one.
+    Malformed {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// The kind of the experimental option used by TCP Fast Open before it was
+/// assigned its own, along with the magic number it is identified by.
+const EXPERIMENTAL_OPTION: u8 = 254;
+const FAST_OPEN_MAGIC: [u8; 2] = [0xf9, 0x89];
+
+/// Splits the options area of a TCP header into options. Parsing stops at the
+/// end of option list marker, or at an option whose length doesn't fit the
+/// area, which is kept as a malformed option.
+fn parse_tcp_options(mut input: &[u8]) -> Vec<TcpOption> {
+    let mut options = Vec::new();
+
+    while let Some((&kind, rest)) = input.split_first() {
+        match kind {
+            0 => {
+                options.push(TcpOption::EndOfOptionList);
+                break;
+            }
+            1 => {
+                options.push(TcpOption::NoOperation);
+                input = rest;
+                continue;
+            }
+            _ => {}
+        }
+
+        let length = rest.first().map_or(0, |&length| length as usize);
+        if length < 2 || length > input.len() {
+            options.push(TcpOption::Malformed {
+                kind,
+                data: rest.to_vec(),
+            });
+            break;
+        }
+
+        options.push(TcpOption::new(kind, &input[2..length]));
+        input = &input[length..];
+    }
+
+    options
+}
+
+impl TcpOption {
+    fn new(kind: u8, data: &[u8]) -> TcpOption {
+        match (kind, data.len()) {
+            (2, 2) => TcpOption::MaximumSegmentSize(be_u16_at(data, 0)),
+            (3, 1) => TcpOption::WindowScale(data[0]),
+            (4, 0) => TcpOption::SackPermitted,
+            (5, length) if length % 8 == 0 => TcpOption::Sack(
+                data.chunks(8)
+                    .map(|block| (be_u32_at(block, 0), be_u32_at(block, 4)))
+                    .collect(),
+            ),
+            (8, 8) => TcpOption::Timestamps {
+                value: be_u32_at(data, 0),
+                echo_reply: be_u32_at(data, 4),
+            },
+            (30, length) if length > 0 => TcpOption::MultipathTcp {
+                subtype: MptcpSubtype::new(data[0] >> 4),
+                data: data.to_vec(),
+            },
+            (34, _) => TcpOption::FastOpen {
+                cookie: data.to_vec(),
+            },
+            (EXPERIMENTAL_OPTION, _) if data.starts_with(&FAST_OPEN_MAGIC) => TcpOption::FastOpen {
+                cookie: data[2..].to_vec(),
+            },
+            _ => TcpOption::Unknown {
+                kind,
+                data: data.to_vec(),
+            },
+        }
+    }
+}
+
+fn be_u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from(data[offset]) << 8 | u16::from(data[offset + 1])
+}
+
+fn be_u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from(be_u16_at(data, offset)) << 16 | u32::from(be_u16_at(data, offset + 2))
+}
+
+/// The kind of a Multipath TCP option, from RFC 8684.
+#[derive(Eq, PartialEq, Debug)]
+pub enum MptcpSubtype {
+    MpCapable,
+    MpJoin,
+    DataSequenceSignal,
+    AddAddress,
+    RemoveAddress,
+    MpPriority,
+    MpFail,
+    MpFastClose,
+    MpTcpReset,
+    Other(u8),
+}
+
+impl MptcpSubtype {
+    pub fn new(value: u8) -> MptcpSubtype {
+        match value {
+            0x0 => MptcpSubtype::MpCapable,
+            0x1 => MptcpSubtype::MpJoin,
+            0x2 => MptcpSubtype::DataSequenceSignal,
+            0x3 => MptcpSubtype::AddAddress,
+            0x4 => MptcpSubtype::RemoveAddress,
+            0x5 => MptcpSubtype::MpPriority,
+            0x6 => MptcpSubtype::MpFail,
+            0x7 => MptcpSubtype::MpFastClose,
+            0x8 => MptcpSubtype::MpTcpReset,
+            _ => MptcpSubtype::Other(value),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     extern crate hex;
-    use tcp::TcpHeader;
+    use tcp::{parse_tcp_options, MptcpSubtype, TcpHeader, TcpOption, TruncatedTcpHeader};
 
     #[test]
     fn parse() {
@@ -89,6 +541,10 @@ mod tests {
                 acknowledgment_number: 0,
                 data_offset: 24,
 
+                reserved: 0,
+                nonce_sum: false,
+                congestion_window_reduced: false,
+                ecn_echo: false,
                 urgent: false,
                 acknowledgment: false,
                 push: false,
@@ -98,8 +554,133 @@ mod tests {
 
                 window_size: 8760,
                 checksum: 0xa92c,
-                urgent_pointer: 0
+                urgent_pointer: 0,
+
+                options: vec![TcpOption::MaximumSegmentSize(1460)],
             },
         )
     }
+
+    #[test]
+    fn display_with_numbers() {
+        let raw = hex::decode("900500177214f1140000000060022238a92c0000020405b4").unwrap();
+        let (_, header) = TcpHeader::parse(&raw).unwrap();
+        assert_eq!(
+            header.to_string(),
+            "36869 -> 23 [SYN] Seq=1913975060 Win=8760"
+        );
+        assert_eq!(
+            header.with_numbers(0, 0).to_string(),
+            "36869 -> 23 [SYN] Seq=0 Win=8760"
+        );
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let raw = hex::decode("900500177214f1140000000060122238a92c").unwrap();
+
+        let header = TruncatedTcpHeader::parse(&raw[..10]).unwrap();
+        assert_eq!(header.sequence_number(), Some(1913975060));
+        assert_eq!(header.acknowledgment_number(), None);
+        assert_eq!(header.flag_names(), None);
+        assert_eq!(header.to_string(), "36869 -> 23 Seq=1913975060");
+
+        let header = TruncatedTcpHeader::parse(&raw[..16]).unwrap();
+        assert_eq!(header.flag_names(), Some(vec!["SYN", "ACK"]));
+        assert_eq!(
+            header.to_string(),
+            "36869 -> 23 [SYN, ACK] Seq=1913975060 Ack=0 Win=8760"
+        );
+
+        assert_eq!(TruncatedTcpHeader::parse(&raw[..3]), None);
+    }
+
+    #[test]
+    fn parse_ecn_setup_syn_with_reserved_bits() {
+        let raw = hex::decode("900500177214f1140000000064c22238a92c0000020405b4").unwrap();
+        let (_, header) = TcpHeader::parse(&raw).unwrap();
+        assert_eq!(header.reserved(), 0b010);
+        assert!(!header.nonce_sum());
+        assert!(header.congestion_window_reduced());
+        assert!(header.ecn_echo());
+        assert!(!header.urgent());
+        assert!(!header.acknowledgment());
+        assert!(!header.push());
+        assert!(!header.reset());
+        assert!(header.synchronize());
+        assert!(!header.fin());
+        assert!(header.is_ecn_setup_syn());
+        assert_eq!(
+            header.to_string(),
+            "36869 -> 23 [SYN, ECE, CWR] Seq=1913975060 Win=8760 (ECN-setup SYN) Reserved=0b010"
+        );
+    }
+
+    #[test]
+    fn parse_options() {
+        let raw = hex::decode(
+            "0204058c0402080a0001b2dd00000000010303070101050a000003e8000007d0\
+             22060102030401011e0d0181000102030405060708fe06f989aabb00",
+        )
+        .unwrap();
+        assert_eq!(
+            parse_tcp_options(&raw),
+            vec![
+                TcpOption::MaximumSegmentSize(1420),
+                TcpOption::SackPermitted,
+                TcpOption::Timestamps {
+                    value: 111325,
+                    echo_reply: 0,
+                },
+                TcpOption::NoOperation,
+                TcpOption::WindowScale(7),
+                TcpOption::NoOperation,
+                TcpOption::NoOperation,
+                TcpOption::Sack(vec![(1000, 2000)]),
+                TcpOption::FastOpen {
+                    cookie: vec![1, 2, 3, 4],
+                },
+                TcpOption::NoOperation,
+                TcpOption::NoOperation,
+                TcpOption::MultipathTcp {
+                    subtype: MptcpSubtype::MpCapable,
+                    data: hex::decode("0181000102030405060708").unwrap(),
+                },
+                TcpOption::FastOpen {
+                    cookie: vec![0xaa, 0xbb],
+                },
+                TcpOption::EndOfOptionList,
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_malformed_option() {
+        let raw = hex::decode("0204058c08ff0000").unwrap();
+        assert_eq!(
+            parse_tcp_options(&raw),
+            vec![
+                TcpOption::MaximumSegmentSize(1420),
+                TcpOption::Malformed {
+                    kind: 8,
+                    data: vec![0xff, 0, 0],
+                },
+            ]
+        );
+
+        assert_eq!(
+            parse_tcp_options(&[0x0f]),
+            vec![TcpOption::Malformed {
+                kind: 15,
+                data: Vec::new(),
+            }]
+        );
+        assert_eq!(
+            parse_tcp_options(&[0x0f, 0x03, 0xaa]),
+            vec![TcpOption::Unknown {
+                kind: 15,
+                data: vec![0xaa],
+            }]
+        );
+    }
 }
@@ -0,0 +1,431 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+const INTERPRET_AS_COMMAND: u8 = 255;
+const SUBNEGOTIATION_END: u8 = 240;
+const SUBNEGOTIATION_BEGIN: u8 = 250;
+
+/// Telnet options from the IANA registry which are commonly negotiated.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum TelnetOption {
+    BinaryTransmission,
+    Echo,
+    SuppressGoAhead,
+    Status,
+    TimingMark,
+    TerminalType,
+    NegotiateAboutWindowSize,
+    TerminalSpeed,
+    RemoteFlowControl,
+    Linemode,
+    XDisplayLocation,
+    Environment,
+    Authentication,
+    Encryption,
+    NewEnvironment,
+    Other(u8),
+}
+
+impl TelnetOption {
+    pub fn from_code(code: u8) -> TelnetOption {
+        match code {
+            0 => TelnetOption::BinaryTransmission,
+            1 => TelnetOption::Echo,
+            3 => TelnetOption::SuppressGoAhead,
+            5 => TelnetOption::Status,
+            6 => TelnetOption::TimingMark,
+            24 => TelnetOption::TerminalType,
+            31 => TelnetOption::NegotiateAboutWindowSize,
+            32 => TelnetOption::TerminalSpeed,
+            33 => TelnetOption::RemoteFlowControl,
+            34 => TelnetOption::Linemode,
+            35 => TelnetOption::XDisplayLocation,
+            36 => TelnetOption::Environment,
+            37 => TelnetOption::Authentication,
+            38 => TelnetOption::Encryption,
+            39 => TelnetOption::NewEnvironment,
+            other => TelnetOption::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for TelnetOption {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            TelnetOption::BinaryTransmission => "Binary Transmission",
+            TelnetOption::Echo => "Echo",
+            TelnetOption::SuppressGoAhead => "Suppress Go Ahead",
+            TelnetOption::Status => "Status",
+            TelnetOption::TimingMark => "Timing Mark",
+            TelnetOption::TerminalType => "Terminal Type",
+            TelnetOption::NegotiateAboutWindowSize => "Negotiate About Window Size",
+            TelnetOption::TerminalSpeed => "Terminal Speed",
+            TelnetOption::RemoteFlowControl => "Remote Flow Control",
+            TelnetOption::Linemode => "Linemode",
+            TelnetOption::XDisplayLocation => "X Display Location",
+            TelnetOption::Environment => "Environment",
+            TelnetOption::Authentication => "Authentication",
+            TelnetOption::Encryption => "Encryption",
+            TelnetOption::NewEnvironment => "New Environment",
+            TelnetOption::Other(code) => return write!(f, "Option {}", code),
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Negotiation {
+    Will,
+    Wont,
+    Do,
+    Dont,
+}
+
+impl fmt::Display for Negotiation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Negotiation::Will => "Will",
+            Negotiation::Wont => "Won't",
+            Negotiation::Do => "Do",
+            Negotiation::Dont => "Don't",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Commands other than option negotiation.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum TelnetCommand {
+    EndOfRecord,
+    NoOperation,
+    DataMark,
+    Break,
+    InterruptProcess,
+    AbortOutput,
+    AreYouThere,
+    EraseCharacter,
+    EraseLine,
+    GoAhead,
+    Other(u8),
+}
+
+impl TelnetCommand {
+    pub fn from_code(code: u8) -> TelnetCommand {
+        match code {
+            239 => TelnetCommand::EndOfRecord,
+            241 => TelnetCommand::NoOperation,
+            242 => TelnetCommand::DataMark,
+            243 => TelnetCommand::Break,
+            244 => TelnetCommand::InterruptProcess,
+            245 => TelnetCommand::AbortOutput,
+            246 => TelnetCommand::AreYouThere,
+            247 => TelnetCommand::EraseCharacter,
+            248 => TelnetCommand::EraseLine,
+            249 => TelnetCommand::GoAhead,
+            other => TelnetCommand::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for TelnetCommand {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            TelnetCommand::EndOfRecord => "End of Record",
+            TelnetCommand::NoOperation => "No Operation",
+            TelnetCommand::DataMark => "Data Mark",
+            TelnetCommand::Break => "Break",
+            TelnetCommand::InterruptProcess => "Interrupt Process",
+            TelnetCommand::AbortOutput => "Abort Output",
+            TelnetCommand::AreYouThere => "Are You There",
+            TelnetCommand::EraseCharacter => "Erase Character",
+            TelnetCommand::EraseLine => "Erase Line",
+            TelnetCommand::GoAhead => "Go Ahead",
+            TelnetCommand::Other(code) => return write!(f, "Command {}", code),
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Subnegotiation {
+    /// NAWS, from RFC 1073.
+    WindowSize {
+        width: u16,
+        height: u16,
+    },
+
+    /// The terminal type sent in reply to `TerminalTypeSend`, from RFC 1091.
+    TerminalTypeIs(String),
+    TerminalTypeSend,
+
+    Other {
+        option: TelnetOption,
+        data: Vec<u8>,
+    },
+}
+
+impl Subnegotiation {
+    /// Parses the body of a subnegotiation, with doubled IAC bytes already
+    /// undone.
+    fn parse(body: &[u8]) -> Option<Subnegotiation> {
+        let (&code, data) = body.split_first()?;
+        let option = TelnetOption::from_code(code);
+
+        let subnegotiation = match (option, data) {
+            (TelnetOption::NegotiateAboutWindowSize, [w1, w2, h1, h2]) => {
+                Subnegotiation::WindowSize {
+                    width: u16::from_be_bytes([*w1, *w2]),
+                    height: u16::from_be_bytes([*h1, *h2]),
+                }
+            }
+            (TelnetOption::TerminalType, [0, name @ ..]) => {
+                Subnegotiation::TerminalTypeIs(String::from_utf8_lossy(name).into_owned())
+            }
+            (TelnetOption::TerminalType, [1]) => Subnegotiation::TerminalTypeSend,
+            _ => Subnegotiation::Other {
+                option,
+                data: data.to_vec(),
+            },
+        };
+
+        Some(subnegotiation)
+    }
+}
+
+impl fmt::Display for Subnegotiation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Subnegotiation::WindowSize { width, height } => {
+                write!(f, "Window size {}x{}", width, height)
+            }
+            Subnegotiation::TerminalTypeIs(name) => write!(f, "Terminal type is {}", name),
+            Subnegotiation::TerminalTypeSend => write!(f, "Send terminal type"),
+            Subnegotiation::Other { option, data } => {
+                write!(f, "Suboption {}, {} bytes", option, data.len())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum TelnetItem {
+    /// Data with doubled IAC bytes undone.
+    Data(Vec<u8>),
+
+    Command(TelnetCommand),
+    Negotiation {
+        negotiation: Negotiation,
+        option: TelnetOption,
+    },
+    Subnegotiation(Subnegotiation),
+}
+
+impl fmt::Display for TelnetItem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TelnetItem::Data(data) => write!(f, "Data: {} bytes", data.len()),
+            TelnetItem::Command(command) => write!(f, "{}", command),
+            TelnetItem::Negotiation {
+                negotiation,
+                option,
+            } => write!(f, "{} {}", negotiation, option),
+            TelnetItem::Subnegotiation(subnegotiation) => write!(f, "{}", subnegotiation),
+        }
+    }
+}
+
+/// The Telnet commands and data in one TCP segment. Commands split across
+/// segments aren't put back together.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TelnetMessage {
+    items: Vec<TelnetItem>,
+}
+
+impl TelnetMessage {
+    pub fn parse(input: &[u8]) -> TelnetMessage {
+        let mut items = Vec::new();
+        let mut data = Vec::new();
+        let mut index = 0;
+
+        while index < input.len() {
+            let byte = input[index];
+            index += 1;
+
+            if byte != INTERPRET_AS_COMMAND {
+                data.push(byte);
+                continue;
+            }
+
+            let code = match input.get(index) {
+                Some(&code) => code,
+                None => break,
+            };
+            index += 1;
+
+            if code == INTERPRET_AS_COMMAND {
+                data.push(INTERPRET_AS_COMMAND);
+                continue;
+            }
+
+            if !data.is_empty() {
+                items.push(TelnetItem::Data(data.split_off(0)));
+            }
+
+            let negotiation = match code {
+                251 => Some(Negotiation::Will),
+                252 => Some(Negotiation::Wont),
+                253 => Some(Negotiation::Do),
+                254 => Some(Negotiation::Dont),
+                _ => None,
+            };
+
+            if let Some(negotiation) = negotiation {
+                if let Some(&option) = input.get(index) {
+                    index += 1;
+                    items.push(TelnetItem::Negotiation {
+                        negotiation,
+                        option: TelnetOption::from_code(option),
+                    });
+                }
+            } else if code == SUBNEGOTIATION_BEGIN {
+                let (body, length) = subnegotiation_body(&input[index..]);
+                index += length;
+                if let Some(subnegotiation) = Subnegotiation::parse(&body) {
+                    items.push(TelnetItem::Subnegotiation(subnegotiation));
+                }
+            } else {
+                items.push(TelnetItem::Command(TelnetCommand::from_code(code)));
+            }
+        }
+
+        if !data.is_empty() {
+            items.push(TelnetItem::Data(data));
+        }
+
+        TelnetMessage { items }
+    }
+
+    pub fn items(&self) -> &[TelnetItem] {
+        &self.items
+    }
+
+    /// The data sent, without commands, doubled IAC bytes or the NUL sent
+    /// after a bare carriage return.
+    pub fn data(&self) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::new();
+        for item in &self.items {
+            if let TelnetItem::Data(data) = item {
+                for &byte in data {
+                    if byte == 0 && output.last() == Some(&b'\r') {
+                        continue;
+                    }
+                    output.push(byte);
+                }
+            }
+        }
+
+        output
+    }
+}
+
+impl fmt::Display for TelnetMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Telnet")?;
+        for (index, item) in self.items.iter().enumerate() {
+            let separator = if index == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, item)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a subnegotiation up to IAC SE, or the end of the input when it is
+/// cut short. Returns the body with doubled IAC bytes undone along with the
+/// number of bytes read.
+fn subnegotiation_body(input: &[u8]) -> (Vec<u8>, usize) {
+    let mut body = Vec::new();
+    let mut index = 0;
+
+    while index < input.len() {
+        let byte = input[index];
+        index += 1;
+
+        if byte != INTERPRET_AS_COMMAND {
+            body.push(byte);
+            continue;
+        }
+
+        match input.get(index) {
+            Some(&INTERPRET_AS_COMMAND) => {
+                body.push(INTERPRET_AS_COMMAND);
+                index += 1;
+            }
+            Some(&SUBNEGOTIATION_END) => return (body, index + 1),
+            _ => break,
+        }
+    }
+
+    (body, index)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use telnet::{
+        Negotiation, Subnegotiation, TelnetCommand, TelnetItem, TelnetMessage, TelnetOption,
+    };
+
+    #[test]
+    fn parse_negotiation() {
+        let raw = hex::decode("fffd18fffb1ffffa1f0050ffff18fff0fffa1800787465726dfff0").unwrap();
+        let message = TelnetMessage::parse(&raw);
+
+        assert_eq!(
+            message,
+            TelnetMessage {
+                items: vec![
+                    TelnetItem::Negotiation {
+                        negotiation: Negotiation::Do,
+                        option: TelnetOption::TerminalType,
+                    },
+                    TelnetItem::Negotiation {
+                        negotiation: Negotiation::Will,
+                        option: TelnetOption::NegotiateAboutWindowSize,
+                    },
+                    TelnetItem::Subnegotiation(Subnegotiation::WindowSize {
+                        width: 80,
+                        height: 0xff18,
+                    }),
+                    TelnetItem::Subnegotiation(Subnegotiation::TerminalTypeIs("xterm".to_string())),
+                ],
+            }
+        );
+
+        assert_eq!(
+            message.to_string(),
+            "Telnet Do Terminal Type, Will Negotiate About Window Size, \
+             Window size 80x65304, Terminal type is xterm"
+        );
+    }
+
+    #[test]
+    fn parse_data() {
+        let raw = hex::decode("6c730d00ffff0d0afff4fffa1801fff0ff").unwrap();
+        let message = TelnetMessage::parse(&raw);
+
+        assert_eq!(
+            message.items(),
+            &[
+                TelnetItem::Data(b"ls\r\x00\xff\r\n".to_vec()),
+                TelnetItem::Command(TelnetCommand::InterruptProcess),
+                TelnetItem::Subnegotiation(Subnegotiation::TerminalTypeSend),
+            ]
+        );
+        assert_eq!(message.data(), b"ls\r\xff\r\n".to_vec());
+    }
+}
@@ -0,0 +1,1336 @@
+use flow::Direction;
+use md5::Md5;
+use nom::{be_u16, be_u8, IResult};
+use reassembly::{ReassembledStreams, StreamHandler};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+use util::to_hex;
+
+/// The longest record allowed, which is a full plaintext plus the most
+/// compression or encryption may add.
+const MAXIMUM_RECORD_LENGTH: u16 = (1 << 14) + 2048;
+
+const SERVER_NAME: u16 = 0;
+const SUPPORTED_GROUPS: u16 = 10;
+const EC_POINT_FORMATS: u16 = 11;
+const SIGNATURE_ALGORITHMS: u16 = 13;
+const APPLICATION_LAYER_PROTOCOL_NEGOTIATION: u16 = 16;
+const SUPPORTED_VERSIONS: u16 = 43;
+
+#[derive(Fail, Debug, Eq, PartialEq)]
+pub enum TlsError {
+    #[fail(display = "Invalid TLS {} message", _0)]
+    InvalidHandshake(HandshakeType),
+}
+
+/// Whether `data` starts with a TLS record header.
+pub fn looks_like_tls(data: &[u8]) -> bool {
+    data.len() >= 5
+        && (20..=24).contains(&data[0])
+        && data[1] == 3
+        && data[2] <= 4
+        && u16::from_be_bytes([data[3], data[4]]) <= MAXIMUM_RECORD_LENGTH
+}
+
+/// GREASE values (RFC 8701) are sent to keep servers tolerant of unknown
+/// values and are left out of fingerprints.
+pub fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+pub fn version_name(version: u16) -> String {
+    match version {
+        0x0300 => "SSL 3.0".to_string(),
+        0x0301 => "TLS 1.0".to_string(),
+        0x0302 => "TLS 1.1".to_string(),
+        0x0303 => "TLS 1.2".to_string(),
+        0x0304 => "TLS 1.3".to_string(),
+        other => format!("0x{:04x}", other),
+    }
+}
+
+/// Names of the cipher suites seen most often.
+pub fn cipher_suite_name(cipher_suite: u16) -> Option<&'static str> {
+    let name = match cipher_suite {
+        0x002f => "TLS_RSA_WITH_AES_128_CBC_SHA",
+        0x0035 => "TLS_RSA_WITH_AES_256_CBC_SHA",
+        0x009c => "TLS_RSA_WITH_AES_128_GCM_SHA256",
+        0x009d => "TLS_RSA_WITH_AES_256_GCM_SHA384",
+        0x1301 => "TLS_AES_128_GCM_SHA256",
+        0x1302 => "TLS_AES_256_GCM_SHA384",
+        0x1303 => "TLS_CHACHA20_POLY1305_SHA256",
+        0xc013 => "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA",
+        0xc014 => "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA",
+        0xc02b => "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+        0xc02c => "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+        0xc02f => "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        0xc030 => "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+        0xcca8 => "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+        0xcca9 => "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ContentType {
+    ChangeCipherSpec,
+    Alert,
+    Handshake,
+    ApplicationData,
+    Heartbeat,
+    Other(u8),
+}
+
+impl ContentType {
+    pub fn from_code(code: u8) -> ContentType {
+        match code {
+            20 => ContentType::ChangeCipherSpec,
+            21 => ContentType::Alert,
+            22 => ContentType::Handshake,
+            23 => ContentType::ApplicationData,
+            24 => ContentType::Heartbeat,
+            other => ContentType::Other(other),
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            ContentType::ChangeCipherSpec => 20,
+            ContentType::Alert => 21,
+            ContentType::Handshake => 22,
+            ContentType::ApplicationData => 23,
+            ContentType::Heartbeat => 24,
+            ContentType::Other(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            ContentType::ChangeCipherSpec => "Change Cipher Spec",
+            ContentType::Alert => "Alert",
+            ContentType::Handshake => "Handshake",
+            ContentType::ApplicationData => "Application Data",
+            ContentType::Heartbeat => "Heartbeat",
+            ContentType::Other(code) => return write!(f, "Content Type {}", code),
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// A record of the TLS record layer. A record at the end of a segment may
+/// be cut short, in which case `fragment` is shorter than `length`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TlsRecord {
+    content_type: ContentType,
+    version: u16,
+    length: u16,
+    fragment: Vec<u8>,
+}
+
+named!(
+    parse_record_header<(u8, u16, u16)>,
+    do_parse!(
+        content_type: be_u8 >>
+        version:      be_u16 >>
+        length:       verify!(be_u16, |length| length <= MAXIMUM_RECORD_LENGTH) >>
+
+        (content_type, version, length)
+    )
+);
+
+impl TlsRecord {
+    /// Parses a whole record.
+    pub fn parse(input: &[u8]) -> IResult<&[u8], TlsRecord> {
+        let (rest, (content_type, version, length)) = parse_record_header(input)?;
+        let (rest, fragment) = take!(rest, length)?;
+
+        Ok((
+            rest,
+            TlsRecord {
+                content_type: ContentType::from_code(content_type),
+                version,
+                length,
+                fragment: fragment.to_vec(),
+            },
+        ))
+    }
+
+    /// Parses the records in a segment which starts on a record boundary.
+    /// The last one may run on into the next segment.
+    pub fn parse_segment(mut input: &[u8]) -> Vec<TlsRecord> {
+        let mut records = Vec::new();
+        while looks_like_tls(input) {
+            match TlsRecord::parse(input) {
+                Ok((rest, record)) => {
+                    records.push(record);
+                    input = rest;
+                }
+                Err(_) => {
+                    let (content_type, version, length) = match parse_record_header(input) {
+                        Ok((_, header)) => header,
+                        Err(_) => break,
+                    };
+
+                    records.push(TlsRecord {
+                        content_type: ContentType::from_code(content_type),
+                        version,
+                        length,
+                        fragment: input[5..].to_vec(),
+                    });
+                    break;
+                }
+            }
+        }
+
+        records
+    }
+
+    pub fn content_type(&self) -> ContentType {
+        self.content_type
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn length(&self) -> u16 {
+        self.length
+    }
+
+    pub fn fragment(&self) -> &[u8] {
+        &self.fragment
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.fragment.len() == self.length as usize
+    }
+
+    /// The handshake messages in a whole handshake record, or `None` when
+    /// the record doesn't hold whole messages, as when they are encrypted or
+    /// split across records.
+    pub fn handshakes(&self) -> Option<Vec<Handshake>> {
+        if self.content_type != ContentType::Handshake || !self.is_complete() {
+            return None;
+        }
+
+        let mut input = &self.fragment[..];
+        let mut handshakes = Vec::new();
+        while !input.is_empty() {
+            match Handshake::parse(input) {
+                Ok(Some((length, handshake))) => {
+                    handshakes.push(handshake);
+                    input = &input[length..];
+                }
+                _ => return None,
+            }
+        }
+
+        Some(handshakes)
+    }
+}
+
+impl fmt::Display for TlsRecord {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, {} bytes)",
+            self.content_type,
+            version_name(self.version),
+            self.length
+        )?;
+
+        if self.content_type == ContentType::Handshake && self.is_complete() {
+            match self.handshakes() {
+                Some(handshakes) => {
+                    for handshake in handshakes {
+                        write!(f, " {}", handshake)?;
+                    }
+                }
+                None => write!(f, " Encrypted Handshake Message")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum HandshakeType {
+    HelloRequest,
+    ClientHello,
+    ServerHello,
+    NewSessionTicket,
+    EndOfEarlyData,
+    EncryptedExtensions,
+    Certificate,
+    ServerKeyExchange,
+    CertificateRequest,
+    ServerHelloDone,
+    CertificateVerify,
+    ClientKeyExchange,
+    Finished,
+    KeyUpdate,
+    Other(u8),
+}
+
+impl HandshakeType {
+    pub fn from_code(code: u8) -> HandshakeType {
+        match code {
+            0 => HandshakeType::HelloRequest,
+            1 => HandshakeType::ClientHello,
+            2 => HandshakeType::ServerHello,
+            4 => HandshakeType::NewSessionTicket,
+            5 => HandshakeType::EndOfEarlyData,
+            8 => HandshakeType::EncryptedExtensions,
+            11 => HandshakeType::Certificate,
+            12 => HandshakeType::ServerKeyExchange,
+            13 => HandshakeType::CertificateRequest,
+            14 => HandshakeType::ServerHelloDone,
+            15 => HandshakeType::CertificateVerify,
+            16 => HandshakeType::ClientKeyExchange,
+            20 => HandshakeType::Finished,
+            24 => HandshakeType::KeyUpdate,
+            other => HandshakeType::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for HandshakeType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            HandshakeType::HelloRequest => "HelloRequest",
+            HandshakeType::ClientHello => "ClientHello",
+            HandshakeType::ServerHello => "ServerHello",
+            HandshakeType::NewSessionTicket => "NewSessionTicket",
+            HandshakeType::EndOfEarlyData => "EndOfEarlyData",
+            HandshakeType::EncryptedExtensions => "EncryptedExtensions",
+            HandshakeType::Certificate => "Certificate",
+            HandshakeType::ServerKeyExchange => "ServerKeyExchange",
+            HandshakeType::CertificateRequest => "CertificateRequest",
+            HandshakeType::ServerHelloDone => "ServerHelloDone",
+            HandshakeType::CertificateVerify => "CertificateVerify",
+            HandshakeType::ClientKeyExchange => "ClientKeyExchange",
+            HandshakeType::Finished => "Finished",
+            HandshakeType::KeyUpdate => "KeyUpdate",
+            HandshakeType::Other(code) => return write!(f, "Handshake Type {}", code),
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Reads the big endian integers and length prefixed vectors TLS messages
+/// are made of.
+struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a [u8]) -> Reader<'a> {
+        Reader { input }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        if self.input.len() < length {
+            return None;
+        }
+
+        let (taken, rest) = self.input.split_at(length);
+        self.input = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u24(&mut self) -> Option<usize> {
+        self.take(3)
+            .map(|bytes| (bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize)
+    }
+
+    fn vector8(&mut self) -> Option<&'a [u8]> {
+        let length = self.u8()?;
+        self.take(length as usize)
+    }
+
+    fn vector16(&mut self) -> Option<&'a [u8]> {
+        let length = self.u16()?;
+        self.take(length as usize)
+    }
+
+    fn vector24(&mut self) -> Option<&'a [u8]> {
+        let length = self.u24()?;
+        self.take(length)
+    }
+}
+
+fn u16_list(data: &[u8]) -> Vec<u16> {
+    data.chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+fn join<T: fmt::Display>(values: &[T], separator: &str) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Extension {
+    extension_type: u16,
+    data: Vec<u8>,
+}
+
+impl Extension {
+    pub fn extension_type(&self) -> u16 {
+        self.extension_type
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Reads the extensions at the end of a hello, which may be left out
+/// altogether.
+fn parse_extensions(reader: &mut Reader) -> Option<Vec<Extension>> {
+    let mut extensions = Vec::new();
+    if reader.is_empty() {
+        return Some(extensions);
+    }
+
+    let mut list = Reader::new(reader.vector16()?);
+    while !list.is_empty() {
+        let extension_type = list.u16()?;
+        let data = list.vector16()?.to_vec();
+        extensions.push(Extension {
+            extension_type,
+            data,
+        });
+    }
+
+    Some(extensions)
+}
+
+fn find_extension(extensions: &[Extension], extension_type: u16) -> Option<&[u8]> {
+    extensions
+        .iter()
+        .find(|extension| extension.extension_type == extension_type)
+        .map(|extension| &extension.data[..])
+}
+
+/// The protocols in an application_layer_protocol_negotiation extension.
+fn parse_alpn(data: &[u8]) -> Vec<String> {
+    let mut protocols = Vec::new();
+    let mut list = match Reader::new(data).vector16() {
+        Some(list) => Reader::new(list),
+        None => return protocols,
+    };
+
+    while let Some(protocol) = list.vector8() {
+        protocols.push(String::from_utf8_lossy(protocol).into_owned());
+    }
+
+    protocols
+}
+
+fn md5_hex(input: &str) -> String {
+    to_hex(&Md5::digest(input.as_bytes()))
+}
+
+/// The first 12 hex digits of the SHA-256 of `input`, as used by JA4.
+fn truncated_sha256(input: &str) -> String {
+    to_hex(&Sha256::digest(input.as_bytes()))[..12].to_string()
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ClientHello {
+    version: u16,
+    random: Vec<u8>,
+    session_id: Vec<u8>,
+    cipher_suites: Vec<u16>,
+    compression_methods: Vec<u8>,
+    extensions: Vec<Extension>,
+}
+
+impl ClientHello {
+    fn parse(body: &[u8]) -> Option<ClientHello> {
+        let mut reader = Reader::new(body);
+        let version = reader.u16()?;
+        let random = reader.take(32)?.to_vec();
+        let session_id = reader.vector8()?.to_vec();
+        let cipher_suites = u16_list(reader.vector16()?);
+        let compression_methods = reader.vector8()?.to_vec();
+        let extensions = parse_extensions(&mut reader)?;
+
+        Some(ClientHello {
+            version,
+            random,
+            session_id,
+            cipher_suites,
+            compression_methods,
+            extensions,
+        })
+    }
+
+    /// The legacy version field, which stays at TLS 1.2 for TLS 1.3.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn random(&self) -> &[u8] {
+        &self.random
+    }
+
+    pub fn session_id(&self) -> &[u8] {
+        &self.session_id
+    }
+
+    pub fn cipher_suites(&self) -> &[u16] {
+        &self.cipher_suites
+    }
+
+    pub fn compression_methods(&self) -> &[u8] {
+        &self.compression_methods
+    }
+
+    pub fn extensions(&self) -> &[Extension] {
+        &self.extensions
+    }
+
+    /// The host name from the server_name extension.
+    pub fn server_name(&self) -> Option<String> {
+        let data = find_extension(&self.extensions, SERVER_NAME)?;
+        let mut list = Reader::new(Reader::new(data).vector16()?);
+        while let Some(name_type) = list.u8() {
+            let name = list.vector16()?;
+            if name_type == 0 {
+                return Some(String::from_utf8_lossy(name).into_owned());
+            }
+        }
+
+        None
+    }
+
+    pub fn alpn_protocols(&self) -> Vec<String> {
+        find_extension(&self.extensions, APPLICATION_LAYER_PROTOCOL_NEGOTIATION)
+            .map(parse_alpn)
+            .unwrap_or_default()
+    }
+
+    /// The versions offered in the supported_versions extension.
+    pub fn supported_versions(&self) -> Vec<u16> {
+        find_extension(&self.extensions, SUPPORTED_VERSIONS)
+            .and_then(|data| Reader::new(data).vector8())
+            .map(u16_list)
+            .unwrap_or_default()
+    }
+
+    pub fn supported_groups(&self) -> Vec<u16> {
+        find_extension(&self.extensions, SUPPORTED_GROUPS)
+            .and_then(|data| Reader::new(data).vector16())
+            .map(u16_list)
+            .unwrap_or_default()
+    }
+
+    pub fn ec_point_formats(&self) -> Vec<u8> {
+        find_extension(&self.extensions, EC_POINT_FORMATS)
+            .and_then(|data| Reader::new(data).vector8())
+            .map(|formats| formats.to_vec())
+            .unwrap_or_default()
+    }
+
+    pub fn signature_algorithms(&self) -> Vec<u16> {
+        find_extension(&self.extensions, SIGNATURE_ALGORITHMS)
+            .and_then(|data| Reader::new(data).vector16())
+            .map(u16_list)
+            .unwrap_or_default()
+    }
+
+    /// The highest version offered, from supported_versions when it's sent.
+    pub fn highest_version(&self) -> u16 {
+        self.supported_versions()
+            .into_iter()
+            .filter(|&version| !is_grease(version))
+            .max()
+            .unwrap_or(self.version)
+    }
+
+    /// The string hashed for the JA3 fingerprint: the version, cipher
+    /// suites, extensions, supported groups and point formats.
+    pub fn ja3_string(&self) -> String {
+        let without_grease = |values: Vec<u16>| -> Vec<u16> {
+            values
+                .into_iter()
+                .filter(|&value| !is_grease(value))
+                .collect()
+        };
+        let extensions: Vec<u16> = self
+            .extensions
+            .iter()
+            .map(|extension| extension.extension_type)
+            .collect();
+
+        format!(
+            "{},{},{},{},{}",
+            self.version,
+            join(&without_grease(self.cipher_suites.clone()), "-"),
+            join(&without_grease(extensions), "-"),
+            join(&without_grease(self.supported_groups()), "-"),
+            join(&self.ec_point_formats(), "-")
+        )
+    }
+
+    pub fn ja3(&self) -> String {
+        md5_hex(&self.ja3_string())
+    }
+
+    /// The JA4 fingerprint, for example `t13d1516h2_8daaf6152771_e5627efa2ab1`.
+    pub fn ja4(&self) -> String {
+        let version = match self.highest_version() {
+            0x0304 => "13",
+            0x0303 => "12",
+            0x0302 => "11",
+            0x0301 => "10",
+            0x0300 => "s3",
+            0x0002 => "s2",
+            _ => "00",
+        };
+        let destination = if self.server_name().is_some() {
+            'd'
+        } else {
+            'i'
+        };
+
+        let mut cipher_suites: Vec<String> = self
+            .cipher_suites
+            .iter()
+            .filter(|&&cipher_suite| !is_grease(cipher_suite))
+            .map(|cipher_suite| format!("{:04x}", cipher_suite))
+            .collect();
+        let extensions: Vec<u16> = self
+            .extensions
+            .iter()
+            .map(|extension| extension.extension_type)
+            .filter(|&extension_type| !is_grease(extension_type))
+            .collect();
+
+        let alpn = match self.alpn_protocols().first() {
+            Some(protocol) if !protocol.is_empty() => {
+                let first = protocol.chars().next().unwrap();
+                let last = protocol.chars().last().unwrap();
+                if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+                    format!("{}{}", first, last)
+                } else {
+                    let hex = to_hex(protocol.as_bytes());
+                    format!("{}{}", &hex[..1], &hex[hex.len() - 1..])
+                }
+            }
+            _ => "00".to_string(),
+        };
+
+        let a = format!(
+            "t{}{}{:02}{:02}{}",
+            version,
+            destination,
+            cipher_suites.len().min(99),
+            extensions.len().min(99),
+            alpn
+        );
+
+        cipher_suites.sort();
+        let b = if cipher_suites.is_empty() {
+            "000000000000".to_string()
+        } else {
+            truncated_sha256(&cipher_suites.join(","))
+        };
+
+        let mut sorted: Vec<String> = extensions
+            .iter()
+            .filter(|&&extension_type| {
+                extension_type != SERVER_NAME
+                    && extension_type != APPLICATION_LAYER_PROTOCOL_NEGOTIATION
+            })
+            .map(|extension_type| format!("{:04x}", extension_type))
+            .collect();
+        sorted.sort();
+        let signature_algorithms: Vec<String> = self
+            .signature_algorithms()
+            .iter()
+            .filter(|&&algorithm| !is_grease(algorithm))
+            .map(|algorithm| format!("{:04x}", algorithm))
+            .collect();
+        let c = if sorted.is_empty() {
+            "000000000000".to_string()
+        } else if signature_algorithms.is_empty() {
+            truncated_sha256(&sorted.join(","))
+        } else {
+            truncated_sha256(&format!(
+                "{}_{}",
+                sorted.join(","),
+                signature_algorithms.join(",")
+            ))
+        };
+
+        format!("{}_{}_{}", a, b, c)
+    }
+}
+
+impl fmt::Display for ClientHello {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}, {} cipher suites",
+            version_name(self.highest_version()),
+            self.cipher_suites.len()
+        )?;
+        if let Some(name) = self.server_name() {
+            write!(f, ", SNI {}", name)?;
+        }
+
+        let protocols = self.alpn_protocols();
+        if !protocols.is_empty() {
+            write!(f, ", ALPN {}", protocols.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The random a server sends in a HelloRetryRequest, which is otherwise
+/// sent as a ServerHello: SHA-256 of "HelloRetryRequest".
+const HELLO_RETRY_REQUEST_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ServerHello {
+    version: u16,
+    random: Vec<u8>,
+    session_id: Vec<u8>,
+    cipher_suite: u16,
+    compression_method: u8,
+    extensions: Vec<Extension>,
+}
+
+impl ServerHello {
+    fn parse(body: &[u8]) -> Option<ServerHello> {
+        let mut reader = Reader::new(body);
+        let version = reader.u16()?;
+        let random = reader.take(32)?.to_vec();
+        let session_id = reader.vector8()?.to_vec();
+        let cipher_suite = reader.u16()?;
+        let compression_method = reader.u8()?;
+        let extensions = parse_extensions(&mut reader)?;
+
+        Some(ServerHello {
+            version,
+            random,
+            session_id,
+            cipher_suite,
+            compression_method,
+            extensions,
+        })
+    }
+
+    /// The legacy version field, which stays at TLS 1.2 for TLS 1.3.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn random(&self) -> &[u8] {
+        &self.random
+    }
+
+    pub fn session_id(&self) -> &[u8] {
+        &self.session_id
+    }
+
+    pub fn cipher_suite(&self) -> u16 {
+        self.cipher_suite
+    }
+
+    pub fn compression_method(&self) -> u8 {
+        self.compression_method
+    }
+
+    pub fn extensions(&self) -> &[Extension] {
+        &self.extensions
+    }
+
+    /// Whether this is a TLS 1.3 HelloRetryRequest asking the client for
+    /// another ClientHello rather than a real ServerHello.
+    pub fn is_hello_retry_request(&self) -> bool {
+        self.random == HELLO_RETRY_REQUEST_RANDOM
+    }
+
+    /// The version chosen, from supported_versions when it's sent.
+    pub fn selected_version(&self) -> u16 {
+        find_extension(&self.extensions, SUPPORTED_VERSIONS)
+            .and_then(|data| Reader::new(data).u16())
+            .unwrap_or(self.version)
+    }
+
+    pub fn alpn_protocol(&self) -> Option<String> {
+        find_extension(&self.extensions, APPLICATION_LAYER_PROTOCOL_NEGOTIATION)
+            .map(parse_alpn)
+            .and_then(|protocols| protocols.into_iter().next())
+    }
+
+    /// The string hashed for the JA3S fingerprint: the version, cipher suite
+    /// and extensions.
+    pub fn ja3s_string(&self) -> String {
+        let extensions: Vec<u16> = self
+            .extensions
+            .iter()
+            .map(|extension| extension.extension_type)
+            .filter(|&extension_type| !is_grease(extension_type))
+            .collect();
+
+        format!(
+            "{},{},{}",
+            self.version,
+            self.cipher_suite,
+            join(&extensions, "-")
+        )
+    }
+
+    pub fn ja3s(&self) -> String {
+        md5_hex(&self.ja3s_string())
+    }
+}
+
+impl fmt::Display for ServerHello {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}, ", version_name(self.selected_version()))?;
+        match cipher_suite_name(self.cipher_suite) {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "cipher suite 0x{:04x}", self.cipher_suite)?,
+        }
+
+        if let Some(protocol) = self.alpn_protocol() {
+            write!(f, ", ALPN {}", protocol)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the certificate_list of a Certificate message. TLS 1.3 puts a
+/// request context before it and extensions after each certificate.
+fn parse_certificates(body: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let read = |body: &[u8], tls13: bool| -> Option<Vec<Vec<u8>>> {
+        let mut reader = Reader::new(body);
+        if tls13 {
+            reader.vector8()?;
+        }
+
+        let mut list = Reader::new(reader.vector24()?);
+        if !reader.is_empty() {
+            return None;
+        }
+
+        let mut certificates = Vec::new();
+        while !list.is_empty() {
+            certificates.push(list.vector24()?.to_vec());
+            if tls13 {
+                list.vector16()?;
+            }
+        }
+
+        Some(certificates)
+    };
+
+    read(body, false).or_else(|| read(body, true))
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Handshake {
+    ClientHello(ClientHello),
+    ServerHello(ServerHello),
+
+    /// The DER encoded certificates sent, starting with the sender's own.
+    Certificate(Vec<Vec<u8>>),
+
+    Other(HandshakeType, Vec<u8>),
+}
+
+impl Handshake {
+    /// Parses a handshake message, returning the number of bytes it took up,
+    /// or `None` if the input ends before the message does.
+    pub fn parse(input: &[u8]) -> Result<Option<(usize, Handshake)>, TlsError> {
+        let mut reader = Reader::new(input);
+        let (message_type, body) = match (reader.u8(), reader.vector24()) {
+            (Some(message_type), Some(body)) => (HandshakeType::from_code(message_type), body),
+            _ => return Ok(None),
+        };
+
+        let handshake = match message_type {
+            HandshakeType::ClientHello => ClientHello::parse(body).map(Handshake::ClientHello),
+            HandshakeType::ServerHello => ServerHello::parse(body).map(Handshake::ServerHello),
+            HandshakeType::Certificate => parse_certificates(body).map(Handshake::Certificate),
+            HandshakeType::Other(_) => None,
+            _ => Some(Handshake::Other(message_type, body.to_vec())),
+        }
+        .ok_or(TlsError::InvalidHandshake(message_type))?;
+
+        Ok(Some((4 + body.len(), handshake)))
+    }
+
+    pub fn handshake_type(&self) -> HandshakeType {
+        match self {
+            Handshake::ClientHello(_) => HandshakeType::ClientHello,
+            Handshake::ServerHello(_) => HandshakeType::ServerHello,
+            Handshake::Certificate(_) => HandshakeType::Certificate,
+            Handshake::Other(message_type, _) => *message_type,
+        }
+    }
+}
+
+impl fmt::Display for Handshake {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Handshake::ClientHello(hello) => write!(f, "ClientHello ({})", hello),
+            Handshake::ServerHello(hello) if hello.is_hello_retry_request() => {
+                write!(f, "HelloRetryRequest ({})", hello)
+            }
+            Handshake::ServerHello(hello) => write!(f, "ServerHello ({})", hello),
+            Handshake::Certificate(certificates) => {
+                write!(f, "Certificate ({} certificates)", certificates.len())
+            }
+            Handshake::Other(message_type, _) => write!(f, "{}", message_type),
+        }
+    }
+}
+
+/// The records sent in one direction of a session.
+#[derive(Debug, Default)]
+struct Side {
+    /// Bytes of a record which hasn't been read in full yet.
+    records: Vec<u8>,
+
+    /// Bytes of a handshake message split across records.
+    handshake: Vec<u8>,
+
+    /// Set once the side has switched to encrypting its records.
+    encrypted: bool,
+
+    /// Set when record boundaries have been lost.
+    broken: bool,
+}
+
+/// The cleartext handshake of a TLS connection and the encrypted records
+/// which follow it.
+#[derive(Debug, Default)]
+pub struct TlsSession {
+    client: Side,
+    server: Side,
+    client_hello: Option<ClientHello>,
+    server_hello: Option<ServerHello>,
+    hello_retry_request: Option<ServerHello>,
+    certificates: Vec<Vec<u8>>,
+    certificates_seen: Option<Duration>,
+    encrypted_records: Vec<(Direction, Duration, TlsRecord)>,
+}
+
+impl TlsSession {
+    pub fn new() -> TlsSession {
+        TlsSession::default()
+    }
+
+    pub fn client_hello(&self) -> Option<&ClientHello> {
+        self.client_hello.as_ref()
+    }
+
+    /// The ServerHello which chose the session's parameters, leaving out a
+    /// HelloRetryRequest sent before it.
+    pub fn server_hello(&self) -> Option<&ServerHello> {
+        self.server_hello.as_ref()
+    }
+
+    pub fn hello_retry_request(&self) -> Option<&ServerHello> {
+        self.hello_retry_request.as_ref()
+    }
+
+    /// The server's certificate chain, when it was sent in the clear.
+    pub fn certificates(&self) -> &[Vec<u8>] {
+        &self.certificates
+    }
+
+    /// When the end of the server's certificate chain was captured.
+    pub fn certificates_seen(&self) -> Option<Duration> {
+        self.certificates_seen
+    }
+
+    /// The records sent once each side started encrypting, in the order
+    /// they were sent, along with the time the end of each was captured.
+    pub fn encrypted_records(&self) -> &[(Direction, Duration, TlsRecord)] {
+        &self.encrypted_records
+    }
+
+    /// Adds bytes sent in `direction`, which must follow on from the last
+    /// bytes sent that way.
+    pub fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration) {
+        let side = match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        };
+        if side.broken {
+            return;
+        }
+
+        side.records.extend_from_slice(data);
+        let mut records = Vec::new();
+        loop {
+            match TlsRecord::parse(&side.records) {
+                Ok((rest, record)) => {
+                    let length = side.records.len() - rest.len();
+                    side.records.drain(..length);
+                    records.push(record);
+                }
+                Err(nom::Err::Incomplete(_)) => break,
+                Err(_) => {
+                    side.broken = true;
+                    side.records.clear();
+                    break;
+                }
+            }
+        }
+
+        for record in records {
+            self.read_record(direction, record, timestamp);
+        }
+    }
+
+    /// Notes that bytes sent in `direction` weren't captured, after which
+    /// records can't be found any more.
+    pub fn gap(&mut self, direction: Direction) {
+        let side = match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        };
+        side.broken = true;
+        side.records.clear();
+    }
+
+    fn read_record(&mut self, direction: Direction, record: TlsRecord, timestamp: Duration) {
+        let side = match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        };
+
+        // A TLS 1.3 client may start encrypting without a ChangeCipherSpec.
+        let encrypted = side.encrypted || record.content_type == ContentType::ApplicationData;
+        if encrypted && record.content_type != ContentType::ChangeCipherSpec {
+            self.encrypted_records.push((direction, timestamp, record));
+            return;
+        }
+
+        // After a HelloRetryRequest, TLS 1.3 peers may send a
+        // ChangeCipherSpec for middlebox compatibility while the handshake
+        // is still in the clear.
+        let retrying = self.hello_retry_request.is_some() && self.server_hello.is_none();
+
+        match record.content_type {
+            ContentType::ChangeCipherSpec => side.encrypted = !retrying,
+            ContentType::Handshake => side.handshake.extend_from_slice(&record.fragment),
+            _ => return,
+        }
+
+        let mut handshakes = Vec::new();
+        loop {
+            match Handshake::parse(&side.handshake) {
+                Ok(Some((length, handshake))) => {
+                    side.handshake.drain(..length);
+                    handshakes.push(handshake);
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    side.handshake.clear();
+                    break;
+                }
+            }
+        }
+
+        for handshake in handshakes {
+            match handshake {
+                Handshake::ClientHello(hello) => {
+                    if self.client_hello.is_none() {
+                        self.client_hello = Some(hello);
+                    }
+                }
+                Handshake::ServerHello(hello) if hello.is_hello_retry_request() => {
+                    if self.hello_retry_request.is_none() {
+                        self.hello_retry_request = Some(hello);
+                    }
+                }
+                Handshake::ServerHello(hello) => {
+                    // Everything after the ServerHello is encrypted in TLS 1.3.
+                    if hello.selected_version() == 0x0304 {
+                        self.server.encrypted = true;
+                    }
+                    if self.server_hello.is_none() {
+                        self.server_hello = Some(hello);
+                    }
+                }
+                Handshake::Certificate(certificates) => {
+                    if direction == Direction::ServerToClient && self.certificates.is_empty() {
+                        self.certificates = certificates;
+                        self.certificates_seen = Some(timestamp);
+                    }
+                }
+                Handshake::Other(..) => {}
+            }
+        }
+    }
+}
+
+impl StreamHandler for TlsSession {
+    fn detect(_ports: [u16; 2], data: &[u8]) -> Option<TlsSession> {
+        if looks_like_tls(data) {
+            Some(TlsSession::new())
+        } else {
+            None
+        }
+    }
+
+    fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration) {
+        TlsSession::push(self, direction, data, timestamp);
+    }
+
+    fn gap(&mut self, direction: Direction) {
+        TlsSession::gap(self, direction);
+    }
+
+    fn finish(&mut self) {}
+}
+
+/// Finds the TLS handshakes in every TCP connection.
+pub type TlsAnalysis = ReassembledStreams<TlsSession>;
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use flow::Direction;
+    use std::time::Duration;
+    use tls::{
+        is_grease, looks_like_tls, ContentType, Handshake, HandshakeType, TlsError, TlsRecord,
+        TlsSession,
+    };
+
+    const CLIENT_HELLO: &str =
+        "160301012e0100012a0303000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c\
+            1d1e1f20222222222222222222222222222222222222222222222222222222222222222200200a0a\
+            130113021303c02bc02fc02cc030cca9cca8c013c014009c009d002f0035010000c10a0a00000000\
+            0010000e00000b6578616d706c652e636f6d00170000ff01000100000a000a00082a2a001d001700\
+            18000b00020100002300000010000e000c02683208687474702f312e31000500050100000000000d\
+            001200100403080404010503080505010806060100120000003300260024001d0020111111111111\
+            1111111111111111111111111111111111111111111111111111002d00020101002b0007063a3a03\
+            040303001b00030200024469000000150004000000001a1a000100";
+    const SERVER_HELLO: &str =
+        "160303007a020000760303202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c\
+            3d3e3f20222222222222222222222222222222222222222222222222222222222222222213010000\
+            2e002b0002030400330024001d002033333333333333333333333333333333333333333333333333\
+            33333333333333";
+
+    #[test]
+    fn parse_client_hello() {
+        let raw = hex::decode(CLIENT_HELLO).unwrap();
+        let (rest, record) = TlsRecord::parse(&raw).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(record.content_type(), ContentType::Handshake);
+        assert_eq!(record.version(), 0x0301);
+
+        let hello = match record.handshakes().unwrap().pop() {
+            Some(Handshake::ClientHello(hello)) => hello,
+            other => panic!("expected a ClientHello, got {:?}", other),
+        };
+        assert_eq!(hello.version(), 0x0303);
+        assert_eq!(hello.cipher_suites().len(), 16);
+        assert_eq!(hello.extensions().len(), 18);
+        assert_eq!(hello.server_name(), Some("example.com".to_string()));
+        assert_eq!(hello.alpn_protocols(), vec!["h2", "http/1.1"]);
+        assert_eq!(hello.supported_versions(), vec![0x3a3a, 0x0304, 0x0303]);
+        assert_eq!(hello.highest_version(), 0x0304);
+        assert_eq!(
+            hello.supported_groups(),
+            vec![0x2a2a, 0x001d, 0x0017, 0x0018]
+        );
+        assert_eq!(hello.ec_point_formats(), vec![0]);
+        assert_eq!(
+            hello.ja3_string(),
+            "771,4865-4866-4867-49195-49199-49196-49200-52393-52392-49171-49172-156-157-47-53,\
+             0-23-65281-10-11-35-16-5-13-18-51-45-43-27-17513-21,29-23-24,0"
+        );
+        assert_eq!(hello.ja3(), "cd08e31494f9531f560d64c695473da9");
+        assert_eq!(hello.ja4(), "t13d1516h2_8daaf6152771_e5627efa2ab1");
+        assert_eq!(
+            record.to_string(),
+            "Handshake (TLS 1.0, 302 bytes) ClientHello (TLS 1.3, 16 cipher suites, \
+             SNI example.com, ALPN h2,http/1.1)"
+        );
+    }
+
+    #[test]
+    fn parse_server_hello() {
+        let raw = hex::decode(SERVER_HELLO).unwrap();
+        let records = TlsRecord::parse_segment(&raw);
+        let hello = match records[0].handshakes().unwrap().pop() {
+            Some(Handshake::ServerHello(hello)) => hello,
+            other => panic!("expected a ServerHello, got {:?}", other),
+        };
+        assert_eq!(hello.version(), 0x0303);
+        assert_eq!(hello.selected_version(), 0x0304);
+        assert_eq!(hello.cipher_suite(), 0x1301);
+        assert_eq!(hello.alpn_protocol(), None);
+        assert_eq!(hello.ja3s_string(), "771,4865,43-51");
+        assert_eq!(hello.ja3s(), "f4febc55ea12b31ae17cfb7e614afda8");
+        assert_eq!(hello.to_string(), "TLS 1.3, TLS_AES_128_GCM_SHA256");
+    }
+
+    #[test]
+    fn parse_segment() {
+        // Application data followed by the start of a record which
+        // continues in the next segment.
+        let raw = hex::decode("17030300020102160303010000").unwrap();
+        let records = TlsRecord::parse_segment(&raw);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].content_type(), ContentType::ApplicationData);
+        assert!(records[0].is_complete());
+        assert_eq!(records[1].length(), 256);
+        assert_eq!(records[1].fragment(), &[0u8][..]);
+        assert!(!records[1].is_complete());
+
+        assert!(looks_like_tls(&raw));
+        assert!(!looks_like_tls(b"GET / HTTP/1.1\r\n"));
+        assert!(is_grease(0x0a0a));
+        assert!(is_grease(0xfafa));
+        assert!(!is_grease(0x0a1a));
+    }
+
+    #[test]
+    fn invalid_handshake() {
+        assert_eq!(
+            Handshake::parse(&[2, 0, 0, 2, 3, 3]),
+            Err(TlsError::InvalidHandshake(HandshakeType::ServerHello))
+        );
+        assert_eq!(Handshake::parse(&[2, 0, 0, 2, 3]), Ok(None));
+    }
+
+    #[test]
+    fn session() {
+        let client_hello = hex::decode(CLIENT_HELLO).unwrap();
+        let server_hello = hex::decode(
+            "160303002a020000260303000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c\
+             1d1e1f00c02f00",
+        )
+        .unwrap();
+
+        // A TLS 1.2 style Certificate message split across two records.
+        let certificate = hex::decode("0b00001300001000000530030201010000053003020102").unwrap();
+        let mut server = server_hello.clone();
+        server.extend_from_slice(&[0x16, 0x03, 0x03, 0x00, 0x08]);
+        server.extend_from_slice(&certificate[..8]);
+        server.extend_from_slice(&[0x16, 0x03, 0x03, 0x00, certificate.len() as u8 - 8]);
+        server.extend_from_slice(&certificate[8..]);
+
+        // The encrypted Finished after ChangeCipherSpec is kept to decrypt.
+        server.extend_from_slice(&hex::decode("1403030001011603030004ffffffff").unwrap());
+
+        let at = Duration::from_secs(1);
+        let mut session = TlsSession::new();
+        session.push(Direction::ClientToServer, &client_hello[..100], at);
+        assert!(session.client_hello().is_none());
+        session.push(Direction::ClientToServer, &client_hello[100..], at);
+        session.push(Direction::ServerToClient, &server, at);
+
+        assert_eq!(
+            session.client_hello().unwrap().server_name(),
+            Some("example.com".to_string())
+        );
+        assert_eq!(session.server_hello().unwrap().cipher_suite(), 0xc02f);
+        assert_eq!(
+            session.certificates(),
+            &[
+                hex::decode("3003020101").unwrap(),
+                hex::decode("3003020102").unwrap()
+            ][..]
+        );
+        assert_eq!(session.certificates_seen(), Some(at));
+
+        let encrypted = session.encrypted_records();
+        assert_eq!(encrypted.len(), 1);
+        assert_eq!(encrypted[0].0, Direction::ServerToClient);
+        assert_eq!(encrypted[0].1, at);
+        assert_eq!(encrypted[0].2.content_type(), ContentType::Handshake);
+        assert_eq!(encrypted[0].2.fragment(), &[0xff; 4][..]);
+    }
+
+    #[test]
+    fn hello_retry_request() {
+        let client_hello = hex::decode(CLIENT_HELLO).unwrap();
+        let hello_retry_request = hex::decode(
+            "1603030058020000540303cf21ad74e59a6111be1d8c021e65b891c2a211167abb8c5e079e09e2\
+             c8a8339c202222222222222222222222222222222222222222222222222222222222222222130100\
+             000c002b0002030400330002001d",
+        )
+        .unwrap();
+        let compatibility = hex::decode("140303000101").unwrap();
+
+        let at = Duration::from_secs(1);
+        let mut session = TlsSession::new();
+        session.push(Direction::ClientToServer, &client_hello, at);
+        session.push(Direction::ServerToClient, &hello_retry_request, at);
+        session.push(Direction::ServerToClient, &compatibility, at);
+        session.push(Direction::ClientToServer, &compatibility, at);
+        session.push(Direction::ClientToServer, &client_hello, at);
+        assert!(session.server_hello().is_none());
+
+        let mut server = hex::decode(SERVER_HELLO).unwrap();
+        server.extend_from_slice(&hex::decode("1703030004ffffffff").unwrap());
+        session.push(Direction::ServerToClient, &server, at);
+
+        let retry = session.hello_retry_request().unwrap();
+        assert!(retry.is_hello_retry_request());
+        let records = TlsRecord::parse_segment(&hello_retry_request);
+        assert_eq!(
+            records[0].to_string(),
+            "Handshake (TLS 1.2, 88 bytes) HelloRetryRequest (TLS 1.3, TLS_AES_128_GCM_SHA256)"
+        );
+
+        let hello = session.server_hello().unwrap();
+        assert!(!hello.is_hello_retry_request());
+        assert_eq!(hello.random()[0], 0x20);
+        assert_eq!(hello.ja3s(), "f4febc55ea12b31ae17cfb7e614afda8");
+
+        // Neither the second ClientHello nor the real ServerHello are taken
+        // for encrypted records.
+        let encrypted = session.encrypted_records();
+        assert_eq!(encrypted.len(), 1);
+        assert_eq!(encrypted[0].0, Direction::ServerToClient);
+        assert_eq!(encrypted[0].2.fragment(), &[0xff; 4][..]);
+    }
+}
@@ -0,0 +1,392 @@
+use expert::{Annotation, ExpertAnalysis};
+use flow::{Direction, Numbering, SequenceNumbers, TcpSegment};
+use serde_json;
+use serde_json::Value;
+use std::io;
+use std::io::Write;
+use std::time::Duration;
+use tcp::TcpOption;
+
+/// The largest shift allowed by RFC 7323.
+const MAXIMUM_WINDOW_SHIFT: u8 = 14;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TraceEvent {
+    /// Sequence numbers covered by a segment sent for the first time.
+    Segment,
+
+    /// Sequence numbers covered by a segment sent again.
+    Retransmission,
+
+    /// The receiver's cumulative acknowledgment.
+    Ack,
+
+    /// The right edge of the receiver's window, scaled by the window scale
+    /// option.
+    Window,
+
+    /// A block the receiver selectively acknowledged.
+    Sack,
+}
+
+impl TraceEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TraceEvent::Segment => "segment",
+            TraceEvent::Retransmission => "retransmission",
+            TraceEvent::Ack => "ack",
+            TraceEvent::Window => "window",
+            TraceEvent::Sack => "sack",
+        }
+    }
+}
+
+/// One point of a time sequence graph, in the style of tcptrace. Points are
+/// grouped by the direction data flows in, so the ACKs, windows and SACKs
+/// sent by the receiver go with the segments they answer. Sequence numbers
+/// are in the numbering the recorder was made with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TracePoint {
+    stream: usize,
+    direction: Direction,
+
+    /// Time since the first packet recorded.
+    time: Duration,
+    event: TraceEvent,
+    start: u32,
+    end: u32,
+}
+
+impl TracePoint {
+    pub fn stream(&self) -> usize {
+        self.stream
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    pub fn event(&self) -> TraceEvent {
+        self.event
+    }
+
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+}
+
+/// Collects the time sequence graph of every TCP connection.
+#[derive(Debug)]
+pub struct TraceRecorder {
+    start: Option<Duration>,
+    numbers: SequenceNumbers,
+    expert: ExpertAnalysis,
+
+    /// Window scale option sent in each direction's SYN, indexed by stream.
+    window_scales: Vec<[Option<u8>; 2]>,
+
+    points: Vec<TracePoint>,
+}
+
+impl TraceRecorder {
+    pub fn new(numbering: Numbering) -> TraceRecorder {
+        TraceRecorder {
+            start: None,
+            numbers: SequenceNumbers::new(numbering),
+            expert: ExpertAnalysis::new(),
+            window_scales: Vec::new(),
+            points: Vec::new(),
+        }
+    }
+
+    /// Records a segment of the connection numbered `stream`. Segments have
+    /// to be passed in the order they were captured.
+    pub fn record(&mut self, stream: usize, direction: Direction, segment: &TcpSegment) {
+        let header = segment.header();
+        let time = segment
+            .timestamp()
+            .checked_sub(*self.start.get_or_insert(segment.timestamp()))
+            .unwrap_or_default();
+
+        if stream >= self.window_scales.len() {
+            self.window_scales.resize(stream + 1, [None, None]);
+        }
+
+        let (side, other) = match direction {
+            Direction::ClientToServer => (0, 1),
+            Direction::ServerToClient => (1, 0),
+        };
+        if header.synchronize() {
+            self.window_scales[stream][side] = header.window_scale();
+        }
+
+        let (sequence, acknowledgment) = self.numbers.numbers(stream, direction, segment);
+        let annotations = self.expert.analyze(stream, direction, segment);
+
+        if segment.sequence_length() > 0 {
+            let retransmission = annotations.iter().any(|annotation| {
+                *annotation == Annotation::Retransmission
+                    || *annotation == Annotation::FastRetransmission
+            });
+
+            self.points.push(TracePoint {
+                stream,
+                direction,
+                time,
+                event: if retransmission {
+                    TraceEvent::Retransmission
+                } else {
+                    TraceEvent::Segment
+                },
+                start: sequence,
+                end: sequence.wrapping_add(segment.sequence_length()),
+            });
+        }
+
+        if !header.acknowledgment() {
+            return;
+        }
+
+        // Windows in SYN segments are never scaled.
+        let scales = self.window_scales[stream];
+        let shift = match (header.synchronize(), scales[side], scales[other]) {
+            (false, Some(shift), Some(_)) => shift.min(MAXIMUM_WINDOW_SHIFT),
+            _ => 0,
+        };
+        let window = u32::from(header.window_size()) << shift;
+
+        let data_direction = direction.reverse();
+        let mut point = |event, start, end| {
+            self.points.push(TracePoint {
+                stream,
+                direction: data_direction,
+                time,
+                event,
+                start,
+                end,
+            })
+        };
+
+        point(TraceEvent::Ack, acknowledgment, acknowledgment);
+        let edge = acknowledgment.wrapping_add(window);
+        point(TraceEvent::Window, edge, edge);
+
+        let base = header.acknowledgment_number().wrapping_sub(acknowledgment);
+        for option in header.options() {
+            if let TcpOption::Sack(blocks) = option {
+                for (left, right) in blocks {
+                    point(
+                        TraceEvent::Sack,
+                        left.wrapping_sub(base),
+                        right.wrapping_sub(base),
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn points(&self) -> &[TracePoint] {
+        &self.points
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TraceFormat {
+    Csv,
+    Json,
+}
+
+impl TraceFormat {
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name {
+            "csv" => Some(TraceFormat::Csv),
+            "json" => Some(TraceFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::ClientToServer => "client-to-server",
+        Direction::ServerToClient => "server-to-client",
+    }
+}
+
+/// Writes points as CSV with a header row, or as a JSON array of objects
+/// with the same fields. Times are in seconds.
+pub fn write_points<W: Write>(
+    output: &mut W,
+    points: &[TracePoint],
+    format: TraceFormat,
+) -> io::Result<()> {
+    match format {
+        TraceFormat::Csv => {
+            writeln!(output, "stream,direction,time,event,start,end")?;
+            for point in points {
+                writeln!(
+                    output,
+                    "{},{},{}.{:06},{},{},{}",
+                    point.stream,
+                    direction_name(point.direction),
+                    point.time.as_secs(),
+                    point.time.subsec_micros(),
+                    point.event.name(),
+                    point.start,
+                    point.end
+                )?;
+            }
+        }
+        TraceFormat::Json => {
+            let points: Vec<Value> = points
+                .iter()
+                .map(|point| {
+                    json!({
+                        "stream": point.stream,
+                        "direction": direction_name(point.direction),
+                        "time": point.time.as_secs_f64(),
+                        "event": point.event.name(),
+                        "start": point.start,
+                        "end": point.end,
+                    })
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut *output, &points)?;
+            writeln!(output)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use flow::tests::{ip_header, tcp_header_with_options};
+    use flow::{Direction, FlowTable, Numbering, TcpSegment};
+    use serde_json;
+    use serde_json::Value;
+    use std::time::Duration;
+    use trace::{write_points, TraceEvent, TraceFormat, TraceRecorder};
+
+    /// Milliseconds, from client, sequence, acknowledgment, flags, window,
+    /// options and payload length.
+    type Packet<'a> = (u64, bool, u32, u32, u8, u16, &'a [u8], usize);
+
+    #[test]
+    fn record() {
+        let wscale_7 = [0x01, 0x03, 0x03, 0x07];
+        let wscale_2 = [0x01, 0x03, 0x03, 0x02];
+        let sack = [0x01, 0x01, 0x05, 0x0a, 0, 0, 0x01, 0xa5, 0, 0, 0x01, 0xaf];
+
+        let packets: [Packet; 7] = [
+            (0, true, 100, 0, 0x02, 1000, &wscale_7, 0),
+            (10, false, 400, 101, 0x12, 1000, &wscale_2, 0),
+            (20, true, 101, 401, 0x10, 100, &[], 0),
+            (30, false, 401, 101, 0x18, 8, &[], 10),
+            (31, false, 421, 101, 0x18, 8, &[], 10),
+            (40, true, 101, 411, 0x10, 100, &sack, 0),
+            (300, false, 411, 101, 0x18, 8, &[], 10),
+        ];
+
+        let payload = [0u8; 10];
+        let record = |numbering| {
+            let mut flows = FlowTable::new();
+            let mut recorder = TraceRecorder::new(numbering);
+            for &(millis, from_client, sequence, acknowledgment, flags, window, options, length) in
+                packets.iter()
+            {
+                let ip = ip_header(from_client);
+                let (source, destination) = if from_client {
+                    (36869, 23)
+                } else {
+                    (23, 36869)
+                };
+                let header = tcp_header_with_options(
+                    source,
+                    destination,
+                    sequence,
+                    acknowledgment,
+                    flags,
+                    window,
+                    options,
+                );
+                let segment = TcpSegment::new(
+                    Duration::from_millis(millis),
+                    &ip,
+                    &header,
+                    &payload[..length],
+                );
+                let (stream, direction) = flows.lookup(&segment);
+                recorder.record(stream, direction, &segment);
+            }
+            recorder
+        };
+        let from_server = |recorder: &TraceRecorder| -> Vec<_> {
+            recorder
+                .points()
+                .iter()
+                .filter(|point| point.direction() == Direction::ServerToClient)
+                .map(|point| {
+                    (
+                        point.time().as_millis(),
+                        point.event(),
+                        point.start(),
+                        point.end(),
+                    )
+                })
+                .collect()
+        };
+
+        let recorder = record(Numbering::Relative);
+        let server = from_server(&recorder);
+
+        assert_eq!(
+            server,
+            vec![
+                (10, TraceEvent::Segment, 0, 1),
+                (20, TraceEvent::Ack, 1, 1),
+                (20, TraceEvent::Window, 12801, 12801),
+                (30, TraceEvent::Segment, 1, 11),
+                (31, TraceEvent::Segment, 21, 31),
+                (40, TraceEvent::Ack, 11, 11),
+                (40, TraceEvent::Window, 12811, 12811),
+                (40, TraceEvent::Sack, 21, 31),
+                (300, TraceEvent::Retransmission, 11, 21),
+            ]
+        );
+
+        let server = from_server(&record(Numbering::Absolute));
+        assert_eq!(server[0], (10, TraceEvent::Segment, 400, 401));
+        assert_eq!(server[7], (40, TraceEvent::Sack, 421, 431));
+
+        let mut csv = Vec::new();
+        write_points(&mut csv, &recorder.points()[..1], TraceFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "stream,direction,time,event,start,end\n\
+             0,client-to-server,0.000000,segment,0,1\n"
+        );
+
+        let mut json = Vec::new();
+        write_points(&mut json, &recorder.points()[..1], TraceFormat::Json).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&json).unwrap(),
+            json!([{
+                "stream": 0,
+                "direction": "client-to-server",
+                "time": 0.0,
+                "event": "segment",
+                "start": 0,
+                "end": 1,
+            }])
+        );
+    }
+}
@@ -1,4 +1,6 @@
-use nom::{be_u16, IResult};
+use nom::{be_u16, rest, IResult};
+use std::fmt;
+use std::fmt::Formatter;
 
 #[derive(Eq, PartialEq, Debug)]
 pub struct UdpPacket {
@@ -10,17 +12,35 @@ pub struct UdpPacket {
 }
 
 impl UdpPacket {
+    /// Parses a UDP packet. When the capture was cut short by the snapshot
+    /// length, `data` holds whatever part of the payload was captured.
     pub fn parse(input: &[u8]) -> IResult<&[u8], UdpPacket> {
         parse_udp_packet(input)
     }
+
+    pub fn checksum(&self) -> u16 {
+        self.checksum
+    }
+}
+
+impl fmt::Display for UdpPacket {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} Len={}",
+            self.source_port,
+            self.destination_port,
+            self.data.len()
+        )
+    }
 }
 
 named!(pub parse_udp_packet<UdpPacket>, do_parse!(
     source_port:      be_u16 >>
     destination_port: be_u16 >>
-    length:           be_u16 >>
+    length:           verify!(be_u16, |length| length >= 8) >>
     checksum:         be_u16 >>
-    data:             take!(length - 8) >>
+    data:             alt_complete!(take!(length - 8) | rest) >>
     (UdpPacket {
         source_port,
         destination_port,
@@ -51,4 +71,11 @@ mod tests {
             },
         )
     }
+
+    #[test]
+    fn parse_truncated() {
+        let raw = hex::decode("99d0043f0012722868656c6c6f68").unwrap();
+        let (_, packet) = UdpPacket::parse(&raw).unwrap();
+        assert_eq!(packet.data, hex::decode("68656c6c6f68").unwrap());
+    }
 }
@@ -0,0 +1,514 @@
+use flow::Direction;
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+
+/// Control frames can't be fragmented or carry more than this.
+const MAXIMUM_CONTROL_PAYLOAD: u64 = 125;
+
+#[derive(Fail, Debug, Eq, PartialEq)]
+pub enum WebSocketError {
+    #[fail(display = "Invalid WebSocket {} frame", _0)]
+    InvalidFrame(Opcode),
+}
+
+/// The name of a status code sent in a Close frame.
+pub fn close_code_name(code: u16) -> String {
+    let name = match code {
+        1000 => "Normal Closure",
+        1001 => "Going Away",
+        1002 => "Protocol Error",
+        1003 => "Unsupported Data",
+        1005 => "No Status Received",
+        1006 => "Abnormal Closure",
+        1007 => "Invalid Frame Payload Data",
+        1008 => "Policy Violation",
+        1009 => "Message Too Big",
+        1010 => "Mandatory Extension",
+        1011 => "Internal Error",
+        1012 => "Service Restart",
+        1013 => "Try Again Later",
+        1014 => "Bad Gateway",
+        1015 => "TLS Handshake",
+        _ => return code.to_string(),
+    };
+
+    format!("{} {}", code, name)
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+    Other(u8),
+}
+
+impl Opcode {
+    pub fn from_code(code: u8) -> Opcode {
+        match code {
+            0x0 => Opcode::Continuation,
+            0x1 => Opcode::Text,
+            0x2 => Opcode::Binary,
+            0x8 => Opcode::Close,
+            0x9 => Opcode::Ping,
+            0xa => Opcode::Pong,
+            other => Opcode::Other(other),
+        }
+    }
+
+    /// Whether this is a Close, Ping or Pong frame, or a reserved control
+    /// opcode.
+    pub fn is_control(self) -> bool {
+        match self {
+            Opcode::Close | Opcode::Ping | Opcode::Pong => true,
+            Opcode::Other(code) => code & 0x8 != 0,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Opcode::Continuation => "Continuation",
+            Opcode::Text => "Text",
+            Opcode::Binary => "Binary",
+            Opcode::Close => "Close",
+            Opcode::Ping => "Ping",
+            Opcode::Pong => "Pong",
+            Opcode::Other(code) => return write!(f, "Opcode {:#x}", code),
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct WebSocketFrame {
+    fin: bool,
+    rsv: u8,
+    opcode: Opcode,
+    mask: Option<[u8; 4]>,
+
+    /// The payload, already unmasked.
+    payload: Vec<u8>,
+}
+
+impl WebSocketFrame {
+    /// Parses the frame at the start of `input`, returning how many bytes it
+    /// took up. `None` means more bytes are needed.
+    pub fn parse(input: &[u8]) -> Result<Option<(usize, WebSocketFrame)>, WebSocketError> {
+        let (first, second) = match input {
+            [first, second, ..] => (*first, *second),
+            _ => return Ok(None),
+        };
+        let opcode = Opcode::from_code(first & 0x0f);
+        let fin = first & 0x80 != 0;
+        let masked = second & 0x80 != 0;
+
+        let (length, mut offset) = match second & 0x7f {
+            126 => match input.get(2..4) {
+                Some(bytes) => (u64::from(u16::from_be_bytes([bytes[0], bytes[1]])), 4),
+                None => return Ok(None),
+            },
+            127 => match input.get(2..10) {
+                Some(bytes) => {
+                    let mut length = [0; 8];
+                    length.copy_from_slice(bytes);
+                    (u64::from_be_bytes(length), 10)
+                }
+                None => return Ok(None),
+            },
+            length => (u64::from(length), 2),
+        };
+
+        // The most significant bit of a 64 bit length has to be zero.
+        let too_long = length >> 63 != 0;
+        let invalid_control = opcode.is_control() && (!fin || length > MAXIMUM_CONTROL_PAYLOAD);
+        if too_long || invalid_control {
+            return Err(WebSocketError::InvalidFrame(opcode));
+        }
+
+        let mask = if masked {
+            let mut mask = [0; 4];
+            match input.get(offset..offset + 4) {
+                Some(bytes) => mask.copy_from_slice(bytes),
+                None => return Ok(None),
+            }
+            offset += 4;
+            Some(mask)
+        } else {
+            None
+        };
+
+        let end = match offset.checked_add(length as usize) {
+            Some(end) if end <= input.len() => end,
+            _ => return Ok(None),
+        };
+        let mut payload = input[offset..end].to_vec();
+        if let Some(mask) = mask {
+            for (index, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[index % 4];
+            }
+        }
+
+        Ok(Some((
+            end,
+            WebSocketFrame {
+                fin,
+                rsv: first >> 4 & 0x7,
+                opcode,
+                mask,
+                payload,
+            },
+        )))
+    }
+
+    /// Whether this is the last frame of a message.
+    pub fn fin(&self) -> bool {
+        self.fin
+    }
+
+    /// The reserved bits, which extensions like permessage-deflate use.
+    pub fn rsv(&self) -> u8 {
+        self.rsv
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
+    }
+
+    /// The masking key, which clients have to use for every frame.
+    pub fn mask(&self) -> Option<[u8; 4]> {
+        self.mask
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// The status code of a Close frame, when it has one.
+    pub fn close_code(&self) -> Option<u16> {
+        match (self.opcode, self.payload.get(..2)) {
+            (Opcode::Close, Some(code)) => Some(u16::from_be_bytes([code[0], code[1]])),
+            _ => None,
+        }
+    }
+
+    /// The text after the status code of a Close frame.
+    pub fn close_reason(&self) -> Option<String> {
+        match (self.opcode, self.payload.get(2..)) {
+            (Opcode::Close, Some(reason)) => Some(String::from_utf8_lossy(reason).into_owned()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WebSocketFrame {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode)?;
+        if self.fin {
+            write!(f, " FIN")?;
+        }
+        if self.mask.is_some() {
+            write!(f, " masked")?;
+        }
+        write!(f, ", {} bytes", self.payload.len())?;
+
+        match self.opcode {
+            Opcode::Text if self.fin && self.rsv == 0 => {
+                write!(f, ": {:?}", String::from_utf8_lossy(&self.payload))?
+            }
+            Opcode::Close => {
+                if let Some(code) = self.close_code() {
+                    write!(f, ", {}", close_code_name(code))?;
+                }
+                match self.close_reason() {
+                    Some(ref reason) if !reason.is_empty() => write!(f, " {:?}", reason)?,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// A Text or Binary message put back together from its frames.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct WebSocketMessage {
+    direction: Direction,
+    opcode: Opcode,
+    data: Vec<u8>,
+
+    /// Whether the final frame was seen.
+    complete: bool,
+
+    first_seen: Duration,
+    last_seen: Duration,
+}
+
+impl WebSocketMessage {
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The data of a Text message, when it is valid UTF-8.
+    pub fn text(&self) -> Option<&str> {
+        match self.opcode {
+            Opcode::Text => std::str::from_utf8(&self.data).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn first_seen(&self) -> Duration {
+        self.first_seen
+    }
+
+    pub fn last_seen(&self) -> Duration {
+        self.last_seen
+    }
+}
+
+impl fmt::Display for WebSocketMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.text() {
+            Some(text) => write!(f, "{} {:?}", self.opcode, text)?,
+            None => write!(f, "{}, {} bytes", self.opcode, self.data.len())?,
+        }
+
+        if !self.complete {
+            write!(f, " (incomplete)")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Where one side of the connection is up to.
+#[derive(Debug, Default)]
+struct Side {
+    buffer: Vec<u8>,
+
+    /// Whether bytes went missing or a frame couldn't be parsed.
+    broken: bool,
+
+    /// A message whose final frame hasn't been seen.
+    message: Option<WebSocketMessage>,
+}
+
+/// Reads the frames sent on a connection after an upgrade to WebSocket.
+#[derive(Debug, Default)]
+pub struct WebSocketConnection {
+    client: Side,
+    server: Side,
+    frames: Vec<(Direction, Duration, WebSocketFrame)>,
+    messages: Vec<WebSocketMessage>,
+}
+
+impl WebSocketConnection {
+    pub fn new() -> WebSocketConnection {
+        WebSocketConnection::default()
+    }
+
+    /// Every frame, in the order it was sent, along with the time the end of
+    /// each was captured.
+    pub fn frames(&self) -> &[(Direction, Duration, WebSocketFrame)] {
+        &self.frames
+    }
+
+    /// The Text and Binary messages, in the order they were finished.
+    pub fn messages(&self) -> &[WebSocketMessage] {
+        &self.messages
+    }
+
+    /// Adds bytes sent in `direction`, which must follow on from the last
+    /// bytes sent that way.
+    pub fn push(&mut self, direction: Direction, data: &[u8], timestamp: Duration) {
+        let side = match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        };
+        if side.broken {
+            return;
+        }
+        side.buffer.extend_from_slice(data);
+
+        loop {
+            let frame = match WebSocketFrame::parse(&side.buffer) {
+                Ok(Some((length, frame))) => {
+                    side.buffer.drain(..length);
+                    frame
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    side.broken = true;
+                    side.buffer.clear();
+                    break;
+                }
+            };
+
+            if !frame.opcode.is_control() {
+                if frame.opcode != Opcode::Continuation {
+                    // A new message ends one which never got its final frame.
+                    self.messages.extend(side.message.take());
+                    side.message = Some(WebSocketMessage {
+                        direction,
+                        opcode: frame.opcode,
+                        data: Vec::new(),
+                        complete: false,
+                        first_seen: timestamp,
+                        last_seen: timestamp,
+                    });
+                }
+
+                if let Some(message) = side.message.as_mut() {
+                    message.data.extend_from_slice(&frame.payload);
+                    message.last_seen = timestamp;
+                    message.complete = frame.fin;
+                }
+                if frame.fin {
+                    self.messages.extend(side.message.take());
+                }
+            }
+
+            self.frames.push((direction, timestamp, frame));
+        }
+    }
+
+    /// Notes that bytes sent in `direction` weren't captured.
+    pub fn gap(&mut self, direction: Direction) {
+        let side = match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        };
+        side.broken = true;
+        side.buffer.clear();
+    }
+
+    /// Ends both directions, keeping any messages cut short.
+    pub fn finish(&mut self) {
+        self.messages.extend(self.client.message.take());
+        self.messages.extend(self.server.message.take());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use flow::Direction;
+    use std::time::Duration;
+    use websocket::{Opcode, WebSocketConnection, WebSocketError, WebSocketFrame};
+
+    /// The examples from RFC 6455 section 5.7.
+    #[test]
+    fn parse() {
+        let raw = hex::decode("810548656c6c6f").unwrap();
+        let (length, frame) = WebSocketFrame::parse(&raw).unwrap().unwrap();
+        assert_eq!(length, 7);
+        assert_eq!(frame.opcode(), Opcode::Text);
+        assert!(frame.fin());
+        assert_eq!(frame.mask(), None);
+        assert_eq!(frame.payload(), b"Hello");
+        assert_eq!(frame.to_string(), "Text FIN, 5 bytes: \"Hello\"");
+
+        let raw = hex::decode("818537fa213d7f9f4d5158").unwrap();
+        let (_, frame) = WebSocketFrame::parse(&raw).unwrap().unwrap();
+        assert_eq!(frame.mask(), Some([0x37, 0xfa, 0x21, 0x3d]));
+        assert_eq!(frame.payload(), b"Hello");
+        assert_eq!(frame.to_string(), "Text FIN masked, 5 bytes: \"Hello\"");
+
+        let raw = hex::decode("010348656c").unwrap();
+        let (_, frame) = WebSocketFrame::parse(&raw).unwrap().unwrap();
+        assert!(!frame.fin());
+        assert_eq!(frame.to_string(), "Text, 3 bytes");
+
+        let mut raw = hex::decode("827e0100").unwrap();
+        assert_eq!(WebSocketFrame::parse(&raw), Ok(None));
+        raw.extend_from_slice(&[0; 256]);
+        let (length, frame) = WebSocketFrame::parse(&raw).unwrap().unwrap();
+        assert_eq!(length, 260);
+        assert_eq!(frame.to_string(), "Binary FIN, 256 bytes");
+
+        let raw = hex::decode("880603e9676f6e65").unwrap();
+        let (_, frame) = WebSocketFrame::parse(&raw).unwrap().unwrap();
+        assert_eq!(frame.close_code(), Some(1001));
+        assert_eq!(frame.close_reason(), Some("gone".to_string()));
+        assert_eq!(
+            frame.to_string(),
+            "Close FIN, 6 bytes, 1001 Going Away \"gone\""
+        );
+
+        // Control frames can't be fragmented.
+        assert_eq!(
+            WebSocketFrame::parse(&hex::decode("0900").unwrap()),
+            Err(WebSocketError::InvalidFrame(Opcode::Ping))
+        );
+    }
+
+    #[test]
+    fn connection() {
+        let at = Duration::from_secs;
+        let mut connection = WebSocketConnection::new();
+
+        // A fragmented message with a Ping between its frames.
+        let client = hex::decode("01830102030449676f8980010203048082010203046d6d").unwrap();
+        connection.push(Direction::ClientToServer, &client[..10], at(1));
+        connection.push(Direction::ClientToServer, &client[10..], at(2));
+        connection.push(
+            Direction::ServerToClient,
+            &hex::decode("8a0401020304").unwrap(),
+            at(3),
+        );
+        connection.push(
+            Direction::ServerToClient,
+            &hex::decode("0203ffffff").unwrap(),
+            at(4),
+        );
+        connection.finish();
+
+        let opcodes: Vec<Opcode> = connection
+            .frames()
+            .iter()
+            .map(|(_, _, frame)| frame.opcode())
+            .collect();
+        assert_eq!(
+            opcodes,
+            [
+                Opcode::Text,
+                Opcode::Ping,
+                Opcode::Continuation,
+                Opcode::Pong,
+                Opcode::Binary
+            ]
+        );
+
+        let messages = connection.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].direction(), Direction::ClientToServer);
+        assert_eq!(messages[0].text(), Some("Hello"));
+        assert_eq!(messages[0].first_seen(), at(1));
+        assert_eq!(messages[0].last_seen(), at(2));
+        assert_eq!(messages[0].to_string(), "Text \"Hello\"");
+        assert!(!messages[1].is_complete());
+        assert_eq!(messages[1].to_string(), "Binary, 3 bytes (incomplete)");
+    }
+}
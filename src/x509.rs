@@ -0,0 +1,775 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flow::Endpoint;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Formatter;
+use std::io;
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use util::{days_from_civil, format_timestamp, to_hex};
+
+/// Certificates expiring within this long of being seen are reported.
+pub const EXPIRY_WARNING: Duration = Duration::from_secs(30 * 86_400);
+
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const VERSION: u8 = 0xa0;
+const EXTENSIONS: u8 = 0xa3;
+const DNS_NAME: u8 = 0x82;
+const IP_ADDRESS: u8 = 0x87;
+
+const SUBJECT_ALT_NAME: &str = "2.5.29.17";
+
+#[derive(Fail, Debug, Eq, PartialEq)]
+pub enum X509Error {
+    #[fail(display = "Invalid X.509 certificate")]
+    InvalidCertificate,
+}
+
+/// Reads the tag, length and value triples of DER.
+struct DerReader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> DerReader<'a> {
+    fn new(input: &'a [u8]) -> DerReader<'a> {
+        DerReader { input }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    fn peek_tag(&self) -> Option<u8> {
+        self.input.first().cloned()
+    }
+
+    /// Reads the next element, returning its tag and contents.
+    fn read(&mut self) -> Option<(u8, &'a [u8])> {
+        let tag = *self.input.first()?;
+        let first = *self.input.get(1)? as usize;
+        let (length, header) = if first < 0x80 {
+            (first, 2)
+        } else {
+            let count = first & 0x7f;
+            if count == 0 || count > 4 {
+                return None;
+            }
+            let bytes = self.input.get(2..2 + count)?;
+            let length = bytes
+                .iter()
+                .fold(0usize, |length, &byte| length << 8 | byte as usize);
+            (length, 2 + count)
+        };
+
+        let contents = self.input.get(header..header.checked_add(length)?)?;
+        self.input = &self.input[header + length..];
+        Some((tag, contents))
+    }
+
+    /// Reads the next element, which must have the tag `tag`.
+    fn expect(&mut self, tag: u8) -> Option<&'a [u8]> {
+        match self.read()? {
+            (found, contents) if found == tag => Some(contents),
+            _ => None,
+        }
+    }
+
+    /// Reads the next element if it has the tag `tag`.
+    fn optional(&mut self, tag: u8) -> Option<&'a [u8]> {
+        if self.peek_tag() == Some(tag) {
+            self.expect(tag)
+        } else {
+            None
+        }
+    }
+}
+
+/// Formats an object identifier in dotted decimal.
+fn object_identifier(contents: &[u8]) -> String {
+    let mut parts = Vec::new();
+    let mut value = 0u64;
+    for &byte in contents {
+        value = value << 7 | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            if parts.is_empty() {
+                let first = (value / 40).min(2);
+                parts.push(first);
+                parts.push(value - first * 40);
+            } else {
+                parts.push(value);
+            }
+            value = 0;
+        }
+    }
+
+    parts
+        .iter()
+        .map(|part| part.to_string())
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+fn attribute_name(oid: &str) -> String {
+    let name = match oid {
+        "2.5.4.3" => "CN",
+        "2.5.4.5" => "serialNumber",
+        "2.5.4.6" => "C",
+        "2.5.4.7" => "L",
+        "2.5.4.8" => "ST",
+        "2.5.4.10" => "O",
+        "2.5.4.11" => "OU",
+        "1.2.840.113549.1.9.1" => "emailAddress",
+        other => return other.to_string(),
+    };
+
+    name.to_string()
+}
+
+/// Decodes a directory string. BMPString is UTF-16 and the rest are read as
+/// UTF-8, which covers the ASCII based types.
+fn directory_string(tag: u8, contents: &[u8]) -> String {
+    if tag == 0x1e {
+        let units: Vec<u16> = contents
+            .chunks(2)
+            .filter(|pair| pair.len() == 2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+
+    String::from_utf8_lossy(contents).into_owned()
+}
+
+/// Formats a distinguished name as `attribute=value` pairs in the order
+/// they are encoded, like OpenSSL does.
+fn parse_name(contents: &[u8]) -> Option<String> {
+    let mut attributes = Vec::new();
+    let mut names = DerReader::new(contents);
+    while !names.is_empty() {
+        let mut set = DerReader::new(names.expect(SET)?);
+        while !set.is_empty() {
+            let mut attribute = DerReader::new(set.expect(SEQUENCE)?);
+            let oid = object_identifier(attribute.expect(OBJECT_IDENTIFIER)?);
+            let (tag, value) = attribute.read()?;
+            attributes.push(format!(
+                "{}={}",
+                attribute_name(&oid),
+                directory_string(tag, value)
+            ));
+        }
+    }
+
+    Some(attributes.join(", "))
+}
+
+/// Reads a UTCTime or GeneralizedTime in UTC as a time since the Unix epoch.
+fn parse_time(tag: u8, contents: &[u8]) -> Option<Duration> {
+    let text = std::str::from_utf8(contents).ok()?;
+    let text = text.strip_suffix('Z')?;
+    if !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    let (year, rest) = match tag {
+        UTC_TIME if text.len() == 12 => {
+            let year: i64 = text[..2].parse().ok()?;
+            (
+                if year < 50 { 2000 + year } else { 1900 + year },
+                &text[2..],
+            )
+        }
+        GENERALIZED_TIME if text.len() == 14 => (text[..4].parse().ok()?, &text[4..]),
+        _ => return None,
+    };
+
+    let field = |index: usize| rest[index..index + 2].parse::<u32>().ok();
+    let (month, day) = (field(0)?, field(2)?);
+    let (hour, minute, second) = (field(4)?, field(6)?, field(8)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let seconds = days * 86_400 + i64::from(hour * 3600 + minute * 60 + second);
+    Some(Duration::from_secs(seconds.max(0) as u64))
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum KeyType {
+    Rsa(usize),
+    Ec(String),
+    Ed25519,
+    Ed448,
+    Dsa,
+
+    /// A key algorithm which isn't recognised, by object identifier.
+    Other(String),
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            KeyType::Rsa(bits) => write!(f, "RSA {} bits", bits),
+            KeyType::Ec(curve) => write!(f, "EC {}", curve),
+            KeyType::Ed25519 => write!(f, "Ed25519"),
+            KeyType::Ed448 => write!(f, "Ed448"),
+            KeyType::Dsa => write!(f, "DSA"),
+            KeyType::Other(oid) => write!(f, "{}", oid),
+        }
+    }
+}
+
+fn parse_key_type(contents: &[u8]) -> Option<KeyType> {
+    let mut info = DerReader::new(contents);
+    let mut algorithm = DerReader::new(info.expect(SEQUENCE)?);
+    let oid = object_identifier(algorithm.expect(OBJECT_IDENTIFIER)?);
+    let key = info.expect(BIT_STRING)?;
+
+    let key_type = match oid.as_str() {
+        "1.2.840.113549.1.1.1" => {
+            // The key is an RSAPublicKey after the count of unused bits.
+            let mut public_key = DerReader::new(DerReader::new(key.get(1..)?).expect(SEQUENCE)?);
+            let modulus = public_key.expect(INTEGER)?;
+            let modulus: Vec<u8> = modulus.iter().cloned().skip_while(|&b| b == 0).collect();
+            let bits = match modulus.first() {
+                Some(first) => modulus.len() * 8 - first.leading_zeros() as usize,
+                None => 0,
+            };
+            KeyType::Rsa(bits)
+        }
+        "1.2.840.10045.2.1" => {
+            let curve = algorithm
+                .optional(OBJECT_IDENTIFIER)
+                .map(object_identifier)
+                .unwrap_or_default();
+            let name = match curve.as_str() {
+                "1.2.840.10045.3.1.7" => "P-256".to_string(),
+                "1.3.132.0.34" => "P-384".to_string(),
+                "1.3.132.0.35" => "P-521".to_string(),
+                _ => curve,
+            };
+            KeyType::Ec(name)
+        }
+        "1.3.101.112" => KeyType::Ed25519,
+        "1.3.101.113" => KeyType::Ed448,
+        "1.2.840.10040.4.1" => KeyType::Dsa,
+        _ => KeyType::Other(oid),
+    };
+
+    Some(key_type)
+}
+
+/// The DNS names and IP addresses in a subjectAltName extension.
+fn parse_subject_alt_names(contents: &[u8]) -> Option<Vec<String>> {
+    let mut names = Vec::new();
+    let mut general_names = DerReader::new(DerReader::new(contents).expect(SEQUENCE)?);
+    while !general_names.is_empty() {
+        let (tag, value) = general_names.read()?;
+        match (tag, value.len()) {
+            (DNS_NAME, _) => names.push(String::from_utf8_lossy(value).into_owned()),
+            (IP_ADDRESS, 4) => {
+                let octets = [value[0], value[1], value[2], value[3]];
+                names.push(IpAddr::V4(Ipv4Addr::from(octets)).to_string());
+            }
+            (IP_ADDRESS, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(value);
+                names.push(IpAddr::V6(Ipv6Addr::from(octets)).to_string());
+            }
+            _ => {}
+        }
+    }
+
+    Some(names)
+}
+
+/// How a certificate's validity window compares to a point in time.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ExpiryStatus {
+    NotYetValid,
+    Valid,
+
+    /// Valid, but not for longer than `EXPIRY_WARNING`.
+    ExpiresSoon(Duration),
+
+    Expired(Duration),
+}
+
+impl ExpiryStatus {
+    /// Whether the certificate is expired or close to it.
+    pub fn needs_attention(self) -> bool {
+        !matches!(self, ExpiryStatus::Valid)
+    }
+}
+
+impl fmt::Display for ExpiryStatus {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ExpiryStatus::NotYetValid => write!(f, "not yet valid"),
+            ExpiryStatus::Valid => write!(f, "valid"),
+            ExpiryStatus::ExpiresSoon(left) => {
+                write!(f, "expires in {} days", left.as_secs() / 86_400)
+            }
+            ExpiryStatus::Expired(ago) => write!(f, "expired {} days ago", ago.as_secs() / 86_400),
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Certificate {
+    der: Vec<u8>,
+    serial_number: Vec<u8>,
+    issuer: String,
+    subject: String,
+    not_before: Duration,
+    not_after: Duration,
+    key_type: KeyType,
+    subject_alt_names: Vec<String>,
+}
+
+impl Certificate {
+    /// Parses a DER encoded certificate.
+    pub fn parse(der: &[u8]) -> Result<Certificate, X509Error> {
+        Certificate::parse_fields(der).ok_or(X509Error::InvalidCertificate)
+    }
+
+    fn parse_fields(der: &[u8]) -> Option<Certificate> {
+        let mut certificate = DerReader::new(DerReader::new(der).expect(SEQUENCE)?);
+        let mut tbs = DerReader::new(certificate.expect(SEQUENCE)?);
+
+        tbs.optional(VERSION);
+        let serial_number = tbs.expect(INTEGER)?.to_vec();
+        tbs.expect(SEQUENCE)?;
+        let issuer = parse_name(tbs.expect(SEQUENCE)?)?;
+        let mut validity = DerReader::new(tbs.expect(SEQUENCE)?);
+        let (tag, contents) = validity.read()?;
+        let not_before = parse_time(tag, contents)?;
+        let (tag, contents) = validity.read()?;
+        let not_after = parse_time(tag, contents)?;
+        let subject = parse_name(tbs.expect(SEQUENCE)?)?;
+        let key_type = parse_key_type(tbs.expect(SEQUENCE)?)?;
+
+        let mut subject_alt_names = Vec::new();
+        while let Some((tag, contents)) = tbs.read() {
+            if tag != EXTENSIONS {
+                continue;
+            }
+
+            let mut extensions = DerReader::new(DerReader::new(contents).expect(SEQUENCE)?);
+            while !extensions.is_empty() {
+                let mut extension = DerReader::new(extensions.expect(SEQUENCE)?);
+                let oid = object_identifier(extension.expect(OBJECT_IDENTIFIER)?);
+                extension.optional(0x01);
+                let value = extension.expect(OCTET_STRING)?;
+                if oid == SUBJECT_ALT_NAME {
+                    subject_alt_names = parse_subject_alt_names(value)?;
+                }
+            }
+        }
+
+        Some(Certificate {
+            der: der.to_vec(),
+            serial_number,
+            issuer,
+            subject,
+            not_before,
+            not_after,
+            key_type,
+            subject_alt_names,
+        })
+    }
+
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    pub fn serial_number(&self) -> &[u8] {
+        &self.serial_number
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn not_before(&self) -> Duration {
+        self.not_before
+    }
+
+    pub fn not_after(&self) -> Duration {
+        self.not_after
+    }
+
+    pub fn key_type(&self) -> &KeyType {
+        &self.key_type
+    }
+
+    pub fn subject_alt_names(&self) -> &[String] {
+        &self.subject_alt_names
+    }
+
+    pub fn is_self_signed(&self) -> bool {
+        self.subject == self.issuer
+    }
+
+    /// The SHA-256 fingerprint of the DER encoding in hex.
+    pub fn fingerprint(&self) -> String {
+        to_hex(&Sha256::digest(&self.der))
+    }
+
+    pub fn status(&self, at: Duration) -> ExpiryStatus {
+        if at < self.not_before {
+            ExpiryStatus::NotYetValid
+        } else if at > self.not_after {
+            ExpiryStatus::Expired(at - self.not_after)
+        } else if self.not_after - at <= EXPIRY_WARNING {
+            ExpiryStatus::ExpiresSoon(self.not_after - at)
+        } else {
+            ExpiryStatus::Valid
+        }
+    }
+
+    /// The certificate in PEM, with lines of 64 base64 characters.
+    pub fn to_pem(&self) -> String {
+        let encoded = STANDARD.encode(&self.der);
+        let mut pem = "-----BEGIN CERTIFICATE-----\n".to_string();
+        for line in encoded.as_bytes().chunks(64) {
+            pem.push_str(&String::from_utf8_lossy(line));
+            pem.push('\n');
+        }
+        pem.push_str("-----END CERTIFICATE-----\n");
+
+        pem
+    }
+}
+
+/// A certificate sent by a server, with where it sat in the chain and when
+/// it was seen.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct InventoryEntry {
+    server: Endpoint,
+    certificate: Certificate,
+    position: usize,
+    first_seen: Duration,
+    last_seen: Duration,
+}
+
+impl InventoryEntry {
+    pub fn server(&self) -> Endpoint {
+        self.server
+    }
+
+    pub fn certificate(&self) -> &Certificate {
+        &self.certificate
+    }
+
+    /// The position in the chain, where 0 is the server's own certificate.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn first_seen(&self) -> Duration {
+        self.first_seen
+    }
+
+    pub fn last_seen(&self) -> Duration {
+        self.last_seen
+    }
+
+    /// Whether the certificate was expired or close to it when it was last
+    /// seen.
+    pub fn status(&self) -> ExpiryStatus {
+        self.certificate.status(self.last_seen)
+    }
+}
+
+/// The distinct certificates each server sent.
+#[derive(Debug, Default)]
+pub struct CertificateInventory {
+    entries: Vec<InventoryEntry>,
+
+    /// The DER of the certificates which couldn't be parsed, along with the
+    /// server which sent them.
+    invalid: HashSet<(Endpoint, Vec<u8>)>,
+}
+
+impl CertificateInventory {
+    pub fn new() -> CertificateInventory {
+        CertificateInventory::default()
+    }
+
+    /// Adds the DER encoded certificate chain a server sent at `timestamp`.
+    pub fn add(&mut self, server: Endpoint, chain: &[Vec<u8>], timestamp: Duration) {
+        for (position, der) in chain.iter().enumerate() {
+            let existing = self
+                .entries
+                .iter_mut()
+                .find(|entry| entry.server == server && entry.certificate.der == *der);
+            if let Some(entry) = existing {
+                entry.first_seen = entry.first_seen.min(timestamp);
+                entry.last_seen = entry.last_seen.max(timestamp);
+                continue;
+            }
+
+            match Certificate::parse(der) {
+                Ok(certificate) => self.entries.push(InventoryEntry {
+                    server,
+                    certificate,
+                    position,
+                    first_seen: timestamp,
+                    last_seen: timestamp,
+                }),
+                Err(_) => {
+                    self.invalid.insert((server, der.clone()));
+                }
+            }
+        }
+    }
+
+    /// Every certificate, grouped by server.
+    pub fn entries(&self) -> Vec<&InventoryEntry> {
+        let mut entries: Vec<&InventoryEntry> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| (entry.server.address(), entry.server.port(), entry.position));
+        entries
+    }
+
+    /// The number of distinct certificates which couldn't be parsed, counting
+    /// each server separately like the parsed ones.
+    pub fn invalid(&self) -> usize {
+        self.invalid.len()
+    }
+}
+
+/// Writes a report of the certificates, grouped by server. Certificates
+/// which were expired or close to expiry when last seen are flagged.
+pub fn write_report<W: Write>(output: &mut W, entries: &[&InventoryEntry]) -> io::Result<()> {
+    let mut server = None;
+    for entry in entries {
+        if server != Some(entry.server()) {
+            server = Some(entry.server());
+            writeln!(output, "{}", entry.server())?;
+        }
+
+        let certificate = entry.certificate();
+        let status = entry.status();
+        writeln!(
+            output,
+            "  [{}] {}{}",
+            entry.position(),
+            certificate.subject(),
+            if status.needs_attention() { " !" } else { "" }
+        )?;
+        if !certificate.subject_alt_names().is_empty() {
+            writeln!(
+                output,
+                "      names:       {}",
+                certificate.subject_alt_names().join(", ")
+            )?;
+        }
+        writeln!(output, "      issuer:      {}", certificate.issuer())?;
+        writeln!(
+            output,
+            "      valid:       {} to {}",
+            format_timestamp(certificate.not_before()),
+            format_timestamp(certificate.not_after())
+        )?;
+        writeln!(output, "      key:         {}", certificate.key_type())?;
+        writeln!(output, "      sha256:      {}", certificate.fingerprint())?;
+        writeln!(
+            output,
+            "      status:      {} at {}",
+            status,
+            format_timestamp(entry.last_seen())
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes the certificates in PEM, each after a comment naming the server
+/// that sent it and its subject.
+pub fn write_pem<W: Write>(output: &mut W, entries: &[&InventoryEntry]) -> io::Result<()> {
+    for entry in entries {
+        writeln!(
+            output,
+            "# {} {}",
+            entry.server(),
+            entry.certificate().subject()
+        )?;
+        output.write_all(entry.certificate().to_pem().as_bytes())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate hex;
+
+    use flow::Endpoint;
+    use std::time::Duration;
+    use x509::{write_report, Certificate, CertificateInventory, ExpiryStatus, KeyType, X509Error};
+
+    /// An EC certificate for example.com with DNS and IP alternative names,
+    /// valid from 2024-01-01 until 2024-04-01 00:00:30.
+    const LEAF: &str =
+        "308201ab30820114a00302010202021234300d06092a864886f70d01010b05003034310b30090603\
+     550406130255533110300e060355040a0c074578616d706c653113301106035504030c0a4578616d\
+     706c65204341301e170d3234303130313030303030305a170d3234303430313030303033305a3016\
+     3114301206035504030c0b6578616d706c652e636f6d3059301306072a8648ce3d020106082a8648\
+     ce3d030107034200049d6999fa46dfb05d27c6928c5dac9e3cc17e6285a72a93d3f1302975666ac4\
+     14692d6e0aab0d9dbf673f366584d46cb7be375dcf4e60b6dff03e8151f0ac287da331302f302d06\
+     03551d1104263024820b6578616d706c652e636f6d820f7777772e6578616d706c652e636f6d8704\
+     c0000201300d06092a864886f70d01010b05000381810072b598715dcc872ad7ed517584a70fb7f3\
+     39571bdca12755b734831d3d48405709f82723432d8fda19afe85b2eb699e422226d4beccd7a74d6\
+     98f2a6397c0a20083b20f85da39beb82eda9e65a23c8d53eb4ec55d1fbed66a79e079bef0e77d046\
+     2a279bca32c5a2b3fef321f76d3194d30100363412bed31d6189dd4dff52a3";
+
+    /// A self-signed RSA certificate valid from 2020 until 2050-06-01 12:00,
+    /// which is encoded as a GeneralizedTime.
+    const CA: &str =
+        "308201de30820147a003020102020101300d06092a864886f70d01010b05003034310b3009060355\
+     0406130255533110300e060355040a0c074578616d706c653113301106035504030c0a4578616d70\
+     6c652043413020170d3230303130313030303030305a180f32303530303630313132303030305a30\
+     34310b30090603550406130255533110300e060355040a0c074578616d706c653113301106035504\
+     030c0a4578616d706c6520434130819f300d06092a864886f70d010101050003818d003081890281\
+     8100de13a657d0301e81caf312b1cca9aa45345c62db7eb69b0859cd465870c25c042c4299d10f9b\
+     d087be31a92d17370a061177e22fb63a02cc162e01ef18be2d5f227d6f99f9c0b55c3e64ae077a0e\
+     c05c5b89de5a6effb975048c1a541be9af1685f9917c3df2ed2fd3c2623b3c116ffce5c50f4fc1ab\
+     b5999074fc4dd317a7c70203010001300d06092a864886f70d01010b050003818100081f3fed9d07\
+     8ae65d4bec49ec2ee9915bd8274bc416ff6cf50d4bcd85b376388eaa1ae19e14e11f7a1e3c307a2e\
+     441c8030b8307f845bfc2c6d9837599841e1aedfd256f8dd98e2dbc577c55a57de733bd022332f0f\
+     1d8f52914039e0720a8dfebebf0e1c91544c9f3e8236fee1788ef831e96e1d4bd9dc199fbded2c10\
+     9d74";
+
+    #[test]
+    fn parse() {
+        let certificate = Certificate::parse(&hex::decode(LEAF).unwrap()).unwrap();
+
+        assert_eq!(certificate.serial_number(), &[0x12, 0x34][..]);
+        assert_eq!(certificate.subject(), "CN=example.com");
+        assert_eq!(certificate.issuer(), "C=US, O=Example, CN=Example CA");
+        assert_eq!(certificate.not_before(), Duration::from_secs(1_704_067_200));
+        assert_eq!(certificate.not_after(), Duration::from_secs(1_711_929_630));
+        assert_eq!(certificate.key_type(), &KeyType::Ec("P-256".to_string()));
+        assert_eq!(
+            certificate.subject_alt_names(),
+            &["example.com", "www.example.com", "192.0.2.1"][..]
+        );
+        assert!(!certificate.is_self_signed());
+        assert_eq!(
+            certificate.fingerprint(),
+            "ebc2f09aec582024a62dbb5170e2ce9dc112afea426da734735ca3902c512334"
+        );
+
+        let pem = certificate.to_pem();
+        assert!(pem.starts_with(
+            "-----BEGIN CERTIFICATE-----\n\
+             MIIBqzCCARSgAwIBAgICEjQwDQYJKoZIhvcNAQELBQAwNDELMAkGA1UEBhMCVVMx\n"
+        ));
+        assert!(pem.ends_with("\n-----END CERTIFICATE-----\n"));
+
+        let ca = Certificate::parse(&hex::decode(CA).unwrap()).unwrap();
+        assert_eq!(ca.not_before(), Duration::from_secs(1_577_836_800));
+        assert_eq!(ca.not_after(), Duration::from_secs(2_537_697_600));
+        assert_eq!(ca.key_type(), &KeyType::Rsa(1024));
+        assert!(ca.subject_alt_names().is_empty());
+        assert!(ca.is_self_signed());
+
+        assert_eq!(
+            Certificate::parse(&hex::decode(LEAF).unwrap()[..200]),
+            Err(X509Error::InvalidCertificate)
+        );
+    }
+
+    #[test]
+    fn status() {
+        let certificate = Certificate::parse(&hex::decode(LEAF).unwrap()).unwrap();
+        let day = Duration::from_secs(86_400);
+        let not_after = certificate.not_after();
+
+        assert_eq!(
+            certificate.status(certificate.not_before() - day),
+            ExpiryStatus::NotYetValid
+        );
+        assert_eq!(
+            certificate.status(certificate.not_before()),
+            ExpiryStatus::Valid
+        );
+        assert_eq!(
+            certificate.status(not_after - day * 10),
+            ExpiryStatus::ExpiresSoon(day * 10)
+        );
+        assert_eq!(
+            certificate.status(not_after + day * 2),
+            ExpiryStatus::Expired(day * 2)
+        );
+        assert_eq!(
+            certificate.status(not_after + day * 2).to_string(),
+            "expired 2 days ago"
+        );
+    }
+
+    #[test]
+    fn inventory() {
+        let leaf = hex::decode(LEAF).unwrap();
+        let ca = hex::decode(CA).unwrap();
+        let first = Endpoint::new("192.0.2.1".parse().unwrap(), 443);
+        let second = Endpoint::new("192.0.2.1".parse().unwrap(), 8443);
+        let seen = Duration::from_secs(1_711_000_000);
+        let later = seen + Duration::from_secs(30 * 86_400);
+
+        let mut inventory = CertificateInventory::new();
+        inventory.add(second, &[hex::decode(CA).unwrap()], seen);
+        inventory.add(first, &[leaf.clone(), ca.clone()], seen);
+        inventory.add(first, &[leaf, ca], later);
+        inventory.add(first, &[vec![0x30, 0x00]], seen);
+        inventory.add(first, &[vec![0x30, 0x00]], later);
+        inventory.add(second, &[vec![0x30, 0x00]], seen);
+
+        let entries = inventory.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(inventory.invalid(), 2);
+
+        assert_eq!(entries[0].server(), first);
+        assert_eq!(entries[0].position(), 0);
+        assert_eq!(entries[0].first_seen(), seen);
+        assert_eq!(entries[0].last_seen(), later);
+        assert_eq!(entries[0].certificate().subject(), "CN=example.com");
+        assert!(entries[0].status().needs_attention());
+
+        assert_eq!(entries[1].server(), first);
+        assert_eq!(entries[1].position(), 1);
+        assert_eq!(entries[1].status(), ExpiryStatus::Valid);
+        assert_eq!(entries[2].server(), second);
+        assert_eq!(entries[2].position(), 0);
+
+        let mut report = Vec::new();
+        write_report(&mut report, &entries[..1]).unwrap();
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "192.0.2.1:443\n\
+             \x20 [0] CN=example.com !\n\
+             \x20     names:       example.com, www.example.com, 192.0.2.1\n\
+             \x20     issuer:      C=US, O=Example, CN=Example CA\n\
+             \x20     valid:       2024-01-01T00:00:00.000Z to 2024-04-01T00:00:30.000Z\n\
+             \x20     key:         EC P-256\n\
+             \x20     sha256:      \
+             ebc2f09aec582024a62dbb5170e2ce9dc112afea426da734735ca3902c512334\n\
+             \x20     status:      expired 19 days ago at 2024-04-20T05:46:40.000Z\n"
+        );
+    }
+}